@@ -0,0 +1,47 @@
+// Benchmark the cost of erasing rules, compared to leaving them generic.
+// Run with `cargo bench`.
+
+#![feature(test)]
+
+extern crate parsell;
+extern crate test;
+
+use parsell::{character, Parser, UncommittedStr};
+use parsell::ParseResult::Done;
+use test::Bencher;
+
+fn input() -> String {
+    let mut input = "abc 123 de 4 fghij 56789 ".repeat(1000);
+    input.push('!');
+    input
+}
+
+#[bench]
+fn bench_generic(bencher: &mut Bencher) {
+    fn ignore() {}
+    let input = input();
+    let word = character(char::is_alphabetic).plus(String::new);
+    let number = character(char::is_numeric).plus(String::new);
+    let space = character(char::is_whitespace).plus(ignore);
+    let token = word.or_else(number).and_then_discard(space.opt());
+    let tokens = token.star(Vec::new);
+    bencher.iter(|| match tokens.init_str(&*input) {
+        Some(Done(result)) => result.len(),
+        _ => panic!("Can't happen"),
+    });
+}
+
+#[bench]
+fn bench_erased(bencher: &mut Bencher) {
+    fn ignore() {}
+    let input = input();
+    let word = character(char::is_alphabetic).plus(String::new).erase();
+    let number = character(char::is_numeric).plus(String::new).erase();
+    let space = character(char::is_whitespace).plus(ignore).erase();
+    let token = word.or_else(number).and_then_discard(space.opt()).erase();
+    let tokens = token.star(Vec::new).erase_committed();
+    bencher.iter(|| match tokens.init_str(&*input) {
+        Some(Done(result)) => result.len(),
+        _ => panic!("Can't happen"),
+    });
+}
@@ -0,0 +1,65 @@
+//! Adapters from [chumsky](https://github.com/zesterer/chumsky) parsers to parsell.
+//!
+//! `from_chumsky(p)` turns a chumsky parser `p: chumsky::Parser<char, T>` into a parsell parser,
+//! which buffers its input, and runs `p` at the end of input. Its output is `Ok` of the chumsky
+//! output, or `Err` of the chumsky errors. Like `parser.parse(input)`, any input that `p`
+//! does not consume is ignored, so use chumsky's `end()` to reject trailing input.
+
+use super::{FromComplete, from_complete};
+use Function;
+
+use chumsky::{Parser, Error};
+
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std;
+
+/// A chumsky parser used as a function on complete input.
+
+pub struct Chumsky<P, T>(P, PhantomData<fn() -> T>);
+
+impl<P, T> Copy for Chumsky<P, T> where P: Copy {}
+impl<P, T> Clone for Chumsky<P, T> where P: Clone {
+    fn clone(&self) -> Self {
+        Chumsky(self.0.clone(), PhantomData)
+    }
+}
+
+impl<P, T> Debug for Chumsky<P, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Chumsky(...)")
+    }
+}
+
+impl<'b, P, T> Function<&'b str> for Chumsky<P, T>
+    where P: Parser<char, T>,
+          P::Error: Error<char, Span = Range<usize>>,
+{
+    type Output = Result<T, Vec<P::Error>>;
+    fn apply(&self, input: &'b str) -> Self::Output {
+        self.0.parse(input)
+    }
+}
+
+/// Build a parsell parser from a chumsky parser.
+
+pub fn from_chumsky<P, T>(parser: P) -> FromComplete<Chumsky<P, T>>
+    where P: Parser<char, T>,
+{
+    from_complete(Chumsky(parser, PhantomData))
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_from_chumsky() {
+    use {UncommittedStr, StatefulStr};
+    use chumsky::prelude::{end, filter, Simple};
+    let letters = filter::<char, _, Simple<char>>(|ch: &char| ch.is_alphabetic()).repeated().collect::<String>();
+    let parser = from_chumsky(letters.then_ignore(end()));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.last_str("cd"), Ok(String::from("abcd")));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert!(parsing.last_str("1").is_err());
+}
@@ -0,0 +1,111 @@
+//! Adapters from [combine](https://github.com/Marwes/combine) parsers to parsell.
+//!
+//! `from_combine(p)` turns a combine parser `p: combine::Parser<Buffer>` into a parsell parser,
+//! which buffers its input, and runs `p` at the end of input. Its output is `Ok` of the combine
+//! output, or `Err` of the combine error. Any input that `p` does not consume is ignored,
+//! so use combine's `eof()` to reject trailing input.
+//!
+//! The buffer outlives any one chunk of input, so combine parsers are run on a `Buffer`
+//! which owns the text, rather than on a `&str`. Parsers built from combine's generic
+//! combinators, such as `many1(digit())`, can be run on either.
+
+use super::{FromComplete, from_complete};
+use Function;
+
+use combine::{Parser, StreamOnce, Positioned};
+use combine::stream::{ResetStream, StreamErrorFor};
+use combine::error::StringStreamError;
+
+/// The buffered input given to a combine parser, which is the text and the position in it.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Buffer {
+    text: String,
+    position: usize,
+}
+
+impl Buffer {
+    /// The input still to be parsed.
+    pub fn as_str(&self) -> &str {
+        &self.text[self.position..]
+    }
+}
+
+impl StreamOnce for Buffer {
+    type Token = char;
+    type Range = char;
+    type Position = usize;
+    type Error = StringStreamError;
+
+    fn uncons(&mut self) -> Result<char, StreamErrorFor<Self>> {
+        match self.as_str().chars().next() {
+            Some(ch) => {
+                self.position += ch.len_utf8();
+                Ok(ch)
+            },
+            None => Err(StringStreamError::Eoi),
+        }
+    }
+}
+
+impl Positioned for Buffer {
+    fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl ResetStream for Buffer {
+    type Checkpoint = usize;
+
+    fn checkpoint(&self) -> usize {
+        self.position
+    }
+
+    fn reset(&mut self, checkpoint: usize) -> Result<(), StringStreamError> {
+        self.position = checkpoint;
+        Ok(())
+    }
+}
+
+/// A combine parser used as a function on complete input.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Combine<P>(P);
+
+impl<'b, P> Function<&'b str> for Combine<P>
+    where P: Clone + Parser<Buffer>,
+{
+    type Output = Result<P::Output, StringStreamError>;
+    fn apply(&self, input: &'b str) -> Self::Output {
+        let buffer = Buffer {
+            text: String::from(input),
+            position: 0,
+        };
+        self.0.clone().parse(buffer).map(|(result, _)| result)
+    }
+}
+
+/// Build a parsell parser from a combine parser.
+
+pub fn from_combine<P>(parser: P) -> FromComplete<Combine<P>> {
+    from_complete(Combine(parser))
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_from_combine() {
+    use {UncommittedStr, StatefulStr};
+    use combine::{many1, eof, satisfy};
+    use combine::parser::char::digit;
+    let parser = from_combine(many1::<String, _, _>(digit()));
+    let parsing = parser.init_str("12").unwrap().unContinue();
+    assert_eq!(parsing.last_str("34"), Ok(String::from("1234")));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert!(parsing.last_str("cd").is_err());
+    let parser = from_combine(many1::<String, _, _>(digit()).or(many1(satisfy(char::is_alphabetic))).skip(eof()));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.last_str("c\u{e9}"), Ok(String::from("abc\u{e9}")));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert!(parsing.last_str("1").is_err());
+}
@@ -0,0 +1,142 @@
+//! Adapters between parsell and other parsing libraries.
+//!
+//! Most parser combinator libraries expect all of their input at once. `from_complete(f)`
+//! adapts any such parser `f: Function<&str, Output = Result<T, E>>` by buffering the input
+//! until the end of input is reached, and then running `f` on the buffer, for example:
+//!
+//! ```
+//! # use parsell::{Parser,UncommittedStr,StatefulStr};
+//! # use parsell::ParseResult::Continue;
+//! # use parsell::compat::from_complete;
+//! fn number(input: &str) -> Result<u32, String> { input.trim().parse().map_err(|_| String::from(input)) }
+//! let parser = from_complete(number);
+//! match parser.init_str(" 12").unwrap() {
+//!     Continue(parsing) => assert_eq!(parsing.last_str("34 "), Ok(1234)),
+//!     _ => panic!("Can't happen"),
+//! }
+//! ```
+//!
+//! Since these parsers consume all of their input, they are usually used for the last
+//! part of a grammar, or with a driver which splits the input into records first.
+//! Adapters for specific libraries are only available with the feature of the same name.
+
+use {Parser, HasOutput, Uncommitted, Committed, Stateful, ParseResult, Function};
+use ParseResult::Continue;
+
+use std::fmt::{Debug, Formatter};
+use std::str::Chars;
+use std;
+
+#[cfg(feature = "chumsky")]
+pub mod chumsky;
+#[cfg(feature = "combine")]
+pub mod combine;
+#[cfg(feature = "nom")]
+pub mod nom;
+
+// ----------- Complete-input parsers -------------
+
+/// A parsell parser built from a parser which needs all of its input at once.
+
+pub struct FromComplete<F>(F);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F> Copy for FromComplete<F> where F: Copy {}
+impl<F> Clone for FromComplete<F> where F: Clone {
+    fn clone(&self) -> Self {
+        FromComplete(self.0.clone())
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F> Debug for FromComplete<F> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "FromComplete(...)")
+    }
+}
+
+impl<F> Parser for FromComplete<F> {}
+
+impl<'a, F> HasOutput<char, Chars<'a>> for FromComplete<F>
+    where F: Function<&'a str>,
+{
+    type Output = F::Output;
+}
+
+impl<'a, F, Output> Uncommitted<char, Chars<'a>, Output> for FromComplete<F>
+    where F: Clone + for<'b> Function<&'b str, Output = Output>,
+{
+    type State = FromCompleteState<F>;
+
+    fn init(&self, data: &mut Chars<'a>) -> Option<ParseResult<Self::State, Output>> {
+        let string = data.as_str();
+        if string.is_empty() {
+            None
+        } else {
+            *data = string[string.len()..].chars();
+            Some(Continue(FromCompleteState(self.0.clone(), String::from(string))))
+        }
+    }
+}
+
+impl<'a, F, Output> Committed<char, Chars<'a>, Output> for FromComplete<F>
+    where F: Clone + for<'b> Function<&'b str, Output = Output>,
+{
+    fn empty(&self) -> Output {
+        self.0.apply("")
+    }
+}
+
+/// Build a parsell parser from a parser which needs all of its input at once.
+
+pub fn from_complete<F>(f: F) -> FromComplete<F> {
+    FromComplete(f)
+}
+
+pub struct FromCompleteState<F>(F, String);
+
+impl<F> Debug for FromCompleteState<F> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "FromCompleteState(..., {:?})", self.1)
+    }
+}
+
+impl<'a, F> HasOutput<char, Chars<'a>> for FromCompleteState<F>
+    where F: Function<&'a str>,
+{
+    type Output = F::Output;
+}
+
+impl<'a, F, Output> Stateful<char, Chars<'a>, Output> for FromCompleteState<F>
+    where F: for<'b> Function<&'b str, Output = Output>,
+{
+    fn more(mut self, data: &mut Chars<'a>) -> ParseResult<Self, Output> {
+        let string = data.as_str();
+        self.1.push_str(string);
+        *data = string[string.len()..].chars();
+        Continue(self)
+    }
+
+    fn done(self) -> Output {
+        self.0.apply(&*self.1)
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_from_complete() {
+    use {UncommittedStr, StatefulStr, CHARACTER};
+    fn count(input: &str) -> usize { input.chars().count() }
+    let parser = from_complete(count);
+    assert!(parser.init_str("").is_none());
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("").unContinue();
+    let parsing = parsing.more_str("c\u{e9}").unContinue();
+    assert_eq!(parsing.last_str("d"), 5);
+    let parser = CHARACTER.and_then(from_complete(count));
+    assert_eq!(parser.init_str("abc").unwrap().unContinue().last_str("de"), (Some('a'), 4));
+    assert_eq!(parser.init_str("a").unwrap().unContinue().last_str(""), (Some('a'), 0));
+}
@@ -0,0 +1,243 @@
+//! Adapters between parsell and [nom](https://github.com/Geal/nom).
+//!
+//! `from_nom(f)` turns a streaming nom parser `f: Fn(&str) -> IResult<&str, T>` into a parsell
+//! parser with output `Result<T, ErrorKind>`, and `to_nom(parser)` turns a parsell string parser
+//! into a nom parser, so nom grammars can be migrated into streaming pipelines one rule at a time.
+//!
+//! For example, using nom's streaming `digit1` parser:
+//!
+//! ```
+//! # extern crate nom;
+//! # extern crate parsell;
+//! # use parsell::{UncommittedStr,StatefulStr};
+//! # use parsell::compat::nom::from_nom;
+//! # use nom::IResult;
+//! fn digits(input: &str) -> IResult<&str, u32> {
+//!     nom::character::streaming::digit1(input).map(|(rest, digits)| (rest, digits.parse().unwrap()))
+//! }
+//! let parsing = from_nom(digits).init_str("12").unwrap().unContinue();
+//! assert_eq!(parsing.more_str("34!").unDone(), Ok(1234));
+//! ```
+//!
+//! A nom parser is run directly on the first chunk of input. If it needs more input, the
+//! chunk is copied into a buffer, and the nom parser is run again on the buffer each time
+//! more input arrives, so nom parsers which often span chunks will do a lot of copying.
+//! The nom parser should be a streaming parser, which reports `Incomplete` rather than
+//! succeeding when it reaches the end of its input. Input which has been buffered cannot
+//! be given back, so if the nom parser does not consume all of it, the output is
+//! `Err(ErrorKind::Fail)`.
+
+use {Parser, HasOutput, Uncommitted, Stateful, ParseResult};
+use ParseResult::{Done, Continue};
+
+use nom::{IResult, Err};
+use nom::Needed;
+use nom::error::{Error, ErrorKind};
+
+use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::str::Chars;
+use std;
+
+// ----------- From nom to parsell -------------
+
+/// A parsell parser built from a nom parser.
+///
+/// If the nom parser returns `Error` on the first chunk of input, this parser backtracks.
+/// Otherwise its output is `Ok` of the nom output, or `Err` of the nom error kind.
+
+pub struct FromNom<F, T>(F, PhantomData<fn() -> T>);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F, T> Copy for FromNom<F, T> where F: Copy {}
+impl<F, T> Clone for FromNom<F, T> where F: Copy {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F, T> Debug for FromNom<F, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "FromNom(...)")
+    }
+}
+
+impl<F, T> Parser for FromNom<F, T> {}
+
+impl<'a, F, T> HasOutput<char, Chars<'a>> for FromNom<F, T> {
+    type Output = Result<T, ErrorKind>;
+}
+
+impl<'a, F, T> Uncommitted<char, Chars<'a>, Result<T, ErrorKind>> for FromNom<F, T>
+    where F: Copy + for<'b> Fn(&'b str) -> IResult<&'b str, T>,
+{
+    type State = FromNomState<F, T>;
+
+    fn init(&self, data: &mut Chars<'a>) -> Option<ParseResult<Self::State, Result<T, ErrorKind>>> {
+        let string = data.as_str();
+        if string.is_empty() {
+            return None;
+        }
+        match (self.0)(string) {
+            Ok((rest, result)) => {
+                *data = rest.chars();
+                Some(Done(Ok(result)))
+            },
+            Err(Err::Incomplete(_)) => {
+                *data = string[string.len()..].chars();
+                Some(Continue(FromNomState(self.0, String::from(string), PhantomData)))
+            },
+            Err(Err::Error(_)) => None,
+            Err(Err::Failure(err)) => Some(Done(Err(err.code))),
+        }
+    }
+}
+
+/// Build a parsell parser from a streaming nom parser.
+
+pub fn from_nom<F, T>(f: F) -> FromNom<F, T>
+    where F: for<'b> Fn(&'b str) -> IResult<&'b str, T>,
+{
+    FromNom(f, PhantomData)
+}
+
+pub struct FromNomState<F, T>(F, String, PhantomData<fn() -> T>);
+
+impl<F, T> Debug for FromNomState<F, T> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "FromNomState(..., {:?})", self.1)
+    }
+}
+
+impl<'a, F, T> HasOutput<char, Chars<'a>> for FromNomState<F, T> {
+    type Output = Result<T, ErrorKind>;
+}
+
+impl<'a, F, T> Stateful<char, Chars<'a>, Result<T, ErrorKind>> for FromNomState<F, T>
+    where F: for<'b> Fn(&'b str) -> IResult<&'b str, T>,
+{
+    fn more(mut self, data: &mut Chars<'a>) -> ParseResult<Self, Result<T, ErrorKind>> {
+        let string = data.as_str();
+        if string.is_empty() {
+            return Continue(self);
+        }
+        let buffered = self.1.len();
+        self.1.push_str(string);
+        let result = match (self.0)(&self.1) {
+            Ok((rest, result)) => Ok((self.1.len() - rest.len(), result)),
+            Err(Err::Incomplete(_)) => Err(None),
+            Err(Err::Error(err)) | Err(Err::Failure(err)) => Err(Some(err.code)),
+        };
+        match result {
+            Ok((consumed, _)) if consumed < buffered => Done(Err(ErrorKind::Fail)),
+            Ok((consumed, result)) => {
+                *data = string[(consumed - buffered)..].chars();
+                Done(Ok(result))
+            },
+            Err(None) => {
+                *data = string[string.len()..].chars();
+                Continue(self)
+            },
+            Err(Some(kind)) => Done(Err(kind)),
+        }
+    }
+
+    fn done(self) -> Result<T, ErrorKind> {
+        match (self.0)(&self.1) {
+            Ok(("", result)) => Ok(result),
+            Ok(_) => Err(ErrorKind::Fail),
+            Err(Err::Incomplete(_)) => Err(ErrorKind::Eof),
+            Err(Err::Error(err)) | Err(Err::Failure(err)) => Err(err.code),
+        }
+    }
+}
+
+// ----------- From parsell to nom -------------
+
+/// A nom parser built from a parsell parser.
+///
+/// If the parsell parser backtracks, this returns a nom `Error`, and if it
+/// needs more input, this returns `Incomplete`.
+
+#[derive(Copy, Clone, Debug)]
+pub struct ToNom<P>(P);
+
+impl<'a, P, Output> ::nom::Parser<&'a str, Output, Error<&'a str>> for ToNom<P>
+    where P: Uncommitted<char, Chars<'a>, Output>,
+{
+    fn parse(&mut self, input: &'a str) -> IResult<&'a str, Output> {
+        let mut data = input.chars();
+        match self.0.init(&mut data) {
+            None => Err(Err::Error(Error::new(input, ErrorKind::Fail))),
+            Some(Done(result)) => Ok((data.as_str(), result)),
+            Some(Continue(_)) => Err(Err::Incomplete(Needed::Unknown)),
+        }
+    }
+}
+
+/// Build a nom parser from a parsell string parser.
+
+pub fn to_nom<P>(parser: P) -> ToNom<P> {
+    ToNom(parser)
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_from_nom() {
+    use nom::character::streaming::digit1;
+    use {UncommittedStr, StatefulStr};
+    fn to_number(digits: Result<String, ErrorKind>) -> Result<u32, ErrorKind> {
+        digits.map(|digits| digits.parse().unwrap())
+    }
+    fn digits(input: &str) -> IResult<&str, String> {
+        digit1(input).map(|(rest, digits)| (rest, String::from(digits)))
+    }
+    let parser = from_nom(digits).map(to_number);
+    assert!(parser.init_str("").is_none());
+    assert!(parser.init_str("abc").is_none());
+    assert_eq!(parser.init_str("12!").unwrap().unDone(), Ok(12));
+    let parsing = parser.init_str("12").unwrap().unContinue();
+    let parsing = parsing.more_str("").unContinue();
+    let parsing = parsing.more_str("3").unContinue();
+    let mut data = "4!".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), Ok(1234));
+    assert_eq!(data.as_str(), "!");
+    let parsing = parser.init_str("56").unwrap().unContinue();
+    assert_eq!(parsing.done(), Err(ErrorKind::Eof));
+}
+
+#[test]
+fn test_from_nom_unconsumed() {
+    use nom::bytes::streaming::take;
+    use nom::character::streaming::digit1;
+    use nom::combinator::peek;
+    use nom::sequence::terminated;
+    use {UncommittedStr, StatefulStr};
+    fn first(input: &str) -> IResult<&str, String> {
+        terminated(take(1usize), peek(digit1))(input).map(|(rest, first)| (rest, String::from(first)))
+    }
+    let parser = from_nom(first);
+    let mut data = "12!".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Ok(String::from("1")));
+    assert_eq!(data.as_str(), "2!");
+    let parsing = parser.init_str("1").unwrap().unContinue();
+    let mut data = "2!".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), Ok(String::from("1")));
+    assert_eq!(data.as_str(), "2!");
+    let parsing = parser.init_str("12").unwrap().unContinue();
+    assert_eq!(parsing.more_str("!").unDone(), Err(ErrorKind::Fail));
+}
+
+#[test]
+fn test_to_nom() {
+    use {character, Parser};
+    use nom::Parser as NomParser;
+    let mut parser = to_nom(character(char::is_alphabetic).plus(String::new));
+    assert_eq!(parser.parse("abc!"), Ok(("!", String::from("abc"))));
+    assert_eq!(parser.parse("abc"), Err(Err::Incomplete(Needed::Unknown)));
+    assert_eq!(parser.parse("123"), Err(Err::Error(Error::new("123", ErrorKind::Fail))));
+}
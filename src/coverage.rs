@@ -0,0 +1,135 @@
+//! Grammar coverage instrumentation.
+//!
+//! Parsers labelled with `parser.named(name)` report each time they match,
+//! suspend waiting for more input, or backtrack on non-empty input. While coverage is
+//! being recorded, these events are counted, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr};
+//! # use parsell::coverage;
+//! let parser = character(char::is_numeric).named("digit")
+//!     .or_else(character(char::is_alphabetic).named("letter"))
+//!     .star(String::new).named("word");
+//! coverage::start();
+//! parser.init_str("a1b2!");
+//! let report = coverage::finish();
+//! assert_eq!(report.get("letter").matched, 2);
+//! assert_eq!(report.get("letter").rejected, 1);
+//! assert_eq!(report.get("word").matched, 1);
+//! ```
+//!
+//! Rules which are often rejected but never matched are candidates for dead code,
+//! and rules with high counts are candidates for optimization.
+//! Counts are recorded per thread.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::collections::btree_map;
+use std::fmt::{Display, Formatter};
+use std;
+
+/// The number of times a named parser matched, suspended or rejected.
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Counts {
+    /// The number of times the parser produced a result.
+    pub matched: usize,
+    /// The number of times the parser ran out of input and had to wait for more.
+    pub suspended: usize,
+    /// The number of times the parser backtracked on non-empty input.
+    pub rejected: usize,
+}
+
+/// A coverage report, produced by `finish()`.
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Coverage(BTreeMap<&'static str, Counts>);
+
+impl Coverage {
+    /// The counts for the parsers with the given name.
+    pub fn get(&self, name: &str) -> Counts {
+        self.0.get(name).cloned().unwrap_or_default()
+    }
+
+    /// Iterate over the names that were seen, in alphabetical order.
+    pub fn iter<'a>(&'a self) -> btree_map::Iter<'a, &'static str, Counts> {
+        self.0.iter()
+    }
+
+    /// The names of parsers that were tried but never matched.
+    pub fn unmatched(&self) -> Vec<&'static str> {
+        self.0.iter().filter(|&(_, counts)| counts.matched == 0).map(|(&name, _)| name).collect()
+    }
+}
+
+impl Display for Coverage {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        let width = self.0.keys().map(|name| name.len()).max().unwrap_or(0).max(4);
+        try!(writeln!(fmt, "{:width$} {:>9} {:>9} {:>9}", "rule", "matched", "suspended", "rejected", width = width));
+        for (name, counts) in &self.0 {
+            try!(writeln!(fmt, "{:width$} {:>9} {:>9} {:>9}", name, counts.matched, counts.suspended, counts.rejected, width = width));
+        }
+        Ok(())
+    }
+}
+
+thread_local!(static COVERAGE: RefCell<Option<Coverage>> = const { RefCell::new(None) });
+
+/// Start recording coverage on this thread, discarding any previous counts.
+
+pub fn start() {
+    COVERAGE.with(|coverage| *coverage.borrow_mut() = Some(Coverage::default()));
+}
+
+/// Stop recording coverage on this thread, and return the counts.
+
+pub fn finish() -> Coverage {
+    COVERAGE.with(|coverage| coverage.borrow_mut().take().unwrap_or_default())
+}
+
+fn update<F>(name: &'static str, f: F) where F: FnOnce(&mut Counts) {
+    COVERAGE.with(|coverage| {
+        if let Some(ref mut coverage) = *coverage.borrow_mut() {
+            f(coverage.0.entry(name).or_insert_with(Counts::default))
+        }
+    })
+}
+
+pub(crate) fn matched(name: &'static str) {
+    update(name, |counts| counts.matched += 1)
+}
+
+pub(crate) fn suspended(name: &'static str) {
+    update(name, |counts| counts.suspended += 1)
+}
+
+pub(crate) fn rejected(name: &'static str) {
+    update(name, |counts| counts.rejected += 1)
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_coverage() {
+    use super::{character, Parser, UncommittedStr, StatefulStr};
+    let DIGIT = character(char::is_numeric).named("digit");
+    let LETTER = character(char::is_alphabetic).named("letter");
+    let parser = DIGIT.or_else(LETTER).star(String::new).named("word");
+    parser.init_str("a1b2!");
+    assert_eq!(finish(), Coverage::default());
+    start();
+    parser.init_str("a1").unwrap().unContinue().more_str("b2!");
+    parser.init_str("");
+    let report = finish();
+    assert_eq!(report.get("digit"), Counts { matched: 2, suspended: 0, rejected: 3 });
+    assert_eq!(report.get("letter"), Counts { matched: 2, suspended: 0, rejected: 1 });
+    assert_eq!(report.get("word"), Counts { matched: 1, suspended: 1, rejected: 0 });
+    assert_eq!(report.get("other"), Counts::default());
+    assert_eq!(report.unmatched(), Vec::<&str>::new());
+    assert_eq!(format!("{}", report),
+               "rule     matched suspended  rejected\n\
+                digit          2         0         3\n\
+                letter         2         0         1\n\
+                word           1         1         0\n");
+}
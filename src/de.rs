@@ -0,0 +1,453 @@
+//! Deserialization driven by parsell grammars.
+//!
+//! This module is only available with the `serde` feature.
+//! A grammar for a custom text format is written as a parser whose output is a `Token`.
+//! A `Deserializer` runs this parser repeatedly, pulling more chunks of input only when
+//! a token is split across chunks, and uses the tokens to populate any type implementing
+//! `serde::Deserialize`, without building an intermediate value tree. For example:
+//!
+//! ```
+//! # extern crate parsell;
+//! #[macro_use]
+//! extern crate serde_derive;
+//! # use parsell::{character,Parser};
+//! # use parsell::de::{from_str,Token};
+//! #[derive(Debug, Deserialize, PartialEq)]
+//! struct Point { x: i64, y: i64 }
+//!
+//! # fn main() {
+//! fn ignore() {}
+//! // A grammar for tokens such as `{`, `x`, `37` and `}`, treating `=` as whitespace.
+//! let space = character(|ch: char| ch.is_whitespace() || ch == '=').star(ignore);
+//! let start = character(|ch| ch == '{').and_then_discard(space).map(|_: char| Token::MapStart);
+//! let end = character(|ch| ch == '}').and_then_discard(space).map(|_: char| Token::MapEnd);
+//! let key = character(char::is_alphabetic).plus(String::new).and_then_discard(space).map(Token::Str);
+//! let int = character(char::is_numeric).plus(String::new).and_then_discard(space)
+//!     .map(|digits: String| Token::Int(digits.parse().unwrap()));
+//! let tokens = start.or_else(end).or_else(key).or_else(int);
+//! let point: Point = from_str(tokens, "{ x = 37 y = 5 }").unwrap();
+//! assert_eq!(point, Point { x: 37, y: 5 });
+//! # }
+//! ```
+//!
+//! Maps and structs are written as `MapStart`, followed by alternating keys and values,
+//! followed by `MapEnd`. Sequences and tuples are written as `SeqStart`, the elements,
+//! and `SeqEnd`. Unit enum variants are written as a `Str` token, and other variants as
+//! a map with one entry, whose key is the variant name.
+//!
+//! Tokens do not have to correspond one-to-one with the syntax of the format:
+//! a token parser may consume punctuation or whitespace without producing a token
+//! of its own, as long as each call produces exactly one token.
+
+use super::Erasable;
+use super::session::Driver;
+
+use serde::de;
+use serde::de::{Visitor, DeserializeOwned, DeserializeSeed, SeqAccess, MapAccess, EnumAccess, VariantAccess, IntoDeserializer};
+
+use std::error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// A token produced by a grammar, and consumed by a `Deserializer`.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Char(char),
+    Str(String),
+    Unit,
+    None,
+    SeqStart,
+    SeqEnd,
+    MapStart,
+    MapEnd,
+}
+
+/// An error produced while deserializing.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Error(String);
+
+impl Error {
+    fn at(position: usize, message: &str) -> Error {
+        Error(format!("{} at byte {}", message, position))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        fmt.write_str(&self.0)
+    }
+}
+
+impl error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: Display>(message: T) -> Error {
+        Error(message.to_string())
+    }
+}
+
+/// A deserializer which reads tokens from chunks of input using a parser.
+
+pub struct Deserializer<P, I> {
+    parser: P,
+    chunks: I,
+    chunk: String,
+    offset: usize,
+    driver: Driver<Token>,
+    peeked: Option<Token>,
+}
+
+impl<P, I> Deserializer<P, I>
+    where P: Erasable<Token>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    /// A deserializer which runs `parser` on the chunks of input from `chunks`.
+    pub fn new(parser: P, chunks: I) -> Self {
+        Deserializer {
+            parser,
+            chunks,
+            chunk: String::new(),
+            offset: 0,
+            driver: Driver::new(),
+            peeked: None,
+        }
+    }
+
+    /// Checks that all of the input has been consumed.
+    pub fn end(&mut self) -> Result<(), Error> {
+        let position = self.driver.position();
+        match try!(self.peek()) {
+            None => Ok(()),
+            Some(_) => Err(Error::at(position, "Trailing input")),
+        }
+    }
+
+    fn next_chunk(&mut self) -> bool {
+        match self.chunks.next() {
+            None => false,
+            Some(chunk) => {
+                self.chunk = chunk.into();
+                self.offset = 0;
+                true
+            }
+        }
+    }
+
+    fn parse(&mut self) -> Result<Option<Token>, Error> {
+        loop {
+            while self.offset == self.chunk.len() {
+                if !self.next_chunk() {
+                    return Ok(self.driver.finish());
+                }
+            }
+            let mut data = self.chunk[self.offset..].chars();
+            let result = self.driver.step(&self.parser, &mut data);
+            self.offset = self.chunk.len() - data.as_str().len();
+            match result {
+                Ok(None) => (),
+                Ok(token) => return Ok(token),
+                Err(position) => return Err(Error::at(position, "Unexpected input")),
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Result<Option<&Token>, Error> {
+        if self.peeked.is_none() {
+            self.peeked = try!(self.parse());
+        }
+        Ok(self.peeked.as_ref())
+    }
+
+    fn next(&mut self) -> Result<Token, Error> {
+        match self.peeked.take() {
+            Some(token) => Ok(token),
+            None => match try!(self.parse()) {
+                Some(token) => Ok(token),
+                None => Err(Error::at(self.driver.position(), "Unexpected end of input")),
+            },
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), Error> {
+        if try!(self.next()) == expected {
+            Ok(())
+        } else {
+            Err(Error::at(self.driver.position(), &format!("Expected {:?}", expected)))
+        }
+    }
+
+    fn next_is(&mut self, expected: Token) -> Result<bool, Error> {
+        if try!(self.peek()) == Some(&expected) {
+            self.peeked = None;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+}
+
+/// Deserialize a value from chunks of input, using a parser for tokens.
+
+pub fn from_chunks<T, P, I>(parser: P, chunks: I) -> Result<T, Error>
+    where T: DeserializeOwned,
+          P: Erasable<Token>,
+          I: IntoIterator,
+          I::Item: Into<String>,
+{
+    let mut deserializer = Deserializer::new(parser, chunks.into_iter());
+    let result = try!(T::deserialize(&mut deserializer));
+    try!(deserializer.end());
+    Ok(result)
+}
+
+/// Deserialize a value from a string, using a parser for tokens.
+
+pub fn from_str<T, P>(parser: P, input: &str) -> Result<T, Error>
+    where T: DeserializeOwned,
+          P: Erasable<Token>,
+{
+    from_chunks(parser, Some(input))
+}
+
+impl<'de, P, I> de::Deserializer<'de> for &mut Deserializer<P, I>
+    where P: Erasable<Token>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    type Error = Error;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match try!(self.next()) {
+            Token::Bool(value) => visitor.visit_bool(value),
+            Token::Int(value) => visitor.visit_i64(value),
+            Token::UInt(value) => visitor.visit_u64(value),
+            Token::Float(value) => visitor.visit_f64(value),
+            Token::Char(value) => visitor.visit_char(value),
+            Token::Str(value) => visitor.visit_string(value),
+            Token::Unit => visitor.visit_unit(),
+            Token::None => visitor.visit_none(),
+            Token::SeqStart => {
+                let result = try!(visitor.visit_seq(Seq(&mut *self)));
+                try!(self.expect(Token::SeqEnd));
+                Ok(result)
+            },
+            Token::MapStart => {
+                let result = try!(visitor.visit_map(Map(&mut *self)));
+                try!(self.expect(Token::MapEnd));
+                Ok(result)
+            },
+            Token::SeqEnd | Token::MapEnd => Err(Error::at(self.driver.position(), "Unexpected end of collection")),
+        }
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        if try!(self.next_is(Token::None)) {
+            visitor.visit_none()
+        } else {
+            visitor.visit_some(self)
+        }
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(self, _name: &'static str, _variants: &'static [&'static str], visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        match try!(self.next()) {
+            Token::Str(variant) => visitor.visit_enum(variant.into_deserializer()),
+            Token::MapStart => {
+                let result = try!(visitor.visit_enum(Enum(&mut *self)));
+                try!(self.expect(Token::MapEnd));
+                Ok(result)
+            },
+            _ => Err(Error::at(self.driver.position(), "Expected an enum variant")),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf
+        unit unit_struct seq tuple tuple_struct map struct identifier ignored_any
+    }
+}
+
+// The elements of a sequence, up to the closing `SeqEnd`.
+
+struct Seq<'a, P: 'a, I: 'a>(&'a mut Deserializer<P, I>);
+
+impl<'de, 'a, P, I> SeqAccess<'de> for Seq<'a, P, I>
+    where P: Erasable<Token>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    type Error = Error;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Error>
+        where T: DeserializeSeed<'de>,
+    {
+        if try!(self.0.peek()) == Some(&Token::SeqEnd) {
+            Ok(None)
+        } else {
+            seed.deserialize(&mut *self.0).map(Some)
+        }
+    }
+}
+
+// The entries of a map, up to the closing `MapEnd`.
+
+struct Map<'a, P: 'a, I: 'a>(&'a mut Deserializer<P, I>);
+
+impl<'de, 'a, P, I> MapAccess<'de> for Map<'a, P, I>
+    where P: Erasable<Token>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    type Error = Error;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Error>
+        where K: DeserializeSeed<'de>,
+    {
+        if try!(self.0.peek()) == Some(&Token::MapEnd) {
+            Ok(None)
+        } else {
+            seed.deserialize(&mut *self.0).map(Some)
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Error>
+        where V: DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut *self.0)
+    }
+}
+
+// An enum variant written as a map with one entry.
+
+struct Enum<'a, P: 'a, I: 'a>(&'a mut Deserializer<P, I>);
+
+impl<'de, 'a, P, I> EnumAccess<'de> for Enum<'a, P, I>
+    where P: Erasable<Token>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    type Error = Error;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self), Error>
+        where V: DeserializeSeed<'de>,
+    {
+        let variant = try!(seed.deserialize(&mut *self.0));
+        Ok((variant, self))
+    }
+}
+
+impl<'de, 'a, P, I> VariantAccess<'de> for Enum<'a, P, I>
+    where P: Erasable<Token>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        self.0.expect(Token::Unit)
+    }
+
+    fn newtype_variant_seed<T>(self, seed: T) -> Result<T::Value, Error>
+        where T: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.0)
+    }
+
+    fn tuple_variant<V>(self, _len: usize, visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self.0, visitor)
+    }
+
+    fn struct_variant<V>(self, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, Error>
+        where V: Visitor<'de>,
+    {
+        de::Deserializer::deserialize_any(self.0, visitor)
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_from_chunks() {
+    use super::{character, Parser};
+    fn ignore() {}
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    fn is_lbracket(ch: char) -> bool { ch == '[' }
+    fn is_rbracket(ch: char) -> bool { ch == ']' }
+    fn mk_int((digits, ()): (String, ())) -> Token { Token::Int(digits.parse().unwrap()) }
+    fn mk_seq_start(_: (char, ())) -> Token { Token::SeqStart }
+    fn mk_seq_end(_: (char, ())) -> Token { Token::SeqEnd }
+    let SPACE = character(char::is_whitespace).star(ignore);
+    let INT = character(is_digit).plus(String::new).and_then(SPACE).map(mk_int);
+    let START = character(is_lbracket).and_then(SPACE).map(mk_seq_start);
+    let END = character(is_rbracket).and_then(SPACE).map(mk_seq_end);
+    let TOKEN = START.or_else(END).or_else(INT);
+    assert_eq!(from_str::<Vec<Vec<i64>>, _>(TOKEN, "[[1 2] [] [34]]"), Ok(vec![vec![1, 2], vec![], vec![34]]));
+    assert_eq!(from_chunks::<Vec<i64>, _, _>(TOKEN, vec!["[1", "2 3", "4]"]), Ok(vec![12, 34]));
+    assert_eq!(from_chunks::<Vec<i64>, _, _>(TOKEN, vec!["", "[1 ", "", "2]", ""]), Ok(vec![1, 2]));
+    assert_eq!(from_str::<Vec<i64>, _>(TOKEN, "[1 x]"), Err(Error(String::from("Unexpected input at byte 3"))));
+    assert_eq!(from_str::<Vec<i64>, _>(TOKEN, "[1 2"), Err(Error(String::from("Unexpected end of input at byte 4"))));
+    assert_eq!(from_str::<i64, _>(TOKEN, "1 2"), Err(Error(String::from("Trailing input at byte 2"))));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_derive() {
+    use super::{character, Parser};
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape { Point, Circle { radius: i64 } }
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Layer { name: String, shapes: Vec<Shape>, hidden: Option<bool> }
+    fn ignore() {}
+    fn is_punctuation(ch: char) -> bool { ch == '{' || ch == '}' || ch == '[' || ch == ']' }
+    fn is_separator(ch: char) -> bool { ch.is_whitespace() || ch == ':' || ch == ',' }
+    fn mk_punctuation(ch: char) -> Token {
+        match ch {
+            '{' => Token::MapStart,
+            '}' => Token::MapEnd,
+            '[' => Token::SeqStart,
+            _ => Token::SeqEnd,
+        }
+    }
+    fn mk_word(word: String) -> Token {
+        match &*word {
+            "true" => Token::Bool(true),
+            "false" => Token::Bool(false),
+            _ => Token::Str(word),
+        }
+    }
+    fn mk_int(digits: String) -> Token { Token::Int(digits.parse().unwrap()) }
+    let SEPARATOR = character(is_separator).star(ignore);
+    let PUNCTUATION = character(is_punctuation).and_then_discard(SEPARATOR).map(mk_punctuation);
+    let WORD = character(char::is_alphabetic).plus(String::new).and_then_discard(SEPARATOR).map(mk_word);
+    let INT = character(char::is_numeric).plus(String::new).and_then_discard(SEPARATOR).map(mk_int);
+    let TOKEN = PUNCTUATION.or_else(WORD).or_else(INT);
+    let layer = Layer { name: String::from("top"), shapes: vec![Shape::Point, Shape::Circle { radius: 12 }], hidden: None };
+    let input = "{ name: top, shapes: [Point, { Circle: { radius: 12 } }] }";
+    assert_eq!(from_str::<Layer, _>(TOKEN, input), Ok(layer));
+    let chunks = vec!["{ name: to", "p, hidden: tr", "ue, shapes: [] }"];
+    let layer = Layer { name: String::from("top"), shapes: vec![], hidden: Some(true) };
+    assert_eq!(from_chunks::<Layer, _, _>(TOKEN, chunks), Ok(layer));
+    assert!(from_str::<Layer, _>(TOKEN, "{ name: top }").is_err());
+}
@@ -0,0 +1,253 @@
+//! Step-by-step debugging of parsers.
+//!
+//! This module is only available with the `debugger` feature.
+//! A `Debugger` runs a parser repeatedly over its input one character at a time,
+//! and after each step reports the outline of the active state (as produced by `dump_state`),
+//! and what the named rules did, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::debugger::{Debugger,RuleEvent,Status};
+//! let word = character(char::is_alphabetic).plus(String::new).named("word");
+//! let mut debugger = Debugger::new(word);
+//! debugger.push("ab!");
+//! let step = debugger.step().unwrap();
+//! assert_eq!(step.status, Status::Waiting);
+//! assert_eq!(step.state, "Rule \"word\" waiting\n  Star\n");
+//! assert_eq!(step.rules, vec![RuleEvent::Enter("word"), RuleEvent::Suspend("word")]);
+//! debugger.step();
+//! let step = debugger.step().unwrap();
+//! assert_eq!((step.status, step.consumed), (Status::Matched, None));
+//! assert_eq!(debugger.outputs(), ["ab"]);
+//! ```
+//!
+//! Debuggers can also step backwards, by running the parser again from the start
+//! of the input, so parsers do not need to be able to copy their state. Like sessions,
+//! debuggers have a simple type, which makes them suitable for driving from a TUI or REPL.
+
+use super::{Erasable, BoxedState, Outline, Stateful};
+use super::ParseResult::{Done, Continue};
+
+use std::cell::RefCell;
+use std::mem;
+
+/// What a named rule did during a step.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RuleEvent {
+    /// The rule started.
+    Enter(&'static str),
+    /// The rule was given more input.
+    Resume(&'static str),
+    /// The rule needs more input.
+    Suspend(&'static str),
+    /// The rule completed.
+    Complete(&'static str),
+    /// The rule backtracked.
+    Reject(&'static str),
+}
+
+/// The state of the parser after a step.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Status {
+    /// The parser is waiting for more input.
+    Waiting,
+    /// The parser produced an output, and will be run again on the rest of the input.
+    Matched,
+    /// The parser rejected the input, and the debugger cannot step any further.
+    Rejected,
+}
+
+/// The report of one step.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Step {
+    /// The character which was consumed, if any.
+    pub consumed: Option<char>,
+    /// The byte position in the input after the step.
+    pub position: usize,
+    /// The state of the parser after the step.
+    pub status: Status,
+    /// What the named rules did during the step.
+    pub rules: Vec<RuleEvent>,
+    /// The outline of the active state after the step, which is empty if there is none.
+    pub state: String,
+}
+
+thread_local!(static RECORDING: RefCell<Option<Vec<RuleEvent>>> = const { RefCell::new(None) });
+
+pub(crate) fn record(event: RuleEvent) {
+    RECORDING.with(|recording| {
+        if let Some(ref mut events) = *recording.borrow_mut() {
+            events.push(event)
+        }
+    })
+}
+
+fn recording<F, T>(f: F) -> (T, Vec<RuleEvent>) where F: FnOnce() -> T {
+    let previous = RECORDING.with(|recording| recording.borrow_mut().replace(Vec::new()));
+    let result = f();
+    let events = RECORDING.with(|recording| mem::replace(&mut *recording.borrow_mut(), previous));
+    (result, events.unwrap_or_default())
+}
+
+/// A debugger, which runs a parser one character at a time.
+
+pub struct Debugger<Output> {
+    parser: Box<Erasable<Output>>,
+    state: Option<BoxedState<Output>>,
+    input: String,
+    position: usize,
+    steps: Vec<Step>,
+    outputs: Vec<Output>,
+}
+
+impl<Output> Debugger<Output> {
+    /// A debugger which runs `parser` repeatedly.
+    pub fn new<P>(parser: P) -> Self where P: 'static + Erasable<Output> {
+        Debugger {
+            parser: Box::new(parser),
+            state: None,
+            input: String::new(),
+            position: 0,
+            steps: Vec::new(),
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Add text to the end of the input.
+    pub fn push(&mut self, text: &str) {
+        self.input.push_str(text);
+    }
+
+    /// Give the parser the next character of input, returning the report of the step.
+    ///
+    /// The result is `None` if there is no more input, or the parser has rejected its input.
+    pub fn step(&mut self) -> Option<&Step> {
+        if self.steps.last().map(|step| step.status) == Some(Status::Rejected) {
+            return None;
+        }
+        let ch = self.input[self.position..].chars().next()?;
+        let chunk = &self.input[self.position..(self.position + ch.len_utf8())];
+        let mut data = chunk.chars();
+        let state = self.state.take();
+        let resumed = state.is_some();
+        let parser = &self.parser;
+        let (result, rules) = recording(|| match state {
+            Some(state) => Some(state.more(&mut data)),
+            None => parser.init_erasable(&mut data),
+        });
+        let consumed = if data.as_str().is_empty() { Some(ch) } else { None };
+        let status = match result {
+            // Like sessions, a parser which succeeds without consuming input is treated as
+            // rejecting it, but a suspended state may complete on seeing the next character.
+            Some(Done(output)) if resumed || consumed.is_some() => {
+                self.outputs.push(output);
+                Status::Matched
+            },
+            Some(Continue(state)) => {
+                self.state = Some(state);
+                Status::Waiting
+            },
+            _ => Status::Rejected,
+        };
+        if consumed.is_some() {
+            self.position += ch.len_utf8();
+        }
+        let state = self.state.as_ref().map(|state| {
+            let mut outline = Outline::new();
+            state.dump(&mut outline);
+            outline.to_string()
+        }).unwrap_or_default();
+        self.steps.push(Step {
+            consumed,
+            position: self.position,
+            status,
+            rules,
+            state,
+        });
+        self.steps.last()
+    }
+
+    /// Undo the last step, by running the parser again from the start of the input.
+    ///
+    /// The result is the report of the step before it, or `None` if there was none.
+    pub fn back(&mut self) -> Option<&Step> {
+        let count = self.steps.len().saturating_sub(1);
+        self.state = None;
+        self.position = 0;
+        self.steps.clear();
+        self.outputs.clear();
+        while self.steps.len() < count {
+            self.step();
+        }
+        self.steps.last()
+    }
+
+    /// The reports of every step so far.
+    pub fn steps(&self) -> &[Step] {
+        &self.steps
+    }
+
+    /// The outputs produced so far.
+    pub fn outputs(&self) -> &[Output] {
+        &self.outputs
+    }
+
+    /// The number of bytes of input consumed so far.
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    /// End the input, returning every output, including any which was waiting for more input.
+    pub fn finish(mut self) -> Vec<Output> {
+        self.outputs.extend(self.state.take().map(Stateful::done));
+        self.outputs
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_debugger() {
+    use super::{character, Parser};
+    use self::RuleEvent::{Enter, Resume, Suspend, Complete, Reject};
+    fn ignore() {}
+    let WORD = character(char::is_alphabetic).plus(String::new).named("word");
+    let SPACE = character(|ch| ch == ' ').plus(ignore).named("space");
+    let WORDS = WORD.and_then_discard(SPACE.opt());
+    let mut debugger = Debugger::new(WORDS);
+    assert_eq!(debugger.step(), None);
+    debugger.push("a b");
+    assert_eq!(debugger.step().unwrap(), &Step {
+        consumed: Some('a'),
+        position: 1,
+        status: Status::Waiting,
+        rules: vec![Enter("word"), Suspend("word")],
+        state: String::from("AndThen: in lhs\n  Rule \"word\" waiting\n    Star\n"),
+    });
+    assert_eq!(debugger.step().unwrap().rules, vec![Resume("word"), Complete("word"), Enter("space"), Suspend("space")]);
+    assert_eq!(debugger.step().unwrap().status, Status::Matched);
+    assert_eq!(debugger.outputs(), ["a"]);
+    assert_eq!(debugger.step().unwrap().consumed, Some('b'));
+    assert_eq!(debugger.step(), None);
+    assert_eq!(debugger.position(), 3);
+    assert_eq!(debugger.back().unwrap().status, Status::Matched);
+    assert_eq!(debugger.position(), 2);
+    assert_eq!(debugger.steps().len(), 3);
+    debugger.push("!");
+    debugger.step();
+    assert_eq!(debugger.step().unwrap().rules, vec![Resume("word"), Complete("word"), Enter("space"), Reject("space")]);
+    let step = debugger.step().unwrap().clone();
+    assert_eq!((step.consumed, step.position, step.status), (None, 3, Status::Rejected));
+    assert_eq!(step.rules, vec![Enter("word"), Reject("word")]);
+    assert_eq!(debugger.step(), None);
+    assert_eq!(debugger.finish(), vec!["a", "b"]);
+    let mut debugger = Debugger::new(WORD);
+    debugger.push("ab");
+    debugger.step();
+    debugger.step();
+    assert_eq!(debugger.finish(), vec!["ab"]);
+}
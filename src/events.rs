@@ -0,0 +1,302 @@
+//! Pull parsing, which turns a grammar into an iterator of events.
+//!
+//! Rather than building an output value, a grammar whose rules are labelled with
+//! `parser.named(name)` can be run by `Events`, which produces an event when each rule
+//! starts and ends, and a token event for the input consumed in between. Events are
+//! produced on demand, pulling chunks of input as they are needed, so a huge document can be
+//! processed with memory proportional to the size of a chunk, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::events::{Events,Event};
+//! fn ignore() {}
+//! let word = character(char::is_alphabetic).plus(ignore).named("word");
+//! let space = character(char::is_whitespace).plus(ignore);
+//! let words = word.and_then_discard(space.opt()).star(ignore).named("words");
+//! let events: Vec<Event> = Events::new(words, vec!["ab c", "d "]).map(Result::unwrap).collect();
+//! assert_eq!(events, vec![
+//!     Event::StartRule("words"), Event::StartRule("word"), Event::Token(String::from("ab")),
+//!     Event::EndRule("word"), Event::Token(String::from(" ")),
+//!     Event::StartRule("word"), Event::Token(String::from("c")),
+//!     Event::Token(String::from("d")), Event::EndRule("word"), Event::Token(String::from(" ")),
+//!     Event::EndRule("words"),
+//! ]);
+//! ```
+//!
+//! The grammar is run over `Input` rather than `Chars`, so it can use any parser
+//! which is generic in its input, but not string-only parsers such as `parser.buffer()`.
+//! When the grammar completes, it is run again on the remaining input.
+//! Rules which backtrack produce no events.
+
+use super::{Function, PeekableIterator, UncommittedInfer, Stateful};
+use super::ParseResult::{Done, Continue};
+use super::session::Error;
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::mem;
+
+/// An event produced by a grammar.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event {
+    /// A named rule started.
+    StartRule(&'static str),
+    /// A named rule ended.
+    EndRule(&'static str),
+    /// Input was consumed. Input which is split across chunks may produce more than one token.
+    Token(String),
+}
+
+/// A chunk of input, which records the characters consumed from it as tokens.
+
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    chunk: String,
+    position: usize,
+}
+
+impl Input {
+    fn new(chunk: String) -> Self {
+        Input {
+            chunk,
+            position: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chunk[self.position..].chars().next()
+    }
+}
+
+impl Iterator for Input {
+    type Item = char;
+    fn next(&mut self) -> Option<char> {
+        let ch = self.peek();
+        if let Some(ch) = ch {
+            self.position += ch.len_utf8();
+            record(|recorder| recorder.token.push(ch));
+        }
+        ch
+    }
+}
+
+impl PeekableIterator for Input {
+    fn is_empty(&mut self) -> bool {
+        self.position == self.chunk.len()
+    }
+
+    fn next_if_ref<F>(&mut self, f: F) -> Option<char>
+        where F: for<'a> Function<&'a char, Output = bool>
+    {
+        match self.peek() {
+            Some(ref ch) if f.apply(ch) => self.next(),
+            _ => None,
+        }
+    }
+
+    fn progress(&self) -> Option<usize> {
+        Some(self.position)
+    }
+}
+
+// The events recorded while a grammar is running. Since rules report their events
+// through `trace`, the recorder is installed in a thread-local while the grammar runs.
+// Rules which have started are recorded as the index of their start event,
+// counting events which have already been pulled.
+
+#[derive(Debug, Default)]
+struct Recorder {
+    events: VecDeque<Event>,
+    open: Vec<usize>,
+    pulled: usize,
+    token: String,
+}
+
+impl Recorder {
+    fn flush(&mut self) {
+        if !self.token.is_empty() {
+            let token = mem::take(&mut self.token);
+            self.events.push_back(Event::Token(token));
+        }
+    }
+}
+
+thread_local!(static RECORDER: RefCell<Option<Recorder>> = const { RefCell::new(None) });
+
+fn install(recorder: Recorder) {
+    RECORDER.with(|installed| *installed.borrow_mut() = Some(recorder))
+}
+
+fn uninstall() -> Recorder {
+    RECORDER.with(|installed| installed.borrow_mut().take()).unwrap_or_default()
+}
+
+fn record<F>(f: F) where F: FnOnce(&mut Recorder) {
+    RECORDER.with(|recorder| {
+        if let Some(ref mut recorder) = *recorder.borrow_mut() {
+            f(recorder)
+        }
+    })
+}
+
+pub(crate) fn entered(name: &'static str) {
+    record(|recorder| {
+        recorder.flush();
+        recorder.open.push(recorder.pulled + recorder.events.len());
+        recorder.events.push_back(Event::StartRule(name));
+    })
+}
+
+pub(crate) fn matched(name: &'static str) {
+    record(|recorder| {
+        recorder.flush();
+        recorder.open.pop();
+        recorder.events.push_back(Event::EndRule(name));
+    })
+}
+
+pub(crate) fn backtracked() {
+    record(|recorder| {
+        if let Some(start) = recorder.open.pop() {
+            recorder.events.truncate(start - recorder.pulled);
+        }
+    })
+}
+
+/// An iterator over the events produced by running a grammar over chunks of input.
+///
+/// If the grammar rejects its input, the iterator produces an error and then stops.
+/// Grammars which succeed without consuming input are treated as rejecting it,
+/// since otherwise they would succeed forever.
+
+pub struct Events<P, I> where P: UncommittedInfer<char, Input> {
+    parser: P,
+    state: Option<P::State>,
+    chunks: I,
+    input: Input,
+    offset: usize,
+    recorder: Recorder,
+    finished: bool,
+}
+
+impl<P, I> Events<P, I>
+    where P: UncommittedInfer<char, Input>,
+          P::State: Stateful<char, Input, P::Output>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    /// The events produced by running `parser` repeatedly over `chunks`.
+    pub fn new<J>(parser: P, chunks: J) -> Self where J: IntoIterator<IntoIter = I, Item = I::Item> {
+        Events {
+            parser,
+            state: None,
+            chunks: chunks.into_iter(),
+            input: Input::default(),
+            offset: 0,
+            recorder: Recorder::default(),
+            finished: false,
+        }
+    }
+
+    /// The byte position of the input consumed so far.
+    pub fn position(&self) -> usize {
+        self.offset + self.input.position
+    }
+
+    // Run one step of the grammar with the recorder installed,
+    // returning whether the grammar accepted its input.
+    fn step(&mut self) -> bool {
+        install(mem::take(&mut self.recorder));
+        let before = self.input.position;
+        let accepted = match self.state.take() {
+            Some(state) => match state.more(&mut self.input) {
+                Done(_) => true,
+                Continue(state) => {
+                    self.state = Some(state);
+                    true
+                },
+            },
+            None if self.input.is_empty() => true,
+            None => match self.parser.init(&mut self.input) {
+                Some(Done(_)) => self.input.position > before,
+                Some(Continue(state)) => {
+                    self.state = Some(state);
+                    true
+                },
+                None => false,
+            },
+        };
+        self.recorder = uninstall();
+        self.recorder.flush();
+        accepted
+    }
+
+    fn finish(&mut self) {
+        if let Some(state) = self.state.take() {
+            install(mem::take(&mut self.recorder));
+            state.done();
+            self.recorder = uninstall();
+        }
+        self.finished = true;
+    }
+}
+
+impl<P, I> Iterator for Events<P, I>
+    where P: UncommittedInfer<char, Input>,
+          P::State: Stateful<char, Input, P::Output>,
+          I: Iterator,
+          I::Item: Into<String>,
+{
+    type Item = Result<Event, Error>;
+
+    fn next(&mut self) -> Option<Result<Event, Error>> {
+        loop {
+            if let Some(event) = self.recorder.events.pop_front() {
+                self.recorder.pulled += 1;
+                return Some(Ok(event));
+            } else if self.finished {
+                return None;
+            } else if self.input.is_empty() {
+                match self.chunks.next() {
+                    Some(chunk) => {
+                        self.offset += self.input.chunk.len();
+                        self.input = Input::new(chunk.into());
+                    },
+                    None => self.finish(),
+                }
+            } else if !self.step() {
+                self.finished = true;
+                return Some(Err(Error { position: self.offset + self.input.position }));
+            }
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_events() {
+    use super::{character, Parser};
+    use self::Event::{StartRule, EndRule, Token};
+    fn ignore() {}
+    fn token(text: &str) -> Event { Token(String::from(text)) }
+    let DIGIT = character(char::is_numeric).named("digit");
+    let LETTER = character(char::is_alphabetic).named("letter");
+    let WORD = DIGIT.or_else(LETTER).plus(ignore).named("word");
+    let SPACE = character(char::is_whitespace).plus(ignore);
+    let events: Vec<Event> = Events::new(WORD.and_then_discard(SPACE.opt()), vec!["a1 ", "", "b"]).map(Result::unwrap).collect();
+    assert_eq!(events, vec![
+        StartRule("word"), StartRule("letter"), token("a"), EndRule("letter"),
+        StartRule("digit"), token("1"), EndRule("digit"), EndRule("word"), token(" "),
+        StartRule("word"), StartRule("letter"), token("b"), EndRule("letter"), EndRule("word"),
+    ]);
+    let mut events = Events::new(WORD.and_then_discard(SPACE.opt()), vec!["ab", "!"]);
+    assert_eq!(events.by_ref().filter_map(Result::err).next(), Some(Error { position: 2 }));
+    assert_eq!(events.position(), 2);
+    assert_eq!(events.next(), None);
+    let mut events = Events::new(character(char::is_whitespace).star(ignore), vec!["x"]);
+    assert_eq!(events.next(), Some(Err(Error { position: 0 })));
+    assert_eq!(Events::new(WORD, Vec::<String>::new()).next(), None);
+}
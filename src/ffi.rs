@@ -0,0 +1,394 @@
+//! A C interface to parsing sessions.
+//!
+//! This module is only available with the `ffi` feature.
+//! Grammars are registered by name from Rust, and can then be used from C to create parsing
+//! sessions, which are fed chunks of UTF-8 encoded bytes. Chunks may split characters.
+//! The outputs of a grammar are strings, which C code retrieves one at a time, for example:
+//!
+//! ```c
+//! parsell_session *session = parsell_session_new("words");
+//! if (parsell_session_push(session, buffer, length) != 0) {
+//!     fprintf(stderr, "%s\n", parsell_session_error(session));
+//! }
+//! char *word;
+//! while ((word = parsell_session_next(session))) {
+//!     puts(word);
+//!     parsell_string_free(word);
+//! }
+//! parsell_session_free(session);
+//! ```
+//!
+//! Rust code registers the grammar before any sessions are created:
+//!
+//! ```text
+//! parsell::ffi::register("words", || Session::new(words));
+//! ```
+
+use super::session::Session;
+
+use std::collections::VecDeque;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::slice;
+use std::str;
+use std::sync::{Mutex, PoisonError};
+
+// A registered grammar: its name, and a constructor for its sessions.
+
+type Grammar = (&'static str, fn() -> Session<String>);
+
+static GRAMMARS: Mutex<Vec<Grammar>> = Mutex::new(Vec::new());
+
+/// Register a grammar, so that C code can create sessions for it by name.
+///
+/// Registering a grammar with the same name as an existing one replaces it.
+
+pub fn register(name: &'static str, grammar: fn() -> Session<String>) {
+    let mut grammars = GRAMMARS.lock().unwrap_or_else(PoisonError::into_inner);
+    grammars.retain(|&(other, _)| other != name);
+    grammars.push((name, grammar));
+}
+
+/// A parsing session created from C.
+
+pub struct FfiSession {
+    session: Option<Session<String>>,
+    partial: Vec<u8>,
+    outputs: VecDeque<String>,
+    error: Option<CString>,
+}
+
+impl FfiSession {
+    fn push(&mut self, bytes: &[u8]) -> Result<(), String> {
+        if self.session.is_none() {
+            return Err(String::from("Session is finished"));
+        }
+        self.partial.extend_from_slice(bytes);
+        let (valid, invalid) = match str::from_utf8(&self.partial) {
+            Ok(string) => (string.len(), None),
+            Err(err) => (err.valid_up_to(), err.error_len()),
+        };
+        let result = {
+            let string = unsafe { str::from_utf8_unchecked(&self.partial[..valid]) };
+            self.session.as_mut().unwrap().push(string)
+        };
+        match result {
+            Ok(outputs) => self.outputs.extend(outputs),
+            Err((outputs, err)) => {
+                self.outputs.extend(outputs);
+                self.partial.clear();
+                return Err(err.to_string());
+            },
+        }
+        match invalid {
+            None => {
+                self.partial.drain(..valid);
+                Ok(())
+            },
+            Some(_) => {
+                self.partial.clear();
+                Err(format!("Invalid UTF-8 at byte {}", self.position()))
+            },
+        }
+    }
+
+    fn finish(&mut self) -> Result<(), String> {
+        if !self.partial.is_empty() {
+            return Err(format!("Incomplete UTF-8 at byte {}", self.position()));
+        }
+        let session = try!(self.session.take().ok_or_else(|| String::from("Session is finished")));
+        self.outputs.extend(session.finish());
+        Ok(())
+    }
+
+    fn position(&self) -> usize {
+        self.session.as_ref().map(Session::position).unwrap_or(0)
+    }
+
+    // Run an operation on the session, catching any panic in the grammar.
+    // The session may be left inconsistent by a panic, so it is then finished.
+    fn guard<T, F>(&mut self, operation: F) -> Result<T, String>
+        where F: FnOnce(&mut Self) -> Result<T, String>,
+    {
+        match catch(|| operation(self)) {
+            Ok(result) => result,
+            Err(message) => {
+                self.session = None;
+                self.partial.clear();
+                Err(message)
+            },
+        }
+    }
+
+    fn result(&mut self, result: Result<(), String>) -> c_int {
+        match result {
+            Ok(()) => { self.error = None; 0 },
+            Err(message) => {
+                self.error = CString::new(message).ok();
+                -1
+            },
+        }
+    }
+}
+
+// Panics must not unwind into C, so the body of each function is run with this,
+// which returns the message of any panic as an error.
+
+fn catch<T, F>(body: F) -> Result<T, String>
+    where F: FnOnce() -> T,
+{
+    panic::catch_unwind(AssertUnwindSafe(body)).map_err(|payload| {
+        let message = payload.downcast_ref::<&str>().map(|message| String::from(*message))
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("unknown payload"));
+        format!("Panic: {}", message)
+    })
+}
+
+/// Create a session for the grammar registered with the given name,
+/// returning null if there is no such grammar, or creating the session panics.
+///
+/// # Safety
+///
+/// `name` must be null or a NUL-terminated string.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_session_new(name: *const c_char) -> *mut FfiSession {
+    if name.is_null() {
+        return ptr::null_mut();
+    }
+    let name = CStr::from_ptr(name).to_bytes();
+    let grammar = GRAMMARS.lock().unwrap_or_else(PoisonError::into_inner).iter()
+        .find(|&&(other, _)| other.as_bytes() == name)
+        .map(|&(_, grammar)| grammar);
+    match grammar {
+        None => ptr::null_mut(),
+        Some(grammar) => catch(|| Box::into_raw(Box::new(FfiSession {
+            session: Some(grammar()),
+            partial: Vec::new(),
+            outputs: VecDeque::new(),
+            error: None,
+        }))).unwrap_or(ptr::null_mut()),
+    }
+}
+
+/// Feed a chunk of bytes to a session, returning 0 on success and -1 on error.
+///
+/// If the grammar rejects the input, or it is not valid UTF-8, the outputs completed before
+/// the error can still be retrieved, and the rest of the chunk is discarded.
+/// If the grammar panics, the error is the panic message, and the session is finished.
+///
+/// # Safety
+///
+/// `session` must be null or a session created by `parsell_session_new` which has not been freed,
+/// and `bytes` must be null or point to `len` bytes.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_session_push(session: *mut FfiSession, bytes: *const u8, len: usize) -> c_int {
+    if session.is_null() {
+        return -1;
+    }
+    let session = &mut *session;
+    if bytes.is_null() && len != 0 {
+        return session.result(Err(String::from("Null input")));
+    }
+    let bytes = if len == 0 { &[][..] } else { slice::from_raw_parts(bytes, len) };
+    let result = session.guard(|session| session.push(bytes));
+    session.result(result)
+}
+
+/// End the input to a session, returning 0 on success and -1 on error.
+/// Any output split across the end of the input can then be retrieved.
+///
+/// # Safety
+///
+/// `session` must be null or a session created by `parsell_session_new` which has not been freed.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_session_finish(session: *mut FfiSession) -> c_int {
+    if session.is_null() {
+        return -1;
+    }
+    let session = &mut *session;
+    let result = session.guard(FfiSession::finish);
+    session.result(result)
+}
+
+/// Retrieve the next output of a session, or null if there are none.
+/// The output must be freed with `parsell_string_free`.
+///
+/// An output which contains a NUL character cannot be returned as a C string,
+/// so it is skipped, and the result is null with an error set.
+///
+/// # Safety
+///
+/// `session` must be null or a session created by `parsell_session_new` which has not been freed.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_session_next(session: *mut FfiSession) -> *mut c_char {
+    if session.is_null() {
+        return ptr::null_mut();
+    }
+    let session = &mut *session;
+    let output = session.guard(|session| match session.outputs.pop_front() {
+        None => Ok(ptr::null_mut()),
+        Some(output) => CString::new(output)
+            .map(CString::into_raw)
+            .map_err(|err| format!("Output contains NUL at byte {}", err.nul_position())),
+    });
+    match output {
+        Ok(output) => {
+            session.error = None;
+            output
+        },
+        Err(message) => {
+            session.result(Err(message));
+            ptr::null_mut()
+        },
+    }
+}
+
+/// The message for the last error of a session, or null if the last call succeeded.
+/// The message is owned by the session.
+///
+/// # Safety
+///
+/// `session` must be null or a session created by `parsell_session_new` which has not been freed.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_session_error(session: *const FfiSession) -> *const c_char {
+    if session.is_null() {
+        return ptr::null();
+    }
+    (*session).error.as_ref().map(|message| message.as_ptr()).unwrap_or(ptr::null())
+}
+
+/// Free a session.
+///
+/// # Safety
+///
+/// `session` must be null or a session created by `parsell_session_new` which has not been freed.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_session_free(session: *mut FfiSession) {
+    if !session.is_null() {
+        let session = Box::from_raw(session);
+        let _ = catch(|| drop(session));
+    }
+}
+
+/// Free an output returned by `parsell_session_next`.
+///
+/// # Safety
+///
+/// `string` must be null or an output returned by `parsell_session_next` which has not been freed.
+
+#[no_mangle]
+pub unsafe extern "C" fn parsell_string_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_ffi() {
+    use super::{character, Parser};
+    fn ignore() {}
+    fn words() -> Session<String> {
+        let ALPHANUMERIC = character(char::is_alphanumeric);
+        let WHITESPACE = character(char::is_whitespace);
+        Session::new(ALPHANUMERIC.plus(String::new).and_then_discard(WHITESPACE.star(ignore)))
+    }
+    fn fields() -> Session<String> {
+        Session::new(character(|ch| ch != ';').plus(String::new).and_then_discard(character(|ch| ch == ';').opt()))
+    }
+    unsafe fn next(session: *mut FfiSession) -> Option<String> {
+        let output = parsell_session_next(session);
+        if output.is_null() { return None; }
+        let result = CStr::from_ptr(output).to_str().unwrap().to_owned();
+        parsell_string_free(output);
+        Some(result)
+    }
+    unsafe fn error(session: *mut FfiSession) -> String {
+        CStr::from_ptr(parsell_session_error(session)).to_str().unwrap().to_owned()
+    }
+    register("test_words", words);
+    unsafe {
+        assert!(parsell_session_new(b"test_other\0".as_ptr() as *const c_char).is_null());
+        let session = parsell_session_new(b"test_words\0".as_ptr() as *const c_char);
+        assert!(!session.is_null());
+        let input = "ab c\u{e9}d ef".as_bytes();
+        assert_eq!(parsell_session_push(session, input.as_ptr(), 5), 0);
+        assert!(parsell_session_error(session).is_null());
+        assert_eq!(next(session), Some(String::from("ab")));
+        assert_eq!(next(session), None);
+        assert_eq!(parsell_session_push(session, input[5..].as_ptr(), input.len() - 5), 0);
+        assert_eq!(next(session), Some(String::from("c\u{e9}d")));
+        assert_eq!(parsell_session_finish(session), 0);
+        assert_eq!(next(session), Some(String::from("ef")));
+        assert_eq!(parsell_session_finish(session), -1);
+        assert_eq!(error(session), "Session is finished");
+        parsell_session_free(session);
+        let session = parsell_session_new(b"test_words\0".as_ptr() as *const c_char);
+        assert_eq!(parsell_session_push(session, b"ab !c".as_ptr(), 5), -1);
+        assert_eq!(error(session), "Unexpected input at byte 3");
+        assert_eq!(next(session), Some(String::from("ab")));
+        assert_eq!(parsell_session_push(session, b"d e".as_ptr(), 3), 0);
+        assert_eq!(next(session), Some(String::from("d")));
+        parsell_session_free(session);
+        let session = parsell_session_new(b"test_words\0".as_ptr() as *const c_char);
+        assert_eq!(parsell_session_push(session, b"ab \xffc".as_ptr(), 5), -1);
+        assert_eq!(error(session), "Invalid UTF-8 at byte 3");
+        assert_eq!(next(session), None);
+        assert_eq!(parsell_session_push(session, b"d e".as_ptr(), 3), 0);
+        assert_eq!(next(session), Some(String::from("ab")));
+        assert_eq!(next(session), Some(String::from("d")));
+        assert_eq!(parsell_session_push(session, ptr::null(), 1), -1);
+        assert_eq!(error(session), "Null input");
+        parsell_session_free(session);
+        assert_eq!(parsell_session_push(ptr::null_mut(), b"a".as_ptr(), 1), -1);
+        assert_eq!(parsell_session_finish(ptr::null_mut()), -1);
+        assert!(parsell_session_next(ptr::null_mut()).is_null());
+        assert!(parsell_session_error(ptr::null()).is_null());
+        register("test_fields", fields);
+        let session = parsell_session_new(b"test_fields\0".as_ptr() as *const c_char);
+        assert_eq!(parsell_session_push(session, b"a\0b;c;".as_ptr(), 6), 0);
+        assert!(parsell_session_next(session).is_null());
+        assert_eq!(error(session), "Output contains NUL at byte 1");
+        assert_eq!(next(session), Some(String::from("c")));
+        assert!(parsell_session_error(session).is_null());
+        parsell_session_free(session);
+    }
+}
+
+#[test]
+fn test_ffi_panic() {
+    use super::{character, Parser};
+    fn is_any(_: char) -> bool { true }
+    fn explode(ch: char) -> String {
+        if ch == '!' { panic!("exploded") }
+        ch.to_string()
+    }
+    fn explosive() -> Session<String> {
+        Session::new(character(is_any).map(explode))
+    }
+    fn broken() -> Session<String> {
+        panic!("broken grammar")
+    }
+    register("test_explosive", explosive);
+    register("test_broken", broken);
+    unsafe {
+        assert!(parsell_session_new(b"test_broken\0".as_ptr() as *const c_char).is_null());
+        let session = parsell_session_new(b"test_explosive\0".as_ptr() as *const c_char);
+        assert_eq!(parsell_session_push(session, b"ab!c".as_ptr(), 4), -1);
+        assert_eq!(CStr::from_ptr(parsell_session_error(session)).to_str(), Ok("Panic: exploded"));
+        assert_eq!(parsell_session_push(session, b"d".as_ptr(), 1), -1);
+        assert_eq!(CStr::from_ptr(parsell_session_error(session)).to_str(), Ok("Session is finished"));
+        parsell_session_free(session);
+    }
+}
@@ -0,0 +1,428 @@
+//! Terminal escape sequences.
+//!
+//! Terminals are controlled by escape sequences embedded in their output, as defined by
+//! ECMA-48 and used by xterm and its descendants. The parser `escape_sequence()` reads one
+//! sequence, starting with `ESC`, into a command. Control Sequences (CSI, such as `ESC [ 1 ; 31 m`)
+//! which move the cursor, erase the screen or set colors and other graphic renditions (SGR)
+//! are decoded, as are Operating System Commands (OSC, such as `ESC ] 0 ; title BEL`) setting
+//! the window title. For example, text with its colors removed can be found by skipping
+//! sequences:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+//! # use parsell::formats::ansi::{escape_sequence,Command,Graphic,Color};
+//! let parser = escape_sequence().map(Some).or_else(character(|ch| ch != '\x1b').map(|_| None));
+//! let parsing = parser.star(Vec::new).init_str("\x1b[1;3").unwrap().unContinue();
+//! let commands = parsing.last_str("1mError\x1b[0m");
+//! assert_eq!(commands[0], Some(Command::Graphics(vec![
+//!     Graphic::Bold,
+//!     Graphic::Foreground(Color::Indexed(1)),
+//! ])));
+//! assert_eq!(commands[6], Some(Command::Graphics(vec![Graphic::Reset])));
+//! ```
+//!
+//! The 8-bit introducers `U+009B` (CSI) and `U+009D` (OSC) are accepted as well as `ESC [`
+//! and `ESC ]`, and an OSC ends with `BEL`, `ESC \` or `U+009C`. Sequences which are not
+//! decoded are given as they were written. A control sequence ends at the first character
+//! which is not a parameter or intermediate byte, and a sequence cut short by the end of
+//! input is `Command::Unfinished`. The parser commits on the first character.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+
+use std::mem;
+
+/// A parser for one escape sequence.
+
+pub fn escape_sequence() -> EscapeSequence {
+    EscapeSequence
+}
+
+/// A command given by an escape sequence.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// `CSI n A`, which moves the cursor up `n` lines.
+    CursorUp(u32),
+    /// `CSI n B`, which moves the cursor down `n` lines.
+    CursorDown(u32),
+    /// `CSI n C`, which moves the cursor forward `n` columns.
+    CursorForward(u32),
+    /// `CSI n D`, which moves the cursor back `n` columns.
+    CursorBack(u32),
+    /// `CSI row ; column H`, which moves the cursor to a position, counting from 1.
+    CursorPosition { row: u32, column: u32 },
+    /// `CSI n J`, which erases below (0), above (1) or all of (2) the screen.
+    EraseDisplay(u32),
+    /// `CSI n K`, which erases to the right of (0), to the left of (1) or all of (2) the line.
+    EraseLine(u32),
+    /// `CSI ... m`, which sets graphic renditions, in order.
+    Graphics(Vec<Graphic>),
+    /// `OSC 0 ; title` or `OSC 2 ; title`, which sets the window title.
+    SetTitle(String),
+    /// Any other control sequence.
+    Csi { parameters: String, intermediates: String, final_byte: char },
+    /// Any other operating system command, without its terminator.
+    Osc(String),
+    /// Any other escape sequence, such as `ESC 7` or `ESC ( B`.
+    Escape { intermediates: String, final_byte: char },
+    /// A sequence cut short by the end of input, as written after the `ESC`.
+    Unfinished(String),
+}
+
+/// A graphic rendition, set by `CSI ... m`.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Graphic {
+    Reset,
+    Bold,
+    Faint,
+    Italic,
+    Underline,
+    Blink,
+    Inverse,
+    Hidden,
+    CrossedOut,
+    NormalIntensity,
+    NotItalic,
+    NotUnderlined,
+    NotBlinking,
+    NotInverse,
+    NotHidden,
+    NotCrossedOut,
+    Foreground(Color),
+    Background(Color),
+    DefaultForeground,
+    DefaultBackground,
+    /// Any other rendition, by its number.
+    Other(u32),
+}
+
+/// A color, as set by a graphic rendition.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Color {
+    /// A color of the 256 color palette, where 0 to 7 are the standard colors,
+    /// and 8 to 15 their bright versions.
+    Indexed(u8),
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+// How much of the sequence has been read.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Escape,
+    Csi,
+    Osc,
+    // After an `ESC` in an OSC, which may be the start of `ESC \`.
+    OscEscape,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct EscapeSequence;
+
+#[derive(Clone, Debug)]
+pub struct EscapeSequenceState {
+    phase: Phase,
+    parameters: String,
+    intermediates: String,
+}
+
+fn is_parameter(ch: char) -> bool {
+    ('\x30'..='\x3f').contains(&ch)
+}
+
+fn is_intermediate(ch: char) -> bool {
+    ('\x20'..='\x2f').contains(&ch)
+}
+
+fn is_introducer(ch: char) -> bool {
+    ch == '\x1b' || ch == '\u{9b}' || ch == '\u{9d}'
+}
+
+// The numeric parameters of a control sequence, where empty or malformed parameters are `None`.
+
+fn numbers(parameters: &str) -> Vec<Option<u32>> {
+    parameters.split([';', ':']).map(|number| number.parse().ok()).collect()
+}
+
+// The parameter at `index`, or `default` if it is missing or zero.
+
+fn parameter(numbers: &[Option<u32>], index: usize, default: u32) -> u32 {
+    match numbers.get(index) {
+        Some(&Some(number)) if number != 0 => number,
+        _ => default,
+    }
+}
+
+// An extended color, `5 ; index` or `2 ; red ; green ; blue`, returning how many parameters it used.
+
+fn color(numbers: &[Option<u32>]) -> Option<(Color, usize)> {
+    let byte = |index: usize| match numbers.get(index) {
+        Some(&Some(number)) if number < 256 => Some(number as u8),
+        _ => None,
+    };
+    match numbers.first() {
+        Some(&Some(5)) => byte(1).map(|index| (Color::Indexed(index), 2)),
+        Some(&Some(2)) => match (byte(1), byte(2), byte(3)) {
+            (Some(red), Some(green), Some(blue)) => Some((Color::Rgb(red, green, blue), 4)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn graphics(numbers: &[Option<u32>]) -> Vec<Graphic> {
+    let mut result = Vec::new();
+    let mut index = 0;
+    while index < numbers.len() {
+        let number = numbers[index].unwrap_or(0);
+        index += 1;
+        result.push(match number {
+            0 => Graphic::Reset,
+            1 => Graphic::Bold,
+            2 => Graphic::Faint,
+            3 => Graphic::Italic,
+            4 => Graphic::Underline,
+            5 | 6 => Graphic::Blink,
+            7 => Graphic::Inverse,
+            8 => Graphic::Hidden,
+            9 => Graphic::CrossedOut,
+            22 => Graphic::NormalIntensity,
+            23 => Graphic::NotItalic,
+            24 => Graphic::NotUnderlined,
+            25 => Graphic::NotBlinking,
+            27 => Graphic::NotInverse,
+            28 => Graphic::NotHidden,
+            29 => Graphic::NotCrossedOut,
+            30..=37 => Graphic::Foreground(Color::Indexed((number - 30) as u8)),
+            39 => Graphic::DefaultForeground,
+            40..=47 => Graphic::Background(Color::Indexed((number - 40) as u8)),
+            49 => Graphic::DefaultBackground,
+            90..=97 => Graphic::Foreground(Color::Indexed((number - 82) as u8)),
+            100..=107 => Graphic::Background(Color::Indexed((number - 92) as u8)),
+            38 | 48 => match color(&numbers[index..]) {
+                Some((color, used)) => {
+                    index += used;
+                    if number == 38 { Graphic::Foreground(color) } else { Graphic::Background(color) }
+                },
+                None => {
+                    index = numbers.len();
+                    Graphic::Other(number)
+                },
+            },
+            _ => Graphic::Other(number),
+        });
+    }
+    result
+}
+
+impl EscapeSequenceState {
+    fn new(phase: Phase) -> Self {
+        EscapeSequenceState {
+            phase,
+            parameters: String::new(),
+            intermediates: String::new(),
+        }
+    }
+
+    fn csi(self, final_byte: char) -> Command {
+        if !self.intermediates.is_empty() || self.parameters.starts_with(|ch| ('\x3c'..='\x3f').contains(&ch)) {
+            return Command::Csi { parameters: self.parameters, intermediates: self.intermediates, final_byte };
+        }
+        let numbers = numbers(&self.parameters);
+        match final_byte {
+            'A' => Command::CursorUp(parameter(&numbers, 0, 1)),
+            'B' => Command::CursorDown(parameter(&numbers, 0, 1)),
+            'C' => Command::CursorForward(parameter(&numbers, 0, 1)),
+            'D' => Command::CursorBack(parameter(&numbers, 0, 1)),
+            'H' | 'f' => Command::CursorPosition { row: parameter(&numbers, 0, 1), column: parameter(&numbers, 1, 1) },
+            'J' => Command::EraseDisplay(parameter(&numbers, 0, 0)),
+            'K' => Command::EraseLine(parameter(&numbers, 0, 0)),
+            'm' => Command::Graphics(graphics(&numbers)),
+            _ => Command::Csi { parameters: self.parameters, intermediates: self.intermediates, final_byte },
+        }
+    }
+
+    fn osc(self) -> Command {
+        let text = self.parameters;
+        if text.starts_with("0;") || text.starts_with("2;") {
+            Command::SetTitle(String::from(&text[2..]))
+        } else {
+            Command::Osc(text)
+        }
+    }
+
+    // Handle the next characters, returning the command if the sequence has ended.
+    fn step<Str>(&mut self, string: &mut Str) -> Option<Option<Command>>
+        where Str: PeekableIterator<Item = char>,
+    {
+        if self.phase == Phase::OscEscape {
+            if string.is_empty() {
+                return None;
+            }
+            self.phase = Phase::Osc;
+            string.next_if(|ch| ch == '\\');
+            return Some(Some(self.take().osc()));
+        }
+        let ch = string.next()?;
+        Some(match (self.phase, ch) {
+            (Phase::Escape, '[') => {
+                self.phase = Phase::Csi;
+                None
+            },
+            (Phase::Escape, ']') => {
+                self.phase = Phase::Osc;
+                None
+            },
+            (Phase::Escape, ch) if is_intermediate(ch) => {
+                self.intermediates.push(ch);
+                None
+            },
+            (Phase::Escape, ch) => Some(Command::Escape { intermediates: self.take().intermediates, final_byte: ch }),
+            (Phase::Csi, ch) if is_parameter(ch) && self.intermediates.is_empty() => {
+                self.parameters.push(ch);
+                None
+            },
+            (Phase::Csi, ch) if is_intermediate(ch) => {
+                self.intermediates.push(ch);
+                None
+            },
+            (Phase::Csi, ch) => Some(self.take().csi(ch)),
+            (Phase::Osc, '\x07') | (Phase::Osc, '\u{9c}') => Some(self.take().osc()),
+            (Phase::Osc, '\x1b') => {
+                self.phase = Phase::OscEscape;
+                None
+            },
+            (_, ch) => {
+                self.parameters.push(ch);
+                None
+            },
+        })
+    }
+
+    fn take(&mut self) -> EscapeSequenceState {
+        let phase = self.phase;
+        mem::replace(self, EscapeSequenceState::new(phase))
+    }
+
+    // The sequence as written after the `ESC`.
+    fn unfinished(self) -> Command {
+        let mut text = String::new();
+        match self.phase {
+            Phase::Escape => (),
+            Phase::Csi => text.push('['),
+            Phase::Osc | Phase::OscEscape => text.push(']'),
+        }
+        text.push_str(&self.parameters);
+        text.push_str(&self.intermediates);
+        if self.phase == Phase::OscEscape {
+            text.push('\x1b');
+        }
+        Command::Unfinished(text)
+    }
+}
+
+impl Parser for EscapeSequence {}
+
+impl<Str> Stateful<char, Str, Command> for EscapeSequenceState
+    where Str: PeekableIterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Command> {
+        loop {
+            match self.step(string) {
+                Some(Some(command)) => return Done(command),
+                Some(None) => (),
+                None => return Continue(self),
+            }
+        }
+    }
+
+    fn done(self) -> Command {
+        self.unfinished()
+    }
+
+}
+
+impl<Str> HasOutput<char, Str> for EscapeSequenceState {
+
+    type Output = Command;
+
+}
+
+impl<Str> HasOutput<char, Str> for EscapeSequence {
+
+    type Output = Command;
+
+}
+
+impl<Str> Uncommitted<char, Str, Command> for EscapeSequence
+    where Str: PeekableIterator<Item = char>,
+{
+
+    type State = EscapeSequenceState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Command>> {
+        let phase = match string.next_if(is_introducer) {
+            Some('\u{9b}') => Phase::Csi,
+            Some('\u{9d}') => Phase::Osc,
+            Some(_) => Phase::Escape,
+            None => return None,
+        };
+        Some(EscapeSequenceState::new(phase).more(string))
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_escape_sequence() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    let parser = escape_sequence();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("text"), None);
+    assert_eq!(parser.init_str("\x1b[Arest").unwrap().unDone(), Command::CursorUp(1));
+    assert_eq!(parser.init_str("\x1b[12;40H").unwrap().unDone(), Command::CursorPosition { row: 12, column: 40 });
+    assert_eq!(parser.init_str("\u{9b};5f").unwrap().unDone(), Command::CursorPosition { row: 1, column: 5 });
+    assert_eq!(parser.init_str("\x1b[2J").unwrap().unDone(), Command::EraseDisplay(2));
+    assert_eq!(parser.init_str("\x1b[K").unwrap().unDone(), Command::EraseLine(0));
+    assert_eq!(parser.init_str("\x1b[m").unwrap().unDone(), Command::Graphics(vec![Graphic::Reset]));
+    assert_eq!(parser.init_str("\x1b[4;38;5;208;48;2;0;128;255;97;7m").unwrap().unDone(), Command::Graphics(vec![
+        Graphic::Underline,
+        Graphic::Foreground(Color::Indexed(208)),
+        Graphic::Background(Color::Rgb(0, 128, 255)),
+        Graphic::Foreground(Color::Indexed(15)),
+        Graphic::Inverse,
+    ]));
+    assert_eq!(parser.init_str("\x1b[38;5m").unwrap().unDone(), Command::Graphics(vec![Graphic::Other(38)]));
+    assert_eq!(parser.init_str("\x1b[?25l").unwrap().unDone(), Command::Csi {
+        parameters: String::from("?25"),
+        intermediates: String::new(),
+        final_byte: 'l',
+    });
+    assert_eq!(parser.init_str("\x1b[2 q").unwrap().unDone(), Command::Csi {
+        parameters: String::from("2"),
+        intermediates: String::from(" "),
+        final_byte: 'q',
+    });
+    assert_eq!(parser.init_str("\x1b]0;vim\x07").unwrap().unDone(), Command::SetTitle(String::from("vim")));
+    assert_eq!(parser.init_str("\x1b]8;;http://example.com\x1b\\x").unwrap().unDone(), Command::Osc(String::from("8;;http://example.com")));
+    assert_eq!(parser.init_str("\u{9d}2;a\x1bb").unwrap().unDone(), Command::SetTitle(String::from("a")));
+    assert_eq!(parser.init_str("\x1b7").unwrap().unDone(), Command::Escape { intermediates: String::new(), final_byte: '7' });
+    assert_eq!(parser.init_str("\x1b(B").unwrap().unDone(), Command::Escape { intermediates: String::from("("), final_byte: 'B' });
+    let parsing = parser.init_str("\x1b").unwrap().unContinue();
+    let parsing = parsing.more_str("[3").unContinue();
+    let parsing = parsing.more_str("1").unContinue();
+    assert_eq!(parsing.last_str("m"), Command::Graphics(vec![Graphic::Foreground(Color::Indexed(1))]));
+    let parsing = parser.init_str("\x1b]2;title\x1b").unwrap().unContinue();
+    assert_eq!(parsing.more_str("\\").unDone(), Command::SetTitle(String::from("title")));
+    let parsing = parser.init_str("\x1b]2;title\x1b").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), Command::Unfinished(String::from("]2;title\x1b")));
+    assert_eq!(parser.init_str("\x1b[1;").unwrap().unContinue().last_str(""), Command::Unfinished(String::from("[1;")));
+    testing::assert_chunk_invariant(parser.star(Vec::new), "\x1b[1;31m\x1b]0;t\x1b\\\x1b[?1049h\x1b[10;20H\x1bc\x1b[");
+}
@@ -0,0 +1,266 @@
+//! ASN.1 tag-length-value framing.
+//!
+//! Data encoded with the ASN.1 Basic Encoding Rules (BER), or its restriction to
+//! the Distinguished Encoding Rules (DER) used by X.509 certificates, is a sequence of
+//! tag-length-value items. A `TlvReader` splits a stream of bytes into items,
+//! borrowing the contents from the input wherever possible, for example:
+//!
+//! ```
+//! # use parsell::formats::ber::{TlvReader, Class};
+//! let mut reader = TlvReader::new();
+//! let items = reader.push(&[0x30, 0x06, 0x02, 0x01, 0x05, 0x04, 0x01]);
+//! assert!(items.is_empty());
+//! let items = reader.push(&[0x61, 0x05, 0x00]);
+//! let (tag, contents) = items[0].clone().unwrap();
+//! assert_eq!((tag.class, tag.constructed, tag.number), (Class::Universal, true, 16));
+//! let mut inner = TlvReader::new();
+//! let items = inner.push(&*contents);
+//! assert_eq!(items[0].clone().unwrap().1, &[0x05][..]);
+//! assert_eq!(items[1].clone().unwrap().1, &[0x61][..]);
+//! ```
+//!
+//! The contents of a constructed item, such as a `SEQUENCE`, can be split by another reader.
+//! Only definite lengths are supported, and the reader does not check the extra restrictions
+//! of DER, such as minimal length encodings.
+
+use super::slice;
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::error;
+use std::fmt;
+use std::mem;
+
+/// The class of a tag.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Class {
+    /// A universal tag, such as `INTEGER` or `SEQUENCE`.
+    Universal,
+    /// An application-specific tag.
+    Application,
+    /// A context-specific tag.
+    Context,
+    /// A private tag.
+    Private,
+}
+
+/// The tag of an item.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Tag {
+    /// The class of the tag.
+    pub class: Class,
+    /// Whether the contents are themselves a sequence of items.
+    pub constructed: bool,
+    /// The tag number.
+    pub number: u64,
+}
+
+/// An error in tag-length-value input.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The input was malformed.
+    Malformed,
+    /// The input used an indefinite length.
+    Indefinite,
+    /// The input ended in the middle of an item.
+    Truncated,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Malformed => write!(fmt, "Malformed tag-length-value item"),
+            Error::Indefinite => write!(fmt, "Indefinite length item"),
+            Error::Truncated => write!(fmt, "Truncated tag-length-value item"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// An item, as its tag and contents.
+
+pub type Item<'a> = Result<(Tag, Cow<'a, [u8]>), Error>;
+
+// Parse the header of an item, returning the tag, content length and header length,
+// or `None` if the header is incomplete.
+
+fn header(bytes: &[u8]) -> Option<Result<(Tag, usize, usize), Error>> {
+    let initial = match bytes.first() {
+        Some(&initial) => initial,
+        None => return None,
+    };
+    let class = match initial >> 6 {
+        0 => Class::Universal,
+        1 => Class::Application,
+        2 => Class::Context,
+        _ => Class::Private,
+    };
+    let mut position = 1;
+    let mut number = (initial & 0x1f) as u64;
+    if number == 0x1f {
+        // A high tag number, in base 128.
+        number = 0;
+        loop {
+            let byte = match bytes.get(position) {
+                Some(&byte) => byte,
+                None => return None,
+            };
+            position += 1;
+            if number >> 57 != 0 {
+                return Some(Err(Error::Malformed));
+            }
+            number = (number << 7) | (byte & 0x7f) as u64;
+            if byte & 0x80 == 0 {
+                break;
+            }
+        }
+    }
+    let tag = Tag { class, constructed: initial & 0x20 != 0, number };
+    let length = match bytes.get(position) {
+        Some(&length) => length,
+        None => return None,
+    };
+    position += 1;
+    let length = match length {
+        0x80 => return Some(Err(Error::Indefinite)),
+        0xff => return Some(Err(Error::Malformed)),
+        length if length < 0x80 => length as u64,
+        length => {
+            let count = (length & 0x7f) as usize;
+            if count > 8 {
+                return Some(Err(Error::Malformed));
+            }
+            let digits = bytes.get(position..position + count)?;
+            position += count;
+            digits.iter().fold(0, |length, &digit| (length << 8) | digit as u64)
+        },
+    };
+    if length > usize::MAX as u64 {
+        return Some(Err(Error::Malformed));
+    }
+    Some(Ok((tag, length as usize, position)))
+}
+
+/// A reader for tag-length-value items.
+
+#[derive(Clone, Debug, Default)]
+pub struct TlvReader {
+    // An incomplete header.
+    pending: Vec<u8>,
+    // An item whose contents are incomplete, with the number of bytes remaining.
+    partial: Option<(Tag, usize, Vec<u8>)>,
+    failed: bool,
+}
+
+impl TlvReader {
+    /// A reader at the start of a sequence of items.
+    pub fn new() -> Self {
+        TlvReader::default()
+    }
+
+    /// Feed a chunk of input, returning the items it completes.
+    ///
+    /// After an error, any remaining input is ignored.
+    pub fn push<'a>(&mut self, chunk: &'a [u8]) -> Vec<Item<'a>> {
+        let input: Cow<'a, [u8]> = if self.pending.is_empty() {
+            Borrowed(chunk)
+        } else {
+            let mut input = mem::take(&mut self.pending);
+            input.extend_from_slice(chunk);
+            Owned(input)
+        };
+        let mut items = Vec::new();
+        let mut position = 0;
+        while !self.failed {
+            if let Some((tag, remaining, mut contents)) = self.partial.take() {
+                let available = input.len() - position;
+                if available < remaining {
+                    contents.extend_from_slice(&input[position..]);
+                    self.partial = Some((tag, remaining - available, contents));
+                    return items;
+                }
+                contents.extend_from_slice(&input[position..position + remaining]);
+                position += remaining;
+                items.push(Ok((tag, Owned(contents))));
+            }
+            match header(&input[position..]) {
+                None => break,
+                Some(Err(err)) => {
+                    items.push(Err(err));
+                    self.failed = true;
+                },
+                Some(Ok((tag, length, header))) => {
+                    position += header;
+                    if input.len() - position >= length {
+                        items.push(Ok((tag, slice(&input, position, position + length))));
+                        position += length;
+                    } else {
+                        self.partial = Some((tag, length, Vec::new()));
+                    }
+                },
+            }
+        }
+        if !self.failed {
+            self.pending.extend_from_slice(&input[position..]);
+        }
+        items
+    }
+
+    /// Finish reading, returning an error if the input ended in the middle of an item.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.pending.is_empty() && self.partial.is_none() {
+            Ok(())
+        } else {
+            Err(Error::Truncated)
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_tlv() {
+    let mut reader = TlvReader::new();
+    let items = reader.push(&[0x02, 0x01, 0x2a, 0x9f, 0x87, 0x68, 0x00, 0xe1, 0x81, 0x02, 0xff, 0xee]);
+    assert_eq!(items, vec![
+        Ok((Tag { class: Class::Universal, constructed: false, number: 2 }, Borrowed(&[0x2a][..]))),
+        Ok((Tag { class: Class::Context, constructed: false, number: 1000 }, Borrowed(&[][..]))),
+        Ok((Tag { class: Class::Private, constructed: true, number: 1 }, Borrowed(&[0xff, 0xee][..]))),
+    ]);
+    assert_eq!(reader.finish(), Ok(()));
+    let mut reader = TlvReader::new();
+    assert_eq!(reader.push(&[0x30, 0x80, 0x00, 0x00]), vec![Err(Error::Indefinite)]);
+    assert!(reader.push(&[0x02, 0x01, 0x00]).is_empty());
+    let mut reader = TlvReader::new();
+    assert_eq!(reader.push(&[0x04, 0x89]), vec![Err(Error::Malformed)]);
+    let mut reader = TlvReader::new();
+    assert!(reader.push(&[0x04, 0x82, 0x01]).is_empty());
+    assert_eq!(reader.finish(), Err(Error::Truncated));
+}
+
+#[test]
+fn test_tlv_chunked() {
+    const BYTES: &[u8] = &[0x30, 0x03, 0x01, 0x01, 0xff, 0x5f, 0x81, 0x00, 0x81, 0x02, 0xab, 0xcd, 0x05, 0x00];
+    let mut reader = TlvReader::new();
+    let expected: Vec<(Tag, Vec<u8>)> = reader.push(BYTES).into_iter().map(|item| {
+        let (tag, contents) = item.unwrap();
+        (tag, contents.into_owned())
+    }).collect();
+    assert_eq!(expected.len(), 3);
+    assert_eq!(expected[1].0, Tag { class: Class::Application, constructed: false, number: 128 });
+    for split in 0..BYTES.len() {
+        let mut reader = TlvReader::new();
+        let mut items = reader.push(&BYTES[..split]);
+        items.extend(reader.push(&BYTES[split..]));
+        let items: Vec<(Tag, Vec<u8>)> = items.into_iter().map(|item| {
+            let (tag, contents) = item.unwrap();
+            (tag, contents.into_owned())
+        }).collect();
+        assert_eq!(items, expected, "Split at {}", split);
+        assert_eq!(reader.finish(), Ok(()));
+    }
+}
@@ -0,0 +1,524 @@
+//! CBOR.
+//!
+//! This module is only available with the `cbor` feature.
+//! CBOR (RFC 8949) is a binary data format. The parser `cbor_event()` reads one data item
+//! header from byte input, together with the contents of a definite-length string,
+//! and the parser `cbor_value()` reads one complete data item into a `Value`, for example:
+//!
+//! ```
+//! # use parsell::{Uncommitted,Stateful};
+//! # use parsell::ParseResult::Continue;
+//! # use parsell::formats::cbor::{cbor_value, Value};
+//! let parser = cbor_value();
+//! let state = match parser.init(&mut [0x82, 0x01, 0x63, 0x61].iter().cloned().peekable()).unwrap() {
+//!     Continue(state) => state,
+//!     _ => panic!("Can't happen"),
+//! };
+//! let value = state.last(&mut [0x62, 0x63].iter().cloned().peekable()).unwrap();
+//! assert_eq!(value, Value::Array(vec![Value::Integer(1), Value::Text(String::from("abc"))]));
+//! ```
+//!
+//! Nested items are parsed using an explicit stack, rather than recursion, so deeply nested
+//! input does not overflow the call stack. The parsers commit on the first byte,
+//! and the result is an error if the item is malformed or the input ends in the middle of it.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+
+use std::error;
+use std::fmt;
+use std::mem;
+
+/// An event in a CBOR data stream.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// An unsigned integer.
+    Unsigned(u64),
+    /// A negative integer, where `Negative(n)` is `-1 - n`.
+    Negative(u64),
+    /// A definite-length byte string, or a chunk of an indefinite-length one.
+    Bytes(Vec<u8>),
+    /// A definite-length text string, or a chunk of an indefinite-length one.
+    Text(String),
+    /// The start of an indefinite-length byte string.
+    IndefiniteBytes,
+    /// The start of an indefinite-length text string.
+    IndefiniteText,
+    /// The start of an array, with its length if it is definite.
+    Array(Option<u64>),
+    /// The start of a map, with its number of entries if it is definite.
+    Map(Option<u64>),
+    /// A tag, which applies to the next data item.
+    Tag(u64),
+    /// A boolean.
+    Bool(bool),
+    /// Null.
+    Null,
+    /// Undefined.
+    Undefined,
+    /// Any other simple value.
+    Simple(u8),
+    /// A floating point number, of any precision.
+    Float(f64),
+    /// The end of an indefinite-length item.
+    Break,
+}
+
+/// A CBOR data item.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// An integer.
+    Integer(i128),
+    /// A byte string.
+    Bytes(Vec<u8>),
+    /// A text string.
+    Text(String),
+    /// An array.
+    Array(Vec<Value>),
+    /// A map, with its entries in the order they were given.
+    Map(Vec<(Value, Value)>),
+    /// A tagged item.
+    Tagged(u64, Box<Value>),
+    /// A boolean.
+    Bool(bool),
+    /// Null.
+    Null,
+    /// Undefined.
+    Undefined,
+    /// Any other simple value.
+    Simple(u8),
+    /// A floating point number.
+    Float(f64),
+}
+
+/// An error in CBOR input.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The input was not well-formed CBOR.
+    Malformed,
+    /// The input ended in the middle of a data item.
+    Truncated,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Malformed => write!(fmt, "Malformed CBOR"),
+            Error::Truncated => write!(fmt, "Truncated CBOR"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A parser for one CBOR event.
+
+pub fn cbor_event() -> CborEvent {
+    CborEvent
+}
+
+/// A parser for one CBOR data item.
+
+pub fn cbor_value() -> CborValue {
+    CborValue
+}
+
+fn half_to_f64(half: u16) -> f64 {
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f64;
+    let magnitude = match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        31 if mantissa == 0.0 => f64::INFINITY,
+        31 => f64::NAN,
+        _ => (mantissa + 1024.0) * 2f64.powi(exponent - 25),
+    };
+    if half & 0x8000 == 0 { magnitude } else { -magnitude }
+}
+
+// How much of an event has been read.
+
+#[derive(Clone, Debug)]
+enum Phase {
+    // The major type, additional information, bytes of argument remaining, and argument so far.
+    Argument(u8, u8, u8, u64),
+    // Whether the string is text, the bytes remaining, and the bytes so far.
+    Payload(bool, u64, Vec<u8>),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CborEvent;
+
+#[derive(Clone, Debug)]
+pub struct CborEventState(Phase);
+
+impl CborEventState {
+    fn new(initial: u8) -> Self {
+        let info = initial & 0x1f;
+        let length = match info {
+            24 => 1,
+            25 => 2,
+            26 => 4,
+            27 => 8,
+            _ => 0,
+        };
+        CborEventState(Phase::Argument(initial >> 5, info, length, 0))
+    }
+
+    // Run the state machine, returning an event if one has been read.
+    fn run<Str>(&mut self, string: &mut Str) -> Option<Result<Event, Error>>
+        where Str: Iterator<Item = u8>,
+    {
+        loop {
+            match self.0 {
+                Phase::Argument(_, _, 0, _) => (),
+                Phase::Argument(_, _, ref mut remaining, ref mut argument) => {
+                    let byte = string.next()?;
+                    *remaining -= 1;
+                    *argument = (*argument << 8) | byte as u64;
+                    continue;
+                },
+                Phase::Payload(_, 0, _) => (),
+                Phase::Payload(_, ref mut remaining, ref mut bytes) => {
+                    let byte = string.next()?;
+                    *remaining -= 1;
+                    bytes.push(byte);
+                    continue;
+                },
+            }
+            let (major, info, argument) = match self.0 {
+                Phase::Argument(major, info, _, argument) => (major, info, argument),
+                Phase::Payload(true, _, ref mut bytes) => {
+                    return Some(String::from_utf8(mem::take(bytes)).map(Event::Text).or(Err(Error::Malformed)));
+                },
+                Phase::Payload(false, _, ref mut bytes) => return Some(Ok(Event::Bytes(mem::take(bytes)))),
+            };
+            let argument = if info < 24 { info as u64 } else { argument };
+            return Some(match (major, info) {
+                (_, 28..=30) => Err(Error::Malformed),
+                (2, 31) => Ok(Event::IndefiniteBytes),
+                (3, 31) => Ok(Event::IndefiniteText),
+                (4, 31) => Ok(Event::Array(None)),
+                (5, 31) => Ok(Event::Map(None)),
+                (7, 31) => Ok(Event::Break),
+                (_, 31) => Err(Error::Malformed),
+                (0, _) => Ok(Event::Unsigned(argument)),
+                (1, _) => Ok(Event::Negative(argument)),
+                (2, _) | (3, _) => {
+                    self.0 = Phase::Payload(major == 3, argument, Vec::new());
+                    continue;
+                },
+                (4, _) => Ok(Event::Array(Some(argument))),
+                (5, _) => Ok(Event::Map(Some(argument))),
+                (6, _) => Ok(Event::Tag(argument)),
+                (_, 20) => Ok(Event::Bool(false)),
+                (_, 21) => Ok(Event::Bool(true)),
+                (_, 22) => Ok(Event::Null),
+                (_, 23) => Ok(Event::Undefined),
+                (_, 24) if argument < 32 => Err(Error::Malformed),
+                (_, 25) => Ok(Event::Float(half_to_f64(argument as u16))),
+                (_, 26) => Ok(Event::Float(f32::from_bits(argument as u32) as f64)),
+                (_, 27) => Ok(Event::Float(f64::from_bits(argument))),
+                (_, _) => Ok(Event::Simple(argument as u8)),
+            });
+        }
+    }
+}
+
+impl Parser for CborEvent {}
+
+impl<Str> Stateful<u8, Str, Result<Event, Error>> for CborEventState
+    where Str: PeekableIterator<Item = u8>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Result<Event, Error>> {
+        match self.run(string) {
+            Some(result) => Done(result),
+            None => Continue(self),
+        }
+    }
+
+    fn done(self) -> Result<Event, Error> {
+        Err(Error::Truncated)
+    }
+
+}
+
+impl<Str> HasOutput<u8, Str> for CborEventState {
+
+    type Output = Result<Event, Error>;
+
+}
+
+impl<Str> HasOutput<u8, Str> for CborEvent {
+
+    type Output = Result<Event, Error>;
+
+}
+
+impl<Str> Uncommitted<u8, Str, Result<Event, Error>> for CborEvent
+    where Str: PeekableIterator<Item = u8>,
+{
+
+    type State = CborEventState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<Event, Error>>> {
+        string.next().map(|initial| CborEventState::new(initial).more(string))
+    }
+
+}
+
+// A partially read container.
+
+#[derive(Clone, Debug)]
+enum Frame {
+    // The number of items remaining, if definite, and the items so far.
+    Array(Option<u64>, Vec<Value>),
+    // The number of entries remaining, if definite, the entries so far, and any key without a value.
+    Map(Option<u64>, Vec<(Value, Value)>, Option<Value>),
+    Tagged(u64),
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CborValue;
+
+#[derive(Clone, Debug)]
+pub struct CborValueState {
+    event: Option<CborEventState>,
+    stack: Vec<Frame>,
+}
+
+impl CborValueState {
+    // Handle an event, returning a value if one has been completed.
+    fn event(&mut self, event: Event) -> Result<Option<Value>, Error> {
+        let mut value = match (event, self.stack.last_mut()) {
+            (Event::Break, Some(&mut Frame::Array(None, _))) |
+            (Event::Break, Some(&mut Frame::Map(None, _, None))) |
+            (Event::Break, Some(&mut Frame::Bytes(_))) |
+            (Event::Break, Some(&mut Frame::Text(_))) => match self.stack.pop() {
+                Some(Frame::Array(_, items)) => Value::Array(items),
+                Some(Frame::Map(_, entries, _)) => Value::Map(entries),
+                Some(Frame::Bytes(bytes)) => Value::Bytes(bytes),
+                Some(Frame::Text(text)) => Value::Text(text),
+                _ => unreachable!(),
+            },
+            (Event::Break, _) => return Err(Error::Malformed),
+            (Event::Bytes(chunk), Some(&mut Frame::Bytes(ref mut bytes))) => {
+                bytes.extend(chunk);
+                return Ok(None);
+            },
+            (Event::Text(chunk), Some(&mut Frame::Text(ref mut text))) => {
+                text.push_str(&chunk);
+                return Ok(None);
+            },
+            (_, Some(&mut Frame::Bytes(_))) | (_, Some(&mut Frame::Text(_))) => return Err(Error::Malformed),
+            (Event::Unsigned(value), _) => Value::Integer(value as i128),
+            (Event::Negative(value), _) => Value::Integer(-1 - value as i128),
+            (Event::Bytes(bytes), _) => Value::Bytes(bytes),
+            (Event::Text(text), _) => Value::Text(text),
+            (Event::IndefiniteBytes, _) => {
+                self.stack.push(Frame::Bytes(Vec::new()));
+                return Ok(None);
+            },
+            (Event::IndefiniteText, _) => {
+                self.stack.push(Frame::Text(String::new()));
+                return Ok(None);
+            },
+            (Event::Array(Some(0)), _) => Value::Array(Vec::new()),
+            (Event::Array(length), _) => {
+                self.stack.push(Frame::Array(length, Vec::new()));
+                return Ok(None);
+            },
+            (Event::Map(Some(0)), _) => Value::Map(Vec::new()),
+            (Event::Map(length), _) => {
+                self.stack.push(Frame::Map(length, Vec::new(), None));
+                return Ok(None);
+            },
+            (Event::Tag(tag), _) => {
+                self.stack.push(Frame::Tagged(tag));
+                return Ok(None);
+            },
+            (Event::Bool(value), _) => Value::Bool(value),
+            (Event::Null, _) => Value::Null,
+            (Event::Undefined, _) => Value::Undefined,
+            (Event::Simple(value), _) => Value::Simple(value),
+            (Event::Float(value), _) => Value::Float(value),
+        };
+        // Add the value to the enclosing containers, closing any which are complete.
+        loop {
+            let complete = match self.stack.last_mut() {
+                None => return Ok(Some(value)),
+                Some(&mut Frame::Array(ref mut remaining, ref mut items)) => {
+                    items.push(value);
+                    if let Some(ref mut remaining) = *remaining {
+                        *remaining -= 1;
+                    }
+                    *remaining == Some(0)
+                },
+                Some(&mut Frame::Map(ref mut remaining, ref mut entries, ref mut key)) => {
+                    match key.take() {
+                        None => *key = Some(value),
+                        Some(key) => {
+                            entries.push((key, value));
+                            if let Some(ref mut remaining) = *remaining {
+                                *remaining -= 1;
+                            }
+                        },
+                    }
+                    *remaining == Some(0)
+                },
+                Some(&mut Frame::Tagged(tag)) => {
+                    value = Value::Tagged(tag, Box::new(value));
+                    self.stack.pop();
+                    continue;
+                },
+                Some(_) => return Err(Error::Malformed),
+            };
+            if !complete {
+                return Ok(None);
+            }
+            value = match self.stack.pop() {
+                Some(Frame::Array(_, items)) => Value::Array(items),
+                Some(Frame::Map(_, entries, _)) => Value::Map(entries),
+                _ => unreachable!(),
+            };
+        }
+    }
+}
+
+impl Parser for CborValue {}
+
+impl<Str> Stateful<u8, Str, Result<Value, Error>> for CborValueState
+    where Str: PeekableIterator<Item = u8>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Result<Value, Error>> {
+        loop {
+            let mut event = match self.event.take() {
+                Some(event) => event,
+                None => match string.next() {
+                    Some(initial) => CborEventState::new(initial),
+                    None => return Continue(self),
+                },
+            };
+            match event.run(string) {
+                None => {
+                    self.event = Some(event);
+                    return Continue(self);
+                },
+                Some(Err(err)) => return Done(Err(err)),
+                Some(Ok(event)) => match self.event(event) {
+                    Ok(None) => (),
+                    Ok(Some(value)) => return Done(Ok(value)),
+                    Err(err) => return Done(Err(err)),
+                },
+            }
+        }
+    }
+
+    fn done(self) -> Result<Value, Error> {
+        Err(Error::Truncated)
+    }
+
+}
+
+impl<Str> HasOutput<u8, Str> for CborValueState {
+
+    type Output = Result<Value, Error>;
+
+}
+
+impl<Str> HasOutput<u8, Str> for CborValue {
+
+    type Output = Result<Value, Error>;
+
+}
+
+impl<Str> Uncommitted<u8, Str, Result<Value, Error>> for CborValue
+    where Str: PeekableIterator<Item = u8>,
+{
+
+    type State = CborValueState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<Value, Error>>> {
+        string.next().map(|initial| CborValueState {
+            event: Some(CborEventState::new(initial)),
+            stack: Vec::new(),
+        }.more(string))
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[cfg(test)]
+fn parse_value(bytes: &[u8]) -> Result<Value, Error> {
+    let empty: &[u8] = &[];
+    match cbor_value().init(&mut bytes.iter().cloned().peekable()) {
+        Some(Done(result)) => result,
+        Some(Continue(state)) => state.last(&mut empty.iter().cloned().peekable()),
+        None => Err(Error::Truncated),
+    }
+}
+
+#[test]
+fn test_cbor_event() {
+    let mut data = [0x19, 0x03, 0xe8, 0x38, 0x63, 0x42, 0x01, 0x02, 0xf5, 0x9f].iter().cloned().peekable();
+    let mut events = Vec::new();
+    while let Some(Done(event)) = cbor_event().init(&mut data) {
+        events.push(event.unwrap());
+    }
+    assert_eq!(events, vec![
+        Event::Unsigned(1000),
+        Event::Negative(99),
+        Event::Bytes(vec![1, 2]),
+        Event::Bool(true),
+        Event::Array(None),
+    ]);
+    let mut data = [0x1c].iter().cloned().peekable();
+    assert_eq!(cbor_event().init(&mut data).unwrap().unDone(), Err(Error::Malformed));
+}
+
+#[test]
+fn test_cbor_value() {
+    assert_eq!(parse_value(&[0x00]), Ok(Value::Integer(0)));
+    assert_eq!(parse_value(&[0x3b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]), Ok(Value::Integer(-18446744073709551616)));
+    assert_eq!(parse_value(&[0xf9, 0x3c, 0x00]), Ok(Value::Float(1.0)));
+    assert_eq!(parse_value(&[0xf9, 0xc4, 0x00]), Ok(Value::Float(-4.0)));
+    assert_eq!(parse_value(&[0xfa, 0x47, 0xc3, 0x50, 0x00]), Ok(Value::Float(100000.0)));
+    assert_eq!(parse_value(&[0xf6]), Ok(Value::Null));
+    assert_eq!(parse_value(&[0x80]), Ok(Value::Array(vec![])));
+    assert_eq!(parse_value(&[0xa2, 0x01, 0x02, 0x03, 0x81, 0x04]), Ok(Value::Map(vec![
+        (Value::Integer(1), Value::Integer(2)),
+        (Value::Integer(3), Value::Array(vec![Value::Integer(4)])),
+    ])));
+    assert_eq!(parse_value(&[0x9f, 0x01, 0x82, 0x02, 0x03, 0x9f, 0xff, 0xff]), Ok(Value::Array(vec![
+        Value::Integer(1),
+        Value::Array(vec![Value::Integer(2), Value::Integer(3)]),
+        Value::Array(vec![]),
+    ])));
+    assert_eq!(parse_value(&[0x7f, 0x62, 0x61, 0x62, 0x61, 0x63, 0xff]), Ok(Value::Text(String::from("abc"))));
+    assert_eq!(parse_value(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]), Ok(Value::Tagged(1, Box::new(Value::Integer(1363896240)))));
+    assert_eq!(parse_value(&[0x82, 0x01]), Err(Error::Truncated));
+    assert_eq!(parse_value(&[0xff]), Err(Error::Malformed));
+    assert_eq!(parse_value(&[0x62, 0xc3, 0x28]), Err(Error::Malformed));
+    assert_eq!(parse_value(&[0x5f, 0x61, 0x61, 0xff]), Err(Error::Malformed));
+    assert_eq!(parse_value(&[0xbf, 0x01, 0xff]), Err(Error::Malformed));
+}
+
+#[test]
+fn test_cbor_chunked() {
+    const BYTES: &[u8] = &[0xa1, 0x63, 0x6b, 0x65, 0x79, 0xc2, 0x9f, 0x19, 0x01, 0x00, 0x44, 0xde, 0xad, 0xbe, 0xef, 0xff];
+    let expected = parse_value(BYTES);
+    assert!(expected.is_ok());
+    for split in 1..BYTES.len() {
+        let mut first = BYTES[..split].iter().cloned().peekable();
+        let mut second = BYTES[split..].iter().cloned().peekable();
+        let state = cbor_value().init(&mut first).unwrap().unContinue();
+        assert_eq!(state.more(&mut second).unDone(), expected, "Split at {}", split);
+    }
+}
@@ -0,0 +1,251 @@
+//! HTTP chunked transfer encoding.
+//!
+//! A body sent with `Transfer-Encoding: chunked` is a sequence of chunks, each a hexadecimal
+//! size line followed by that many bytes of payload, ending with a zero-sized chunk and
+//! optional trailer headers. A `Decoder` keeps track of the framing, and `decoder.payload(input)`
+//! is an input adapter which skips the framing, so a parser over bytes sees only the payload,
+//! for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,Uncommitted,Stateful};
+//! # use parsell::ParseResult::Continue;
+//! # use parsell::formats::chunked::Decoder;
+//! fn is_digit(byte: u8) -> bool { byte.is_ascii_digit() }
+//! fn mk_buffer() -> Vec<u8> { Vec::new() }
+//! let parser = character(is_digit).star(mk_buffer);
+//! let mut decoder = Decoder::new();
+//! let mut input = b"3\r\n123\r\n2\r".iter().cloned().peekable();
+//! let state = match parser.init(&mut decoder.payload(&mut input)).unwrap() {
+//!     Continue(state) => state,
+//!     _ => panic!("Can't happen"),
+//! };
+//! let mut input = b"\n45\r\n0\r\nExpires: never\r\n\r\n".iter().cloned().peekable();
+//! assert_eq!(state.last(&mut decoder.payload(&mut input)), b"12345");
+//! assert!(decoder.is_finished());
+//! assert_eq!(decoder.trailers()[0].0, "Expires");
+//! ```
+//!
+//! Chunk extensions are ignored, and a bare LF is accepted as a line ending.
+//! If the framing is malformed, the payload ends, and `decoder.is_malformed()` is `true`.
+
+use super::super::{Function, PeekableIterator};
+use super::multipart::{Headers, parse_headers};
+
+// Where we are in the framing.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    // The chunk size read so far, if any digits have been read.
+    Size(Option<u64>),
+    Extension(Option<u64>),
+    // The number of bytes of payload remaining in the chunk.
+    Data(u64),
+    DataEnd,
+    // Whether we are at the start of a trailer line.
+    Trailer(bool),
+    Finished,
+    Malformed,
+}
+
+impl Phase {
+    fn end_of_size(size: Option<u64>) -> Phase {
+        match size {
+            None => Phase::Malformed,
+            Some(0) => Phase::Trailer(true),
+            Some(size) => Phase::Data(size),
+        }
+    }
+}
+
+/// A decoder for the framing of a chunked body.
+
+#[derive(Clone, Debug)]
+pub struct Decoder {
+    phase: Phase,
+    trailer: Vec<u8>,
+}
+
+impl Decoder {
+    /// A decoder at the start of a chunked body.
+    pub fn new() -> Self {
+        Decoder {
+            phase: Phase::Size(None),
+            trailer: Vec::new(),
+        }
+    }
+
+    /// The payload of a chunk of input, as input for parsers over bytes.
+    pub fn payload<'a, I>(&'a mut self, input: &'a mut I) -> Payload<'a, I>
+        where I: PeekableIterator<Item = u8>,
+    {
+        Payload {
+            decoder: self,
+            input,
+        }
+    }
+
+    /// Whether the last chunk and the trailers have been read.
+    pub fn is_finished(&self) -> bool {
+        self.phase == Phase::Finished
+    }
+
+    /// Whether the framing was malformed.
+    pub fn is_malformed(&self) -> bool {
+        self.phase == Phase::Malformed
+    }
+
+    /// The trailer headers read so far.
+    pub fn trailers(&self) -> Headers {
+        parse_headers(&self.trailer)
+    }
+
+    fn step(&mut self, byte: u8) {
+        self.phase = match (self.phase, byte) {
+            (Phase::Size(size), _) if (byte as char).is_ascii_hexdigit() => {
+                let digit = (byte as char).to_digit(16).unwrap() as u64;
+                match size.unwrap_or(0).checked_mul(16) {
+                    Some(size) => Phase::Size(Some(size + digit)),
+                    None => Phase::Malformed,
+                }
+            },
+            (Phase::Size(size), b';') | (Phase::Size(size), b' ') | (Phase::Size(size), b'\t') => Phase::Extension(size),
+            (Phase::Size(size), b'\n') | (Phase::Extension(size), b'\n') => Phase::end_of_size(size),
+            (Phase::Size(size), b'\r') => Phase::Extension(size),
+            (Phase::Size(_), _) => Phase::Malformed,
+            (Phase::DataEnd, b'\r') => Phase::DataEnd,
+            (Phase::DataEnd, b'\n') => Phase::Size(None),
+            (Phase::DataEnd, _) => Phase::Malformed,
+            (Phase::Trailer(start), b'\r') => Phase::Trailer(start),
+            (Phase::Trailer(true), b'\n') => Phase::Finished,
+            (Phase::Trailer(false), b'\n') => {
+                self.trailer.extend_from_slice(b"\r\n");
+                Phase::Trailer(true)
+            },
+            (Phase::Trailer(_), _) => {
+                self.trailer.push(byte);
+                Phase::Trailer(false)
+            },
+            (phase, _) => phase,
+        };
+    }
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::new()
+    }
+}
+
+/// The payload of a chunked body.
+///
+/// Reading from the payload skips over any framing in the underlying input,
+/// and stops at the end of the body.
+
+pub struct Payload<'a, I: 'a> {
+    decoder: &'a mut Decoder,
+    input: &'a mut I,
+}
+
+impl<'a, I> Payload<'a, I>
+    where I: PeekableIterator<Item = u8>,
+{
+    // Skip any framing, returning whether there is payload to read.
+    fn skip(&mut self) -> bool {
+        loop {
+            match self.decoder.phase {
+                Phase::Data(0) => self.decoder.phase = Phase::DataEnd,
+                Phase::Data(_) => return true,
+                Phase::Finished | Phase::Malformed => return false,
+                _ => match self.input.next() {
+                    Some(byte) => self.decoder.step(byte),
+                    None => return false,
+                },
+            }
+        }
+    }
+
+    fn consumed(&mut self, byte: Option<u8>) -> Option<u8> {
+        if let (Some(_), Phase::Data(remaining)) = (byte, self.decoder.phase) {
+            self.decoder.phase = Phase::Data(remaining - 1);
+        }
+        byte
+    }
+}
+
+impl<'a, I> Iterator for Payload<'a, I>
+    where I: PeekableIterator<Item = u8>,
+{
+    type Item = u8;
+
+    fn next(&mut self) -> Option<u8> {
+        if self.skip() {
+            let byte = self.input.next();
+            self.consumed(byte)
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a, I> PeekableIterator for Payload<'a, I>
+    where I: PeekableIterator<Item = u8>,
+{
+    fn is_empty(&mut self) -> bool {
+        !self.skip() || self.input.is_empty()
+    }
+
+    fn next_if_ref<F>(&mut self, f: F) -> Option<u8>
+        where F: for<'b> Function<&'b u8, Output = bool>
+    {
+        if self.skip() {
+            let byte = self.input.next_if_ref(f);
+            self.consumed(byte)
+        } else {
+            None
+        }
+    }
+
+    fn progress(&self) -> Option<usize> {
+        self.input.progress()
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_chunked() {
+    const BODY: &[u8] = b"5;name=value\r\nhello\r\n1\n \n0000B\r\n, world!!!!\r\n0\r\nA: 1\r\nB: 2\r\n\r\ntrailing";
+    for split in 0..BODY.len() {
+        let mut decoder = Decoder::new();
+        let mut first = BODY[..split].iter().cloned().peekable();
+        let mut payload: Vec<u8> = decoder.payload(&mut first).collect();
+        let mut second = BODY[split..].iter().cloned().peekable();
+        payload.extend(decoder.payload(&mut second));
+        assert_eq!(payload, b"hello , world!!!!", "Split at {}", split);
+        assert!(decoder.is_finished());
+        assert!(!decoder.is_malformed());
+        assert_eq!(decoder.trailers(), vec![(String::from("A"), String::from("1")), (String::from("B"), String::from("2"))]);
+        assert_eq!(first.chain(second).collect::<Vec<u8>>(), b"trailing", "Split at {}", split);
+    }
+    let mut decoder = Decoder::new();
+    let mut input = b"2\r\nabc\r\n".iter().cloned().peekable();
+    assert_eq!(decoder.payload(&mut input).collect::<Vec<u8>>(), b"ab");
+    assert!(decoder.is_malformed());
+    let mut decoder = Decoder::new();
+    let mut input = b"x\r\n".iter().cloned().peekable();
+    assert_eq!(decoder.payload(&mut input).next(), None);
+    assert!(decoder.is_malformed());
+}
+
+#[test]
+fn test_chunked_next_if() {
+    let mut decoder = Decoder::new();
+    let mut input = b"1\r\na\r\n1\r\nb\r\n0\r\n\r\n".iter().cloned().peekable();
+    let mut payload = decoder.payload(&mut input);
+    assert_eq!(payload.next_if(|byte: u8| byte == b'b'), None);
+    assert_eq!(payload.next_if(|byte: u8| byte == b'a'), Some(b'a'));
+    assert!(!payload.is_empty());
+    assert_eq!(payload.next_if(|byte: u8| byte == b'b'), Some(b'b'));
+    assert!(payload.is_empty());
+    assert!(decoder.is_finished());
+}
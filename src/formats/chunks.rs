@@ -0,0 +1,276 @@
+//! Chunked binary file formats.
+//!
+//! Many binary formats, such as RIFF (used by WAV and AVI), IFF and PNG, are sequences of chunks,
+//! each with a four-byte type, a length, the data, and sometimes padding or a checksum.
+//! A `ChunkReader` splits a stream of bytes into chunks, given the `Layout` of the format,
+//! borrowing the data from the input wherever possible, for example:
+//!
+//! ```
+//! # use parsell::formats::chunks::{ChunkReader, Layout};
+//! let mut reader = ChunkReader::new(Layout::riff());
+//! let chunks = reader.push(b"fmt \x03\x00\x00\x00abc\x00da");
+//! assert_eq!(&chunks[0].as_ref().unwrap().kind, b"fmt ");
+//! assert_eq!(&*chunks[0].as_ref().unwrap().data, b"abc");
+//! let chunks = reader.push(b"ta\x00\x00\x00\x00");
+//! assert_eq!(&chunks[0].as_ref().unwrap().kind, b"data");
+//! ```
+//!
+//! The data of a container chunk, such as a RIFF `LIST`, can be split by another reader.
+
+use super::slice;
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::error;
+use std::fmt;
+use std::mem;
+
+/// The PNG file signature.
+
+pub const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// The layout of the chunks in a file format.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Layout {
+    signature: &'static [u8],
+    length_first: bool,
+    big_endian: bool,
+    padded: bool,
+    crc: bool,
+}
+
+impl Layout {
+    /// RIFF chunks: a type, a little-endian length, and the data, padded to an even length.
+    pub fn riff() -> Self {
+        Layout { signature: b"", length_first: false, big_endian: false, padded: true, crc: false }
+    }
+
+    /// IFF chunks: a type, a big-endian length, and the data, padded to an even length.
+    pub fn iff() -> Self {
+        Layout { signature: b"", length_first: false, big_endian: true, padded: true, crc: false }
+    }
+
+    /// PNG chunks: a big-endian length, a type, the data, and a CRC-32 of the type and data.
+    ///
+    /// The input starts with the PNG file signature.
+    pub fn png() -> Self {
+        Layout { signature: PNG_SIGNATURE, length_first: true, big_endian: true, padded: false, crc: true }
+    }
+
+    // The type, the length of the data, and the length of the data and any trailer.
+    fn header(&self, header: &[u8]) -> ([u8; 4], usize, usize) {
+        let (kind, length) = if self.length_first { (&header[4..], &header[..4]) } else { (&header[..4], &header[4..]) };
+        let length = length.iter().enumerate().fold(0, |result, (index, &byte)| {
+            result | (byte as u32) << (8 * if self.big_endian { 3 - index } else { index })
+        }) as usize;
+        let pad = if self.padded { length % 2 } else { 0 };
+        let crc = if self.crc { 4 } else { 0 };
+        ([kind[0], kind[1], kind[2], kind[3]], length, length + pad + crc)
+    }
+}
+
+/// A chunk, with its type and data.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Chunk<'a> {
+    /// The type of the chunk.
+    pub kind: [u8; 4],
+    /// The data of the chunk.
+    pub data: Cow<'a, [u8]>,
+}
+
+/// An error in chunked input.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// The input did not start with the file signature.
+    Signature,
+    /// The checksum of a chunk did not match its contents.
+    Checksum([u8; 4]),
+    /// The input ended in the middle of a chunk.
+    Truncated,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Signature => write!(fmt, "Missing file signature"),
+            Error::Checksum(kind) => write!(fmt, "Checksum mismatch in {} chunk", String::from_utf8_lossy(&kind)),
+            Error::Truncated => write!(fmt, "Truncated chunk"),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// The CRC-32 used by PNG and zlib, of some bytes.
+
+pub fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| if crc & 1 == 0 { crc >> 1 } else { (crc >> 1) ^ 0xedb8_8320 })
+    })
+}
+
+/// A reader for chunks.
+
+#[derive(Clone, Debug)]
+pub struct ChunkReader {
+    layout: Layout,
+    // How much of the signature has been read.
+    signature: usize,
+    // An incomplete header.
+    pending: Vec<u8>,
+    // A chunk whose data is incomplete, with its type, length, and bytes remaining.
+    partial: Option<([u8; 4], usize, usize, Vec<u8>)>,
+    failed: bool,
+}
+
+impl ChunkReader {
+    /// A reader at the start of a sequence of chunks.
+    pub fn new(layout: Layout) -> Self {
+        ChunkReader {
+            layout,
+            signature: 0,
+            pending: Vec::new(),
+            partial: None,
+            failed: false,
+        }
+    }
+
+    // Check any checksum, and produce the chunk.
+    fn chunk<'a>(&self, kind: [u8; 4], length: usize, record: Cow<'a, [u8]>) -> Result<Chunk<'a>, Error> {
+        if self.layout.crc {
+            let found = record[length..length + 4].iter().fold(0, |crc, &byte| (crc << 8) | byte as u32);
+            let mut bytes = kind.to_vec();
+            bytes.extend_from_slice(&record[..length]);
+            if crc32(&bytes) != found {
+                return Err(Error::Checksum(kind));
+            }
+        }
+        let data = match record {
+            Borrowed(record) => Borrowed(&record[..length]),
+            Owned(mut record) => {
+                record.truncate(length);
+                Owned(record)
+            },
+        };
+        Ok(Chunk { kind, data })
+    }
+
+    /// Feed a chunk of input, returning the chunks it completes.
+    ///
+    /// A chunk whose checksum does not match is reported as an error, and reading continues
+    /// with the next chunk. If the signature does not match, any remaining input is ignored.
+    pub fn push<'a>(&mut self, input: &'a [u8]) -> Vec<Result<Chunk<'a>, Error>> {
+        let mut chunks = Vec::new();
+        let mut input = input;
+        if self.failed {
+            return chunks;
+        }
+        while self.signature < self.layout.signature.len() {
+            match input.split_first() {
+                None => return chunks,
+                Some((&byte, rest)) if byte == self.layout.signature[self.signature] => {
+                    self.signature += 1;
+                    input = rest;
+                },
+                Some(_) => {
+                    self.failed = true;
+                    chunks.push(Err(Error::Signature));
+                    return chunks;
+                },
+            }
+        }
+        let input: Cow<'a, [u8]> = if self.pending.is_empty() {
+            Borrowed(input)
+        } else {
+            let mut pending = mem::take(&mut self.pending);
+            pending.extend_from_slice(input);
+            Owned(pending)
+        };
+        let mut position = 0;
+        while !self.failed {
+            if let Some((kind, length, remaining, mut record)) = self.partial.take() {
+                let available = input.len() - position;
+                if available < remaining {
+                    record.extend_from_slice(&input[position..]);
+                    self.partial = Some((kind, length, remaining - available, record));
+                    return chunks;
+                }
+                record.extend_from_slice(&input[position..position + remaining]);
+                position += remaining;
+                chunks.push(self.chunk(kind, length, Owned(record)));
+            }
+            if input.len() - position < 8 {
+                break;
+            }
+            let (kind, length, total) = self.layout.header(&input[position..position + 8]);
+            position += 8;
+            if input.len() - position >= total {
+                chunks.push(self.chunk(kind, length, slice(&input, position, position + total)));
+                position += total;
+            } else {
+                self.partial = Some((kind, length, total, Vec::new()));
+            }
+        }
+        self.pending.extend_from_slice(&input[position..]);
+        chunks
+    }
+
+    /// Finish reading, returning an error if the input ended in the middle of a chunk.
+    pub fn finish(self) -> Result<(), Error> {
+        if self.signature < self.layout.signature.len() {
+            Err(Error::Signature)
+        } else if self.pending.is_empty() && self.partial.is_none() {
+            Ok(())
+        } else {
+            Err(Error::Truncated)
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_crc32() {
+    assert_eq!(crc32(b""), 0);
+    assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    assert_eq!(crc32(b"IEND"), 0xae42_6082);
+}
+
+#[test]
+fn test_png_chunks() {
+    const PNG: &[u8] = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x04teXtabcd\xb4\xff\xfd\xd9\x00\x00\x00\x00IEND\xae\x42\x60\x82";
+    for split in 0..PNG.len() {
+        let mut reader = ChunkReader::new(Layout::png());
+        let mut chunks = reader.push(&PNG[..split]);
+        chunks.extend(reader.push(&PNG[split..]));
+        assert_eq!(chunks, vec![
+            Ok(Chunk { kind: *b"teXt", data: Owned(b"abcd".to_vec()) }),
+            Ok(Chunk { kind: *b"IEND", data: Owned(vec![]) }),
+        ], "Split at {}", split);
+        assert_eq!(reader.finish(), Ok(()));
+    }
+    let mut reader = ChunkReader::new(Layout::png());
+    assert_eq!(reader.push(b"GIF89a"), vec![Err(Error::Signature)]);
+    let mut reader = ChunkReader::new(Layout::png());
+    let chunks = reader.push(b"\x89PNG\r\n\x1a\n\x00\x00\x00\x00IEND\x00\x00\x00\x00");
+    assert_eq!(chunks, vec![Err(Error::Checksum(*b"IEND"))]);
+}
+
+#[test]
+fn test_riff_chunks() {
+    let mut reader = ChunkReader::new(Layout::riff());
+    let chunks = reader.push(b"abcd\x01\x00\x00\x00x\x00efgh\x00\x00\x00\x00ijkl\x02\x00");
+    match chunks[0] {
+        Ok(Chunk { kind, data: Borrowed(data) }) => assert_eq!((&kind, data), (b"abcd", &b"x"[..])),
+        ref chunk => panic!("Unexpected {:?}", chunk),
+    }
+    assert_eq!(chunks[1], Ok(Chunk { kind: *b"efgh", data: Borrowed(b"") }));
+    assert_eq!(chunks.len(), 2);
+    assert_eq!(reader.finish(), Err(Error::Truncated));
+    let mut reader = ChunkReader::new(Layout::iff());
+    let chunks = reader.push(b"FORM\x00\x00\x00\x04AIFF");
+    assert_eq!(chunks, vec![Ok(Chunk { kind: *b"FORM", data: Borrowed(b"AIFF") })]);
+}
@@ -0,0 +1,220 @@
+//! Email addresses.
+//!
+//! This is a lightweight parser for the `local@domain` addr-spec of RFC 5322,
+//! as used in mail logs and headers. The local part is a dot-atom or a quoted string,
+//! and the domain is a dot-atom or a domain literal in square brackets. For example:
+//!
+//! ```
+//! # use parsell::UncommittedStr;
+//! # use parsell::formats::email::addr_spec;
+//! let address = addr_spec().init_str("\"J. Smith\"@example.com>").unwrap().unDone().unwrap();
+//! assert_eq!(address.local, "\"J. Smith\"");
+//! assert_eq!(address.domain, "example.com");
+//! ```
+//!
+//! The components are borrowed from the input, unless the address is split across chunks.
+//! They are given as written, so a quoted local part includes its quotes and escapes.
+//! Comments, folding whitespace and obsolete syntax are not supported, and consecutive
+//! dots are allowed. The parser commits on the first character, so the result is `None`
+//! if the input starts like an address but is malformed.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::str::Chars;
+
+/// An email address.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Address<'a> {
+    /// The local part, before the `@`.
+    pub local: Cow<'a, str>,
+    /// The domain, after the `@`.
+    pub domain: Cow<'a, str>,
+}
+
+/// A parser for email addresses.
+
+pub fn addr_spec() -> AddrSpec {
+    AddrSpec
+}
+
+// How much of an address has been read.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Start,
+    LocalAtom,
+    Quoted,
+    QuotedEscape,
+    QuotedEnd,
+    DomainStart,
+    DomainAtom,
+    DomainLiteral,
+    DomainLiteralEscape,
+    Finished,
+}
+
+fn is_atext(ch: char) -> bool {
+    ch.is_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(ch)
+}
+
+fn is_domain_text(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '-' || ch == '.'
+}
+
+impl Phase {
+    fn step(self, ch: char) -> Option<Phase> {
+        use self::Phase::*;
+        match (self, ch) {
+            (Start, '"') => Some(Quoted),
+            (Start, _) | (LocalAtom, _) if is_atext(ch) => Some(LocalAtom),
+            (LocalAtom, '@') | (QuotedEnd, '@') => Some(DomainStart),
+            (Quoted, '"') => Some(QuotedEnd),
+            (Quoted, '\\') => Some(QuotedEscape),
+            (Quoted, '\r') | (Quoted, '\n') => None,
+            (Quoted, _) | (QuotedEscape, _) => Some(Quoted),
+            (DomainStart, '[') => Some(DomainLiteral),
+            (DomainStart, _) | (DomainAtom, _) if is_domain_text(ch) => Some(DomainAtom),
+            (DomainLiteral, ']') => Some(Finished),
+            (DomainLiteral, '\\') => Some(DomainLiteralEscape),
+            (DomainLiteral, '[') | (DomainLiteral, '\r') | (DomainLiteral, '\n') => None,
+            (DomainLiteral, _) | (DomainLiteralEscape, _) => Some(DomainLiteral),
+            _ => None,
+        }
+    }
+
+    fn is_complete(self) -> bool {
+        self == Phase::DomainAtom || self == Phase::Finished
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AddrSpec;
+
+#[derive(Clone, Debug)]
+pub struct AddrSpecState {
+    phase: Phase,
+    // The byte offset of the `@`, once it has been read.
+    at: Option<usize>,
+    buffer: String,
+}
+
+// Run the state machine as far as it will go, returning the number of bytes consumed,
+// and whether the address has ended.
+
+fn run(phase: &mut Phase, at: &mut Option<usize>, offset: usize, string: &mut Chars) -> (usize, bool) {
+    let string0 = string.as_str();
+    while *phase != Phase::Finished {
+        let current = *phase;
+        match string.next_if(move |ch| current.step(ch).is_some()) {
+            Some(ch) => {
+                *phase = current.step(ch).unwrap();
+                if *phase == Phase::DomainStart {
+                    *at = Some(offset + string0.len() - string.as_str().len() - 1);
+                }
+            },
+            None => return (string0.len() - string.as_str().len(), !string.is_empty()),
+        }
+    }
+    (string0.len() - string.as_str().len(), true)
+}
+
+fn split<'a>(address: Cow<'a, str>, phase: Phase, at: Option<usize>) -> Option<Address<'a>> {
+    match at {
+        Some(at) if phase.is_complete() => Some(match address {
+            Borrowed(address) => Address { local: Borrowed(&address[..at]), domain: Borrowed(&address[at + 1..]) },
+            Owned(mut address) => {
+                let domain = address.split_off(at + 1);
+                address.pop();
+                Address { local: Owned(address), domain: Owned(domain) }
+            },
+        }),
+        _ => None,
+    }
+}
+
+impl Parser for AddrSpec {}
+
+impl<'a> Stateful<char, Chars<'a>, Option<Address<'a>>> for AddrSpecState {
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Option<Address<'a>>> {
+        let string0 = string.as_str();
+        let offset = self.buffer.len();
+        let (consumed, ended) = run(&mut self.phase, &mut self.at, offset, string);
+        self.buffer.push_str(&string0[..consumed]);
+        if ended {
+            Done(split(Owned(self.buffer), self.phase, self.at))
+        } else {
+            Continue(self)
+        }
+    }
+
+    fn done(self) -> Option<Address<'a>> {
+        split(Owned(self.buffer), self.phase, self.at)
+    }
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for AddrSpecState {
+
+    type Output = Option<Address<'a>>;
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for AddrSpec {
+
+    type Output = Option<Address<'a>>;
+
+}
+
+impl<'a> Uncommitted<char, Chars<'a>, Option<Address<'a>>> for AddrSpec {
+
+    type State = AddrSpecState;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Option<Address<'a>>>> {
+        Phase::Start.step(string.clone().next()?)?;
+        let string0 = string.as_str();
+        let mut phase = Phase::Start;
+        let mut at = None;
+        let (consumed, ended) = run(&mut phase, &mut at, 0, string);
+        if ended {
+            Some(Done(split(Borrowed(&string0[..consumed]), phase, at)))
+        } else {
+            Some(Continue(AddrSpecState { phase, at, buffer: String::from(&string0[..consumed]) }))
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_addr_spec() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    fn address<'a>(local: &'a str, domain: &'a str) -> Option<Address<'a>> {
+        Some(Address { local: Borrowed(local), domain: Borrowed(domain) })
+    }
+    let parser = addr_spec();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("@example.com"), None);
+    assert_eq!(parser.init_str("user.name+tag@mail.example.com, ").unwrap().unDone(), address("user.name+tag", "mail.example.com"));
+    assert_eq!(parser.init_str("\"a\\\"b@c\"@[127.0.0.1]x").unwrap().unDone(), address("\"a\\\"b@c\"", "[127.0.0.1]"));
+    assert_eq!(parser.init_str("user ").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("user@ ").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("\"user\" ").unwrap().unDone(), None);
+    let parsing = parser.init_str("us").unwrap().unContinue();
+    let parsing = parsing.more_str("er@exa").unContinue();
+    match parsing.last_str("mple.org") {
+        Some(Address { local: Owned(local), domain: Owned(domain) }) => {
+            assert_eq!(local, "user");
+            assert_eq!(domain, "example.org");
+        },
+        result => panic!("Unexpected {:?}", result),
+    }
+    testing::assert_chunk_invariant(parser, "\"x y\"@[::1]");
+    testing::assert_chunk_invariant(parser, "x@y.z;");
+}
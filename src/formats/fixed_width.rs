@@ -0,0 +1,214 @@
+//! Fixed-width records.
+//!
+//! A fixed-width record is a sequence of fields, each a fixed number of characters,
+//! usually padded with spaces or zeros. A record parser is built by declaring the
+//! width of each field and a function to convert its text, for example:
+//!
+//! ```
+//! # use parsell::UncommittedStr;
+//! # use parsell::formats::fixed_width::fixed_width;
+//! fn amount(field: &str) -> Option<u64> { field.trim_start_matches('0').parse().ok() }
+//! let parser = fixed_width(8, |field: &str| String::from(field.trim_end()))
+//!     .field(2, |field: &str| field == "CR")
+//!     .field(6, amount);
+//! let ((name, credit), amount) = parser.init_str("ACME    CR001250\n").unwrap().unDone();
+//! assert_eq!(name, "ACME");
+//! assert!(credit);
+//! assert_eq!(amount, Some(1250));
+//! ```
+//!
+//! As with `and_then`, the output of a record with more than one field is a nested pair.
+//! The record parser is committed: it reads exactly as many characters as the fields' total width,
+//! and if the input ends early, the missing fields are converted from shorter (possibly empty) text.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, Committed, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+
+use std::str::Chars;
+
+/// A record whose first field is `width` characters wide, converted by `convert`.
+
+pub fn fixed_width<T>(width: usize, convert: fn(&str) -> T) -> FixedWidth<FirstField<T>> {
+    FixedWidth(FirstField(width, convert))
+}
+
+/// The layout of the fields of a record.
+
+pub trait Fields: Copy {
+    /// The output of converting the fields.
+    type Output;
+
+    /// The total width of the fields, in characters.
+    fn width(&self) -> usize;
+
+    /// Convert the fields at the start of a record, returning the result and the rest of the record.
+    fn convert<'a>(&self, record: &'a str) -> (Self::Output, &'a str);
+}
+
+// Split a string after `width` characters, or at the end if it is shorter.
+
+fn split_chars(string: &str, width: usize) -> (&str, &str) {
+    match string.char_indices().nth(width) {
+        Some((index, _)) => string.split_at(index),
+        None => (string, ""),
+    }
+}
+
+pub struct FirstField<T>(usize, fn(&str) -> T);
+
+impl<T> Copy for FirstField<T> {}
+impl<T> Clone for FirstField<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Fields for FirstField<T> {
+    type Output = T;
+    fn width(&self) -> usize {
+        self.0
+    }
+    fn convert<'a>(&self, record: &'a str) -> (T, &'a str) {
+        let (field, rest) = split_chars(record, self.0);
+        ((self.1)(field), rest)
+    }
+}
+
+pub struct NextField<L, T>(L, usize, fn(&str) -> T);
+
+impl<L, T> Copy for NextField<L, T> where L: Copy {}
+impl<L, T> Clone for NextField<L, T> where L: Copy {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<L, T> Fields for NextField<L, T> where L: Fields {
+    type Output = (L::Output, T);
+    fn width(&self) -> usize {
+        self.0.width() + self.1
+    }
+    fn convert<'a>(&self, record: &'a str) -> ((L::Output, T), &'a str) {
+        let (previous, rest) = self.0.convert(record);
+        let (field, rest) = split_chars(rest, self.1);
+        ((previous, (self.2)(field)), rest)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FixedWidth<L>(L);
+
+impl<L> FixedWidth<L> where L: Fields {
+    /// Add a field `width` characters wide, converted by `convert`.
+    pub fn field<T>(self, width: usize, convert: fn(&str) -> T) -> FixedWidth<NextField<L, T>> {
+        FixedWidth(NextField(self.0, width, convert))
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FixedWidthState<L> {
+    layout: L,
+    // The number of characters remaining in the record.
+    remaining: usize,
+    buffer: String,
+}
+
+// Read up to `remaining` characters, returning the text read.
+
+fn read<'a>(remaining: &mut usize, string: &mut Chars<'a>) -> &'a str {
+    let string0 = string.as_str();
+    while *remaining > 0 && string.next().is_some() {
+        *remaining -= 1;
+    }
+    &string0[..(string0.len() - string.as_str().len())]
+}
+
+impl<L> Parser for FixedWidth<L> {}
+
+impl<'a, L> Stateful<char, Chars<'a>, L::Output> for FixedWidthState<L>
+    where L: Fields,
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, L::Output> {
+        self.buffer.push_str(read(&mut self.remaining, string));
+        if self.remaining == 0 {
+            Done(self.layout.convert(&self.buffer).0)
+        } else {
+            Continue(self)
+        }
+    }
+
+    fn done(self) -> L::Output {
+        self.layout.convert(&self.buffer).0
+    }
+
+}
+
+impl<'a, L> HasOutput<char, Chars<'a>> for FixedWidthState<L>
+    where L: Fields,
+{
+
+    type Output = L::Output;
+
+}
+
+impl<'a, L> HasOutput<char, Chars<'a>> for FixedWidth<L>
+    where L: Fields,
+{
+
+    type Output = L::Output;
+
+}
+
+impl<'a, L> Uncommitted<char, Chars<'a>, L::Output> for FixedWidth<L>
+    where L: Fields,
+{
+
+    type State = FixedWidthState<L>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, L::Output>> {
+        if string.is_empty() {
+            return None;
+        }
+        let mut remaining = self.0.width();
+        let record = read(&mut remaining, string);
+        if remaining == 0 {
+            Some(Done(self.0.convert(record).0))
+        } else {
+            Some(Continue(FixedWidthState { layout: self.0, remaining, buffer: String::from(record) }))
+        }
+    }
+
+}
+
+impl<'a, L> Committed<char, Chars<'a>, L::Output> for FixedWidth<L>
+    where L: Fields,
+{
+
+    fn empty(&self) -> L::Output {
+        self.0.convert("").0
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_fixed_width() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    fn trim(field: &str) -> String { String::from(field.trim()) }
+    fn number(field: &str) -> Option<u32> { field.trim().parse().ok() }
+    let parser = fixed_width(3, trim).field(2, number).field(1, trim);
+    assert_eq!(parser.init_str(""), None);
+    let mut data = "x\u{e9}z 7!rest".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), ((String::from("x\u{e9}z"), Some(7)), String::from("!")));
+    assert_eq!(data.as_str(), "rest");
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("c1").unContinue();
+    assert_eq!(parsing.more_str("2-").unDone(), ((String::from("abc"), Some(12)), String::from("-")));
+    let parsing = parser.init_str("abcd").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), ((String::from("abc"), None), String::new()));
+    assert_eq!(parser.empty(), ((String::new(), None), String::new()));
+    testing::assert_chunk_invariant(parser, "abc42z");
+}
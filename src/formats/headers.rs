@@ -0,0 +1,217 @@
+//! Header blocks.
+//!
+//! A header block, as used by email (RFC 5322), HTTP/1.1 and MIME, is a sequence of
+//! `Name: value` lines ending with a blank line. A line starting with a space or tab
+//! continues the value of the previous header. The parser `header_block(factory)` reads
+//! a block into a consumer of name-value pairs built by `factory`, such as a `Vec` or
+//! a `HashMap`, for example:
+//!
+//! ```
+//! # use parsell::UncommittedStr;
+//! # use parsell::formats::headers::header_block;
+//! # use std::collections::HashMap;
+//! let block = "Subject: Streaming\r\n parsers\r\nFrom: a@example.com\r\n\r\nBody";
+//! let headers: HashMap<String, String> = header_block(HashMap::new).init_str(block).unwrap().unDone();
+//! assert_eq!(headers["Subject"], "Streaming parsers");
+//! assert_eq!(headers["From"], "a@example.com");
+//! ```
+//!
+//! Folded values are unfolded by removing the line breaks before continuation lines,
+//! keeping their whitespace, and whitespace around values is trimmed.
+//! Names are given as written, lines without a colon are ignored, and a block may end
+//! with the end of input rather than a blank line. The parser commits on the first character.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, Committed, Consumer, Factory, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+
+use std::mem;
+
+/// A parser for a header block, whose name-value pairs are added to a consumer built by `factory`.
+
+pub fn header_block<F>(factory: F) -> HeaderBlock<F> {
+    HeaderBlock(factory)
+}
+
+// How much of the block has been read.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    LineStart,
+    Name,
+    Value,
+    BlankLine,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct HeaderBlock<F>(F);
+
+#[derive(Clone, Debug)]
+pub struct HeaderBlockState<C> {
+    phase: Phase,
+    name: String,
+    value: String,
+    // Whether the name and value are of a header which may still be continued.
+    pending: bool,
+    headers: C,
+}
+
+impl<C> HeaderBlockState<C> where C: Consumer<(String, String)> {
+    fn new(headers: C) -> Self {
+        HeaderBlockState {
+            phase: Phase::LineStart,
+            name: String::new(),
+            value: String::new(),
+            pending: false,
+            headers,
+        }
+    }
+
+    fn flush(&mut self) {
+        if mem::replace(&mut self.pending, false) {
+            let name = String::from(self.name.trim());
+            let value = String::from(self.value.trim());
+            self.headers.accept((name, value));
+            self.name.clear();
+            self.value.clear();
+        }
+    }
+
+    // Handle a character, returning whether the block has ended.
+    fn step(&mut self, ch: char) -> bool {
+        self.phase = match (self.phase, ch) {
+            (Phase::LineStart, '\r') => Phase::BlankLine,
+            (Phase::LineStart, '\n') | (Phase::BlankLine, '\n') => return true,
+            (Phase::LineStart, ' ') | (Phase::LineStart, '\t') if self.pending => {
+                if self.value.ends_with('\r') {
+                    self.value.pop();
+                }
+                self.value.push(ch);
+                Phase::Value
+            },
+            (Phase::LineStart, _) => {
+                self.flush();
+                self.name.push(ch);
+                Phase::Name
+            },
+            (Phase::BlankLine, _) => {
+                self.flush();
+                self.name.push('\r');
+                self.name.push(ch);
+                Phase::Name
+            },
+            (Phase::Name, ':') => {
+                self.pending = true;
+                Phase::Value
+            },
+            (Phase::Name, '\n') => {
+                self.name.clear();
+                Phase::LineStart
+            },
+            (Phase::Name, _) => {
+                self.name.push(ch);
+                Phase::Name
+            },
+            (Phase::Value, '\n') => Phase::LineStart,
+            (Phase::Value, _) => {
+                self.value.push(ch);
+                Phase::Value
+            },
+        };
+        false
+    }
+}
+
+impl<F> Parser for HeaderBlock<F> {}
+
+impl<C, Str> Stateful<char, Str, C> for HeaderBlockState<C>
+    where C: Consumer<(String, String)>,
+          Str: Iterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, C> {
+        for ch in string.by_ref() {
+            if self.step(ch) {
+                self.flush();
+                return Done(self.headers);
+            }
+        }
+        Continue(self)
+    }
+
+    fn done(mut self) -> C {
+        self.flush();
+        self.headers
+    }
+
+}
+
+impl<C, Str> HasOutput<char, Str> for HeaderBlockState<C> {
+
+    type Output = C;
+
+}
+
+impl<F, Str> HasOutput<char, Str> for HeaderBlock<F>
+    where F: Factory,
+{
+
+    type Output = F::Output;
+
+}
+
+impl<F, Str> Uncommitted<char, Str, F::Output> for HeaderBlock<F>
+    where F: Factory,
+          F::Output: Consumer<(String, String)>,
+          Str: PeekableIterator<Item = char>,
+{
+
+    type State = HeaderBlockState<F::Output>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, F::Output>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(HeaderBlockState::new(self.0.build()).more(string))
+        }
+    }
+
+}
+
+impl<F, Str> Committed<char, Str, F::Output> for HeaderBlock<F>
+    where F: Factory,
+          F::Output: Consumer<(String, String)>,
+          Str: PeekableIterator<Item = char>,
+{
+
+    fn empty(&self) -> F::Output {
+        self.0.build()
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_header_block() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    use std::collections::BTreeMap;
+    fn pair(name: &str, value: &str) -> (String, String) {
+        (String::from(name), String::from(value))
+    }
+    let parser = header_block(Vec::new);
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("\r\nbody").unwrap().unDone(), vec![]);
+    let block = "Host: example.com\nX-Long: one\n\t two\nnot a header\nContent-Length:42\n\nbody";
+    assert_eq!(parser.init_str(block).unwrap().unDone(), vec![
+        pair("Host", "example.com"),
+        pair("X-Long", "one\t two"),
+        pair("Content-Length", "42"),
+    ]);
+    let parsing = parser.init_str("A: 1\r\n").unwrap().unContinue();
+    let parsing = parsing.more_str(" 2\r").unContinue();
+    assert_eq!(parsing.last_str("\nB: 3"), vec![pair("A", "1 2"), pair("B", "3")]);
+    let headers = header_block(BTreeMap::new).init_str("A: 1\r\nA: 2\r\n\r\n").unwrap().unDone();
+    assert_eq!(headers.get("A").map(|value: &String| &**value), Some("2"));
+    testing::assert_chunk_invariant(parser, "Subject: a\r\n b\r\nTo: c\r\n\r\n");
+}
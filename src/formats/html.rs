@@ -0,0 +1,402 @@
+//! HTML tokens.
+//!
+//! A `Tokenizer` splits chunks of HTML into tokens: text, start and end tags with their
+//! attributes, comments, and declarations such as `<!DOCTYPE html>`. Tokens are produced
+//! as soon as they are complete, so a scraper can process a response as it arrives,
+//! for example:
+//!
+//! ```
+//! # use parsell::formats::html::{Tokenizer,Token};
+//! # use std::borrow::Cow::Borrowed;
+//! let mut tokenizer = Tokenizer::new();
+//! let tokens = tokenizer.push("<p class=intro>Hello <b>wor");
+//! assert_eq!(tokens, vec![
+//!     Token::StartTag { name: Borrowed("p"), attributes: vec![(Borrowed("class"), Borrowed("intro"))], self_closing: false },
+//!     Token::Text(Borrowed("Hello ")),
+//!     Token::StartTag { name: Borrowed("b"), attributes: vec![], self_closing: false },
+//!     Token::Text(Borrowed("wor")),
+//! ]);
+//! let tokens = tokenizer.push("ld</b>!");
+//! assert_eq!(tokens, vec![Token::Text(Borrowed("ld")), Token::EndTag(Borrowed("b")), Token::Text(Borrowed("!"))]);
+//! assert_eq!(tokenizer.finish(), None);
+//! ```
+//!
+//! Tokens are borrowed from the chunk they lie in, and only tokens which are split across
+//! chunks are copied. Text is produced as soon as it arrives, so a run of text which is
+//! split across chunks is split into several tokens.
+//! The contents of `<script>` and `<style>` elements are text, up to their end tag.
+//!
+//! The tokenizer is lenient: a `<` which does not start a tag is text, attribute values
+//! may be quoted with `"` or `'` or unquoted, and names are given as written. Character
+//! references such as `&amp;` are not decoded. Input left over at the end is text.
+//! This module is only available with the `html` feature.
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+
+/// An HTML token.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Token<'a> {
+    /// Text, including the contents of `<script>` and `<style>` elements.
+    Text(Cow<'a, str>),
+    /// A start tag, such as `<a href="/">` or `<br/>`.
+    StartTag {
+        /// The name of the element.
+        name: Cow<'a, str>,
+        /// The attribute names and values, in order. Attributes without a value have an empty value.
+        attributes: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+        /// Whether the tag ends with `/>`.
+        self_closing: bool,
+    },
+    /// An end tag, such as `</a>`, with the name of the element.
+    EndTag(Cow<'a, str>),
+    /// The text of a comment, between `<!--` and `-->`.
+    Comment(Cow<'a, str>),
+    /// The text of a declaration or processing instruction, such as `DOCTYPE html` for `<!DOCTYPE html>`.
+    Declaration(Cow<'a, str>),
+}
+
+fn owned(text: Cow<str>) -> Cow<'static, str> {
+    Owned(text.into_owned())
+}
+
+impl<'a> Token<'a> {
+    /// A copy of the token which does not borrow from the input.
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            Token::Text(text) => Token::Text(owned(text)),
+            Token::StartTag { name, attributes, self_closing } => Token::StartTag {
+                name: owned(name),
+                attributes: attributes.into_iter().map(|(name, value)| (owned(name), owned(value))).collect(),
+                self_closing,
+            },
+            Token::EndTag(name) => Token::EndTag(owned(name)),
+            Token::Comment(text) => Token::Comment(owned(text)),
+            Token::Declaration(text) => Token::Declaration(owned(text)),
+        }
+    }
+}
+
+// How much of a token has been read. Positions are byte offsets from the start of the token.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Scan {
+    Text,
+    // After a `<` at the given position, in text.
+    Open(usize),
+    // After `<!`, and the given number of dashes.
+    Bang(usize),
+    // In a comment, after the given number of dashes.
+    Comment(usize),
+    Declaration,
+    // In a tag, maybe in a quoted attribute value.
+    Tag(Option<char>),
+    // In the contents of a raw text element, having matched the given length of `</name`,
+    // starting at the given position.
+    RawText(&'static str, usize, usize),
+}
+
+/// A tokenizer, which splits chunks of HTML into tokens.
+
+#[derive(Clone, Debug)]
+pub struct Tokenizer {
+    scan: Scan,
+    // The start of an unfinished token, from earlier chunks.
+    pending: String,
+}
+
+impl Default for Tokenizer {
+    fn default() -> Self {
+        Tokenizer::new()
+    }
+}
+
+// The elements whose contents are raw text.
+
+const RAW_TEXT: [&str; 2] = ["script", "style"];
+
+fn is_space(ch: char) -> bool {
+    ch.is_ascii_whitespace()
+}
+
+fn split_name(text: &str) -> (&str, &str) {
+    let end = text.find(|ch| is_space(ch) || ch == '/' || ch == '=').unwrap_or(text.len());
+    (&text[..end], &text[end..])
+}
+
+// Parse the text of a tag, including the `<` and `>`.
+
+fn tag<'a>(text: &'a str) -> Token<'a> {
+    let inner = &text[1..(text.len() - 1)];
+    if let Some(end) = inner.strip_prefix('/') {
+        let (name, _) = split_name(end);
+        return Token::EndTag(Borrowed(name));
+    }
+    let (name, mut rest) = split_name(inner);
+    let mut attributes = Vec::new();
+    loop {
+        rest = rest.trim_start_matches(|ch| is_space(ch) || ch == '/');
+        if rest.is_empty() {
+            break;
+        }
+        let end = rest.find(|ch| is_space(ch) || ch == '/' || ch == '=').unwrap_or(rest.len());
+        let attribute = &rest[..end.max(1)];
+        rest = rest[attribute.len()..].trim_start_matches(is_space);
+        let mut value = "";
+        if rest.starts_with('=') {
+            rest = rest[1..].trim_start_matches(is_space);
+            match rest.chars().next() {
+                Some(quote) if quote == '"' || quote == '\'' => {
+                    let end = rest[1..].find(quote).map(|end| end + 1).unwrap_or(rest.len());
+                    value = &rest[1..end];
+                    rest = &rest[(end + 1).min(rest.len())..];
+                },
+                _ => {
+                    let end = rest.find(is_space).unwrap_or(rest.len());
+                    value = &rest[..end];
+                    rest = &rest[end..];
+                },
+            }
+        }
+        attributes.push((Borrowed(attribute), Borrowed(value)));
+    }
+    Token::StartTag {
+        name: Borrowed(name),
+        attributes,
+        self_closing: inner.ends_with('/'),
+    }
+}
+
+fn comment<'a>(text: &'a str) -> Token<'a> {
+    Token::Comment(Borrowed(&text[4..(text.len() - 3)]))
+}
+
+fn declaration<'a>(text: &'a str) -> Token<'a> {
+    Token::Declaration(Borrowed(&text[2..(text.len() - 1)]))
+}
+
+// Build a token from its text, which is borrowed if it lies in one chunk.
+
+fn token<'a>(text: Cow<'a, str>, build: for<'b> fn(&'b str) -> Token<'b>) -> Token<'a> {
+    match text {
+        Borrowed(text) => build(text),
+        Owned(text) => build(&text).into_owned(),
+    }
+}
+
+// The raw text element started by a token, if any.
+
+fn raw_text(token: &Token) -> Option<&'static str> {
+    match *token {
+        Token::StartTag { ref name, self_closing: false, .. } =>
+            RAW_TEXT.iter().cloned().find(|raw| raw.eq_ignore_ascii_case(name)),
+        _ => None,
+    }
+}
+
+impl Tokenizer {
+    /// A tokenizer at the start of a document.
+    pub fn new() -> Self {
+        Tokenizer {
+            scan: Scan::Text,
+            pending: String::new(),
+        }
+    }
+
+    /// Feed a chunk of input, returning the tokens it completes.
+    ///
+    /// Any unfinished token at the end of the chunk is kept until the next chunk.
+    pub fn push<'a>(&mut self, chunk: &'a str) -> Vec<Token<'a>> {
+        let mut tokens = Vec::new();
+        let length = self.pending.len();
+        let mut start = 0;
+        {
+            let pending = &self.pending;
+            // The input from `from` to `to`, as offsets from the start of the pending input.
+            let text = |from: usize, to: usize| -> Cow<'a, str> {
+                if from >= length {
+                    Borrowed(&chunk[(from - length)..(to - length)])
+                } else if to <= length {
+                    Owned(String::from(&pending[from..to]))
+                } else {
+                    Owned(String::from(&pending[from..]) + &chunk[..(to - length)])
+                }
+            };
+            for (index, ch) in chunk.char_indices() {
+                let here = length + index;
+                let next = here + ch.len_utf8();
+                self.scan = match (self.scan, ch) {
+                    (Scan::Text, '<') => Scan::Open(here),
+                    (Scan::Text, _) => Scan::Text,
+                    (Scan::Open(_), '<') => Scan::Open(here),
+                    (Scan::Open(open), _) if ch.is_ascii_alphabetic() || ch == '/' || ch == '!' || ch == '?' => {
+                        if start < open {
+                            tokens.push(Token::Text(text(start, open)));
+                        }
+                        start = open;
+                        match ch {
+                            '!' => Scan::Bang(0),
+                            '?' => Scan::Declaration,
+                            _ => Scan::Tag(None),
+                        }
+                    },
+                    (Scan::Open(_), _) => Scan::Text,
+                    (Scan::Bang(0), '-') => Scan::Bang(1),
+                    (Scan::Bang(1), '-') => Scan::Comment(0),
+                    (Scan::Bang(_), '>') | (Scan::Declaration, '>') => {
+                        tokens.push(token(text(start, next), declaration));
+                        start = next;
+                        Scan::Text
+                    },
+                    (Scan::Bang(_), _) => Scan::Declaration,
+                    (Scan::Declaration, _) => Scan::Declaration,
+                    (Scan::Comment(dashes), '>') if dashes >= 2 => {
+                        tokens.push(token(text(start, next), comment));
+                        start = next;
+                        Scan::Text
+                    },
+                    (Scan::Comment(dashes), '-') => Scan::Comment((dashes + 1).min(2)),
+                    (Scan::Comment(_), _) => Scan::Comment(0),
+                    (Scan::Tag(None), '>') => {
+                        let tag = token(text(start, next), tag);
+                        start = next;
+                        let scan = match raw_text(&tag) {
+                            Some(name) => Scan::RawText(name, 0, 0),
+                            None => Scan::Text,
+                        };
+                        tokens.push(tag);
+                        scan
+                    },
+                    (Scan::Tag(None), '"') | (Scan::Tag(None), '\'') => Scan::Tag(Some(ch)),
+                    (Scan::Tag(Some(quote)), _) if ch == quote => Scan::Tag(None),
+                    (Scan::Tag(quote), _) => Scan::Tag(quote),
+                    (Scan::RawText(name, _, _), '<') => Scan::RawText(name, 1, here),
+                    (Scan::RawText(name, 1, open), '/') => Scan::RawText(name, 2, open),
+                    (Scan::RawText(name, matched, open), _) if matched >= 2 && name[(matched - 2)..].starts_with(ch.to_ascii_lowercase()) =>
+                        Scan::RawText(name, matched + 1, open),
+                    // The end tag is only recognized if the name is not the start of a longer one.
+                    (Scan::RawText(name, matched, open), _) if matched == name.len() + 2 && (is_space(ch) || ch == '/' || ch == '>') => {
+                        if start < open {
+                            tokens.push(Token::Text(text(start, open)));
+                        }
+                        start = open;
+                        if ch == '>' {
+                            tokens.push(token(text(start, next), tag));
+                            start = next;
+                            Scan::Text
+                        } else {
+                            Scan::Tag(None)
+                        }
+                    },
+                    (Scan::RawText(name, _, _), _) => Scan::RawText(name, 0, 0),
+                };
+            }
+            // Produce the text read so far, up to any `<` which may start a tag.
+            let flush = match self.scan {
+                Scan::Text | Scan::RawText(_, 0, _) => length + chunk.len(),
+                Scan::Open(open) | Scan::RawText(_, _, open) => open,
+                _ => start,
+            };
+            if start < flush {
+                tokens.push(Token::Text(text(start, flush)));
+                start = flush;
+            }
+        }
+        // Keep the unfinished token, with positions from its start.
+        self.pending = if start < length {
+            String::from(&self.pending[start..]) + chunk
+        } else {
+            String::from(&chunk[(start - length)..])
+        };
+        self.scan = match self.scan {
+            Scan::Open(open) => Scan::Open(open - start),
+            Scan::RawText(name, matched, open) if matched > 0 => Scan::RawText(name, matched, open - start),
+            scan => scan,
+        };
+        tokens
+    }
+
+    /// End the input, returning any unfinished token as text.
+    pub fn finish(self) -> Option<Token<'static>> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(Token::Text(Owned(self.pending)))
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_tokenizer() {
+    fn start<'a>(name: &'a str, attributes: Vec<(&'a str, &'a str)>, self_closing: bool) -> Token<'a> {
+        Token::StartTag {
+            name: Borrowed(name),
+            attributes: attributes.into_iter().map(|(name, value)| (Borrowed(name), Borrowed(value))).collect(),
+            self_closing,
+        }
+    }
+    fn text<'a>(text: &'a str) -> Token<'a> { Token::Text(Borrowed(text)) }
+    let document = "<!DOCTYPE html><a href=\"/x?a=1&amp;b>2\" data-x='y' hidden>a < b</a><br/>\
+                    <!-- a -- b --><SCRIPT>if (a</b) {}</script ><?xml v?><img src=a.png />x";
+    let expected = vec![
+        Token::Declaration(Borrowed("DOCTYPE html")),
+        start("a", vec![("href", "/x?a=1&amp;b>2"), ("data-x", "y"), ("hidden", "")], false),
+        text("a < b"),
+        Token::EndTag(Borrowed("a")),
+        start("br", vec![], true),
+        Token::Comment(Borrowed(" a -- b ")),
+        start("SCRIPT", vec![], false),
+        text("if (a</b) {}"),
+        Token::EndTag(Borrowed("script")),
+        Token::Declaration(Borrowed("xml v?")),
+        start("img", vec![("src", "a.png")], true),
+    ];
+    // Adjacent text tokens, which may be split differently depending on the chunks.
+    fn merged<'a>(tokens: Vec<Token<'a>>) -> Vec<Token<'static>> {
+        let mut result: Vec<Token<'static>> = Vec::new();
+        for token in tokens {
+            match (result.last_mut(), token) {
+                (Some(&mut Token::Text(ref mut text)), Token::Text(more)) => text.to_mut().push_str(&more),
+                (_, token) => result.push(token.into_owned()),
+            }
+        }
+        result
+    }
+    let mut tokenizer = Tokenizer::new();
+    let mut tokens = tokenizer.push(document);
+    assert_eq!(tokens.pop(), Some(text("x")));
+    assert_eq!(tokens, expected);
+    assert_eq!(tokenizer.finish(), None);
+    let mut expected = expected;
+    expected.push(text("x"));
+    // However the input is chunked, the tokens are the same.
+    for size in 1..8 {
+        let mut tokenizer = Tokenizer::new();
+        let mut tokens = Vec::new();
+        let mut rest = document;
+        while !rest.is_empty() {
+            let mut end = size.min(rest.len());
+            while !rest.is_char_boundary(end) {
+                end += 1;
+            }
+            tokens.extend(tokenizer.push(&rest[..end]).into_iter().map(Token::into_owned));
+            rest = &rest[end..];
+        }
+        assert_eq!(merged(tokens), expected);
+        assert_eq!(tokenizer.finish(), None);
+    }
+    let mut tokenizer = Tokenizer::new();
+    assert_eq!(tokenizer.push("<style>p { }</sty"), vec![start("style", vec![], false), text("p { }")]);
+    assert_eq!(tokenizer.push("le><p>"), vec![Token::EndTag("style".into()), start("p", vec![], false)]);
+    assert_eq!(tokenizer.push("a"), vec![text("a")]);
+    assert_eq!(tokenizer.push("b<"), vec![text("b")]);
+    assert_eq!(tokenizer.push("<3 <!-- x"), vec![Token::Text("<<3 ".into())]);
+    assert_eq!(tokenizer.finish(), Some(Token::Text("<!-- x".into())));
+    assert_eq!(Tokenizer::new().finish(), None);
+    let mut tokenizer = Tokenizer::new();
+    let tokens = tokenizer.push("<script>a</scripts>b</script\n>c");
+    assert_eq!(tokens, vec![start("script", vec![], false), text("a</scripts>b"), Token::EndTag("script".into()), text("c")]);
+}
@@ -0,0 +1,530 @@
+//! Log records.
+//!
+//! These parsers read one line of a log file into a record, for three common formats:
+//! `syslog()` reads BSD syslog (RFC 3164) lines, as written to `/var/log/syslog`,
+//! `logfmt()` reads `key=value` pairs, and `common_log()` reads the Common Log Format
+//! of web servers, with the extra fields of the Combined Log Format if they are present.
+//! For example:
+//!
+//! ```
+//! # use parsell::{UncommittedStr,StatefulStr};
+//! # use parsell::formats::logs::common_log;
+//! let line = "127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] \"GET /index.html HTTP/1.0\" 200 2326\n";
+//! let record = common_log().init_str(line).unwrap().unDone().unwrap();
+//! assert_eq!(record.user.unwrap(), "frank");
+//! assert_eq!(record.request, "GET /index.html HTTP/1.0");
+//! assert_eq!(record.status, 200);
+//! assert_eq!(record.size, Some(2326));
+//! ```
+//!
+//! Each parser consumes a line, including its line ending, and produces `None` if the line
+//! is malformed, so a file can be read by repeating a parser. The fields of a record
+//! are borrowed from the input, unless the line is split across chunks, or the field
+//! contains escapes.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted};
+use super::super::ParseResult::{Done, Continue};
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::str::Chars;
+
+/// A log format, which parses a line into a record.
+
+pub trait Format<'a> {
+    /// The type of records.
+    type Record;
+
+    /// Parse a line, without its line ending.
+    fn parse(line: Cow<'a, str>) -> Option<Self::Record>;
+}
+
+/// A BSD syslog record.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyslogRecord<'a> {
+    /// The priority, if the line starts with one, such as `<34>`.
+    pub priority: Option<u8>,
+    /// The timestamp, such as `Oct 11 22:14:15`, or an RFC 3339 timestamp.
+    pub timestamp: Cow<'a, str>,
+    /// The host name.
+    pub hostname: Cow<'a, str>,
+    /// The name of the application, if the message has a tag.
+    pub app: Option<Cow<'a, str>>,
+    /// The process id, if the tag includes one.
+    pub pid: Option<u32>,
+    /// The message, after the tag.
+    pub message: Cow<'a, str>,
+}
+
+impl<'a> SyslogRecord<'a> {
+    /// The facility, given by the priority.
+    pub fn facility(&self) -> Option<u8> {
+        self.priority.map(|priority| priority / 8)
+    }
+
+    /// The severity, given by the priority.
+    pub fn severity(&self) -> Option<u8> {
+        self.priority.map(|priority| priority % 8)
+    }
+}
+
+/// A logfmt record.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct LogfmtRecord<'a> {
+    /// The keys and values, in the order they were given. Keys without values have empty values.
+    pub fields: Vec<(Cow<'a, str>, Cow<'a, str>)>,
+}
+
+impl<'a> LogfmtRecord<'a> {
+    /// The value of the first field with the given key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|&(name, _)| name == key).map(|(_, value)| &**value)
+    }
+}
+
+/// A Common Log Format record.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CommonLogRecord<'a> {
+    /// The client host.
+    pub host: Cow<'a, str>,
+    /// The identity of the client, unless it is `-`.
+    pub ident: Option<Cow<'a, str>>,
+    /// The authenticated user, unless it is `-`.
+    pub user: Option<Cow<'a, str>>,
+    /// The timestamp, without its brackets.
+    pub timestamp: Cow<'a, str>,
+    /// The request line, without its quotes.
+    pub request: Cow<'a, str>,
+    /// The status code.
+    pub status: u16,
+    /// The size of the response in bytes, unless it is `-`.
+    pub size: Option<u64>,
+    /// The referer, in the Combined Log Format.
+    pub referer: Option<Cow<'a, str>>,
+    /// The user agent, in the Combined Log Format.
+    pub user_agent: Option<Cow<'a, str>>,
+}
+
+/// The BSD syslog format.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Syslog;
+
+/// The logfmt format.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Logfmt;
+
+/// The Common Log Format.
+
+#[derive(Copy, Clone, Debug)]
+pub struct CommonLog;
+
+/// A parser for one line of BSD syslog.
+
+pub fn syslog() -> LogLine<Syslog> {
+    LogLine(PhantomData)
+}
+
+/// A parser for one line of logfmt.
+
+pub fn logfmt() -> LogLine<Logfmt> {
+    LogLine(PhantomData)
+}
+
+/// A parser for one line of the Common Log Format.
+
+pub fn common_log() -> LogLine<CommonLog> {
+    LogLine(PhantomData)
+}
+
+// Part of a line, borrowed if the line was.
+
+fn field<'a>(line: &Cow<'a, str>, range: Range<usize>) -> Cow<'a, str> {
+    match *line {
+        Borrowed(line) => Borrowed(&line[range]),
+        Owned(ref line) => Owned(String::from(&line[range])),
+    }
+}
+
+// A position in a line being split into fields.
+
+struct Cursor<'l> {
+    line: &'l str,
+    position: usize,
+}
+
+impl<'l> Cursor<'l> {
+    fn new(line: &'l str) -> Self {
+        Cursor { line, position: 0 }
+    }
+
+    fn rest(&self) -> &'l str {
+        &self.line[self.position..]
+    }
+
+    fn skip_spaces(&mut self) {
+        let rest = self.rest();
+        self.position += rest.len() - rest.trim_start_matches(' ').len();
+    }
+
+    fn expect(&mut self, ch: char) -> Result<(), ()> {
+        if self.rest().starts_with(ch) {
+            self.position += ch.len_utf8();
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+
+    // The text up to the next space, followed by any spaces.
+    fn token(&mut self) -> Result<Range<usize>, ()> {
+        let start = self.position;
+        self.position += self.rest().find(' ').unwrap_or(self.rest().len());
+        let range = start..self.position;
+        self.skip_spaces();
+        if range.start < range.end { Ok(range) } else { Err(()) }
+    }
+
+    // The text up to `close`, which is consumed, skipping characters escaped by a backslash.
+    fn until(&mut self, close: char) -> Result<Range<usize>, ()> {
+        let start = self.position;
+        let mut chars = self.rest().char_indices();
+        while let Some((index, ch)) = chars.next() {
+            if ch == '\\' {
+                chars.next();
+            } else if ch == close {
+                self.position = start + index + ch.len_utf8();
+                return Ok(start..(start + index));
+            }
+        }
+        Err(())
+    }
+
+    // The text of a field surrounded by `open` and `close`, followed by any spaces.
+    fn delimited(&mut self, open: char, close: char) -> Result<Range<usize>, ()> {
+        try!(self.expect(open));
+        let range = try!(self.until(close));
+        self.skip_spaces();
+        Ok(range)
+    }
+}
+
+// The `-` used in Common Log Format for missing fields.
+
+fn present<'a>(line: &Cow<'a, str>, range: Range<usize>) -> Option<Cow<'a, str>> {
+    if &line[range.clone()] == "-" { None } else { Some(field(line, range)) }
+}
+
+// A quoted logfmt value, which is only copied if it contains escapes.
+
+fn unescape<'a>(line: &Cow<'a, str>, range: Range<usize>) -> Cow<'a, str> {
+    if !line[range.clone()].contains('\\') {
+        return field(line, range);
+    }
+    let mut result = String::new();
+    let mut chars = line[range].chars();
+    while let Some(ch) = chars.next() {
+        match (ch, if ch == '\\' { chars.next() } else { None }) {
+            ('\\', Some('n')) => result.push('\n'),
+            ('\\', Some('t')) => result.push('\t'),
+            ('\\', Some(escaped)) => result.push(escaped),
+            (ch, _) => result.push(ch),
+        }
+    }
+    Owned(result)
+}
+
+impl<'a> Format<'a> for Syslog {
+    type Record = SyslogRecord<'a>;
+
+    fn parse(line: Cow<'a, str>) -> Option<SyslogRecord<'a>> {
+        parse_syslog(line).ok()
+    }
+}
+
+impl<'a> Format<'a> for Logfmt {
+    type Record = LogfmtRecord<'a>;
+
+    fn parse(line: Cow<'a, str>) -> Option<LogfmtRecord<'a>> {
+        parse_logfmt(line).ok()
+    }
+}
+
+impl<'a> Format<'a> for CommonLog {
+    type Record = CommonLogRecord<'a>;
+
+    fn parse(line: Cow<'a, str>) -> Option<CommonLogRecord<'a>> {
+        parse_common_log(line).ok()
+    }
+}
+
+fn parse_syslog<'a>(line: Cow<'a, str>) -> Result<SyslogRecord<'a>, ()> {
+    let mut cursor = Cursor::new(&line);
+    let priority = if cursor.rest().starts_with('<') {
+        let range = try!(cursor.until('>'));
+        match line[(range.start + 1)..range.end].parse() {
+            Ok(priority) if priority < 192 => Some(priority),
+            _ => return Err(()),
+        }
+    } else {
+        None
+    };
+    let timestamp = if cursor.rest().starts_with(|ch: char| ch.is_ascii_digit()) {
+        try!(cursor.token())
+    } else {
+        // A timestamp such as `Oct  1 22:14:15`, whose day may be padded with a space.
+        let start = cursor.position;
+        try!(cursor.token());
+        try!(cursor.token());
+        try!(cursor.token());
+        start..(cursor.line[..cursor.position].trim_end().len())
+    };
+    let hostname = try!(cursor.token());
+    let rest = cursor.position;
+    let mut app = None;
+    let mut pid = None;
+    if let Some(end) = cursor.rest().find([':', '[', ' ']) {
+        cursor.position += end;
+        if cursor.rest().starts_with('[') {
+            let range = try!(cursor.until(']'));
+            pid = Some(try!(line[(range.start + 1)..range.end].parse().map_err(|_| ())));
+        }
+        if end > 0 && cursor.expect(':').is_ok() {
+            cursor.skip_spaces();
+            app = Some(rest..(rest + end));
+        } else {
+            cursor.position = rest;
+            pid = None;
+        }
+    }
+    let message = cursor.position..line.len();
+    Ok(SyslogRecord {
+        priority,
+        timestamp: field(&line, timestamp),
+        hostname: field(&line, hostname),
+        app: app.map(|app| field(&line, app)),
+        pid,
+        message: field(&line, message),
+    })
+}
+
+fn parse_logfmt<'a>(line: Cow<'a, str>) -> Result<LogfmtRecord<'a>, ()> {
+    let mut cursor = Cursor::new(&line);
+    let mut fields = Vec::new();
+    cursor.skip_spaces();
+    while !cursor.rest().is_empty() {
+        let start = cursor.position;
+        let end = start + cursor.rest().find(['=', ' ', '"']).unwrap_or(cursor.rest().len());
+        if start == end {
+            return Err(());
+        }
+        cursor.position = end;
+        let value = if cursor.expect('=').is_err() {
+            Borrowed("")
+        } else if cursor.rest().starts_with('"') {
+            let range = try!(cursor.delimited('"', '"'));
+            unescape(&line, range)
+        } else {
+            cursor.token().map(|range| field(&line, range)).unwrap_or(Borrowed(""))
+        };
+        fields.push((field(&line, start..end), value));
+        cursor.skip_spaces();
+    }
+    Ok(LogfmtRecord { fields })
+}
+
+fn parse_common_log<'a>(line: Cow<'a, str>) -> Result<CommonLogRecord<'a>, ()> {
+    let mut cursor = Cursor::new(&line);
+    let host = try!(cursor.token());
+    let ident = try!(cursor.token());
+    let user = try!(cursor.token());
+    let timestamp = try!(cursor.delimited('[', ']'));
+    let request = try!(cursor.delimited('"', '"'));
+    let status = try!(cursor.token());
+    let status = try!(line[status].parse().map_err(|_| ()));
+    let size = try!(cursor.token());
+    let size = if &line[size.clone()] == "-" { None } else { Some(try!(line[size].parse().map_err(|_| ()))) };
+    let (referer, user_agent) = if cursor.rest().is_empty() {
+        (None, None)
+    } else {
+        let referer = try!(cursor.delimited('"', '"'));
+        let user_agent = try!(cursor.delimited('"', '"'));
+        (present(&line, referer), present(&line, user_agent))
+    };
+    if !cursor.rest().is_empty() {
+        return Err(());
+    }
+    Ok(CommonLogRecord {
+        host: field(&line, host),
+        ident: present(&line, ident),
+        user: present(&line, user),
+        timestamp: field(&line, timestamp),
+        request: field(&line, request),
+        status,
+        size,
+        referer,
+        user_agent,
+    })
+}
+
+// Remove a carriage return before the end of a line.
+
+fn strip_cr(line: &str) -> &str {
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct LogLine<F>(PhantomData<F>);
+
+#[derive(Clone, Debug)]
+pub struct LogLineState<F>(String, PhantomData<F>);
+
+impl<F> Parser for LogLine<F> {}
+
+impl<'a, F> Stateful<char, Chars<'a>, Option<F::Record>> for LogLineState<F>
+    where F: Format<'a>,
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Option<F::Record>> {
+        let string0 = string.as_str();
+        match string0.find('\n') {
+            Some(end) => {
+                *string = string0[(end + 1)..].chars();
+                self.0.push_str(&string0[..end]);
+                let length = strip_cr(&self.0).len();
+                self.0.truncate(length);
+                Done(F::parse(Owned(self.0)))
+            },
+            None => {
+                *string = string0[string0.len()..].chars();
+                self.0.push_str(string0);
+                Continue(self)
+            },
+        }
+    }
+
+    fn done(mut self) -> Option<F::Record> {
+        let length = strip_cr(&self.0).len();
+        self.0.truncate(length);
+        F::parse(Owned(self.0))
+    }
+
+}
+
+impl<'a, F> HasOutput<char, Chars<'a>> for LogLineState<F>
+    where F: Format<'a>,
+{
+
+    type Output = Option<F::Record>;
+
+}
+
+impl<'a, F> HasOutput<char, Chars<'a>> for LogLine<F>
+    where F: Format<'a>,
+{
+
+    type Output = Option<F::Record>;
+
+}
+
+impl<'a, F> Uncommitted<char, Chars<'a>, Option<F::Record>> for LogLine<F>
+    where F: Format<'a>,
+{
+
+    type State = LogLineState<F>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Option<F::Record>>> {
+        let string0 = string.as_str();
+        match string0.find('\n') {
+            _ if string0.is_empty() => None,
+            Some(end) => {
+                *string = string0[(end + 1)..].chars();
+                Some(Done(F::parse(Borrowed(strip_cr(&string0[..end])))))
+            },
+            None => {
+                *string = string0[string0.len()..].chars();
+                Some(Continue(LogLineState(String::from(string0), PhantomData)))
+            },
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_syslog() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    let parser = syslog();
+    assert_eq!(parser.init_str(""), None);
+    let record = parser.init_str("<34>Oct  1 22:14:15 mymachine su[230]: 'su root' failed\nnext").unwrap().unDone().unwrap();
+    assert_eq!(record.priority, Some(34));
+    assert_eq!((record.facility(), record.severity()), (Some(4), Some(2)));
+    assert_eq!(record.timestamp, "Oct  1 22:14:15");
+    assert_eq!(record.hostname, "mymachine");
+    assert_eq!(record.app.unwrap(), "su");
+    assert_eq!(record.pid, Some(230));
+    assert_eq!(record.message, "'su root' failed");
+    let record = parser.init_str("2024-05-01T10:00:00Z host kernel: usb 1-1: reset\r\n").unwrap().unDone().unwrap();
+    assert_eq!(record.timestamp, "2024-05-01T10:00:00Z");
+    assert_eq!((record.app.unwrap(), record.pid), (Borrowed("kernel"), None));
+    assert_eq!(record.message, "usb 1-1: reset");
+    let record = parser.init_str("Oct 11 22:14:15 host -- MARK --\n").unwrap().unDone().unwrap();
+    assert_eq!(record.app, None);
+    assert_eq!(record.message, "-- MARK --");
+    assert_eq!(parser.init_str("<999>Oct 11 22:14:15 host x\n").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("Oct 11\n").unwrap().unDone(), None);
+    let parsing = parser.init_str("Oct 11 22:14:15 ho").unwrap().unContinue();
+    match parsing.last_str("st cron[1]: job") {
+        Some(SyslogRecord { hostname: Owned(hostname), .. }) => assert_eq!(hostname, "host"),
+        result => panic!("Unexpected {:?}", result),
+    }
+    testing::assert_chunk_invariant(parser, "<13>Oct 11 22:14:15 host app[7]: hello\n");
+}
+
+#[test]
+fn test_logfmt() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    let parser = logfmt();
+    let record = parser.init_str("level=info msg=\"user \\\"a\\\" logged in\" dur=1.5s debug path=\n").unwrap().unDone().unwrap();
+    assert_eq!(record.get("level"), Some("info"));
+    assert_eq!(record.get("msg"), Some("user \"a\" logged in"));
+    assert_eq!(record.get("debug"), Some(""));
+    assert_eq!(record.get("path"), Some(""));
+    assert_eq!(record.get("missing"), None);
+    assert_eq!(record.fields.len(), 5);
+    match record.fields[0].1 {
+        Borrowed(_) => (),
+        _ => panic!("Expected a borrowed value"),
+    }
+    assert_eq!(parser.init_str("a=\"open\n").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("=x\n").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("a=1").unwrap().unContinue().last_str(" b=2").unwrap().get("b"), Some("2"));
+    testing::assert_chunk_invariant(parser, "at=\"x y\" n=1\n");
+}
+
+#[test]
+fn test_common_log() {
+    use super::super::{UncommittedStr};
+    use super::super::testing;
+    let parser = common_log();
+    let line = "::1 - - [10/Oct/2000:13:55:36 -0700] \"GET /a?q=\\\"x\\\" HTTP/1.1\" 304 - \"-\" \"curl/8.0\"\n";
+    let record = parser.init_str(line).unwrap().unDone().unwrap();
+    assert_eq!(record.host, "::1");
+    assert_eq!((record.ident, record.user), (None, None));
+    assert_eq!(record.timestamp, "10/Oct/2000:13:55:36 -0700");
+    assert_eq!(record.request, "GET /a?q=\\\"x\\\" HTTP/1.1");
+    assert_eq!((record.status, record.size), (304, None));
+    assert_eq!((record.referer, record.user_agent.unwrap()), (None, Borrowed("curl/8.0")));
+    assert_eq!(parser.init_str("h - - [t] \"GET /\" abc 1\n").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("h - - [t] \"GET /\" 200 1 extra\n").unwrap().unDone(), None);
+    testing::assert_chunk_invariant(parser, "h i u [t] \"GET / HTTP/1.0\" 200 12\n");
+}
@@ -0,0 +1,40 @@
+//! Parsers for common data formats.
+//!
+//! These parsers are written using the same combinators and traits as any other
+//! parsell grammar, so they support streaming input, and can be combined with
+//! application-specific parsers.
+
+pub mod ansi;
+pub mod ber;
+#[cfg(feature = "cbor")]
+pub mod cbor;
+pub mod chunked;
+pub mod chunks;
+pub mod email;
+pub mod fixed_width;
+pub mod headers;
+#[cfg(feature = "html")]
+pub mod html;
+pub mod logs;
+pub mod multipart;
+#[cfg(feature = "shell")]
+pub mod shell;
+#[cfg(feature = "sql")]
+pub mod sql;
+pub mod urlencoded;
+pub mod uuid;
+pub mod varint;
+#[cfg(feature = "yaml")]
+pub mod yaml;
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+
+// Part of an input chunk, borrowed if the chunk was.
+
+fn slice<'a>(input: &Cow<'a, [u8]>, from: usize, to: usize) -> Cow<'a, [u8]> {
+    match *input {
+        Borrowed(input) => Borrowed(&input[from..to]),
+        Owned(ref input) => Owned(input[from..to].to_vec()),
+    }
+}
@@ -0,0 +1,346 @@
+//! Multipart bodies.
+//!
+//! A `multipart/form-data` (or other `multipart/*`) body is a sequence of parts separated
+//! by a boundary string, each with its own headers and body. A `Splitter` segments a stream
+//! of bytes into events for the headers and body of each part, borrowing body bytes from
+//! the input wherever possible, and a `Multipart` uses it to run a string parser on the body
+//! of each part, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::formats::multipart::Multipart;
+//! let mut parts = Multipart::new("XyZ", character(char::is_alphanumeric).star(String::new));
+//! let parsed = parts.push(b"--XyZ\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nabc\r\n--Xy");
+//! assert!(parsed.is_empty());
+//! let parsed = parts.push(b"Z\r\n\r\ndef\r\n--XyZ--\r\n");
+//! assert_eq!(parsed[0].headers[0].0, "Content-Disposition");
+//! assert_eq!(parsed[0].body, Some(String::from("abc")));
+//! assert_eq!(parsed[1].headers.len(), 0);
+//! assert_eq!(parsed[1].body, Some(String::from("def")));
+//! ```
+
+use super::super::{Erasable, Stateful, Uncommitted};
+use super::super::ParseResult::{Done, Continue};
+use super::super::session::Driver;
+
+use super::slice;
+use super::headers::header_block;
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::mem;
+use std::str;
+use std::str::Chars;
+
+/// The headers of a part, as name-value pairs in the order they were given.
+
+pub type Headers = Vec<(String, String)>;
+
+/// An event produced by a `Splitter`.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Event<'a> {
+    /// The start of a part, with its headers.
+    Headers(Headers),
+    /// Some of the body of the current part.
+    Body(Cow<'a, [u8]>),
+    /// The end of the current part.
+    PartEnd,
+    /// The end of the multipart body. Any remaining input is ignored.
+    End,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Start,
+    Preamble,
+    Delimiter,
+    Headers,
+    Body,
+    Epilogue,
+}
+
+/// A splitter for multipart bodies.
+
+#[derive(Clone, Debug)]
+pub struct Splitter {
+    // The delimiter, which is CRLF followed by `--` and the boundary.
+    delimiter: Vec<u8>,
+    phase: Phase,
+    pending: Vec<u8>,
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+// The length of the longest proper prefix of `needle` which is a suffix of `haystack`.
+fn partial(haystack: &[u8], needle: &[u8]) -> usize {
+    let longest = if haystack.len() < needle.len() { haystack.len() } else { needle.len() - 1 };
+    (1..(longest + 1)).rev().find(|&len| haystack.ends_with(&needle[..len])).unwrap_or(0)
+}
+
+pub(crate) fn parse_headers(bytes: &[u8]) -> Headers {
+    let text = String::from_utf8_lossy(bytes);
+    let mut data = text.chars();
+    match header_block(Vec::new).init(&mut data) {
+        Some(Done(headers)) => headers,
+        Some(Continue(state)) => Stateful::<char, Chars, Headers>::done(state),
+        None => Vec::new(),
+    }
+}
+
+impl Splitter {
+    /// A splitter for parts separated by `boundary`.
+    pub fn new(boundary: &str) -> Self {
+        Splitter {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            phase: Phase::Start,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Feed a chunk of input, returning the events it completes.
+    ///
+    /// Body bytes which might be the start of a delimiter are kept until the next chunk.
+    pub fn push<'a>(&mut self, chunk: &'a [u8]) -> Vec<Event<'a>> {
+        let input: Cow<'a, [u8]> = if self.pending.is_empty() {
+            Borrowed(chunk)
+        } else {
+            let mut input = mem::take(&mut self.pending);
+            input.extend_from_slice(chunk);
+            Owned(input)
+        };
+        let mut events = Vec::new();
+        let mut position = 0;
+        loop {
+            let rest = &input[position..];
+            match self.phase {
+                Phase::Start => {
+                    // The first delimiter need not be preceded by CRLF.
+                    let first = &self.delimiter[2..];
+                    if rest.len() < first.len() && first.starts_with(rest) {
+                        break;
+                    } else if rest.starts_with(first) {
+                        position += first.len();
+                        self.phase = Phase::Delimiter;
+                    } else {
+                        self.phase = Phase::Preamble;
+                    }
+                },
+                Phase::Preamble | Phase::Body => match find(rest, &self.delimiter) {
+                    Some(index) => {
+                        if self.phase == Phase::Body {
+                            if index > 0 {
+                                events.push(Event::Body(slice(&input, position, position + index)));
+                            }
+                            events.push(Event::PartEnd);
+                        }
+                        position += index + self.delimiter.len();
+                        self.phase = Phase::Delimiter;
+                    },
+                    None => {
+                        let end = input.len() - partial(rest, &self.delimiter);
+                        if self.phase == Phase::Body && end > position {
+                            events.push(Event::Body(slice(&input, position, end)));
+                        }
+                        position = end;
+                        break;
+                    },
+                },
+                Phase::Delimiter => {
+                    if rest.len() < 2 {
+                        break;
+                    } else if rest.starts_with(b"--") {
+                        events.push(Event::End);
+                        self.phase = Phase::Epilogue;
+                    } else {
+                        // Skip any transport padding after the boundary.
+                        match find(rest, b"\r\n") {
+                            Some(index) => {
+                                position += index + 2;
+                                self.phase = Phase::Headers;
+                            },
+                            None => break,
+                        }
+                    }
+                },
+                Phase::Headers => {
+                    if rest.starts_with(b"\r\n") {
+                        events.push(Event::Headers(Vec::new()));
+                        position += 2;
+                        self.phase = Phase::Body;
+                    } else if rest.len() < 2 {
+                        break;
+                    } else {
+                        match find(rest, b"\r\n\r\n") {
+                            Some(index) => {
+                                events.push(Event::Headers(parse_headers(&rest[..index])));
+                                position += index + 4;
+                                self.phase = Phase::Body;
+                            },
+                            None => break,
+                        }
+                    }
+                },
+                Phase::Epilogue => {
+                    position = input.len();
+                    break;
+                },
+            }
+        }
+        self.pending.extend_from_slice(&input[position..]);
+        events
+    }
+
+    /// Whether the closing delimiter has been seen.
+    pub fn is_finished(&self) -> bool {
+        self.phase == Phase::Epilogue
+    }
+}
+
+/// A part of a multipart body, with the result of parsing its body.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Part<Output> {
+    /// The headers of the part.
+    pub headers: Headers,
+    /// The result of parsing the body, or `None` if the parser rejected it.
+    pub body: Option<Output>,
+}
+
+/// A driver which runs a string parser on the body of each part of a multipart body.
+///
+/// The body of each part is decoded as UTF-8, replacing invalid sequences.
+/// If the parser finishes before the end of a part, the rest of the part is ignored.
+/// As in a `Session`, a parser which succeeds without consuming any input is treated as rejecting it.
+
+pub struct Multipart<Output> {
+    splitter: Splitter,
+    parser: Box<Erasable<Output>>,
+    headers: Option<Headers>,
+    // Whether the parser has been started on the current part.
+    started: bool,
+    driver: Driver<Output>,
+    result: Option<Output>,
+    partial: Vec<u8>,
+}
+
+impl<Output> Multipart<Output> {
+    /// A driver for parts separated by `boundary`, whose bodies are parsed by `parser`.
+    pub fn new<P>(boundary: &str, parser: P) -> Self where P: 'static + Erasable<Output> {
+        Multipart {
+            splitter: Splitter::new(boundary),
+            parser: Box::new(parser),
+            headers: None,
+            started: false,
+            driver: Driver::new(),
+            result: None,
+            partial: Vec::new(),
+        }
+    }
+
+    /// Feed a chunk of input, returning the parts it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<Part<Output>> {
+        let mut parts = Vec::new();
+        for event in self.splitter.push(chunk) {
+            match event {
+                Event::Headers(headers) => {
+                    self.headers = Some(headers);
+                    self.started = false;
+                    self.partial.clear();
+                },
+                Event::Body(bytes) => {
+                    self.partial.extend_from_slice(&bytes);
+                    let valid = match str::from_utf8(&self.partial) {
+                        Ok(string) => string.len(),
+                        Err(err) => err.valid_up_to() + err.error_len().unwrap_or(0),
+                    };
+                    let text = String::from_utf8_lossy(&self.partial[..valid]).into_owned();
+                    self.partial.drain(..valid);
+                    self.parse(&text);
+                },
+                Event::PartEnd => {
+                    if !self.partial.is_empty() {
+                        let text = String::from_utf8_lossy(&self.partial).into_owned();
+                        self.partial.clear();
+                        self.parse(&text);
+                    }
+                    let body = match self.driver.finish() {
+                        Some(body) => Some(body),
+                        None => self.result.take(),
+                    };
+                    parts.push(Part { headers: self.headers.take().unwrap_or_default(), body });
+                },
+                Event::End => (),
+            }
+        }
+        parts
+    }
+
+    /// Whether the closing delimiter has been seen.
+    pub fn is_finished(&self) -> bool {
+        self.splitter.is_finished()
+    }
+
+    fn parse(&mut self, text: &str) {
+        if text.is_empty() || (self.started && !self.driver.is_waiting()) {
+            return;
+        }
+        self.started = true;
+        if let Ok(Some(result)) = self.driver.step(&*self.parser, &mut text.chars()) {
+            self.result = Some(result);
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_splitter() {
+    const BODY: &[u8] = b"preamble\r\n--b\r\nA: 1\r\nB:2\r\n\r\nx\r\n--\r\ny\r\n--b  \r\n\r\n\r\n--b--\r\nepilogue";
+    let mut splitter = Splitter::new("b");
+    let events = splitter.push(BODY);
+    assert_eq!(events, vec![
+        Event::Headers(vec![(String::from("A"), String::from("1")), (String::from("B"), String::from("2"))]),
+        Event::Body(Borrowed(b"x\r\n--\r\ny")),
+        Event::PartEnd,
+        Event::Headers(vec![]),
+        Event::PartEnd,
+        Event::End,
+    ]);
+    assert!(splitter.is_finished());
+    for split in 0..BODY.len() {
+        let mut splitter = Splitter::new("b");
+        let mut chunked = splitter.push(&BODY[..split]);
+        chunked.extend(splitter.push(&BODY[split..]));
+        let mut body = Vec::new();
+        let mut others = Vec::new();
+        for event in chunked {
+            match event {
+                Event::Body(bytes) => body.extend_from_slice(&bytes),
+                event => others.push(event),
+            }
+        }
+        assert_eq!(body, b"x\r\n--\r\ny", "Split at {}", split);
+        assert_eq!(others.len(), 5, "Split at {}", split);
+    }
+}
+
+#[test]
+fn test_multipart() {
+    use super::super::{character, Parser};
+    let mut parts = Multipart::new("b", character(char::is_alphabetic).plus(String::new));
+    let parsed = parts.push("--b\r\n\r\nd\u{e9}f\r\n--b\r\n\r\n123\r\n--b\r\n\r\n\r\n--b--".as_bytes());
+    assert_eq!(parsed, vec![
+        Part { headers: vec![], body: Some(String::from("d\u{e9}f")) },
+        Part { headers: vec![], body: None },
+        Part { headers: vec![], body: None },
+    ]);
+    assert!(parts.is_finished());
+    let mut parts = Multipart::new("b", character(char::is_alphabetic).plus(String::new));
+    let body = "--b\r\n\r\nd\u{e9}f\r\n--b--".as_bytes();
+    let mut parsed = parts.push(&body[..9]);
+    parsed.extend(parts.push(&body[9..]));
+    assert_eq!(parsed, vec![Part { headers: vec![], body: Some(String::from("d\u{e9}f")) }]);
+}
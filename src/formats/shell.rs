@@ -0,0 +1,181 @@
+//! Shell-style words.
+//!
+//! This module is only available with the `shell` feature.
+//! Words are split and unquoted following POSIX shell rules: single quotes preserve
+//! everything up to the closing quote, double quotes preserve everything except
+//! backslash escapes of `$`, `` ` ``, `"`, `\` and newline, and an unquoted backslash
+//! preserves the next character. For example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr};
+//! # use parsell::formats::shell::word;
+//! fn ignore() {}
+//! let words = word().and_then_discard(character(char::is_whitespace).star(ignore)).star(Vec::new);
+//! let result = words.init_str(r#"grep -e 'a b' "\$HOME" c\ d;"#).unwrap().unDone();
+//! assert_eq!(result, vec!["grep", "-e", "a b", "$HOME", "c d"]);
+//! ```
+//!
+//! Words end at unquoted whitespace or one of the shell metacharacters `;&|<>()`,
+//! which are not consumed. Unquoted words are borrowed from the input, unless they are
+//! split across chunks. Expansions such as `$HOME` are not performed, and quotes which
+//! are still open at the end of input are treated as closed.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted};
+use super::super::ParseResult::{Done, Continue};
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::str::Chars;
+
+/// A parser for one shell word, producing the word with its quoting removed.
+
+pub fn word() -> ShellWord {
+    ShellWord
+}
+
+// The quoting context.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Unquoted,
+    Single,
+    Double,
+    Escape,
+    DoubleEscape,
+}
+
+fn is_terminator(ch: char) -> bool {
+    ch.is_whitespace() || ";&|<>()".contains(ch)
+}
+
+// Run the tokenizer as far as it will go, returning whether the word has ended.
+// The word is only copied into a buffer once it contains quoting.
+
+fn run(phase: &mut Phase, word: &mut Option<String>, string: &mut Chars) -> bool {
+    let string0 = string.as_str();
+    loop {
+        let ch = match string.clone().next() {
+            None => return false,
+            Some(ch) if *phase == Phase::Unquoted && is_terminator(ch) => return true,
+            Some(ch) => ch,
+        };
+        let consumed = string0.len() - string.as_str().len();
+        string.next();
+        if *phase == Phase::Unquoted && word.is_none() && "'\"\\".contains(ch) {
+            *word = Some(String::from(&string0[..consumed]));
+        }
+        *phase = match (*phase, ch) {
+            (Phase::Unquoted, '\'') => Phase::Single,
+            (Phase::Unquoted, '"') => Phase::Double,
+            (Phase::Unquoted, '\\') => Phase::Escape,
+            (Phase::Single, '\'') | (Phase::Double, '"') => Phase::Unquoted,
+            (Phase::Double, '\\') => Phase::DoubleEscape,
+            (Phase::Escape, '\n') => Phase::Unquoted,
+            (Phase::DoubleEscape, '\n') => Phase::Double,
+            (phase, _) => {
+                if let Some(ref mut word) = *word {
+                    if phase == Phase::DoubleEscape && !"$`\"\\".contains(ch) {
+                        word.push('\\');
+                    }
+                    word.push(ch);
+                }
+                match phase {
+                    Phase::Escape => Phase::Unquoted,
+                    Phase::DoubleEscape => Phase::Double,
+                    phase => phase,
+                }
+            },
+        };
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ShellWord;
+
+#[derive(Clone, Debug)]
+pub struct ShellWordState(Phase, String);
+
+impl Parser for ShellWord {}
+
+impl<'a> Stateful<char, Chars<'a>, Cow<'a, str>> for ShellWordState {
+
+    fn more(self, string: &mut Chars<'a>) -> ParseResult<Self, Cow<'a, str>> {
+        let mut phase = self.0;
+        let mut word = Some(self.1);
+        let ended = run(&mut phase, &mut word, string);
+        let word = word.unwrap();
+        if ended {
+            Done(Owned(word))
+        } else {
+            Continue(ShellWordState(phase, word))
+        }
+    }
+
+    fn done(self) -> Cow<'a, str> {
+        Owned(self.1)
+    }
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for ShellWordState {
+
+    type Output = Cow<'a, str>;
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for ShellWord {
+
+    type Output = Cow<'a, str>;
+
+}
+
+impl<'a> Uncommitted<char, Chars<'a>, Cow<'a, str>> for ShellWord {
+
+    type State = ShellWordState;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Cow<'a, str>>> {
+        match string.clone().next() {
+            None => return None,
+            Some(ch) if is_terminator(ch) => return None,
+            Some(_) => (),
+        }
+        let string0 = string.as_str();
+        let mut phase = Phase::Unquoted;
+        let mut word = None;
+        let ended = run(&mut phase, &mut word, string);
+        let consumed = &string0[..(string0.len() - string.as_str().len())];
+        match (ended, word) {
+            (true, None) => Some(Done(Borrowed(consumed))),
+            (true, Some(word)) => Some(Done(Owned(word))),
+            (false, word) => Some(Continue(ShellWordState(phase, word.unwrap_or_else(|| String::from(consumed))))),
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_shell_word() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    let parser = word();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str(" a"), None);
+    assert_eq!(parser.init_str(";"), None);
+    match parser.init_str("abc def").unwrap().unDone() {
+        Borrowed(word) => assert_eq!(word, "abc"),
+        Owned(word) => panic!("Unexpected copy of {}", word),
+    }
+    assert_eq!(parser.init_str("a'b c'd e").unwrap().unDone(), "ab cd");
+    assert_eq!(parser.init_str("'' ").unwrap().unDone(), "");
+    assert_eq!(parser.init_str("\"a\\\"\\n\\\\\"|").unwrap().unDone(), "a\"\\n\\");
+    assert_eq!(parser.init_str("a\\\nb c").unwrap().unDone(), "ab");
+    assert_eq!(parser.init_str("a\\;b;").unwrap().unDone(), "a;b");
+    assert_eq!(parser.init_str("a>b").unwrap().unDone(), "a");
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.last_str("c d"), "abc");
+    let parsing = parser.init_str("'a ").unwrap().unContinue();
+    assert_eq!(parsing.last_str("b"), "a b");
+    testing::assert_chunk_invariant(parser, "x\"y \\$z\"'w'\\ v ");
+}
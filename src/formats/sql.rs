@@ -0,0 +1,401 @@
+//! SQL tokens.
+//!
+//! This module is only available with the `sql` feature.
+//! The parser `token()` reads one token of a SQL-like lexical grammar, as used by
+//! PostgreSQL and most other databases: words, numbers, punctuation, whitespace, comments,
+//! strings in single quotes, identifiers in double quotes, and dollar-quoted strings.
+//! Runs of tokens can be read with `token().star(Vec::new)`, for example:
+//!
+//! ```
+//! # use parsell::{Parser,UncommittedStr,StatefulStr};
+//! # use parsell::formats::sql::{token,Token};
+//! let parsing = token().star(Vec::new).init_str("SELECT 'it''s' -- x\n FROM \"T").unwrap().unContinue();
+//! let tokens: Vec<Token> = parsing.finish_str("\"\"1\"").into_iter()
+//!     .filter(|token| !token.is_trivia())
+//!     .collect();
+//! assert_eq!(tokens, vec![
+//!     Token::Word("SELECT".into()),
+//!     Token::StringLiteral("it's".into()),
+//!     Token::Word("FROM".into()),
+//!     Token::QuotedIdentifier("T\"1".into()),
+//! ]);
+//! ```
+//!
+//! Quotes are doubled to include them in strings and quoted identifiers, which are given
+//! with their quoting removed. A dollar-quoted string such as `$fn$ SELECT 'x' $fn$` ends at
+//! the next occurrence of its opening delimiter, and its body is given as written.
+//! Line comments run to the end of the line, and block comments may be nested.
+//! Tokens are borrowed from the input, unless they are split across chunks or contain
+//! doubled quotes. Strings and comments which are still open at the end of input are treated
+//! as closed, and other characters are read as punctuation.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted};
+use super::super::ParseResult::{Done, Continue};
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::mem;
+use std::ops::Range;
+use std::str::Chars;
+
+/// A parser for one SQL token.
+
+pub fn token() -> SqlToken {
+    SqlToken
+}
+
+/// A SQL token.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Token<'a> {
+    /// A run of whitespace.
+    Whitespace(Cow<'a, str>),
+    /// A comment, including its delimiters.
+    Comment(Cow<'a, str>),
+    /// A keyword or unquoted identifier, as written.
+    Word(Cow<'a, str>),
+    /// An identifier in double quotes, with its quoting removed.
+    QuotedIdentifier(Cow<'a, str>),
+    /// A string in single quotes, with its quoting removed.
+    StringLiteral(Cow<'a, str>),
+    /// A dollar-quoted string, with the tag between its dollar signs, which may be empty.
+    DollarQuoted { tag: Cow<'a, str>, body: Cow<'a, str> },
+    /// A number, as written.
+    Number(Cow<'a, str>),
+    /// A positional parameter, such as `$1`.
+    Parameter(Cow<'a, str>),
+    /// An operator or other punctuation, such as `(` or `<=`.
+    Punctuation(Cow<'a, str>),
+}
+
+impl<'a> Token<'a> {
+    /// Whether this token is whitespace or a comment.
+    pub fn is_trivia(&self) -> bool {
+        matches!(*self, Token::Whitespace(_) | Token::Comment(_))
+    }
+}
+
+// How much of the token has been read.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    Start,
+    Whitespace,
+    Word,
+    Number,
+    Punctuation,
+    // After a character which may start a two-character operator.
+    Operator(char),
+    Minus,
+    Slash,
+    LineComment,
+    BlockComment,
+    BlockStar,
+    BlockSlash,
+    String,
+    StringQuote,
+    Identifier,
+    IdentifierQuote,
+    Dollar,
+    DollarTag,
+    DollarBody,
+    Parameter,
+}
+
+// What to do with the next character.
+
+enum Action {
+    Consume,
+    ConsumeAndEnd,
+    End,
+}
+
+const OPERATORS: &[&str] = &["<=", ">=", "<>", "!=", "::", "||", "=>"];
+
+fn is_word_start(ch: char) -> bool {
+    ch.is_alphabetic() || ch == '_'
+}
+
+fn is_word(ch: char) -> bool {
+    ch.is_alphanumeric() || ch == '_'
+}
+
+// Part of a token, borrowed if the token was.
+
+fn slice<'a>(text: &Cow<'a, str>, range: Range<usize>) -> Cow<'a, str> {
+    match *text {
+        Borrowed(text) => Borrowed(&text[range]),
+        Owned(ref text) => Owned(String::from(&text[range])),
+    }
+}
+
+// The text between quotes, with doubled quotes undone.
+
+fn unquote<'a>(text: &Cow<'a, str>, quote: char, closed: bool) -> Cow<'a, str> {
+    let end = if closed { text.len() - 1 } else { text.len() };
+    let inner = slice(text, 1..end);
+    let doubled: String = vec![quote, quote].into_iter().collect();
+    if inner.contains(&*doubled) {
+        Owned(inner.replace(&*doubled, &quote.to_string()))
+    } else {
+        inner
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SqlToken;
+
+#[derive(Clone, Debug)]
+pub struct SqlTokenState {
+    phase: Phase,
+    // The text of the token, once it is split across chunks.
+    buffer: String,
+    // The tag of a dollar-quoted string, and how much of its closing delimiter has been read.
+    tag: String,
+    matched: usize,
+    // The nesting depth of a block comment.
+    depth: usize,
+}
+
+impl SqlTokenState {
+    fn new() -> Self {
+        SqlTokenState {
+            phase: Phase::Start,
+            buffer: String::new(),
+            tag: String::new(),
+            matched: 0,
+            depth: 0,
+        }
+    }
+
+    fn step(&mut self, ch: char) -> Action {
+        let (phase, action) = match (self.phase, ch) {
+            (Phase::Start, ch) if ch.is_whitespace() => (Phase::Whitespace, Action::Consume),
+            (Phase::Start, '-') => (Phase::Minus, Action::Consume),
+            (Phase::Start, '/') => (Phase::Slash, Action::Consume),
+            (Phase::Start, '\'') => (Phase::String, Action::Consume),
+            (Phase::Start, '"') => (Phase::Identifier, Action::Consume),
+            (Phase::Start, '$') => (Phase::Dollar, Action::Consume),
+            (Phase::Start, ch) if ch.is_ascii_digit() => (Phase::Number, Action::Consume),
+            (Phase::Start, ch) if is_word_start(ch) => (Phase::Word, Action::Consume),
+            (Phase::Start, ch) if OPERATORS.iter().any(|op| op.starts_with(ch)) => (Phase::Operator(ch), Action::Consume),
+            (Phase::Start, _) => (Phase::Punctuation, Action::ConsumeAndEnd),
+            (Phase::Whitespace, ch) if ch.is_whitespace() => (Phase::Whitespace, Action::Consume),
+            (Phase::Word, ch) if is_word(ch) || ch == '$' => (Phase::Word, Action::Consume),
+            (Phase::Number, ch) if ch.is_alphanumeric() || ch == '.' => (Phase::Number, Action::Consume),
+            (Phase::Operator(first), ch) if OPERATORS.iter().any(|op| op.starts_with(first) && op.ends_with(ch)) => {
+                (Phase::Punctuation, Action::ConsumeAndEnd)
+            },
+            (Phase::Operator(_), _) => (Phase::Punctuation, Action::End),
+            (Phase::Minus, '-') => (Phase::LineComment, Action::Consume),
+            (Phase::Minus, _) => (Phase::Punctuation, Action::End),
+            (Phase::Slash, '*') => {
+                self.depth = 1;
+                (Phase::BlockComment, Action::Consume)
+            },
+            (Phase::Slash, _) => (Phase::Punctuation, Action::End),
+            (Phase::LineComment, '\n') => (Phase::LineComment, Action::End),
+            (Phase::LineComment, _) => (Phase::LineComment, Action::Consume),
+            (Phase::BlockStar, '/') => {
+                self.depth -= 1;
+                if self.depth == 0 {
+                    (Phase::BlockComment, Action::ConsumeAndEnd)
+                } else {
+                    (Phase::BlockComment, Action::Consume)
+                }
+            },
+            (Phase::BlockSlash, '*') => {
+                self.depth += 1;
+                (Phase::BlockComment, Action::Consume)
+            },
+            (Phase::BlockComment, '*') | (Phase::BlockStar, '*') => (Phase::BlockStar, Action::Consume),
+            (Phase::BlockComment, '/') | (Phase::BlockSlash, '/') => (Phase::BlockSlash, Action::Consume),
+            (Phase::BlockComment, _) | (Phase::BlockStar, _) | (Phase::BlockSlash, _) => (Phase::BlockComment, Action::Consume),
+            (Phase::String, '\'') => (Phase::StringQuote, Action::Consume),
+            (Phase::String, _) | (Phase::StringQuote, '\'') => (Phase::String, Action::Consume),
+            (Phase::Identifier, '"') => (Phase::IdentifierQuote, Action::Consume),
+            (Phase::Identifier, _) | (Phase::IdentifierQuote, '"') => (Phase::Identifier, Action::Consume),
+            (Phase::Dollar, ch) if ch.is_ascii_digit() => (Phase::Parameter, Action::Consume),
+            (Phase::Dollar, ch) if is_word_start(ch) => {
+                self.tag.push(ch);
+                (Phase::DollarTag, Action::Consume)
+            },
+            (Phase::Dollar, '$') | (Phase::DollarTag, '$') => (Phase::DollarBody, Action::Consume),
+            (Phase::Dollar, _) => (Phase::Punctuation, Action::End),
+            (Phase::DollarTag, ch) if is_word(ch) => {
+                self.tag.push(ch);
+                (Phase::DollarTag, Action::Consume)
+            },
+            (Phase::DollarTag, _) => (Phase::Word, Action::End),
+            (Phase::DollarBody, ch) => {
+                // The tag contains no `$`, so a failed match can only restart at a `$`.
+                let expected = if self.matched == 0 || self.matched == self.tag.len() + 1 {
+                    Some('$')
+                } else {
+                    self.tag[(self.matched - 1)..].chars().next()
+                };
+                if expected == Some(ch) {
+                    self.matched += ch.len_utf8();
+                } else {
+                    self.matched = if ch == '$' { 1 } else { 0 };
+                }
+                if self.matched == self.tag.len() + 2 {
+                    (Phase::DollarBody, Action::ConsumeAndEnd)
+                } else {
+                    (Phase::DollarBody, Action::Consume)
+                }
+            },
+            (Phase::Parameter, ch) if ch.is_ascii_digit() => (Phase::Parameter, Action::Consume),
+            (phase, _) => (phase, Action::End),
+        };
+        self.phase = phase;
+        action
+    }
+
+    // The token, given its text.
+    fn token<'a>(self, text: Cow<'a, str>) -> Token<'a> {
+        match self.phase {
+            Phase::Start | Phase::Punctuation | Phase::Operator(_) | Phase::Minus | Phase::Slash | Phase::Dollar => Token::Punctuation(text),
+            Phase::Whitespace => Token::Whitespace(text),
+            Phase::Word | Phase::DollarTag => Token::Word(text),
+            Phase::Number => Token::Number(text),
+            Phase::LineComment | Phase::BlockComment | Phase::BlockStar | Phase::BlockSlash => Token::Comment(text),
+            Phase::String => Token::StringLiteral(unquote(&text, '\'', false)),
+            Phase::StringQuote => Token::StringLiteral(unquote(&text, '\'', true)),
+            Phase::Identifier => Token::QuotedIdentifier(unquote(&text, '"', false)),
+            Phase::IdentifierQuote => Token::QuotedIdentifier(unquote(&text, '"', true)),
+            Phase::DollarBody => {
+                let delimiter = self.tag.len() + 2;
+                let end = if self.matched == delimiter { text.len() - delimiter } else { text.len() };
+                Token::DollarQuoted {
+                    tag: slice(&text, 1..(delimiter - 1)),
+                    body: slice(&text, delimiter..end),
+                }
+            },
+            Phase::Parameter => Token::Parameter(text),
+        }
+    }
+}
+
+// Run the tokenizer as far as it will go, returning whether the token has ended.
+
+fn run(state: &mut SqlTokenState, string: &mut Chars) -> bool {
+    loop {
+        let ch = match string.clone().next() {
+            None => return false,
+            Some(ch) => ch,
+        };
+        match state.step(ch) {
+            Action::Consume => {
+                string.next();
+            },
+            Action::ConsumeAndEnd => {
+                string.next();
+                return true;
+            },
+            Action::End => return true,
+        }
+    }
+}
+
+impl Parser for SqlToken {}
+
+impl<'a> Stateful<char, Chars<'a>, Token<'a>> for SqlTokenState {
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Token<'a>> {
+        let string0 = string.as_str();
+        let ended = run(&mut self, string);
+        self.buffer.push_str(&string0[..(string0.len() - string.as_str().len())]);
+        if ended {
+            let text = mem::take(&mut self.buffer);
+            Done(self.token(Owned(text)))
+        } else {
+            Continue(self)
+        }
+    }
+
+    fn done(mut self) -> Token<'a> {
+        let text = mem::take(&mut self.buffer);
+        self.token(Owned(text))
+    }
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for SqlTokenState {
+
+    type Output = Token<'a>;
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for SqlToken {
+
+    type Output = Token<'a>;
+
+}
+
+impl<'a> Uncommitted<char, Chars<'a>, Token<'a>> for SqlToken {
+
+    type State = SqlTokenState;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Token<'a>>> {
+        if string.as_str().is_empty() {
+            return None;
+        }
+        let string0 = string.as_str();
+        let mut state = SqlTokenState::new();
+        let ended = run(&mut state, string);
+        let text = &string0[..(string0.len() - string.as_str().len())];
+        if ended {
+            Some(Done(state.token(Borrowed(text))))
+        } else {
+            state.buffer.push_str(text);
+            Some(Continue(state))
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_sql_token() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    fn tokens<'a>(input: &'a str) -> Vec<Token<'a>> {
+        token().star(Vec::new).init_str(input).unwrap().unContinue().finish_str("")
+    }
+    fn punctuation(text: &'static str) -> Token<'static> {
+        Token::Punctuation(Borrowed(text))
+    }
+    let parser = token();
+    assert_eq!(parser.init_str(""), None);
+    match parser.init_str("select*").unwrap().unDone() {
+        Token::Word(Borrowed("select")) => (),
+        result => panic!("Unexpected {:?}", result),
+    }
+    assert_eq!(tokens("a<=b-c/d::e"), vec![
+        Token::Word("a".into()), punctuation("<="), Token::Word("b".into()), punctuation("-"),
+        Token::Word("c".into()), punctuation("/"), Token::Word("d".into()), punctuation("::"),
+        Token::Word("e".into()),
+    ]);
+    assert_eq!(tokens("$1 < 3.5e2"), vec![
+        Token::Parameter("$1".into()), Token::Whitespace(" ".into()), punctuation("<"),
+        Token::Whitespace(" ".into()), Token::Number("3.5e2".into()),
+    ]);
+    assert_eq!(tokens("'a''b'''"), vec![Token::StringLiteral("a'b'".into())]);
+    assert_eq!(tokens("\"x\"\"y\"z"), vec![Token::QuotedIdentifier("x\"y".into()), Token::Word("z".into())]);
+    assert_eq!(tokens("'open"), vec![Token::StringLiteral("open".into())]);
+    assert_eq!(tokens("--a\nb"), vec![Token::Comment("--a".into()), Token::Whitespace("\n".into()), Token::Word("b".into())]);
+    assert_eq!(tokens("/* a /* b */ c */d"), vec![Token::Comment("/* a /* b */ c */".into()), Token::Word("d".into())]);
+    assert_eq!(tokens("/*/ */x"), vec![Token::Comment("/*/ */".into()), Token::Word("x".into())]);
+    assert_eq!(tokens("$fn$ a $f $fn $$fn$;"), vec![
+        Token::DollarQuoted { tag: "fn".into(), body: " a $f $fn $".into() },
+        punctuation(";"),
+    ]);
+    assert_eq!(tokens("$$it's$$"), vec![Token::DollarQuoted { tag: "".into(), body: "it's".into() }]);
+    assert_eq!(tokens("$$open$"), vec![Token::DollarQuoted { tag: "".into(), body: "open$".into() }]);
+    assert_eq!(tokens("$x y"), vec![Token::Word("$x".into()), Token::Whitespace(" ".into()), Token::Word("y".into())]);
+    let parsing = parser.init_str("'it'").unwrap().unContinue();
+    assert_eq!(parsing.more_str("'s' ").unDone(), Token::StringLiteral("it's".into()));
+    testing::assert_chunk_invariant(token().star(Vec::new), "SELECT \"a\"\"b\", 'c''d' -- e\nFROM t /* f */ WHERE x <> $1 AND y = $q$ g $ h $q$;");
+}
@@ -0,0 +1,225 @@
+//! Query strings and form data.
+//!
+//! The `application/x-www-form-urlencoded` format, as used by URL query strings and HTML forms,
+//! is a sequence of `key=value` pairs separated by `&`, where `+` stands for a space
+//! and `%XX` for a byte in hexadecimal. The parser `form_pair()` reads one pair, and decodes
+//! its key and value, for example:
+//!
+//! ```
+//! # use parsell::{Parser,UncommittedStr,StatefulStr};
+//! # use parsell::formats::urlencoded::form_pair;
+//! let parser = form_pair().star(Vec::new);
+//! let parsing = parser.init_str("a=1&b=two%2").unwrap().unContinue();
+//! let pairs = parsing.more_str("0words+%26+more#top").unDone();
+//! assert_eq!(pairs[0], ("a".into(), "1".into()));
+//! assert_eq!(pairs[1], ("b".into(), "two words & more".into()));
+//! ```
+//!
+//! A pair ends at a `&`, which is consumed, or before a `#`, whitespace or the end of input.
+//! A pair without `=` has an empty value. The key and value are borrowed from the input,
+//! unless the pair is split across chunks or contains escapes. Escapes are decoded as they
+//! are read, even if they are split across chunks, and are kept as they are if they are not
+//! followed by two hex digits. Decoded bytes which are not UTF-8 are replaced by `U+FFFD`.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted};
+use super::super::ParseResult::{Done, Continue};
+
+use std::borrow::Cow;
+use std::borrow::Cow::{Borrowed, Owned};
+use std::mem;
+use std::str::Chars;
+
+/// A parser for one `key=value` pair.
+
+pub fn form_pair() -> FormPair {
+    FormPair
+}
+
+fn is_terminator(ch: char) -> bool {
+    ch == '#' || ch.is_whitespace()
+}
+
+fn decoded(bytes: Vec<u8>) -> Cow<'static, str> {
+    match String::from_utf8(bytes) {
+        Ok(text) => Owned(text),
+        Err(err) => Owned(String::from_utf8_lossy(err.as_bytes()).into_owned()),
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FormPair;
+
+#[derive(Clone, Debug)]
+pub struct FormPairState {
+    key: Vec<u8>,
+    value: Vec<u8>,
+    in_value: bool,
+    // The text of an escape which has started, such as `%` or `%4`.
+    escape: String,
+}
+
+impl FormPairState {
+    fn new() -> Self {
+        FormPairState {
+            key: Vec::new(),
+            value: Vec::new(),
+            in_value: false,
+            escape: String::new(),
+        }
+    }
+
+    fn push(&mut self, bytes: &[u8]) {
+        if self.in_value {
+            self.value.extend_from_slice(bytes);
+        } else {
+            self.key.extend_from_slice(bytes);
+        }
+    }
+
+    // Keep an unfinished escape as it was written.
+    fn flush(&mut self) {
+        let escape = mem::take(&mut self.escape);
+        self.push(escape.as_bytes());
+    }
+
+    // Decode a character of the pair.
+    fn step(&mut self, ch: char) {
+        if !self.escape.is_empty() {
+            if ch.is_ascii_hexdigit() {
+                self.escape.push(ch);
+                if self.escape.len() == 3 {
+                    let byte = u8::from_str_radix(&self.escape[1..], 16).unwrap();
+                    self.escape.clear();
+                    self.push(&[byte]);
+                }
+                return;
+            }
+            self.flush();
+        }
+        match ch {
+            '%' => self.escape.push(ch),
+            '+' => self.push(b" "),
+            '=' if !self.in_value => self.in_value = true,
+            _ => {
+                let mut buffer = [0; 4];
+                self.push(ch.encode_utf8(&mut buffer).as_bytes());
+            },
+        }
+    }
+
+    fn finish(mut self) -> (Cow<'static, str>, Cow<'static, str>) {
+        self.flush();
+        (decoded(self.key), decoded(self.value))
+    }
+}
+
+// Run the decoder as far as it will go, returning the number of bytes of the pair read,
+// and whether the pair has ended.
+
+fn run(state: &mut FormPairState, string: &mut Chars) -> (usize, bool) {
+    let string0 = string.as_str();
+    loop {
+        match string.clone().next() {
+            None => return (string0.len(), false),
+            Some(ch) if is_terminator(ch) => return (string0.len() - string.as_str().len(), true),
+            Some('&') => {
+                let consumed = string0.len() - string.as_str().len();
+                string.next();
+                return (consumed, true);
+            },
+            Some(ch) => {
+                string.next();
+                state.step(ch);
+            },
+        }
+    }
+}
+
+impl Parser for FormPair {}
+
+impl<'a> Stateful<char, Chars<'a>, (Cow<'a, str>, Cow<'a, str>)> for FormPairState {
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, (Cow<'a, str>, Cow<'a, str>)> {
+        if run(&mut self, string).1 {
+            Done(self.finish())
+        } else {
+            Continue(self)
+        }
+    }
+
+    fn done(self) -> (Cow<'a, str>, Cow<'a, str>) {
+        self.finish()
+    }
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for FormPairState {
+
+    type Output = (Cow<'a, str>, Cow<'a, str>);
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for FormPair {
+
+    type Output = (Cow<'a, str>, Cow<'a, str>);
+
+}
+
+impl<'a> Uncommitted<char, Chars<'a>, (Cow<'a, str>, Cow<'a, str>)> for FormPair {
+
+    type State = FormPairState;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, (Cow<'a, str>, Cow<'a, str>)>> {
+        match string.clone().next() {
+            Some(ch) if !is_terminator(ch) => (),
+            _ => return None,
+        }
+        let string0 = string.as_str();
+        let mut state = FormPairState::new();
+        let (length, ended) = run(&mut state, string);
+        let pair = &string0[..length];
+        if !ended {
+            Some(Continue(state))
+        } else if pair.contains(['%', '+']) {
+            Some(Done(state.finish()))
+        } else {
+            let (key, value) = match pair.find('=') {
+                Some(index) => (&pair[..index], &pair[(index + 1)..]),
+                None => (pair, ""),
+            };
+            Some(Done((Borrowed(key), Borrowed(value))))
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_form_pair() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    use std::collections::HashMap;
+    fn pair(key: &'static str, value: &'static str) -> (Cow<'static, str>, Cow<'static, str>) {
+        (Borrowed(key), Borrowed(value))
+    }
+    let parser = form_pair();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("#top"), None);
+    match parser.init_str("key=value&rest").unwrap().unDone() {
+        (Borrowed("key"), Borrowed("value")) => (),
+        result => panic!("Unexpected {:?}", result),
+    }
+    assert_eq!(parser.init_str("flag&").unwrap().unDone(), pair("flag", ""));
+    assert_eq!(parser.init_str("a=b=c ").unwrap().unDone(), pair("a", "b=c"));
+    assert_eq!(parser.init_str("q=%E2%82%AC+%zz%4 ").unwrap().unDone(), pair("q", "\u{20ac} %zz%4"));
+    assert_eq!(parser.init_str("bad=%FF&").unwrap().unDone(), pair("bad", "\u{fffd}"));
+    assert_eq!(parser.init_str("%3D=%26&").unwrap().unDone(), pair("=", "&"));
+    let parsing = parser.init_str("k=%").unwrap().unContinue();
+    let parsing = parsing.more_str("4").unContinue();
+    assert_eq!(parsing.last_str("1"), pair("k", "A"));
+    let pairs: HashMap<Cow<str>, Cow<str>> = parser.star(HashMap::new).init_str("x=1&y=2&x=3#frag").unwrap().unDone();
+    assert_eq!(pairs["x"], "3");
+    assert_eq!(pairs["y"], "2");
+    testing::assert_chunk_invariant(parser.star(Vec::new), "a=%C3%A9t%C3%A9&b+c=d%2Be&e");
+}
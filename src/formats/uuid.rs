@@ -0,0 +1,196 @@
+//! UUIDs.
+//!
+//! UUIDs are parsed from their hyphenated form, such as `67e55044-10b1-426f-9247-bb680e5fe0c8`,
+//! optionally surrounded by braces, or prefixed by `urn:uuid:`. For example:
+//!
+//! ```
+//! # use parsell::UncommittedStr;
+//! # use parsell::formats::uuid::uuid;
+//! let parser = uuid();
+//! let bytes = parser.init_str("{67e55044-10b1-426f-9247-bb680e5fe0c8}").unwrap().unDone().unwrap();
+//! assert_eq!(bytes[0], 0x67);
+//! assert_eq!(bytes[15], 0xc8);
+//! ```
+//!
+//! The parser commits on the first character, so the result is `None` if the input
+//! starts like a UUID but is malformed. Any malformed character is not consumed.
+//! With the `uuid` feature, `uuid_value()` produces a `uuid::Uuid`.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+#[cfg(feature = "uuid")]
+use super::super::Function;
+#[cfg(feature = "uuid")]
+use super::super::impls::Map;
+
+#[cfg(feature = "uuid")]
+use uuid::Uuid;
+
+const URN: &str = "urn:uuid:";
+
+/// A parser for UUIDs, producing their bytes.
+
+pub fn uuid() -> UuidParser {
+    UuidParser
+}
+
+/// A parser for UUIDs, producing a `uuid::Uuid`.
+///
+/// This is only available with the `uuid` feature.
+
+#[cfg(feature = "uuid")]
+pub fn uuid_value() -> Map<UuidParser, ToUuid> {
+    UuidParser.map(ToUuid)
+}
+
+#[cfg(feature = "uuid")]
+#[derive(Copy, Clone, Debug)]
+pub struct ToUuid;
+
+#[cfg(feature = "uuid")]
+impl Function<Option<[u8; 16]>> for ToUuid {
+    type Output = Option<Uuid>;
+    fn apply(&self, bytes: Option<[u8; 16]>) -> Option<Uuid> {
+        bytes.map(Uuid::from_bytes)
+    }
+}
+
+// How much of a UUID has been read.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    // The number of characters of the URN prefix read.
+    Urn(usize),
+    // The number of characters of the hyphenated form read.
+    Body(usize),
+    Finished,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct UuidParser;
+
+#[derive(Copy, Clone, Debug)]
+pub struct UuidState {
+    braced: bool,
+    phase: Phase,
+    bytes: [u8; 16],
+}
+
+fn is_dash(position: usize) -> bool {
+    position == 8 || position == 13 || position == 18 || position == 23
+}
+
+impl UuidState {
+    fn accepts(&self, ch: char) -> bool {
+        match self.phase {
+            Phase::Urn(read) => URN[read..].chars().next().is_some_and(|expected| ch.to_ascii_lowercase() == expected),
+            Phase::Body(36) => self.braced && ch == '}',
+            Phase::Body(position) if is_dash(position) => ch == '-',
+            Phase::Body(_) => ch.is_ascii_hexdigit(),
+            Phase::Finished => false,
+        }
+    }
+
+    fn accept(&mut self, ch: char) {
+        self.phase = match self.phase {
+            Phase::Urn(read) if read + 1 == URN.len() => Phase::Body(0),
+            Phase::Urn(read) => Phase::Urn(read + 1),
+            Phase::Body(36) => Phase::Finished,
+            Phase::Body(position) => {
+                if let Some(digit) = ch.to_digit(16) {
+                    let nibble = position - (0..position).filter(|&previous| is_dash(previous)).count();
+                    self.bytes[nibble / 2] |= (digit as u8) << (4 * (1 - nibble % 2));
+                }
+                if position == 35 && !self.braced { Phase::Finished } else { Phase::Body(position + 1) }
+            },
+            Phase::Finished => Phase::Finished,
+        };
+    }
+}
+
+impl Parser for UuidParser {}
+
+impl<Str> Stateful<char, Str, Option<[u8; 16]>> for UuidState
+    where Str: PeekableIterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Option<[u8; 16]>> {
+        while self.phase != Phase::Finished {
+            let state = self;
+            match string.next_if(move |ch| state.accepts(ch)) {
+                Some(ch) => self.accept(ch),
+                None if string.is_empty() => return Continue(self),
+                None => return Done(None),
+            }
+        }
+        Done(Some(self.bytes))
+    }
+
+    fn done(self) -> Option<[u8; 16]> {
+        if self.phase == Phase::Finished { Some(self.bytes) } else { None }
+    }
+
+}
+
+impl<Str> HasOutput<char, Str> for UuidState {
+
+    type Output = Option<[u8; 16]>;
+
+}
+
+impl<Str> HasOutput<char, Str> for UuidParser {
+
+    type Output = Option<[u8; 16]>;
+
+}
+
+impl<Str> Uncommitted<char, Str, Option<[u8; 16]>> for UuidParser
+    where Str: PeekableIterator<Item = char>,
+{
+
+    type State = UuidState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<[u8; 16]>>> {
+        let mut state = UuidState { braced: false, phase: Phase::Body(0), bytes: [0; 16] };
+        match string.next_if(|ch: char| ch == '{' || ch == 'u' || ch == 'U' || ch.is_ascii_hexdigit()) {
+            None => return None,
+            Some('{') => state.braced = true,
+            Some('u') | Some('U') => state.phase = Phase::Urn(1),
+            Some(ch) => state.accept(ch),
+        }
+        Some(state.more(string))
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_uuid() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    const BYTES: [u8; 16] = [0x67, 0xe5, 0x50, 0x44, 0x10, 0xb1, 0x42, 0x6f, 0x92, 0x47, 0xbb, 0x68, 0x0e, 0x5f, 0xe0, 0xc8];
+    let parser = uuid();
+    assert_eq!(parser.init_str("xyz"), None);
+    assert_eq!(parser.init_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap().unDone(), Some(BYTES));
+    assert_eq!(parser.init_str("67E55044-10B1-426F-9247-BB680E5FE0C8").unwrap().unDone(), Some(BYTES));
+    assert_eq!(parser.init_str("{67e55044-10b1-426f-9247-bb680e5fe0c8}").unwrap().unDone(), Some(BYTES));
+    assert_eq!(parser.init_str("URN:UUID:67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap().unDone(), Some(BYTES));
+    let parsing = parser.init_str("67e55044-10").unwrap().unContinue();
+    let parsing = parsing.more_str("b1-426f-").unContinue();
+    assert_eq!(parsing.last_str("9247-bb680e5fe0c8"), Some(BYTES));
+    let mut data = "67e55044_10b1".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), None);
+    assert_eq!(data.as_str(), "_10b1");
+    assert_eq!(parser.init_str("{67e55044-10b1-426f-9247-bb680e5fe0c8!").unwrap().unDone(), None);
+    assert_eq!(parser.init_str("67e55044").unwrap().unContinue().last_str(""), None);
+    testing::assert_chunk_invariant(parser, "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8");
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn test_uuid_value() {
+    use super::super::UncommittedStr;
+    let uuid = uuid_value().init_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap().unDone().unwrap();
+    assert_eq!(uuid.as_bytes()[1], 0xe5);
+}
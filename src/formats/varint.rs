@@ -0,0 +1,256 @@
+//! Variable-length integers.
+//!
+//! These are the base 128 varints used by Protocol Buffers and other wire formats,
+//! parsed from byte input. Each byte contributes seven bits, least significant first,
+//! and has its high bit set if more bytes follow, for example:
+//!
+//! ```
+//! # use parsell::{Uncommitted,Stateful};
+//! # use parsell::ParseResult::{Continue, Done};
+//! # use parsell::formats::varint::{varint_u64, field_key, WireType};
+//! let parser = varint_u64();
+//! let state = match parser.init(&mut [0xac].iter().cloned().peekable()).unwrap() {
+//!     Continue(state) => state,
+//!     _ => panic!("Can't happen"),
+//! };
+//! assert_eq!(state.last(&mut [0x02, 0x08].iter().cloned().peekable()), Some(300));
+//! let mut data = [0x12, 0x07].iter().cloned().peekable();
+//! match field_key().init(&mut data).unwrap() {
+//!     Done(key) => assert_eq!(key, Some((2, WireType::Len))),
+//!     _ => panic!("Can't happen"),
+//! }
+//! ```
+//!
+//! The parsers commit on the first byte, and the result is `None` if the input ends
+//! in the middle of a varint, or if it is longer than ten bytes or overflows 64 bits.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator, Function};
+use super::super::ParseResult::{Done, Continue};
+use super::super::impls::Map;
+
+/// A parser for unsigned varints.
+
+pub fn varint_u64() -> Varint {
+    Varint
+}
+
+/// A parser for signed varints, using ZigZag encoding, as in the protobuf `sint64` type.
+
+pub fn varint_i64() -> Map<Varint, ZigZag> {
+    Varint.map(ZigZag)
+}
+
+/// A parser for protobuf field keys, producing the field number and wire type.
+///
+/// The result is `None` if the field number is zero or does not fit in 29 bits,
+/// or the wire type is unknown.
+
+pub fn field_key() -> Map<Varint, FieldKey> {
+    Varint.map(FieldKey)
+}
+
+/// Decode a ZigZag encoded integer, which maps 0, 1, 2, 3, ... to 0, -1, 1, -2, ...
+
+pub fn zigzag_decode(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// ZigZag encode an integer.
+
+pub fn zigzag_encode(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+/// The wire type of a protobuf field.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WireType {
+    /// A varint.
+    Varint,
+    /// A fixed 64 bit value.
+    I64,
+    /// A length-delimited value.
+    Len,
+    /// The start of a group (deprecated).
+    StartGroup,
+    /// The end of a group (deprecated).
+    EndGroup,
+    /// A fixed 32 bit value.
+    I32,
+}
+
+/// Decode a protobuf field key into its field number and wire type.
+
+pub fn decode_key(key: u64) -> Option<(u32, WireType)> {
+    let wire_type = match key & 7 {
+        0 => WireType::Varint,
+        1 => WireType::I64,
+        2 => WireType::Len,
+        3 => WireType::StartGroup,
+        4 => WireType::EndGroup,
+        5 => WireType::I32,
+        _ => return None,
+    };
+    let field = key >> 3;
+    if field == 0 || field >= (1 << 29) {
+        None
+    } else {
+        Some((field as u32, wire_type))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ZigZag;
+
+impl Function<Option<u64>> for ZigZag {
+    type Output = Option<i64>;
+    fn apply(&self, value: Option<u64>) -> Option<i64> {
+        value.map(zigzag_decode)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct FieldKey;
+
+impl Function<Option<u64>> for FieldKey {
+    type Output = Option<(u32, WireType)>;
+    fn apply(&self, key: Option<u64>) -> Option<(u32, WireType)> {
+        key.and_then(decode_key)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Varint;
+
+#[derive(Copy, Clone, Debug)]
+pub struct VarintState {
+    value: u64,
+    shift: u32,
+    overflow: bool,
+}
+
+impl VarintState {
+    // Accept a byte, returning whether the varint has ended.
+    fn accept(&mut self, byte: u8) -> bool {
+        let bits = (byte & 0x7f) as u64;
+        if self.shift >= 70 || (self.shift == 63 && bits > 1) {
+            self.overflow = true;
+        } else if self.shift < 64 {
+            self.value |= bits << self.shift;
+        }
+        self.shift += 7;
+        byte & 0x80 == 0
+    }
+
+    fn result(self) -> Option<u64> {
+        if self.overflow { None } else { Some(self.value) }
+    }
+}
+
+impl Parser for Varint {}
+
+impl<Str> Stateful<u8, Str, Option<u64>> for VarintState
+    where Str: PeekableIterator<Item = u8>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Option<u64>> {
+        for byte in string {
+            if self.accept(byte) {
+                return Done(self.result());
+            }
+        }
+        Continue(self)
+    }
+
+    fn done(self) -> Option<u64> {
+        None
+    }
+
+}
+
+impl<Str> HasOutput<u8, Str> for VarintState {
+
+    type Output = Option<u64>;
+
+}
+
+impl<Str> HasOutput<u8, Str> for Varint {
+
+    type Output = Option<u64>;
+
+}
+
+impl<Str> Uncommitted<u8, Str, Option<u64>> for Varint
+    where Str: PeekableIterator<Item = u8>,
+{
+
+    type State = VarintState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<u64>>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(VarintState { value: 0, shift: 0, overflow: false }.more(string))
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_varint() {
+    use std::iter::{Cloned, Peekable};
+    use std::slice::Iter;
+    fn parse<P, T>(parser: P, bytes: &[u8]) -> Option<T>
+        where P: for<'a> Uncommitted<u8, Peekable<Cloned<Iter<'a, u8>>>, T>,
+    {
+        let mut data = bytes.iter().cloned().peekable();
+        match parser.init(&mut data) {
+            None => None,
+            Some(Done(result)) => {
+                assert!(data.next().is_none());
+                Some(result)
+            },
+            Some(Continue(_)) => panic!("Unexpected end of input"),
+        }
+    }
+    assert_eq!(parse(varint_u64(), &[]), None);
+    assert_eq!(parse(varint_u64(), &[0x00]), Some(Some(0)));
+    assert_eq!(parse(varint_u64(), &[0x96, 0x01]), Some(Some(150)));
+    let empty: &[u8] = &[];
+    let parsing = varint_u64().init(&mut [0x96].iter().cloned().peekable()).unwrap().unContinue();
+    assert_eq!(parsing.last(&mut empty.iter().cloned().peekable()), None);
+    assert_eq!(parse(varint_u64(), &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]), Some(Some(u64::MAX)));
+    assert_eq!(parse(varint_u64(), &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x02]), Some(None));
+    assert_eq!(parse(varint_u64(), &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00]), Some(None));
+    assert_eq!(parse(varint_i64(), &[0x03]), Some(Some(-2)));
+    assert_eq!(parse(varint_i64(), &[0xff, 0xff, 0xff, 0xff, 0x0f]), Some(Some(-2147483648)));
+    assert_eq!(parse(field_key(), &[0x08]), Some(Some((1, WireType::Varint))));
+    assert_eq!(parse(field_key(), &[0x07]), Some(None));
+    assert_eq!(parse(field_key(), &[0x06]), Some(None));
+    for &value in &[0, 1, -1, 63, -64, i64::MAX, i64::MIN] {
+        assert_eq!(zigzag_decode(zigzag_encode(value)), value);
+    }
+    assert_eq!(zigzag_encode(-1), 1);
+    assert_eq!(zigzag_encode(1), 2);
+}
+
+#[test]
+fn test_varint_chunked() {
+    let bytes = [0xe5, 0x8e, 0x26, 0x01];
+    for split in 0..3 {
+        let mut first = bytes[..split].iter().cloned().peekable();
+        let mut second = bytes[split..].iter().cloned().peekable();
+        let result = match varint_u64().init(&mut first) {
+            None => match varint_u64().init(&mut second) {
+                Some(Done(result)) => result,
+                _ => panic!("Split at {}", split),
+            },
+            Some(Continue(state)) => state.more(&mut second).unDone(),
+            Some(Done(_)) => panic!("Split at {}", split),
+        };
+        assert_eq!(result, Some(624485));
+        assert_eq!(second.next(), Some(0x01));
+    }
+}
@@ -0,0 +1,628 @@
+//! A YAML subset.
+//!
+//! This module is only available with the `yaml` feature.
+//! The parser `yaml_document()` reads one YAML document from character input into a `Value`,
+//! for example:
+//!
+//! ```
+//! # use parsell::{UncommittedStr,StatefulStr};
+//! # use parsell::formats::yaml::{yaml_document, Value};
+//! let parsing = yaml_document().init_str("name: parsell # a comment\ntags: [parser, 'streaming']\n").unwrap().unContinue();
+//! let value = parsing.last_str("deps:\n  - name: serde\n    optional: true\n").unwrap();
+//! assert_eq!(value, Value::Mapping(vec![
+//!     (String::from("name"), Value::String(String::from("parsell"))),
+//!     (String::from("tags"), Value::Sequence(vec![
+//!         Value::String(String::from("parser")),
+//!         Value::String(String::from("streaming")),
+//!     ])),
+//!     (String::from("deps"), Value::Sequence(vec![Value::Mapping(vec![
+//!         (String::from("name"), Value::String(String::from("serde"))),
+//!         (String::from("optional"), Value::Bool(true)),
+//!     ])])),
+//! ]));
+//! ```
+//!
+//! The subset covers block sequences and mappings, nested by indentation, flow sequences
+//! and mappings, which may span lines, plain, single-quoted and double-quoted scalars,
+//! and comments. Plain scalars are resolved as null, booleans, integers or floats using
+//! the YAML core schema, and mapping keys are strings. Anchors, tags, block scalars (`|` and `>`),
+//! multi-line plain scalars, explicit keys and single-pair mappings in flow sequences are not supported.
+//!
+//! Input is processed a line at a time, so only the current line, and the value built so far,
+//! are kept in memory. A document ends at a `...` line, at a `---` line after some content,
+//! or at the end of input, and the result is an error if the document is malformed.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+
+use std::error;
+use std::fmt;
+use std::mem;
+
+/// A YAML value.
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    /// Null, written `null`, `~` or nothing.
+    Null,
+    /// A boolean.
+    Bool(bool),
+    /// An integer.
+    Integer(i64),
+    /// A floating point number.
+    Float(f64),
+    /// A string.
+    String(String),
+    /// A sequence.
+    Sequence(Vec<Value>),
+    /// A mapping, with its entries in the order they were given.
+    Mapping(Vec<(String, Value)>),
+}
+
+/// An error in YAML input.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Error {
+    /// The line of the error, counting from 1.
+    pub line: usize,
+    /// What was wrong with the line.
+    pub reason: &'static str,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Malformed YAML on line {}: {}", self.line, self.reason)
+    }
+}
+
+impl error::Error for Error {}
+
+/// A parser for one YAML document.
+
+pub fn yaml_document() -> YamlDocument {
+    YamlDocument
+}
+
+// Flow collections nested deeper than this are rejected, rather than overflowing the stack.
+
+const MAX_DEPTH: usize = 128;
+
+// Strip any comment from a line, returning the rest of the line
+// and the change in the nesting depth of flow collections.
+
+fn scan(line: &str) -> (&str, isize) {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut previous = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((index, ch)) = chars.next() {
+        match (quote, ch) {
+            (Some('\''), '\'') if chars.peek().map(|&(_, next)| next) == Some('\'') => { chars.next(); },
+            (Some('"'), '\\') => { chars.next(); },
+            (Some(open), _) if ch == open => quote = None,
+            (Some(_), _) => (),
+            (None, '\'') | (None, '"') if previous.map(|previous| "[{,:-".contains(previous)) != Some(false) => quote = Some(ch),
+            (None, '#') if index == 0 || line[..index].ends_with(char::is_whitespace) => return (&line[..index], depth),
+            (None, '[') | (None, '{') => depth += 1,
+            (None, ']') | (None, '}') => depth -= 1,
+            (None, _) => (),
+        }
+        if !ch.is_whitespace() {
+            previous = Some(ch);
+        }
+    }
+    (line, depth)
+}
+
+// Resolve a plain scalar using the core schema.
+
+fn resolve(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Null,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => return Value::Float(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => return Value::Float(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Value::Float(f64::NAN),
+        _ => (),
+    }
+    if let Ok(integer) = text.parse() {
+        return Value::Integer(integer);
+    }
+    let numeric = text.trim_start_matches(['+', '-']).trim_start_matches('.');
+    if numeric.starts_with(|ch: char| ch.is_ascii_digit()) {
+        if let Ok(float) = text.parse() {
+            return Value::Float(float);
+        }
+    }
+    Value::String(String::from(text))
+}
+
+// Parse a scalar from the start of `text`, returning its text, whether it was quoted,
+// and the rest of the input. In flow context, plain scalars end at flow indicators.
+
+fn scalar(text: &str, flow: bool) -> Result<(String, bool, &str), &'static str> {
+    let mut chars = text.char_indices();
+    match chars.next() {
+        Some((_, '\'')) => {
+            let mut result = String::new();
+            while let Some((index, ch)) = chars.next() {
+                if ch != '\'' {
+                    result.push(ch);
+                } else if text[index + 1..].starts_with('\'') {
+                    result.push('\'');
+                    chars.next();
+                } else {
+                    return Ok((result, true, &text[index + 1..]));
+                }
+            }
+            Err("unterminated single-quoted scalar")
+        },
+        Some((_, '"')) => {
+            let mut result = String::new();
+            while let Some((index, ch)) = chars.next() {
+                match ch {
+                    '"' => return Ok((result, true, &text[index + 1..])),
+                    '\\' => match chars.next().map(|(_, escape)| escape) {
+                        Some('n') => result.push('\n'),
+                        Some('t') => result.push('\t'),
+                        Some('r') => result.push('\r'),
+                        Some('0') => result.push('\0'),
+                        Some('u') => {
+                            let hex: String = chars.by_ref().take(4).map(|(_, digit)| digit).collect();
+                            match u32::from_str_radix(&hex, 16).ok().and_then(::std::char::from_u32) {
+                                Some(ch) if hex.len() == 4 => result.push(ch),
+                                _ => return Err("invalid unicode escape"),
+                            }
+                        },
+                        Some(ch) if "\"\\/ ".contains(ch) => result.push(ch),
+                        _ => return Err("invalid escape"),
+                    },
+                    _ => result.push(ch),
+                }
+            }
+            Err("unterminated double-quoted scalar")
+        },
+        _ => {
+            let mut end = text.len();
+            for (index, ch) in text.char_indices() {
+                let next = text[index + ch.len_utf8()..].chars().next();
+                let indicator = if flow { ",[]{}" } else { "" };
+                if indicator.contains(ch) || (ch == ':' && next.map(|next| next.is_whitespace() || indicator.contains(next)) != Some(false)) {
+                    end = index;
+                    break;
+                }
+            }
+            Ok((String::from(text[..end].trim()), false, &text[end..]))
+        },
+    }
+}
+
+// Parse a flow node from the start of `text`, returning it and the rest of the input.
+
+fn flow(text: &str, depth: usize) -> Result<(Value, &str), &'static str> {
+    if depth > MAX_DEPTH {
+        return Err("flow collections are nested too deeply");
+    }
+    let text = text.trim_start();
+    if let Some(inner) = text.strip_prefix('[') {
+        let mut items = Vec::new();
+        let mut rest = inner.trim_start();
+        while !rest.starts_with(']') {
+            let (item, after) = try!(flow(rest, depth + 1));
+            items.push(item);
+            rest = try!(separator(after, ']'));
+        }
+        Ok((Value::Sequence(items), &rest[1..]))
+    } else if let Some(inner) = text.strip_prefix('{') {
+        let mut entries = Vec::new();
+        let mut rest = inner.trim_start();
+        while !rest.starts_with('}') {
+            let (key, _, after) = try!(scalar(rest, true));
+            let after = after.trim_start();
+            let (value, after) = if let Some(after) = after.strip_prefix(':') {
+                try!(flow(after, depth + 1))
+            } else {
+                (Value::Null, after)
+            };
+            entries.push((key, value));
+            rest = try!(separator(after, '}'));
+        }
+        Ok((Value::Mapping(entries), &rest[1..]))
+    } else {
+        let (text, quoted, rest) = try!(scalar(text, true));
+        if quoted {
+            Ok((Value::String(text), rest))
+        } else {
+            Ok((resolve(&text), rest))
+        }
+    }
+}
+
+// Skip the separator after an item of a flow collection.
+
+fn separator(text: &str, close: char) -> Result<&str, &'static str> {
+    let text = text.trim_start();
+    if let Some(rest) = text.strip_prefix(',') {
+        Ok(rest.trim_start())
+    } else if text.starts_with(close) {
+        Ok(text)
+    } else if text.is_empty() {
+        Err("unterminated flow collection")
+    } else {
+        Err("expected a comma")
+    }
+}
+
+// Parse a complete value in block context.
+
+fn node(text: &str) -> Result<Value, &'static str> {
+    let (value, rest) = if text.starts_with('[') || text.starts_with('{') {
+        try!(flow(text, 0))
+    } else {
+        let (text, quoted, rest) = try!(scalar(text, false));
+        (if quoted { Value::String(text) } else { resolve(&text) }, rest)
+    };
+    if rest.trim().is_empty() {
+        Ok(value)
+    } else {
+        Err("unexpected text after a value")
+    }
+}
+
+// Split a mapping entry into its key and the rest of the line.
+
+fn entry(text: &str) -> Result<Option<(String, &str)>, &'static str> {
+    if text.starts_with('[') || text.starts_with('{') {
+        return Ok(None);
+    }
+    let (key, _, rest) = try!(scalar(text, false));
+    if let Some(rest) = rest.strip_prefix(':') {
+        Ok(Some((key, rest.trim_start())))
+    } else {
+        Ok(None)
+    }
+}
+
+// A block collection which is being parsed, with its indentation.
+// Sequences record whether an item has started, and mappings record the key of an entry which has started.
+
+#[derive(Clone, Debug)]
+enum Frame {
+    Sequence(usize, Vec<Value>, bool),
+    Mapping(usize, Vec<(String, Value)>, Option<String>),
+}
+
+impl Frame {
+    fn indent(&self) -> usize {
+        match *self {
+            Frame::Sequence(indent, _, _) | Frame::Mapping(indent, _, _) => indent,
+        }
+    }
+
+    fn into_value(self) -> Value {
+        match self {
+            Frame::Sequence(_, mut items, started) => {
+                if started {
+                    items.push(Value::Null);
+                }
+                Value::Sequence(items)
+            },
+            Frame::Mapping(_, mut entries, key) => {
+                if let Some(key) = key {
+                    entries.push((key, Value::Null));
+                }
+                Value::Mapping(entries)
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct YamlDocument;
+
+#[derive(Clone, Debug)]
+pub struct YamlDocumentState {
+    line: String,
+    number: usize,
+    flow: Option<(usize, String, isize)>,
+    stack: Vec<Frame>,
+    root: Option<Value>,
+}
+
+impl YamlDocumentState {
+    fn new() -> Self {
+        YamlDocumentState {
+            line: String::new(),
+            number: 0,
+            flow: None,
+            stack: Vec::new(),
+            root: None,
+        }
+    }
+
+    // Handle a line, returning whether it ends the document.
+    fn line(&mut self, line: &str) -> Result<bool, &'static str> {
+        let (content, depth) = scan(line.trim_end_matches('\r'));
+        if let Some((indent, mut text, open)) = self.flow.take() {
+            text.push(' ');
+            text.push_str(content.trim());
+            if open + depth > 0 {
+                self.flow = Some((indent, text, open + depth));
+                return Ok(false);
+            }
+            return self.logical(indent, &text);
+        }
+        let text = content.trim_start_matches(' ');
+        let indent = content.len() - text.len();
+        if text.starts_with('\t') {
+            return Err("tab in indentation");
+        }
+        let text = text.trim_end();
+        if text.is_empty() {
+            Ok(false)
+        } else if depth > 0 {
+            self.flow = Some((indent, String::from(text), depth));
+            Ok(false)
+        } else {
+            self.logical(indent, text)
+        }
+    }
+
+    // Handle a line, after joining the lines of flow collections.
+    fn logical(&mut self, indent: usize, text: &str) -> Result<bool, &'static str> {
+        if indent == 0 && (text == "..." || text == "---") {
+            return Ok(text == "..." || self.root.is_some() || !self.stack.is_empty());
+        }
+        let item = text == "-" || text.starts_with("- ");
+        loop {
+            match self.stack.last() {
+                Some(frame) if frame.indent() > indent => (),
+                Some(&Frame::Sequence(sequence, _, _)) if sequence == indent && !item => (),
+                _ => break,
+            }
+            try!(self.close());
+        }
+        self.block(indent, text).map(|_| false)
+    }
+
+    // Handle a block node at the given column.
+    fn block(&mut self, column: usize, text: &str) -> Result<(), &'static str> {
+        if text == "-" || text.starts_with("- ") {
+            match self.stack.last() {
+                Some(&Frame::Sequence(indent, _, _)) if indent == column => (),
+                _ => try!(self.open(Frame::Sequence(column, Vec::new(), false))),
+            }
+            if let Some(&mut Frame::Sequence(_, ref mut items, ref mut started)) = self.stack.last_mut() {
+                if mem::replace(started, true) {
+                    items.push(Value::Null);
+                }
+            }
+            let rest = text[1..].trim_start();
+            if rest.is_empty() {
+                Ok(())
+            } else {
+                self.block(column + text.len() - rest.len(), rest)
+            }
+        } else if let Some((key, rest)) = try!(entry(text)) {
+            match self.stack.last() {
+                Some(&Frame::Mapping(indent, _, _)) if indent == column => (),
+                _ => try!(self.open(Frame::Mapping(column, Vec::new(), None))),
+            }
+            if let Some(&mut Frame::Mapping(_, ref mut entries, ref mut pending)) = self.stack.last_mut() {
+                if let Some(previous) = pending.replace(key) {
+                    entries.push((previous, Value::Null));
+                }
+            }
+            if rest.is_empty() {
+                Ok(())
+            } else {
+                let value = try!(node(rest));
+                self.attach(value)
+            }
+        } else {
+            let value = try!(node(text));
+            self.attach(value)
+        }
+    }
+
+    // Whether there is somewhere to put a value.
+    fn expecting(&self) -> bool {
+        match self.stack.last() {
+            None => self.root.is_none(),
+            Some(&Frame::Sequence(_, _, started)) => started,
+            Some(Frame::Mapping(_, _, key)) => key.is_some(),
+        }
+    }
+
+    fn open(&mut self, frame: Frame) -> Result<(), &'static str> {
+        if !self.expecting() {
+            return Err("unexpected indentation");
+        }
+        self.stack.push(frame);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<(), &'static str> {
+        match self.stack.pop() {
+            Some(frame) => self.attach(frame.into_value()),
+            None => Ok(()),
+        }
+    }
+
+    fn attach(&mut self, value: Value) -> Result<(), &'static str> {
+        match self.stack.last_mut() {
+            None if self.root.is_none() => self.root = Some(value),
+            Some(&mut Frame::Sequence(_, ref mut items, ref mut started)) if *started => {
+                items.push(value);
+                *started = false;
+            },
+            Some(&mut Frame::Mapping(_, ref mut entries, ref mut key)) if key.is_some() => {
+                entries.push((key.take().unwrap(), value));
+            },
+            _ => return Err("unexpected value"),
+        }
+        Ok(())
+    }
+
+    fn end(&mut self) -> Result<Value, Error> {
+        while !self.stack.is_empty() {
+            try!(self.close().map_err(|reason| Error { line: self.number, reason }));
+        }
+        Ok(self.root.take().unwrap_or(Value::Null))
+    }
+}
+
+impl Parser for YamlDocument {}
+
+impl<Str> Stateful<char, Str, Result<Value, Error>> for YamlDocumentState
+    where Str: Iterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Result<Value, Error>> {
+        loop {
+            match string.next() {
+                None => return Continue(self),
+                Some('\n') => {
+                    let line = mem::take(&mut self.line);
+                    self.number += 1;
+                    match self.line(&line) {
+                        Ok(false) => (),
+                        Ok(true) => return Done(self.end()),
+                        Err(reason) => return Done(Err(Error { line: self.number, reason })),
+                    }
+                },
+                Some(ch) => self.line.push(ch),
+            }
+        }
+    }
+
+    fn done(mut self) -> Result<Value, Error> {
+        let line = mem::take(&mut self.line);
+        if !line.is_empty() {
+            self.number += 1;
+            if let Err(reason) = self.line(&line) {
+                return Err(Error { line: self.number, reason });
+            }
+        }
+        if self.flow.is_some() {
+            return Err(Error { line: self.number, reason: "unterminated flow collection" });
+        }
+        self.end()
+    }
+
+}
+
+impl<Str> HasOutput<char, Str> for YamlDocumentState {
+
+    type Output = Result<Value, Error>;
+
+}
+
+impl<Str> HasOutput<char, Str> for YamlDocument {
+
+    type Output = Result<Value, Error>;
+
+}
+
+impl<Str> Uncommitted<char, Str, Result<Value, Error>> for YamlDocument
+    where Str: PeekableIterator<Item = char>,
+{
+
+    type State = YamlDocumentState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<Value, Error>>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(YamlDocumentState::new().more(string))
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[cfg(test)]
+fn parse(text: &str) -> Result<Value, Error> {
+    use super::super::{UncommittedStr, StatefulStr};
+    match yaml_document().init_str(text) {
+        Some(Done(result)) => result,
+        Some(Continue(state)) => state.last_str(""),
+        None => Ok(Value::Null),
+    }
+}
+
+#[cfg(test)]
+fn string(text: &str) -> Value {
+    Value::String(String::from(text))
+}
+
+#[test]
+fn test_yaml_scalars() {
+    assert_eq!(parse("~"), Ok(Value::Null));
+    assert_eq!(parse("True"), Ok(Value::Bool(true)));
+    assert_eq!(parse("-12"), Ok(Value::Integer(-12)));
+    assert_eq!(parse("1.5e3"), Ok(Value::Float(1500.0)));
+    assert_eq!(parse("-.inf"), Ok(Value::Float(f64::NEG_INFINITY)));
+    assert_eq!(parse("inf"), Ok(string("inf")));
+    assert_eq!(parse("hello world # comment"), Ok(string("hello world")));
+    assert_eq!(parse("a#b"), Ok(string("a#b")));
+    assert_eq!(parse("'it''s # not a comment'"), Ok(string("it's # not a comment")));
+    assert_eq!(parse(r#""tab\there é \"q\"""#), Ok(string("tab\there \u{e9} \"q\"")));
+    assert_eq!(parse("'12'"), Ok(string("12")));
+    assert_eq!(parse("\"open"), Err(Error { line: 1, reason: "unterminated double-quoted scalar" }));
+}
+
+#[test]
+fn test_yaml_blocks() {
+    let text = "# configuration\nserver:\n  host: localhost\n  ports:\n  - 80\n  - 443\n  tls:\nusers:\n  - name: a\n    roles: [admin, {level: 2}]\n  -\n  - - x\n    - 'y'\n";
+    assert_eq!(parse(text), Ok(Value::Mapping(vec![
+        (String::from("server"), Value::Mapping(vec![
+            (String::from("host"), string("localhost")),
+            (String::from("ports"), Value::Sequence(vec![Value::Integer(80), Value::Integer(443)])),
+            (String::from("tls"), Value::Null),
+        ])),
+        (String::from("users"), Value::Sequence(vec![
+            Value::Mapping(vec![
+                (String::from("name"), string("a")),
+                (String::from("roles"), Value::Sequence(vec![
+                    string("admin"),
+                    Value::Mapping(vec![(String::from("level"), Value::Integer(2))]),
+                ])),
+            ]),
+            Value::Null,
+            Value::Sequence(vec![string("x"), string("y")]),
+        ])),
+    ])));
+    assert_eq!(parse("a: [1,\n  2, 3]\nb: {}\n"), Ok(Value::Mapping(vec![
+        (String::from("a"), Value::Sequence(vec![Value::Integer(1), Value::Integer(2), Value::Integer(3)])),
+        (String::from("b"), Value::Mapping(vec![])),
+    ])));
+    assert_eq!(parse("a: 1\n    b: 2\n"), Err(Error { line: 2, reason: "unexpected indentation" }));
+    assert_eq!(parse("a: 1\nb\n"), Err(Error { line: 2, reason: "unexpected value" }));
+    assert_eq!(parse("a: [1, 2\n"), Err(Error { line: 1, reason: "unterminated flow collection" }));
+    assert_eq!(parse("\ta: 1\n"), Err(Error { line: 1, reason: "tab in indentation" }));
+}
+
+#[test]
+fn test_yaml_documents() {
+    use super::super::UncommittedStr;
+    let mut data = "---\na: 1\n---\n- b\n...\nrest".chars();
+    assert_eq!(yaml_document().init(&mut data).unwrap().unDone(), Ok(Value::Mapping(vec![(String::from("a"), Value::Integer(1))])));
+    assert_eq!(yaml_document().init(&mut data).unwrap().unDone(), Ok(Value::Sequence(vec![string("b")])));
+    assert_eq!(data.as_str(), "rest");
+    assert_eq!(yaml_document().init_str(""), None);
+}
+
+#[test]
+fn test_yaml_chunked() {
+    use super::super::{Uncommitted, StatefulStr};
+    const TEXT: &str = "k: [a, 'b c']\nm:\n  - n: \"x\\ty\"\n    o: ~\n";
+    let expected = parse(TEXT);
+    assert!(expected.is_ok());
+    for split in 1..TEXT.len() {
+        let state = yaml_document().init(&mut TEXT[..split].chars()).unwrap().unContinue();
+        assert_eq!(state.last_str(&TEXT[split..]), expected, "Split at {}", split);
+    }
+}
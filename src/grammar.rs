@@ -0,0 +1,216 @@
+//! Grammar-wide policies for whitespace and case.
+//!
+//! Rather than following every token of a grammar with a whitespace parser, and
+//! deciding for every literal whether it ignores case, a `Grammar` records these policies
+//! once, and applies them to the rules built from it. Rules are combined using the
+//! operators of `parser.rule()`, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+//! # use parsell::grammar::GrammarBuilder;
+//! let grammar = GrammarBuilder::new().skip_whitespace(true).case_insensitive(true).build();
+//! let name = grammar.rule(character(char::is_alphanumeric).star(String::new));
+//! let statement = grammar.literal("let") >> name << grammar.literal("=").opt();
+//! assert_eq!(statement.init_str("LET  x =").unwrap().unContinue().last_str(""), "x");
+//! ```
+//!
+//! Whitespace is skipped after each token, so a grammar which allows leading whitespace
+//! should start by skipping it.
+
+use super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, Committed, PeekableIterator, Outline};
+use super::ParseResult::{Done, Continue};
+use super::impls::{Rule, Tag};
+
+/// A builder for grammar policies.
+///
+/// By default, whitespace is not skipped and literals are case-sensitive.
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GrammarBuilder {
+    skip_whitespace: bool,
+    case_insensitive: bool,
+}
+
+impl GrammarBuilder {
+    /// A builder with the default policies.
+    pub fn new() -> Self {
+        GrammarBuilder::default()
+    }
+
+    /// Whether to skip whitespace after each token.
+    pub fn skip_whitespace(mut self, skip: bool) -> Self {
+        self.skip_whitespace = skip;
+        self
+    }
+
+    /// Whether literals ignore case.
+    pub fn case_insensitive(mut self, ignore: bool) -> Self {
+        self.case_insensitive = ignore;
+        self
+    }
+
+    /// The grammar with these policies.
+    pub fn build(self) -> Grammar {
+        Grammar {
+            skip_whitespace: self.skip_whitespace,
+            case_insensitive: self.case_insensitive,
+        }
+    }
+}
+
+/// A grammar, which builds rules following its policies.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Grammar {
+    skip_whitespace: bool,
+    case_insensitive: bool,
+}
+
+impl Grammar {
+    /// A rule for a token parsed by `parser`.
+    pub fn rule<P>(&self, parser: P) -> Rule<Token<P>> where P: Parser {
+        Rule::new(Token(parser, self.skip_whitespace))
+    }
+
+    /// A rule for a literal string.
+    pub fn literal(&self, literal: &'static str) -> Rule<Token<Tag>> {
+        if self.case_insensitive {
+            self.rule(Tag::no_case(literal))
+        } else {
+            self.rule(Tag::new(literal))
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Token<P>(P, bool);
+
+#[derive(Clone, Debug)]
+pub enum TokenState<S, T> {
+    Parsing(S, bool),
+    Skipping(T),
+}
+
+// Skip any whitespace after a token.
+
+fn skip<S, T, Str>(result: T, skip_whitespace: bool, string: &mut Str) -> ParseResult<TokenState<S, T>, T>
+    where Str: PeekableIterator<Item = char>,
+{
+    if !skip_whitespace {
+        return Done(result);
+    }
+    while string.next_if(|ch: char| ch.is_whitespace()).is_some() {}
+    if string.is_empty() {
+        Continue(TokenState::Skipping(result))
+    } else {
+        Done(result)
+    }
+}
+
+impl<P> Parser for Token<P> where P: Parser {}
+
+impl<S, T, Str> Stateful<char, Str, T> for TokenState<S, T>
+    where S: Stateful<char, Str, T>,
+          Str: PeekableIterator<Item = char>,
+{
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, T> {
+        match self {
+            TokenState::Parsing(state, skip_whitespace) => match state.more(string) {
+                Done(result) => skip(result, skip_whitespace, string),
+                Continue(state) => Continue(TokenState::Parsing(state, skip_whitespace)),
+            },
+            TokenState::Skipping(result) => skip(result, true, string),
+        }
+    }
+
+    fn done(self) -> T {
+        match self {
+            TokenState::Parsing(state, _) => state.done(),
+            TokenState::Skipping(result) => result,
+        }
+    }
+
+    fn finish(self, string: &mut Str) -> T {
+        let (result, skip_whitespace) = match self {
+            TokenState::Parsing(state, skip_whitespace) => (state.finish(string), skip_whitespace),
+            TokenState::Skipping(result) => (result, true),
+        };
+        if skip_whitespace {
+            while string.next_if(|ch: char| ch.is_whitespace()).is_some() {}
+        }
+        result
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match *self {
+            TokenState::Parsing(ref state, _) => state.dump(outline),
+            TokenState::Skipping(_) => outline.line("Token: skipping whitespace"),
+        }
+    }
+
+}
+
+impl<S, T, Str> HasOutput<char, Str> for TokenState<S, T> {
+
+    type Output = T;
+
+}
+
+impl<P, Str> HasOutput<char, Str> for Token<P>
+    where P: HasOutput<char, Str>,
+{
+
+    type Output = P::Output;
+
+}
+
+impl<P, T, Str> Uncommitted<char, Str, T> for Token<P>
+    where P: Uncommitted<char, Str, T>,
+          P::State: Stateful<char, Str, T>,
+          Str: PeekableIterator<Item = char>,
+{
+
+    type State = TokenState<P::State, T>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, T>> {
+        match self.0.init(string) {
+            None => None,
+            Some(Done(result)) => Some(skip(result, self.1, string)),
+            Some(Continue(state)) => Some(Continue(TokenState::Parsing(state, self.1))),
+        }
+    }
+
+}
+
+impl<P, T, Str> Committed<char, Str, T> for Token<P>
+    where P: Committed<char, Str, T>,
+          P::State: Stateful<char, Str, T>,
+          Str: PeekableIterator<Item = char>,
+{
+
+    fn empty(&self) -> T {
+        self.0.empty()
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_grammar() {
+    use super::{character, UncommittedStr, StatefulStr};
+    use super::testing;
+    let grammar = GrammarBuilder::new().skip_whitespace(true).build();
+    let number = grammar.rule(character(char::is_numeric).plus(String::new));
+    assert_eq!(number.init_str("12  +").unwrap().unDone(), "12");
+    let parsing = number.init_str("12 ").unwrap().unContinue();
+    assert_eq!(parsing.last_str(" "), "12");
+    assert_eq!(grammar.literal("if").init_str("IF"), None);
+    let sum = number << grammar.literal("+").opt();
+    testing::assert_chunk_invariant(sum, "12 + 3");
+    let grammar = GrammarBuilder::new().case_insensitive(true).build();
+    assert_eq!(grammar.literal("if").init_str("If x").unwrap().unDone(), Ok("if"));
+    let number = grammar.rule(character(char::is_numeric).plus(String::new));
+    assert_eq!(number.init_str("12 +").unwrap().unDone(), "12");
+}
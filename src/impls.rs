@@ -1,9 +1,14 @@
 //! Provide implementations of parser traits.
 
-use super::{Parser, ParseResult};
+use super::{Parser, ParseResult, IntoParser};
 use super::{HasOutput, StatefulInfer, Stateful, CommittedInfer, Committed, UncommittedInfer, Uncommitted, Boxable};
-use super::{Function, VariantFunction, Consumer, Factory, PeekableIterator};
-use super::{Upcast, Downcast, ToStatic};
+use super::{Erasable, ErasableCommitted, ErasedState, BoxedState, DynUncommitted, DynState};
+use super::{Function, VariantFunction, Consumer, Factory, PeekableIterator, ArrayConsumer};
+use super::{Upcast, Downcast, ToStatic, Outline, Keywords};
+use super::trace;
+use super::intern::Interner;
+#[cfg(feature = "metrics")]
+use super::metrics;
 use super::ParseResult::{Done, Continue};
 
 use self::OrElseState::{Lhs, Rhs};
@@ -11,7 +16,16 @@ use self::AndThenState::{InLhs, InBetween, InRhs};
 
 use std::borrow::Cow;
 use std::borrow::Cow::{Borrowed, Owned};
+use std::cell::Cell;
+use std::io;
+use std::iter::Peekable;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::rc::Rc;
 use std::str::Chars;
+use std::sync::Arc;
 use std::fmt::{Formatter, Debug};
 use std;
 
@@ -257,6 +271,59 @@ impl<F> Unwrap<F> {
     }
 }
 
+#[derive(Copy, Clone, Debug)]
+pub struct UnwrapOrDefault;
+impl<T> Function<Option<T>> for UnwrapOrDefault
+    where T: Default
+{
+    type Output = T;
+    fn apply(&self, arg: Option<T>) -> T {
+        arg.unwrap_or_default()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct UnwrapOrElse<F>(F);
+impl<F, T> Function<Option<T>> for UnwrapOrElse<F>
+    where F: Factory<Output = T>
+{
+    type Output = T;
+    fn apply(&self, arg: Option<T>) -> T {
+        arg.unwrap_or_else(|| self.0.build())
+    }
+}
+impl<F> UnwrapOrElse<F> {
+    pub fn new(f: F) -> UnwrapOrElse<F> {
+        UnwrapOrElse(f)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct OkOrElse<F>(F);
+impl<F, T> Function<Option<T>> for OkOrElse<F>
+    where F: Factory
+{
+    type Output = Result<T, F::Output>;
+    fn apply(&self, arg: Option<T>) -> Result<T, F::Output> {
+        arg.ok_or_else(|| self.0.build())
+    }
+}
+impl<F> OkOrElse<F> {
+    pub fn new(f: F) -> OkOrElse<F> {
+        OkOrElse(f)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DropCount;
+impl<T> Function<Option<(usize, T)>> for DropCount
+{
+    type Output = Option<T>;
+    fn apply(&self, arg: Option<(usize, T)>) -> Option<T> {
+        arg.map(|(_, result)| result)
+    }
+}
+
 // ----------- Deal with dereferencing ---------------
 
 #[derive(Copy, Clone, Debug)]
@@ -313,6 +380,233 @@ impl<T> VariantFunction<T> for Second
 }
 
 
+#[derive(Copy, Clone, Debug)]
+pub struct Triple;
+impl<S, T, U> Function<((S, T), U)> for Triple
+{
+    type Output = (S, T, U);
+    fn apply(&self, arg: ((S, T), U)) -> (S, T, U) {
+        ((arg.0).0, (arg.0).1, arg.1)
+    }
+}
+impl<S, T, U> VariantFunction<(S, T, U)> for Triple
+{
+    type Input = ((S, T), U);
+    fn apply(&self, arg: ((S, T), U)) -> (S, T, U) {
+        ((arg.0).0, (arg.0).1, arg.1)
+    }
+}
+
+// The parser built by p.try_seq3(q, r).
+
+pub type TrySeq3<P, Q, R> = VariantMap<VariantMap<AndThen<VariantMap<AndThen<P, Q>, TryZipTry>, R>, TryZipTry>, Try<Triple>>;
+
+// ----------- Composing functions ---------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Identity;
+impl<S> Function<S> for Identity
+{
+    type Output = S;
+    fn apply(&self, arg: S) -> S {
+        arg
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Const<T>(pub T);
+impl<S, T> Function<S> for Const<T>
+    where T: Clone
+{
+    type Output = T;
+    fn apply(&self, _: S) -> T {
+        self.0.clone()
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Compose<F, G>(F, G);
+impl<F, G, S> Function<S> for Compose<F, G>
+    where G: Function<S>,
+          F: Function<G::Output>,
+{
+    type Output = F::Output;
+    fn apply(&self, arg: S) -> F::Output {
+        self.0.apply(self.1.apply(arg))
+    }
+}
+impl<F, G> Compose<F, G> {
+    pub fn new(f: F, g: G) -> Compose<F, G> {
+        Compose(f, g)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct AndThenFn<F, G>(F, G);
+impl<F, G, S> Function<S> for AndThenFn<F, G>
+    where F: Function<S>,
+          G: Function<F::Output>,
+{
+    type Output = G::Output;
+    fn apply(&self, arg: S) -> G::Output {
+        self.1.apply(self.0.apply(arg))
+    }
+}
+impl<F, G> AndThenFn<F, G> {
+    pub fn new(f: F, g: G) -> AndThenFn<F, G> {
+        AndThenFn(f, g)
+    }
+}
+
+// ----------- Conversions ---------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct DigitsToU64(pub u32);
+impl<S> Function<S> for DigitsToU64
+    where S: AsRef<str>
+{
+    type Output = Option<u64>;
+    fn apply(&self, arg: S) -> Option<u64> {
+        let digits = arg.as_ref();
+        if digits.starts_with('+') {
+            None
+        } else {
+            u64::from_str_radix(digits, self.0).ok()
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct HexPairToByte;
+impl Function<(char, char)> for HexPairToByte
+{
+    type Output = Option<u8>;
+    fn apply(&self, arg: (char, char)) -> Option<u8> {
+        match (arg.0.to_digit(16), arg.1.to_digit(16)) {
+            (Some(high), Some(low)) => Some((high * 16 + low) as u8),
+            _ => None,
+        }
+    }
+}
+impl Function<(char, Option<char>)> for HexPairToByte
+{
+    type Output = Option<u8>;
+    fn apply(&self, arg: (char, Option<char>)) -> Option<u8> {
+        arg.1.and_then(|low| self.apply((arg.0, low)))
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CharsToString;
+impl<S> Function<S> for CharsToString
+    where S: IntoIterator<Item = char>
+{
+    type Output = String;
+    fn apply(&self, arg: S) -> String {
+        arg.into_iter().collect()
+    }
+}
+
+// The size of a value, as counted by a BoundedConsumer.
+#[derive(Copy, Clone, Debug)]
+pub struct CountItems;
+impl<'a, T> Function<&'a T> for CountItems {
+    type Output = usize;
+    fn apply(&self, _: &'a T) -> usize {
+        1
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct CountBytes;
+impl<'a> Function<&'a char> for CountBytes {
+    type Output = usize;
+    fn apply(&self, arg: &'a char) -> usize {
+        arg.len_utf8()
+    }
+}
+impl<'a> Function<&'a u8> for CountBytes {
+    type Output = usize;
+    fn apply(&self, _: &'a u8) -> usize {
+        1
+    }
+}
+impl<'a, 'b> Function<&'a &'b str> for CountBytes {
+    type Output = usize;
+    fn apply(&self, arg: &'a &'b str) -> usize {
+        arg.len()
+    }
+}
+impl<'a> Function<&'a String> for CountBytes {
+    type Output = usize;
+    fn apply(&self, arg: &'a String) -> usize {
+        arg.len()
+    }
+}
+impl<'a, 'b> Function<&'a Cow<'b, str>> for CountBytes {
+    type Output = usize;
+    fn apply(&self, arg: &'a Cow<'b, str>) -> usize {
+        arg.len()
+    }
+}
+impl<'a, 'b> Function<&'a &'b [u8]> for CountBytes {
+    type Output = usize;
+    fn apply(&self, arg: &'a &'b [u8]) -> usize {
+        arg.len()
+    }
+}
+impl<'a> Function<&'a Vec<u8>> for CountBytes {
+    type Output = usize;
+    fn apply(&self, arg: &'a Vec<u8>) -> usize {
+        arg.len()
+    }
+}
+
+// Keywords are looked up by binary search, so the table is sorted by name,
+// which `Keywords::new` checks at compile time using this comparison.
+pub const fn keyword_less(lhs: &str, rhs: &str) -> bool {
+    let (lhs, rhs) = (lhs.as_bytes(), rhs.as_bytes());
+    let mut index = 0;
+    while index < lhs.len() && index < rhs.len() {
+        if lhs[index] != rhs[index] {
+            return lhs[index] < rhs[index];
+        }
+        index += 1;
+    }
+    lhs.len() < rhs.len()
+}
+
+pub struct KeywordMap<K: 'static, S>(&'static [(&'static str, K)], fn(S) -> K);
+impl<K, S> Copy for KeywordMap<K, S> {}
+impl<K, S> Clone for KeywordMap<K, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+// A work around for functions not implementing Debug
+impl<K, S> Debug for KeywordMap<K, S> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "KeywordMap(...)")
+    }
+}
+impl<K, S> Function<S> for KeywordMap<K, S>
+    where K: Clone,
+          S: AsRef<str>,
+{
+    type Output = K;
+    fn apply(&self, arg: S) -> K {
+        match self.0.binary_search_by(|&(keyword, _)| keyword.cmp(arg.as_ref())) {
+            Ok(index) => self.0[index].1.clone(),
+            Err(_) => (self.1)(arg),
+        }
+    }
+}
+impl<K, S> KeywordMap<K, S> {
+    pub fn new(keywords: &'static Keywords<K>, other: fn(S) -> K) -> Self {
+        KeywordMap(keywords.0, other)
+    }
+}
+
 // ----------- Map ---------------
 
 pub struct Map<P, F>(P, F);
@@ -360,6 +654,14 @@ impl<P, F, Ch, Str, Output> Stateful<Ch, Str, Output> for Map<P, F>
         }
     }
 
+    fn finish(self, string: &mut Str) -> Output {
+        self.1.apply(self.0.finish(string))
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        self.0.dump(outline)
+    }
+
 }
 
 impl<P, F, Ch, Str> HasOutput<Ch, Str> for Map<P, F>
@@ -389,11 +691,7 @@ impl<P, F, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Map<P, F>
     type State = Map<P::State, F>;
 
     fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
-        match self.0.init(string) {
-            None => None,
-            Some(Done(result)) => Some(Done(self.1.apply(result))),
-            Some(Continue(state)) => Some(Continue(Map(state, self.1))),
-        }
+        self.0.init(string).map(|result| result.map(|result| self.1.apply(result)).map_continue(|state| Map(state, self.1)))
     }
 
 }
@@ -404,6 +702,173 @@ impl<P, F> Map<P, F> {
     }
 }
 
+// ----------- Tee ---------------
+
+// The parser p.tee(consumer) passes a copy of each output of p to the consumer,
+// as well as producing it. The parser is copied, so the consumer is a handle,
+// such as a &RefCell<C>, which is cloned each time it is used.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Tee<P, C>(P, C);
+
+impl<P, C> Parser for Tee<P, C> {}
+
+impl<P, C, Ch, Str, Output> Stateful<Ch, Str, Output> for Tee<P, C>
+    where P: Stateful<Ch, Str, Output>,
+          C: Clone + Consumer<Output>,
+          Output: Clone,
+{
+
+    fn done(self) -> Output {
+        tee(self.1, self.0.done())
+    }
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, Output> {
+        match self.0.more(string) {
+            Done(result) => Done(tee(self.1, result)),
+            Continue(state) => Continue(Tee(state, self.1)),
+        }
+    }
+
+    fn finish(self, string: &mut Str) -> Output {
+        tee(self.1, self.0.finish(string))
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        self.0.dump(outline)
+    }
+
+}
+
+fn tee<C, Output>(mut consumer: C, result: Output) -> Output
+    where C: Consumer<Output>,
+          Output: Clone,
+{
+    consumer.accept(result.clone());
+    result
+}
+
+impl<P, C, Ch, Str> HasOutput<Ch, Str> for Tee<P, C>
+    where P: HasOutput<Ch, Str>,
+{
+
+    type Output = P::Output;
+
+}
+
+impl<P, C, Ch, Str, Output> Committed<Ch, Str, Output> for Tee<P, C>
+    where P: Committed<Ch, Str, Output>,
+          C: Clone + Consumer<Output>,
+          Output: Clone,
+{
+
+    fn empty(&self) -> Output {
+        tee(self.1.clone(), self.0.empty())
+    }
+
+}
+
+impl<P, C, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Tee<P, C>
+    where P: Uncommitted<Ch, Str, Output>,
+          C: Clone + Consumer<Output>,
+          Output: Clone,
+{
+    type State = Tee<P::State, C>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        self.0.init(string).map(|result| match result {
+            Done(result) => Done(tee(self.1.clone(), result)),
+            Continue(state) => Continue(Tee(state, self.1.clone())),
+        })
+    }
+
+}
+
+impl<P, C> Tee<P, C> {
+    pub fn new(p: P, consumer: C) -> Self {
+        Tee(p, consumer)
+    }
+}
+
+// ----------- Interned ---------------
+
+// The parser p.interned(interner) interns each string output of p, producing a symbol.
+// Like tee, the interner is a handle, such as a &RefCell<I>, which is cloned each time it is used.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Interned<P, I>(P, I);
+
+impl<P, I> Parser for Interned<P, I> {}
+
+impl<P, I, Ch, Str> Stateful<Ch, Str, I::Symbol> for Interned<P, I>
+    where P: StatefulInfer<Ch, Str>,
+          P::Output: AsRef<str>,
+          I: Clone + Interner,
+{
+
+    fn done(mut self) -> I::Symbol {
+        self.1.intern(self.0.done().as_ref())
+    }
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, I::Symbol> {
+        match self.0.more(string) {
+            Done(result) => Done(self.1.intern(result.as_ref())),
+            Continue(state) => Continue(Interned(state, self.1)),
+        }
+    }
+
+    fn finish(mut self, string: &mut Str) -> I::Symbol {
+        self.1.intern(self.0.finish(string).as_ref())
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        self.0.dump(outline)
+    }
+
+}
+
+impl<P, I, Ch, Str> HasOutput<Ch, Str> for Interned<P, I>
+    where I: Interner,
+{
+
+    type Output = I::Symbol;
+
+}
+
+impl<P, I, Ch, Str> Committed<Ch, Str, I::Symbol> for Interned<P, I>
+    where P: CommittedInfer<Ch, Str>,
+          P::Output: AsRef<str>,
+          I: Clone + Interner,
+{
+
+    fn empty(&self) -> I::Symbol {
+        self.1.clone().intern(self.0.empty().as_ref())
+    }
+
+}
+
+impl<P, I, Ch, Str> Uncommitted<Ch, Str, I::Symbol> for Interned<P, I>
+    where P: UncommittedInfer<Ch, Str>,
+          P::Output: AsRef<str>,
+          I: Clone + Interner,
+{
+    type State = Interned<P::State, I>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, I::Symbol>> {
+        self.0.init(string).map(|result| match result {
+            Done(result) => Done(self.1.clone().intern(result.as_ref())),
+            Continue(state) => Continue(Interned(state, self.1.clone())),
+        })
+    }
+
+}
+
+impl<P, I> Interned<P, I> {
+    pub fn new(p: P, interner: I) -> Self {
+        Interned(p, interner)
+    }
+}
+
 // ----------- Variant map ---------------
 
 // A version of map for functions that can comute their input types from their output types
@@ -453,6 +918,14 @@ impl<P, F, Ch, Str, Output> Stateful<Ch, Str, Output> for VariantMap<P, F>
         }
     }
 
+    fn finish(self, string: &mut Str) -> Output {
+        self.1.apply(self.0.finish(string))
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        self.0.dump(outline)
+    }
+
 }
 
 impl<P, F, Ch, Str> HasOutput<Ch, Str> for VariantMap<P, F>
@@ -483,11 +956,7 @@ impl<P, F, Ch, Str, Output> Uncommitted<Ch, Str, Output> for VariantMap<P, F>
     type State = VariantMap<P::State, F>;
 
     fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
-        match self.0.init(string) {
-            None => None,
-            Some(Done(result)) => Some(Done(self.1.apply(result))),
-            Some(Continue(state)) => Some(Continue(VariantMap(state, self.1))),
-        }
+        self.0.init(string).map(|result| result.map(|result| self.1.apply(result)).map_continue(|state| VariantMap(state, self.1)))
     }
 
 }
@@ -507,7 +976,7 @@ impl<P, Q> Parser for AndThen<P, Q> {}
 
 impl<P, Q, Ch, Str, POutput, PStaticOutput, QOutput> Committed<Ch, Str, (POutput, QOutput)> for AndThen<P, Q>
     where P: Committed<Ch, Str, POutput>,
-          Q: 'static + Copy + Committed<Ch, Str, QOutput>,
+          Q: 'static + Clone + Committed<Ch, Str, QOutput>,
           POutput: ToStatic<Static = PStaticOutput> + Downcast<PStaticOutput>,
 {
 
@@ -519,7 +988,7 @@ impl<P, Q, Ch, Str, POutput, PStaticOutput, QOutput> Committed<Ch, Str, (POutput
 
 impl<P, Q, Ch, Str, POutput, PStaticOutput, QOutput> Uncommitted<Ch, Str, (POutput, QOutput)> for AndThen<P, Q>
     where P: Uncommitted<Ch, Str, POutput>,
-          Q: 'static + Copy + Committed<Ch, Str, QOutput>,
+          Q: 'static + Clone + Committed<Ch, Str, QOutput>,
           POutput: ToStatic<Static = PStaticOutput> + Downcast<PStaticOutput>,
 {
 
@@ -529,11 +998,11 @@ impl<P, Q, Ch, Str, POutput, PStaticOutput, QOutput> Uncommitted<Ch, Str, (POutp
         match self.0.init(string) {
             None => None,
             Some(Done(fst)) => match self.1.init(string) {
-                None => Some(Continue(InBetween(fst.downcast(), self.1))),
+                None => Some(Continue(InBetween(fst.downcast(), self.1.clone()))),
                 Some(Done(snd)) => Some(Done((fst, snd))),
                 Some(Continue(snd)) => Some(Continue(InRhs(fst.downcast(), snd))),
             },
-            Some(Continue(fst)) => Some(Continue(InLhs(fst, self.1))),
+            Some(Continue(fst)) => Some(Continue(InLhs(fst, self.1.clone()))),
         }
     }
 
@@ -607,6 +1076,45 @@ impl<PState, Q, PStaticOutput, QState, Ch, Str, POutput, QOutput> Stateful<Ch, S
         }
     }
 
+    fn finish(self, string: &mut Str) -> (POutput, QOutput)
+    {
+        match self {
+            InLhs(fst, snd) => {
+                let fst = fst.finish(string);
+                (fst, finish_committed(snd, string))
+            },
+            InBetween(fst, snd) => (fst.upcast(), finish_committed(snd, string)),
+            InRhs(fst, snd) => (fst.upcast(), snd.finish(string)),
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match *self {
+            InLhs(ref fst, _) => {
+                outline.line("AndThen: in lhs");
+                outline.nested(|outline| fst.dump(outline));
+            },
+            InBetween(..) => outline.line("AndThen: between"),
+            InRhs(_, ref snd) => {
+                outline.line("AndThen: in rhs");
+                outline.nested(|outline| snd.dump(outline));
+            },
+        }
+    }
+
+}
+
+// Run a committed parser at the end of input.
+
+fn finish_committed<Q, Ch, Str, Output>(parser: Q, string: &mut Str) -> Output
+    where Q: Committed<Ch, Str, Output>,
+          Q::State: Stateful<Ch, Str, Output>,
+{
+    match parser.init(string) {
+        None => parser.empty(),
+        Some(Done(result)) => result,
+        Some(Continue(state)) => state.finish(string),
+    }
 }
 
 impl<PState, Q, PStaticOutput, QState, Ch, Str> HasOutput<Ch, Str> for AndThenState<PState, Q, PStaticOutput, QState>
@@ -618,16 +1126,247 @@ impl<PState, Q, PStaticOutput, QState, Ch, Str> HasOutput<Ch, Str> for AndThenSt
 
 }
 
-// ----------- Choice ---------------
+// ----------- Sequencing which skips after errors ---------------
 
-#[derive(Copy, Clone, Debug)]
-pub struct OrElse<P, Q>(P, Q);
+// The parser p.try_and_then_try_or_skip(q, sync) is like p.try_and_then_try(q), except that if
+// p produces an error, q is not run. Instead the input is skipped up to and including the next
+// item satisfying sync, and the error is produced.
 
-impl<P, Q> Parser for OrElse<P, Q> {}
+pub struct TryAndThenOrSkip<P, Q, F>(P, Q, F);
 
-impl<P, Q, Ch, Str, Output> Committed<Ch, Str, Output> for OrElse<P, Q>
-    where P: Uncommitted<Ch, Str, Output>,
-          Q: Committed<Ch, Str, Output>,
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<P, Q, F> Copy for TryAndThenOrSkip<P, Q, F>
+    where P: Copy,
+          Q: Copy,
+          F: Copy
+{}
+impl<P, Q, F> Clone for TryAndThenOrSkip<P, Q, F>
+    where P: Clone,
+          Q: Clone,
+          F: Copy
+{
+    fn clone(&self) -> Self {
+        TryAndThenOrSkip(self.0.clone(), self.1.clone(), self.2)
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<P, Q, F> Debug for TryAndThenOrSkip<P, Q, F>
+    where P: Debug,
+          Q: Debug,
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "TryAndThenOrSkip({:?}, {:?}, ...)", self.0, self.1)
+    }
+}
+
+impl<P, Q, F> Parser for TryAndThenOrSkip<P, Q, F> {}
+
+// Skip to the synchronization point, returning whether it was found.
+
+fn skip_to_sync<F, Ch, Str>(sync: &F, string: &mut Str) -> bool
+    where Str: Iterator<Item = Ch>,
+          F: Function<Ch, Output = bool>,
+{
+    string.any(|ch| sync.apply(ch))
+}
+
+fn try_zip<S, T, E>(fst: S, snd: Result<T, E>) -> Result<(S, T), E> {
+    snd.map(|snd| (fst, snd))
+}
+
+impl<P, Q, F, Ch, Str, S, SStatic, T, E> Committed<Ch, Str, Result<(S, T), E>> for TryAndThenOrSkip<P, Q, F>
+    where P: Committed<Ch, Str, Result<S, E>>,
+          Q: 'static + Clone + Committed<Ch, Str, Result<T, E>>,
+          F: 'static + Copy + Function<Ch, Output = bool>,
+          Str: Iterator<Item = Ch>,
+          S: ToStatic<Static = SStatic> + Downcast<SStatic>,
+          SStatic: 'static + Upcast<S>,
+{
+
+    fn empty(&self) -> Result<(S, T), E> {
+        try_zip(try!(self.0.empty()), self.1.empty())
+    }
+
+}
+
+impl<P, Q, F, Ch, Str, S, SStatic, T, E> Uncommitted<Ch, Str, Result<(S, T), E>> for TryAndThenOrSkip<P, Q, F>
+    where P: Uncommitted<Ch, Str, Result<S, E>>,
+          Q: 'static + Clone + Committed<Ch, Str, Result<T, E>>,
+          F: 'static + Copy + Function<Ch, Output = bool>,
+          Str: Iterator<Item = Ch>,
+          S: ToStatic<Static = SStatic> + Downcast<SStatic>,
+          SStatic: 'static + Upcast<S>,
+{
+
+    type State = TryAndThenOrSkipState<P::State, Q, SStatic, Q::State, F, E>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<(S, T), E>>> {
+        match self.0.init(string) {
+            None => None,
+            Some(Done(fst)) => Some(TryAndThenOrSkipState::after_lhs(fst, self.1.clone(), self.2, string)),
+            Some(Continue(fst)) => Some(Continue(TryAndThenOrSkipState::InLhs(fst, self.1.clone(), self.2))),
+        }
+    }
+
+}
+
+impl<P, Q, F, Ch, Str> HasOutput<Ch, Str> for TryAndThenOrSkip<P, Q, F>
+    where P: HasOutput<Ch, Str>,
+          Q: HasOutput<Ch, Str>,
+          TryZipTry: Function<(P::Output, Q::Output)>,
+{
+
+    type Output = <TryZipTry as Function<(P::Output, Q::Output)>>::Output;
+
+}
+
+impl<P, Q, F> TryAndThenOrSkip<P, Q, F> {
+    pub fn new(p: P, q: Q, sync: F) -> Self {
+        TryAndThenOrSkip(p, q, sync)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum TryAndThenOrSkipState<PState, Q, SStatic, QState, F, E> {
+    InLhs(PState, Q, F),
+    InBetween(SStatic, Q),
+    InRhs(SStatic, QState),
+    Skipping(E, F),
+}
+
+impl<PState, Q, SStatic, QState, F, E> TryAndThenOrSkipState<PState, Q, SStatic, QState, F, E> {
+    fn after_lhs<Ch, Str, S, T>(fst: Result<S, E>, snd: Q, sync: F, string: &mut Str) -> ParseResult<Self, Result<(S, T), E>>
+        where Q: Committed<Ch, Str, Result<T, E>, State = QState>,
+              F: Function<Ch, Output = bool>,
+              Str: Iterator<Item = Ch>,
+              S: Downcast<SStatic>,
+    {
+        match fst {
+            Err(err) => if skip_to_sync(&sync, string) {
+                Done(Err(err))
+            } else {
+                Continue(TryAndThenOrSkipState::Skipping(err, sync))
+            },
+            Ok(fst) => match snd.init(string) {
+                None => Continue(TryAndThenOrSkipState::InBetween(fst.downcast(), snd)),
+                Some(Done(snd)) => Done(try_zip(fst, snd)),
+                Some(Continue(snd)) => Continue(TryAndThenOrSkipState::InRhs(fst.downcast(), snd)),
+            },
+        }
+    }
+}
+
+impl<PState, Q, SStatic, QState, F, Ch, Str, S, T, E> Stateful<Ch, Str, Result<(S, T), E>> for TryAndThenOrSkipState<PState, Q, SStatic, QState, F, E>
+    where PState: Stateful<Ch, Str, Result<S, E>>,
+          Q: Committed<Ch, Str, Result<T, E>, State = QState>,
+          QState: Stateful<Ch, Str, Result<T, E>>,
+          F: Function<Ch, Output = bool>,
+          Str: Iterator<Item = Ch>,
+          S: Downcast<SStatic>,
+          SStatic: 'static + Upcast<S>,
+{
+
+    fn done(self) -> Result<(S, T), E>
+    {
+        match self {
+            TryAndThenOrSkipState::InLhs(fst, snd, _) => try_zip(try!(fst.done()), snd.empty()),
+            TryAndThenOrSkipState::InBetween(fst, snd) => try_zip(fst.upcast(), snd.empty()),
+            TryAndThenOrSkipState::InRhs(fst, snd) => try_zip(fst.upcast(), snd.done()),
+            TryAndThenOrSkipState::Skipping(err, _) => Err(err),
+        }
+    }
+
+    fn finish(self, string: &mut Str) -> Result<(S, T), E>
+    {
+        match self {
+            TryAndThenOrSkipState::InLhs(fst, snd, sync) => match fst.finish(string) {
+                Ok(fst) => try_zip(fst, finish_committed(snd, string)),
+                Err(err) => {
+                    skip_to_sync(&sync, string);
+                    Err(err)
+                },
+            },
+            TryAndThenOrSkipState::InBetween(fst, snd) => try_zip(fst.upcast(), finish_committed(snd, string)),
+            TryAndThenOrSkipState::InRhs(fst, snd) => try_zip(fst.upcast(), snd.finish(string)),
+            TryAndThenOrSkipState::Skipping(err, sync) => {
+                skip_to_sync(&sync, string);
+                Err(err)
+            },
+        }
+    }
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, Result<(S, T), E>>
+    {
+        match self {
+            TryAndThenOrSkipState::InLhs(fst, snd, sync) => {
+                match fst.more(string) {
+                    Done(fst) => TryAndThenOrSkipState::after_lhs(fst, snd, sync, string),
+                    Continue(fst) => Continue(TryAndThenOrSkipState::InLhs(fst, snd, sync)),
+                }
+            }
+            TryAndThenOrSkipState::InBetween(fst, snd) => {
+                match snd.init(string) {
+                    None => Continue(TryAndThenOrSkipState::InBetween(fst, snd)),
+                    Some(Done(snd)) => Done(try_zip(fst.upcast(), snd)),
+                    Some(Continue(snd)) => Continue(TryAndThenOrSkipState::InRhs(fst, snd)),
+                }
+            }
+            TryAndThenOrSkipState::InRhs(fst, snd) => {
+                match snd.more(string) {
+                    Done(snd) => Done(try_zip(fst.upcast(), snd)),
+                    Continue(snd) => Continue(TryAndThenOrSkipState::InRhs(fst, snd)),
+                }
+            }
+            TryAndThenOrSkipState::Skipping(err, sync) => {
+                if skip_to_sync(&sync, string) {
+                    Done(Err(err))
+                } else {
+                    Continue(TryAndThenOrSkipState::Skipping(err, sync))
+                }
+            }
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match *self {
+            TryAndThenOrSkipState::InLhs(ref fst, _, _) => {
+                outline.line("TryAndThenOrSkip: in lhs");
+                outline.nested(|outline| fst.dump(outline));
+            },
+            TryAndThenOrSkipState::InBetween(..) => outline.line("TryAndThenOrSkip: between"),
+            TryAndThenOrSkipState::InRhs(_, ref snd) => {
+                outline.line("TryAndThenOrSkip: in rhs");
+                outline.nested(|outline| snd.dump(outline));
+            },
+            TryAndThenOrSkipState::Skipping(..) => outline.line("TryAndThenOrSkip: skipping to synchronization point"),
+        }
+    }
+
+}
+
+impl<PState, Q, SStatic, QState, F, E, Ch, Str> HasOutput<Ch, Str> for TryAndThenOrSkipState<PState, Q, SStatic, QState, F, E>
+    where PState: HasOutput<Ch, Str>,
+          Q: HasOutput<Ch, Str>,
+          TryZipTry: Function<(PState::Output, Q::Output)>,
+{
+
+    type Output = <TryZipTry as Function<(PState::Output, Q::Output)>>::Output;
+
+}
+
+// ----------- Choice ---------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct OrElse<P, Q>(P, Q);
+
+impl<P, Q> Parser for OrElse<P, Q> {}
+
+impl<P, Q, Ch, Str, Output> Committed<Ch, Str, Output> for OrElse<P, Q>
+    where P: Uncommitted<Ch, Str, Output>,
+          Q: Committed<Ch, Str, Output>,
 {
 
     fn empty(&self) -> Output {
@@ -701,6 +1440,20 @@ impl<P, Q, Ch, Str, Output> Stateful<Ch, Str, Output> for OrElseState<P, Q>
         }
     }
 
+    fn finish(self, string: &mut Str) -> Output {
+        match self {
+            Lhs(lhs) => lhs.finish(string),
+            Rhs(rhs) => rhs.finish(string),
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match *self {
+            Lhs(ref lhs) => lhs.dump(outline),
+            Rhs(ref rhs) => rhs.dump(outline),
+        }
+    }
+
 }
 
 impl<P, Q, Ch, Str> HasOutput<Ch, Str> for OrElseState<P, Q>
@@ -709,13 +1462,98 @@ impl<P, Q, Ch, Str> HasOutput<Ch, Str> for OrElseState<P, Q>
     type Output = P::Output;
 }
 
+// ----------- Conditional choice ---------------
+
+// The parser when(flag, p, q) runs p if flag.build() is true when it starts, and q otherwise.
+// The flag typically reads some context shared with the caller, such as a protocol version.
+
+pub struct When<F, P, Q>(F, P, Q);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F, P, Q> Copy for When<F, P, Q>
+    where F: Copy,
+          P: Copy,
+          Q: Copy
+{}
+impl<F, P, Q> Clone for When<F, P, Q>
+    where F: Copy,
+          P: Clone,
+          Q: Clone
+{
+    fn clone(&self) -> Self {
+        When(self.0, self.1.clone(), self.2.clone())
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F, P, Q> Debug for When<F, P, Q>
+    where P: Debug,
+          Q: Debug
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "When(..., {:?}, {:?})", self.1, self.2)
+    }
+}
+
+impl<F, P, Q> Parser for When<F, P, Q> {}
+
+impl<F, P, Q, Ch, Str, Output> Committed<Ch, Str, Output> for When<F, P, Q>
+    where F: Factory<Output = bool>,
+          P: Committed<Ch, Str, Output>,
+          Q: Committed<Ch, Str, Output>,
+{
+
+    fn empty(&self) -> Output {
+        if self.0.build() {
+            self.1.empty()
+        } else {
+            self.2.empty()
+        }
+    }
+
+}
+
+impl<F, P, Q, Ch, Str, Output> Uncommitted<Ch, Str, Output> for When<F, P, Q>
+    where F: Factory<Output = bool>,
+          P: Uncommitted<Ch, Str, Output>,
+          Q: Uncommitted<Ch, Str, Output>,
+{
+
+    type State = OrElseState<P::State, Q::State>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        if self.0.build() {
+            self.1.init(string).map(|result| result.map_continue(Lhs))
+        } else {
+            self.2.init(string).map(|result| result.map_continue(Rhs))
+        }
+    }
+
+}
+
+impl<F, P, Q, Ch, Str> HasOutput<Ch, Str> for When<F, P, Q>
+    where P: HasOutput<Ch, Str>,
+{
+
+    type Output = P::Output;
+
+}
+
+impl<F, P, Q> When<F, P, Q> {
+    pub fn new(flag: F, lhs: P, rhs: Q) -> Self {
+        When(flag, lhs, rhs)
+    }
+}
+
 // ----------- Kleene star ---------------
 
 #[derive(Clone,Debug)]
 pub struct StarState<P, PState, T>(P, Option<PState>, T);
 
 impl<P, PState, T, Ch, Str> Stateful<Ch, Str, T> for StarState<P, PState, T>
-    where P: Copy + UncommittedInfer<Ch, Str, State = PState>,
+    where P: Clone + UncommittedInfer<Ch, Str, State = PState>,
           PState: Stateful<Ch, Str, P::Output>,
           T: Consumer<P::Output>,
           Str: PeekableIterator,
@@ -724,9 +1562,15 @@ impl<P, PState, T, Ch, Str> Stateful<Ch, Str, T> for StarState<P, PState, T>
         loop {
             match self.1.take() {
                 None => {
+                    self.0.init_repeat(string, &mut self.2);
+                    let before = Progress::of(string);
                     match self.0.init(string) {
                         Some(Continue(state)) => return Continue(StarState(self.0, Some(state), self.2)),
-                        Some(Done(result)) => self.2.accept(result),
+                        Some(Done(result)) => if before.made(string) {
+                            self.2.accept(result)
+                        } else {
+                            return Done(self.2)
+                        },
                         None => return if string.is_empty() {
                             Continue(self)
                         } else {
@@ -746,6 +1590,50 @@ impl<P, PState, T, Ch, Str> Stateful<Ch, Str, T> for StarState<P, PState, T>
     fn done(self) -> T {
         self.2
     }
+    fn finish(mut self, string: &mut Str) -> T {
+        loop {
+            match self.1.take() {
+                Some(state) => self.2.accept(state.finish(string)),
+                None if string.is_empty() => return self.2,
+                None => {
+                    let before = Progress::of(string);
+                    match self.0.init(string) {
+                        Some(Continue(state)) => self.1 = Some(state),
+                        Some(Done(result)) if before.made(string) => self.2.accept(result),
+                        Some(Done(_)) | None => return self.2,
+                    }
+                }
+            }
+        }
+    }
+    fn dump(&self, outline: &mut Outline) {
+        outline.line("Star");
+        if let Some(ref state) = self.1 {
+            outline.nested(|outline| state.dump(outline));
+        }
+    }
+}
+
+// A repeated parser which succeeds without consuming any input would be repeated forever,
+// for example `p.opt().star(f)`, so the repetition ends instead. We can tell no input was
+// consumed if the input was already empty, or if its measure of progress did not change.
+// Inputs with no measure of progress are only checked at the end of input.
+
+#[derive(Copy, Clone, Debug)]
+struct Progress(bool, Option<usize>);
+
+impl Progress {
+    fn of<Str>(string: &mut Str) -> Self
+        where Str: PeekableIterator,
+    {
+        Progress(string.is_empty(), string.progress())
+    }
+
+    fn made<Str>(self, string: &Str) -> bool
+        where Str: PeekableIterator,
+    {
+        !self.0 && (self.1.is_none() || self.1 != string.progress())
+    }
 }
 
 impl<P, PState, T, Ch, Str> HasOutput<Ch, Str> for StarState<P, PState, T>
@@ -783,8 +1671,8 @@ impl<P, F> Debug for Plus<P, F>
 impl<P, F> Parser for Plus<P, F> {}
 
 impl<P, F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Plus<P, F>
-    where P: 'static + Copy + UncommittedInfer<Ch, Str>,
-          F: 'static + Factory,
+    where P: 'static + Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
           Str: PeekableIterator,
           P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
           F::Output: Consumer<P::Output>,
@@ -794,11 +1682,11 @@ impl<P, F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Plus<P, F>
     fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, F::Output>> {
         match self.0.init(string) {
             None => None,
-            Some(Continue(state)) => Some(Continue(StarState(self.0, Some(state), self.1.build()))),
+            Some(Continue(state)) => Some(Continue(StarState(self.0.clone(), Some(state), self.1.build()))),
             Some(Done(result)) => {
                 let mut buffer = self.1.build();
                 buffer.accept(result);
-                Some(StarState(self.0, None, buffer).more(string))
+                Some(StarState(self.0.clone(), None, buffer).more(string))
             },
         }
     }
@@ -854,8 +1742,8 @@ impl<P, F, Ch, Str> HasOutput<Ch, Str> for Star<P, F>
 }
 
 impl<P, F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Star<P, F>
-    where P: 'static + Copy + UncommittedInfer<Ch, Str>,
-          F: 'static + Factory,
+    where P: 'static + Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
           Str: PeekableIterator,
           P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
           F::Output: Consumer<P::Output>,
@@ -867,15 +1755,15 @@ impl<P, F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Star<P, F>
         if string.is_empty() {
             None
         } else {
-            Some(StarState(self.0, None, self.1.build()).more(string))
+            Some(StarState(self.0.clone(), None, self.1.build()).more(string))
         }
     }
 
 }
 
 impl<P, F, Ch, Str> Committed<Ch, Str, F::Output> for Star<P, F>
-    where P: 'static + Copy + UncommittedInfer<Ch, Str>,
-          F: 'static + Factory,
+    where P: 'static + Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
           Str: PeekableIterator,
           P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
           F::Output: Consumer<P::Output>,
@@ -893,452 +1781,3014 @@ impl<P, F> Star<P, F> {
     }
 }
 
-// ----------- Optional parse -------------
-
-#[derive(Copy, Clone, Debug)]
-pub struct Opt<P>(P);
+// ----------- Lazy Kleene star ---------------
 
-impl<P> Parser for Opt<P> where P: Parser {}
+#[derive(Clone,Debug)]
+pub struct StarLazyState<P, Q, PState, QState, T>(P, Q, Option<OrElseState<QState, PState>>, T);
 
-impl<P, Ch, Str, Output> Stateful<Ch, Str, Option<Output>> for Opt<P>
-    where P: Stateful<Ch, Str, Output>,
+impl<P, Q, PState, QState, T, Ch, Str> Stateful<Ch, Str, (T, Option<Q::Output>)> for StarLazyState<P, Q, PState, QState, T>
+    where P: Clone + UncommittedInfer<Ch, Str, State = PState>,
+          Q: Clone + UncommittedInfer<Ch, Str, State = QState>,
+          PState: Stateful<Ch, Str, P::Output>,
+          QState: Stateful<Ch, Str, Q::Output>,
+          T: Consumer<P::Output>,
+          Str: PeekableIterator,
 {
-
-    fn more(self, string: &mut Str) -> ParseResult<Self, Option<Output>> {
-        match self.0.more(string) {
-            Done(result) => Done(Some(result)),
-            Continue(parsing) => Continue(Opt(parsing)),
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, (T, Option<Q::Output>)> {
+        loop {
+            match self.2.take() {
+                None => {
+                    let before = Progress::of(string);
+                    match self.1.init(string) {
+                        Some(Continue(state)) => return Continue(StarLazyState(self.0, self.1, Some(Lhs(state)), self.3)),
+                        Some(Done(end)) => return Done((self.3, Some(end))),
+                        None => match self.0.init(string) {
+                            Some(Continue(state)) => return Continue(StarLazyState(self.0, self.1, Some(Rhs(state)), self.3)),
+                            Some(Done(result)) => if before.made(string) {
+                                self.3.accept(result)
+                            } else {
+                                return Done((self.3, None))
+                            },
+                            None => return if string.is_empty() {
+                                Continue(self)
+                            } else {
+                                Done((self.3, None))
+                            },
+                        },
+                    }
+                }
+                Some(Lhs(state)) => {
+                    match state.more(string) {
+                        Continue(state) => return Continue(StarLazyState(self.0, self.1, Some(Lhs(state)), self.3)),
+                        Done(end) => return Done((self.3, Some(end))),
+                    }
+                }
+                Some(Rhs(state)) => {
+                    match state.more(string) {
+                        Continue(state) => return Continue(StarLazyState(self.0, self.1, Some(Rhs(state)), self.3)),
+                        Done(result) => self.3.accept(result),
+                    }
+                }
+            }
         }
     }
-
-    fn done(self) -> Option<Output> {
-        Some(self.0.done())
+    fn done(mut self) -> (T, Option<Q::Output>) {
+        match self.2 {
+            None => (self.3, None),
+            Some(Lhs(state)) => (self.3, Some(state.done())),
+            Some(Rhs(state)) => {
+                self.3.accept(state.done());
+                (self.3, None)
+            },
+        }
+    }
+    fn finish(mut self, string: &mut Str) -> (T, Option<Q::Output>) {
+        loop {
+            match self.2.take() {
+                Some(Lhs(state)) => return (self.3, Some(state.finish(string))),
+                Some(Rhs(state)) => self.3.accept(state.finish(string)),
+                None if string.is_empty() => return (self.3, None),
+                None => {
+                    let before = Progress::of(string);
+                    match self.1.init(string) {
+                        Some(Continue(state)) => self.2 = Some(Lhs(state)),
+                        Some(Done(end)) => return (self.3, Some(end)),
+                        None => match self.0.init(string) {
+                            Some(Continue(state)) => self.2 = Some(Rhs(state)),
+                            Some(Done(result)) if before.made(string) => self.3.accept(result),
+                            Some(Done(_)) | None => return (self.3, None),
+                        },
+                    }
+                }
+            }
+        }
+    }
+    fn dump(&self, outline: &mut Outline) {
+        match self.2 {
+            None => outline.line("StarLazy"),
+            Some(Lhs(ref state)) => {
+                outline.line("StarLazy: in terminator");
+                outline.nested(|outline| state.dump(outline));
+            },
+            Some(Rhs(ref state)) => {
+                outline.line("StarLazy: in item");
+                outline.nested(|outline| state.dump(outline));
+            },
+        }
     }
-
 }
 
-impl<P, Ch, Str> HasOutput<Ch, Str> for Opt<P>
-    where P: HasOutput<Ch, Str>,
+impl<P, Q, PState, QState, T, Ch, Str> HasOutput<Ch, Str> for StarLazyState<P, Q, PState, QState, T>
+    where Q: HasOutput<Ch, Str>,
 {
-
-    type Output = Option<P::Output>;
-
+    type Output = (T, Option<Q::Output>);
 }
 
-impl<P, Ch, Str, Output> Uncommitted<Ch, Str, Option<Output>> for Opt<P>
-    where Str: PeekableIterator,
-          P: Uncommitted<Ch, Str, Output>,
-{
-
-    type State = Opt<P::State>;
+pub struct StarLazy<P, Q, F>(P, Q, F);
 
-    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<Output>>> {
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<P, Q, F> Copy for StarLazy<P, Q, F>
+    where P: Copy,
+          Q: Copy,
+          F: Copy
+{}
+impl<P, Q, F> Clone for StarLazy<P, Q, F>
+    where P: Clone,
+          Q: Clone,
+          F: Copy
+{
+    fn clone(&self) -> Self {
+        StarLazy(self.0.clone(), self.1.clone(), self.2)
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<P, Q, F> Debug for StarLazy<P, Q, F>
+    where P: Debug,
+          Q: Debug,
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "StarLazy({:?}, {:?}, ...)", self.0, self.1)
+    }
+}
+
+impl<P, Q, F> Parser for StarLazy<P, Q, F> {}
+
+impl<P, Q, F, Ch, Str> HasOutput<Ch, Str> for StarLazy<P, Q, F>
+    where Q: HasOutput<Ch, Str>,
+          F: Factory,
+{
+
+    type Output = (F::Output, Option<Q::Output>);
+
+}
+
+impl<P, Q, F, Ch, Str> Uncommitted<Ch, Str, (F::Output, Option<Q::Output>)> for StarLazy<P, Q, F>
+    where P: 'static + Clone + UncommittedInfer<Ch, Str>,
+          Q: 'static + Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
+          Str: PeekableIterator,
+          P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
+          Q::State: Stateful<Ch, Str, <Q as HasOutput<Ch, Str>>::Output>,
+          F::Output: Consumer<P::Output>,
+{
+
+    type State = StarLazyState<P, Q, P::State, Q::State, F::Output>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, (F::Output, Option<Q::Output>)>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(StarLazyState(self.0.clone(), self.1.clone(), None, self.2.build()).more(string))
+        }
+    }
+
+}
+
+impl<P, Q, F, Ch, Str> Committed<Ch, Str, (F::Output, Option<Q::Output>)> for StarLazy<P, Q, F>
+    where P: 'static + Clone + UncommittedInfer<Ch, Str>,
+          Q: 'static + Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
+          Str: PeekableIterator,
+          P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
+          Q::State: Stateful<Ch, Str, <Q as HasOutput<Ch, Str>>::Output>,
+          F::Output: Consumer<P::Output>,
+{
+
+    fn empty(&self) -> (F::Output, Option<Q::Output>) {
+        (self.2.build(), None)
+    }
+
+}
+
+impl<P, Q, F> StarLazy<P, Q, F> {
+    pub fn new(parser: P, until: Q, factory: F) -> Self {
+        StarLazy(parser, until, factory)
+    }
+}
+
+// ----------- Fixed-length repetition -------------
+
+#[derive(Clone, Debug)]
+pub struct CountState<P, PState, T, const N: usize>(P, Option<PState>, ArrayConsumer<T, N>);
+
+impl<P, PState, T, Ch, Str, const N: usize> Stateful<Ch, Str, [T; N]> for CountState<P, PState, T, N>
+    where P: Clone + Committed<Ch, Str, T, State = PState>,
+          PState: Stateful<Ch, Str, T>,
+{
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, [T; N]> {
+        while !self.2.is_full() {
+            match self.1.take() {
+                None => match self.0.init(string) {
+                    None => return Continue(self),
+                    Some(Done(result)) => self.2.accept(result),
+                    Some(Continue(state)) => return Continue(CountState(self.0, Some(state), self.2)),
+                },
+                Some(state) => match state.more(string) {
+                    Done(result) => self.2.accept(result),
+                    Continue(state) => return Continue(CountState(self.0, Some(state), self.2)),
+                },
+            }
+        }
+        Done(self.2.into_array().unwrap())
+    }
+    fn done(mut self) -> [T; N] {
+        if let Some(state) = self.1.take() {
+            self.2.accept(state.done());
+        }
+        while !self.2.is_full() {
+            self.2.accept(self.0.empty());
+        }
+        self.2.into_array().unwrap()
+    }
+    fn finish(mut self, string: &mut Str) -> [T; N] {
+        if let Some(state) = self.1.take() {
+            self.2.accept(state.finish(string));
+        }
+        while !self.2.is_full() {
+            self.2.accept(finish_committed(self.0.clone(), string));
+        }
+        self.2.into_array().unwrap()
+    }
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("Count: {} of {}", self.2.len(), N));
+        if let Some(ref state) = self.1 {
+            outline.nested(|outline| state.dump(outline));
+        }
+    }
+}
+
+impl<P, PState, T, Ch, Str, const N: usize> HasOutput<Ch, Str> for CountState<P, PState, T, N>
+{
+    type Output = [T; N];
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Count<P, const N: usize>(P);
+
+impl<P, const N: usize> Parser for Count<P, N> {}
+
+impl<P, Ch, Str, const N: usize> HasOutput<Ch, Str> for Count<P, N>
+    where P: HasOutput<Ch, Str>,
+{
+    type Output = [P::Output; N];
+}
+
+impl<P, Ch, Str, Output, const N: usize> Uncommitted<Ch, Str, [Output; N]> for Count<P, N>
+    where P: 'static + Clone + Committed<Ch, Str, Output>,
+          P::State: Stateful<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+    type State = CountState<P, P::State, Output, N>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, [Output; N]>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(CountState(self.0.clone(), None, ArrayConsumer::new()).more(string))
+        }
+    }
+}
+
+impl<P, Ch, Str, Output, const N: usize> Committed<Ch, Str, [Output; N]> for Count<P, N>
+    where P: 'static + Clone + Committed<Ch, Str, Output>,
+          P::State: Stateful<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+    fn empty(&self) -> [Output; N] {
+        CountState(self.0.clone(), None, ArrayConsumer::new()).done()
+    }
+}
+
+impl<P, const N: usize> Count<P, N> {
+    pub fn new(parser: P) -> Self {
+        Count(parser)
+    }
+}
+
+// ----------- Searching -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct FindState<P, PState>(P, usize, Option<PState>);
+
+impl<P, PState, Ch, Str, Output> Stateful<Ch, Str, Option<(usize, Output)>> for FindState<P, PState>
+    where P: Clone + Uncommitted<Ch, Str, Output, State = PState>,
+          PState: Stateful<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Option<(usize, Output)>> {
+        if let Some(state) = self.2.take() {
+            return match state.more(string) {
+                Done(result) => Done(Some((self.1, result))),
+                Continue(state) => Continue(FindState(self.0, self.1, Some(state))),
+            };
+        }
+        loop {
+            match self.0.init(string) {
+                Some(Done(result)) => return Done(Some((self.1, result))),
+                Some(Continue(state)) => return Continue(FindState(self.0, self.1, Some(state))),
+                None => match string.next() {
+                    Some(_) => self.1 += 1,
+                    None => return Continue(self),
+                },
+            }
+        }
+    }
+
+    fn done(self) -> Option<(usize, Output)> {
+        let skipped = self.1;
+        self.2.map(|state| (skipped, state.done()))
+    }
+
+    fn finish(mut self, string: &mut Str) -> Option<(usize, Output)> {
+        if let Some(state) = self.2.take() {
+            return Some((self.1, state.finish(string)));
+        }
+        loop {
+            match self.0.init(string) {
+                Some(Done(result)) => return Some((self.1, result)),
+                Some(Continue(state)) => return Some((self.1, state.finish(string))),
+                None => match string.next() {
+                    Some(_) => self.1 += 1,
+                    None => return None,
+                },
+            }
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("Find: skipped {}", self.1));
+        if let Some(ref state) = self.2 {
+            outline.nested(|outline| state.dump(outline));
+        }
+    }
+
+}
+
+impl<P, PState, Ch, Str> HasOutput<Ch, Str> for FindState<P, PState>
+    where P: HasOutput<Ch, Str>,
+{
+
+    type Output = Option<(usize, P::Output)>;
+
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Find<P>(P);
+
+impl<P> Parser for Find<P> where P: Parser {}
+
+impl<P, Ch, Str> HasOutput<Ch, Str> for Find<P>
+    where P: HasOutput<Ch, Str>,
+{
+
+    type Output = Option<(usize, P::Output)>;
+
+}
+
+impl<P, Ch, Str, Output> Uncommitted<Ch, Str, Option<(usize, Output)>> for Find<P>
+    where P: Clone + Uncommitted<Ch, Str, Output>,
+          P::State: Stateful<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+
+    type State = FindState<P, P::State>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<(usize, Output)>>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(FindState(self.0.clone(), 0, None).more(string))
+        }
+    }
+
+}
+
+impl<P, Ch, Str, Output> Committed<Ch, Str, Option<(usize, Output)>> for Find<P>
+    where P: Clone + Uncommitted<Ch, Str, Output>,
+          P::State: Stateful<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+
+    fn empty(&self) -> Option<(usize, Output)> {
+        None
+    }
+
+}
+
+impl<P> Find<P> {
+    pub fn new(parser: P) -> Self {
+        Find(parser)
+    }
+}
+
+// ----------- Removing matched regions -------------
+
+// The parser strip(p, factory) copies each item of input to a consumer, except for
+// the regions matched by p, which are discarded. It keeps no more than the state of p,
+// so it can filter a stream of any length.
+
+#[derive(Clone, Debug)]
+pub struct StripState<P, PState, T>(P, Option<PState>, T);
+
+impl<P, PState, T, Ch, Str> Stateful<Ch, Str, T> for StripState<P, PState, T>
+    where P: Clone + UncommittedInfer<Ch, Str, State = PState>,
+          PState: Stateful<Ch, Str, P::Output>,
+          T: Consumer<Ch>,
+          Str: PeekableIterator<Item = Ch>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, T> {
+        if let Some(state) = self.1.take() {
+            match state.more(string) {
+                Done(_) => (),
+                Continue(state) => return Continue(StripState(self.0, Some(state), self.2)),
+            }
+        }
+        loop {
+            let before = Progress::of(string);
+            match self.0.init(string) {
+                Some(Continue(state)) => return Continue(StripState(self.0, Some(state), self.2)),
+                // A match which consumed no input is not a region to remove.
+                Some(Done(_)) if before.made(string) => (),
+                _ => match string.next() {
+                    Some(ch) => self.2.accept(ch),
+                    None => return Continue(self),
+                },
+            }
+        }
+    }
+
+    fn done(self) -> T {
+        self.2
+    }
+
+    fn finish(mut self, string: &mut Str) -> T {
+        if let Some(state) = self.1.take() {
+            state.finish(string);
+        }
+        loop {
+            let before = Progress::of(string);
+            match self.0.init(string) {
+                Some(Continue(state)) => {
+                    state.finish(string);
+                },
+                Some(Done(_)) if before.made(string) => (),
+                _ => match string.next() {
+                    Some(ch) => self.2.accept(ch),
+                    None => return self.2,
+                },
+            }
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line("Strip");
+        if let Some(ref state) = self.1 {
+            outline.nested(|outline| state.dump(outline));
+        }
+    }
+
+}
+
+impl<P, PState, T, Ch, Str> HasOutput<Ch, Str> for StripState<P, PState, T>
+{
+
+    type Output = T;
+
+}
+
+pub struct Strip<P, F>(P, F);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<P, F> Copy for Strip<P, F>
+    where P: Copy,
+          F: Copy
+{}
+impl<P, F> Clone for Strip<P, F>
+    where P: Clone,
+          F: Copy
+{
+    fn clone(&self) -> Self {
+        Strip(self.0.clone(), self.1)
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<P, F> Debug for Strip<P, F>
+    where P: Debug
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Strip({:?}, ...)", self.0)
+    }
+}
+
+impl<P, F> Parser for Strip<P, F> {}
+
+impl<P, F, Ch, Str> HasOutput<Ch, Str> for Strip<P, F>
+    where F: Factory,
+{
+
+    type Output = F::Output;
+
+}
+
+impl<P, F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Strip<P, F>
+    where P: Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
+          Str: PeekableIterator<Item = Ch>,
+          P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
+          F::Output: Consumer<Ch>,
+{
+
+    type State = StripState<P, P::State, F::Output>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, F::Output>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(StripState(self.0.clone(), None, self.1.build()).more(string))
+        }
+    }
+
+}
+
+impl<P, F, Ch, Str> Committed<Ch, Str, F::Output> for Strip<P, F>
+    where P: Clone + UncommittedInfer<Ch, Str>,
+          F: Factory,
+          Str: PeekableIterator<Item = Ch>,
+          P::State: Stateful<Ch, Str, <P as HasOutput<Ch, Str>>::Output>,
+          F::Output: Consumer<Ch>,
+{
+
+    fn empty(&self) -> F::Output {
+        self.1.build()
+    }
+
+}
+
+impl<P, F> Strip<P, F> {
+    pub fn new(parser: P, factory: F) -> Self {
+        Strip(parser, factory)
+    }
+}
+
+// ----------- Optional parse -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Opt<P>(P);
+
+impl<P> Parser for Opt<P> where P: Parser {}
+
+impl<P, Ch, Str, Output> Stateful<Ch, Str, Option<Output>> for Opt<P>
+    where P: Stateful<Ch, Str, Output>,
+{
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, Option<Output>> {
+        self.0.more(string).map(Some).map_continue(Opt)
+    }
+
+    fn done(self) -> Option<Output> {
+        Some(self.0.done())
+    }
+
+    fn finish(self, string: &mut Str) -> Option<Output> {
+        Some(self.0.finish(string))
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        self.0.dump(outline)
+    }
+
+}
+
+impl<P, Ch, Str> HasOutput<Ch, Str> for Opt<P>
+    where P: HasOutput<Ch, Str>,
+{
+
+    type Output = Option<P::Output>;
+
+}
+
+impl<P, Ch, Str, Output> Uncommitted<Ch, Str, Option<Output>> for Opt<P>
+    where Str: PeekableIterator,
+          P: Uncommitted<Ch, Str, Output>,
+{
+
+    type State = Opt<P::State>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<Output>>> {
+        match self.0.init(string) {
+            None => if string.is_empty() {
+                None
+            } else {
+                Some(Done(None))
+            },
+            Some(result) => Some(result.map(Some).map_continue(Opt)),
+        }
+    }
+
+}
+
+impl<P, Ch, Str, Output> Committed<Ch, Str, Option<Output>> for Opt<P>
+    where Str: PeekableIterator,
+          P: Uncommitted<Ch, Str, Output>,
+{
+
+    fn empty(&self) -> Option<Output>{
+        None
+    }
+
+}
+
+impl<P> Opt<P> {
+    pub fn new(parser: P) -> Self {
+        Opt(parser)
+    }
+}
+
+// ----------- Parse but discard the result -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Discard<P>(P);
+
+impl<P> Parser for Discard<P> where P: Parser {}
+
+impl<P, Ch, Str> Stateful<Ch, Str, ()> for Discard<P>
+    where P: StatefulInfer<Ch, Str>,
+{
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, ()> {
+        match self.0.more(string) {
+            Done(_) => Done(()),
+            Continue(parsing) => Continue(Discard(parsing)),
+        }
+    }
+
+    fn done(self) -> () {
+        ()
+    }
+
+    fn finish(self, string: &mut Str) {
+        self.0.finish(string);
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        self.0.dump(outline)
+    }
+
+}
+
+impl<P, Ch, Str> HasOutput<Ch, Str> for Discard<P>
+{
+
+    type Output = ();
+
+}
+
+impl<P, Ch, Str> Uncommitted<Ch, Str, ()> for Discard<P>
+    where P: UncommittedInfer<Ch, Str>,
+{
+
+    type State = Discard<P::State>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, ()>> {
+        match self.0.init(string) {
+            None => None,
+            Some(Done(_)) => Some(Done(())),
+            Some(Continue(parsing)) => Some(Continue(Discard(parsing))),
+        }
+    }
+
+}
+
+impl<P, Ch, Str> Committed<Ch, Str, ()> for Discard<P>
+    where P: CommittedInfer<Ch, Str>,
+{
+
+    fn empty(&self) -> () {
+        ()
+    }
+
+}
+
+impl<P> Discard<P> {
+    pub fn new(parser: P) -> Self {
+        Discard(parser)
+    }
+}
+
+// ----------- Named parsers -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Named<P>(P, &'static str);
+
+impl<P> Parser for Named<P> where P: Parser {}
+
+impl<P, Ch, Str, Output> Stateful<Ch, Str, Output> for Named<P>
+    where P: Stateful<Ch, Str, Output>,
+{
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, Output> {
+        let _span = trace::resume(self.1);
+        match self.0.more(string) {
+            Done(result) => {
+                trace::matched(self.1);
+                Done(result)
+            },
+            Continue(parsing) => {
+                trace::suspended(self.1);
+                Continue(Named(parsing, self.1))
+            },
+        }
+    }
+
+    fn done(self) -> Output {
+        let result = self.0.done();
+        trace::matched(self.1);
+        result
+    }
+
+    fn finish(self, string: &mut Str) -> Output {
+        let _span = trace::resume(self.1);
+        let result = self.0.finish(string);
+        trace::matched(self.1);
+        result
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("Rule {:?} waiting", self.1));
+        outline.nested(|outline| self.0.dump(outline));
+    }
+
+}
+
+impl<P, Ch, Str> HasOutput<Ch, Str> for Named<P>
+    where P: HasOutput<Ch, Str>,
+{
+
+    type Output = P::Output;
+
+}
+
+impl<P, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Named<P>
+    where P: Uncommitted<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+
+    type State = Named<P::State>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        let _span = trace::enter(self.1);
+        match self.0.init(string) {
+            None => {
+                if !string.is_empty() {
+                    trace::rejected(self.1);
+                }
+                trace::backtracked();
+                None
+            },
+            Some(Done(result)) => {
+                trace::matched(self.1);
+                Some(Done(result))
+            },
+            Some(Continue(parsing)) => {
+                trace::suspended(self.1);
+                Some(Continue(Named(parsing, self.1)))
+            },
+        }
+    }
+
+}
+
+impl<P, Ch, Str, Output> Committed<Ch, Str, Output> for Named<P>
+    where P: Committed<Ch, Str, Output>,
+          Str: PeekableIterator,
+{
+
+    fn empty(&self) -> Output {
+        let _span = trace::enter(self.1);
+        let result = self.0.empty();
+        trace::matched(self.1);
+        result
+    }
+
+}
+
+impl<P> Named<P> {
+    pub fn new(parser: P, name: &'static str) -> Self {
+        Named(parser, name)
+    }
+    pub fn name(&self) -> &'static str {
+        self.1
+    }
+}
+
+// ----------- A type for parsers which immediately emit a result -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Emit<F>(F);
+
+impl<F> Parser for Emit<F> {}
+
+impl<F, Ch, Str> Stateful<Ch, Str, F::Output> for Emit<F>
+    where F: Factory,
+{
+
+    fn more(self, _: &mut Str) -> ParseResult<Self, F::Output> {
+        Done(self.0.build())
+    }
+
+    fn done(self) -> F::Output {
+        self.0.build()
+    }
+
+}
+
+impl<F, Ch, Str> HasOutput<Ch, Str> for Emit<F>
+    where F: Factory,
+{
+
+    type Output = F::Output;
+
+}
+
+impl<F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Emit<F>
+    where Str: PeekableIterator,
+          F: 'static + Copy + Factory,
+{
+
+    type State = Self;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self, F::Output>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(Done(self.0.build()))
+        }
+    }
+
+}
+
+impl<F, Ch, Str> Committed<Ch, Str, F::Output> for Emit<F>
+    where Str: PeekableIterator,
+          F: 'static + Copy + Factory,
+{
+
+    fn empty(&self) -> F::Output {
+        self.0.build()
+    }
+}
+
+impl<T> Emit<T> {
+    pub fn new(t: T) -> Self {
+        Emit(t)
+    }
+}
+
+// Unlike Emit, EmitWith does not store its factory in its state, which is uninhabited
+// since the parser never continues, so the factory need not be 'static or Copy.
+
+#[derive(Copy, Clone, Debug)]
+pub struct EmitWith<F>(F);
+
+#[derive(Copy, Clone, Debug)]
+pub struct EmitWithState<T>(CharacterState, PhantomData<T>);
+
+impl<F> Parser for EmitWith<F> {}
+
+impl<Ch, Str, T> Stateful<Ch, Str, T> for EmitWithState<T>
+{
+    fn more(self, _: &mut Str) -> ParseResult<Self, T> {
+        match self.0 {}
+    }
+
+    fn done(self) -> T {
+        match self.0 {}
+    }
+}
+
+impl<Ch, Str, T> HasOutput<Ch, Str> for EmitWithState<T>
+{
+    type Output = T;
+}
+
+impl<F, Ch, Str> HasOutput<Ch, Str> for EmitWith<F>
+    where F: Factory,
+{
+
+    type Output = F::Output;
+
+}
+
+impl<F, Ch, Str> Uncommitted<Ch, Str, F::Output> for EmitWith<F>
+    where Str: PeekableIterator,
+          F: Factory,
+{
+
+    type State = EmitWithState<F::Output>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, F::Output>> {
+        if string.is_empty() {
+            None
+        } else {
+            Some(Done(self.0.build()))
+        }
+    }
+
+}
+
+impl<F, Ch, Str> Committed<Ch, Str, F::Output> for EmitWith<F>
+    where Str: PeekableIterator,
+          F: Factory,
+{
+
+    fn empty(&self) -> F::Output {
+        self.0.build()
+    }
+}
+
+impl<F> EmitWith<F> {
+    pub fn new(factory: F) -> Self {
+        EmitWith(factory)
+    }
+}
+
+// ----------- Character parsers -------------
+
+#[derive(Copy, Clone, Debug)]
+pub enum CharacterState {}
+
+impl<Ch, Str> Stateful<Ch, Str, Ch> for CharacterState
+{
+    fn more(self, _: &mut Str) -> ParseResult<Self, Ch> { 
+        match self {}
+   }
+
+    fn done(self) -> Ch {
+        match self {}
+    }
+}
+
+impl<Ch, Str> HasOutput<Ch, Str> for CharacterState
+{
+    type Output = Ch;
+}
+
+pub struct Character<F>(F);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F> Copy for Character<F> where F: Copy {}
+impl<F> Clone for Character<F> where F: Copy
+{
+    fn clone(&self) -> Self {
+        Character(self.0)
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F> Debug for Character<F>
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Character(...)")
+    }
+}
+
+impl<F> Parser for Character<F> {}
+
+impl<F, Ch, Str> HasOutput<Ch, Str> for Character<F>
+{
+    type Output = Ch;
+}
+
+impl<F, Ch, Str> Uncommitted<Ch, Str, Ch> for Character<F>
+    where Str: PeekableIterator<Item = Ch>,
+          F: Function<Ch, Output = bool>,
+          Ch: Copy,
+{
+    type State = CharacterState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Ch>> {
+        string.next_if(|ch: Ch| self.0.apply(ch)).map(Done)
+    }
+
+    fn init_repeat<C>(&self, string: &mut Str, consumer: &mut C) where C: Consumer<Ch> {
+        string.next_while(|ch: Ch| self.0.apply(ch), consumer)
+    }
+
+}
+
+impl<F> Character<F> {
+    pub fn new(function: F) -> Self {
+        Character(function)
+    }
+}
+
+pub struct CharacterRef<F>(F);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F> Copy for CharacterRef<F> where F: Copy {}
+impl<F> Clone for CharacterRef<F> where F: Copy
+{
+    fn clone(&self) -> Self {
+        CharacterRef(self.0)
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F> Debug for CharacterRef<F>
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "CharacterRef(...)")
+    }
+}
+
+impl<F> Parser for CharacterRef<F> {}
+
+impl<F, Ch, Str> HasOutput<Ch, Str> for CharacterRef<F>
+{
+    type Output = Ch;
+}
+
+impl<F, Ch, Str> Uncommitted<Ch, Str, Ch> for CharacterRef<F>
+    where Str: PeekableIterator<Item = Ch>,
+          F: for<'a> Function<&'a Ch, Output = bool>,
+{
+    type State = CharacterState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Ch>> {
+        string.next_if_ref(|ch: &Ch| self.0.apply(ch)).map(Done)
+    }
+
+}
+
+impl<F> CharacterRef<F> {
+    pub fn new(function: F) -> Self {
+        CharacterRef(function)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct SatisfyMap<F>(F);
+
+// Like CharacterState, this is uninhabited, since the parser never continues.
+#[derive(Copy, Clone, Debug)]
+pub struct SatisfyMapState<T>(CharacterState, PhantomData<T>);
+
+impl<Ch, Str, T> Stateful<Ch, Str, T> for SatisfyMapState<T>
+{
+    fn more(self, _: &mut Str) -> ParseResult<Self, T> {
+        match self.0 {}
+    }
+
+    fn done(self) -> T {
+        match self.0 {}
+    }
+}
+
+impl<Ch, Str, T> HasOutput<Ch, Str> for SatisfyMapState<T>
+{
+    type Output = T;
+}
+
+impl<F> Parser for SatisfyMap<F> {}
+
+impl<F, Ch, Str, T> HasOutput<Ch, Str> for SatisfyMap<F>
+    where F: Function<Ch, Output = Option<T>>,
+{
+    type Output = T;
+}
+
+impl<F, Ch, Str, T> Uncommitted<Ch, Str, T> for SatisfyMap<F>
+    where Str: PeekableIterator<Item = Ch>,
+          F: Function<Ch, Output = Option<T>>,
+          Ch: Clone,
+{
+    type State = SatisfyMapState<T>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, T>> {
+        // Apply the function once, keeping its result if the item is consumed.
+        let result = Cell::new(None);
+        string.next_if_ref(|ch: &Ch| {
+            let value = self.0.apply(ch.clone());
+            let matched = value.is_some();
+            result.set(value);
+            matched
+        });
+        result.into_inner().map(Done)
+    }
+}
+
+impl<F> SatisfyMap<F> {
+    pub fn new(function: F) -> Self {
+        SatisfyMap(function)
+    }
+}
+
+// ----------- Literals -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Equals<T>(T);
+
+impl<T> Function<T> for Equals<T> where T: PartialEq {
+    type Output = bool;
+    fn apply(&self, arg: T) -> bool {
+        self.0 == arg
+    }
+}
+
+impl<T> Equals<T> {
+    pub fn new(value: T) -> Self {
+        Equals(value)
+    }
+}
+
+impl From<char> for Character<Equals<char>> {
+    fn from(ch: char) -> Self {
+        Character(Equals(ch))
+    }
+}
+
+// The bounds of a range, and whether it includes its upper bound.
+#[derive(Copy, Clone, Debug)]
+pub struct InRange<T>(T, T, bool);
+
+impl<T> Function<T> for InRange<T> where T: PartialOrd {
+    type Output = bool;
+    fn apply(&self, arg: T) -> bool {
+        self.0 <= arg && (arg < self.1 || (self.2 && arg == self.1))
+    }
+}
+
+impl<T> From<std::ops::RangeInclusive<T>> for Character<InRange<T>> where T: Copy {
+    fn from(range: std::ops::RangeInclusive<T>) -> Self {
+        Character(InRange(*range.start(), *range.end(), true))
+    }
+}
+
+impl<T> From<std::ops::Range<T>> for Character<InRange<T>> {
+    fn from(range: std::ops::Range<T>) -> Self {
+        Character(InRange(range.start, range.end, false))
+    }
+}
+
+/// The error produced by a literal parser, such as `"abc".parser()`, when its input does not match.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Expected<T>(pub T);
+
+impl<T> std::fmt::Display for Expected<T> where T: Debug {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Expected {:?}", self.0)
+    }
+}
+
+impl<T> std::error::Error for Expected<T> where T: Debug + Send + Sync + 'static {}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Tag(&'static str, bool);
+
+// The tag, whether it ignores case, and the number of bytes of it matched so far.
+#[derive(Copy, Clone, Debug)]
+pub struct TagState(&'static str, bool, usize);
+
+fn tag_char(expected: char, no_case: bool, ch: char) -> bool {
+    ch == expected || (no_case && ch.to_lowercase().eq(expected.to_lowercase()))
+}
+
+fn match_tag<Str>(tag: &'static str, no_case: bool, mut matched: usize, string: &mut Str) -> ParseResult<TagState, Result<&'static str, Expected<&'static str>>>
+    where Str: PeekableIterator<Item = char>,
+{
+    // String slices are compared directly, but case-insensitive matches may differ in length.
+    if !no_case {
+        if let Some(length) = string.next_prefix(&tag[matched..]) {
+            matched += length;
+            return if matched == tag.len() {
+                Done(Ok(tag))
+            } else if string.is_empty() {
+                Continue(TagState(tag, no_case, matched))
+            } else {
+                Done(Err(Expected(tag)))
+            };
+        }
+    }
+    for expected in tag[matched..].chars() {
+        match string.next_if(|ch: char| tag_char(expected, no_case, ch)) {
+            Some(_) => matched += expected.len_utf8(),
+            None if string.is_empty() => return Continue(TagState(tag, no_case, matched)),
+            None => return Done(Err(Expected(tag))),
+        }
+    }
+    Done(Ok(tag))
+}
+
+impl Parser for Tag {}
+
+impl<Str> Stateful<char, Str, Result<&'static str, Expected<&'static str>>> for TagState
+    where Str: PeekableIterator<Item = char>,
+{
+    fn more(self, string: &mut Str) -> ParseResult<Self, Result<&'static str, Expected<&'static str>>> {
+        match_tag(self.0, self.1, self.2, string)
+    }
+
+    fn done(self) -> Result<&'static str, Expected<&'static str>> {
+        Err(Expected(self.0))
+    }
+}
+
+impl<Str> HasOutput<char, Str> for TagState {
+    type Output = Result<&'static str, Expected<&'static str>>;
+}
+
+impl<Str> HasOutput<char, Str> for Tag {
+    type Output = Result<&'static str, Expected<&'static str>>;
+}
+
+impl<Str> Uncommitted<char, Str, Result<&'static str, Expected<&'static str>>> for Tag
+    where Str: PeekableIterator<Item = char>,
+{
+    type State = TagState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<&'static str, Expected<&'static str>>>> {
+        let first = match self.0.chars().next() {
+            Some(first) => first,
+            None if string.is_empty() => return None,
+            None => return Some(Done(Ok(self.0))),
+        };
+        string.next_if(|ch: char| tag_char(first, self.1, ch))
+            .map(|_| match_tag(self.0, self.1, first.len_utf8(), string))
+    }
+}
+
+impl From<&'static str> for Tag {
+    fn from(tag: &'static str) -> Self {
+        Tag(tag, false)
+    }
+}
+
+impl Tag {
+    pub fn new(tag: &'static str) -> Self {
+        Tag(tag, false)
+    }
+
+    /// A tag which ignores case, producing the tag as written rather than as matched.
+    pub fn no_case(tag: &'static str) -> Self {
+        Tag(tag, true)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct ByteTag<const N: usize>([u8; N]);
+
+// The tag, and the number of bytes of it matched so far.
+#[derive(Copy, Clone, Debug)]
+pub struct ByteTagState<const N: usize>([u8; N], usize);
+
+fn match_byte_tag<Str, const N: usize>(tag: [u8; N], mut matched: usize, string: &mut Str) -> ParseResult<ByteTagState<N>, Result<[u8; N], Expected<[u8; N]>>>
+    where Str: PeekableIterator<Item = u8>,
+{
+    while matched < N {
+        match string.next_if(|byte: u8| byte == tag[matched]) {
+            Some(_) => matched += 1,
+            None if string.is_empty() => return Continue(ByteTagState(tag, matched)),
+            None => return Done(Err(Expected(tag))),
+        }
+    }
+    Done(Ok(tag))
+}
+
+impl<const N: usize> Parser for ByteTag<N> {}
+
+impl<Str, const N: usize> Stateful<u8, Str, Result<[u8; N], Expected<[u8; N]>>> for ByteTagState<N>
+    where Str: PeekableIterator<Item = u8>,
+{
+    fn more(self, string: &mut Str) -> ParseResult<Self, Result<[u8; N], Expected<[u8; N]>>> {
+        match_byte_tag(self.0, self.1, string)
+    }
+
+    fn done(self) -> Result<[u8; N], Expected<[u8; N]>> {
+        Err(Expected(self.0))
+    }
+}
+
+impl<Str, const N: usize> HasOutput<u8, Str> for ByteTagState<N> {
+    type Output = Result<[u8; N], Expected<[u8; N]>>;
+}
+
+impl<Str, const N: usize> HasOutput<u8, Str> for ByteTag<N> {
+    type Output = Result<[u8; N], Expected<[u8; N]>>;
+}
+
+impl<Str, const N: usize> Uncommitted<u8, Str, Result<[u8; N], Expected<[u8; N]>>> for ByteTag<N>
+    where Str: PeekableIterator<Item = u8>,
+{
+    type State = ByteTagState<N>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<[u8; N], Expected<[u8; N]>>>> {
+        if N == 0 {
+            return if string.is_empty() { None } else { Some(Done(Ok(self.0))) };
+        }
+        string.next_if(|byte: u8| byte == self.0[0]).map(|_| match_byte_tag(self.0, 1, string))
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for ByteTag<N> {
+    fn from(tag: [u8; N]) -> Self {
+        ByteTag(tag)
+    }
+}
+
+pub struct TryCharacter<F>(F);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F> Copy for TryCharacter<F> where F: Copy {}
+impl<F> Clone for TryCharacter<F> where F: Copy
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F> Debug for TryCharacter<F>
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "TryCharacter(...)")
+    }
+}
+
+impl<F> Parser for TryCharacter<F> {}
+
+impl<F, Ch, Str> HasOutput<Ch, Str> for TryCharacter<F>
+{
+    type Output = Ch;
+}
+
+impl<F, Ch, E, Str> Uncommitted<Result<Ch, E>, Str, Result<Ch, E>> for TryCharacter<F>
+    where Str: PeekableIterator<Item = Result<Ch, E>>,
+          F: Copy + Function<Ch, Output = bool>,
+          Ch: Copy,
+{
+    type State = CharacterState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<Ch, E>>> {
+        string.next_if_ref(IsErrOr(self.0)).map(Done)
+    }
+
+}
+
+impl<F> TryCharacter<F> {
+    pub fn new(function: F) -> Self {
+        TryCharacter(function)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct IsErrOr<F>(F);
+
+impl<'a, F, Ch, E> Function<&'a Result<Ch, E>> for IsErrOr<F>
+    where F: Function<Ch, Output = bool>,
+          Ch: Copy,
+{
+    type Output = bool;
+    fn apply(&self, arg: &'a Result<Ch, E>) -> bool {
+        match *arg {
+            Ok(ch) => self.0.apply(ch),
+            Err(_) => true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct UntilError<I>(Option<I>);
+
+impl<I, T, E> Iterator for UntilError<I>
+    where I: Iterator<Item = Result<T, E>>,
+{
+    type Item = Result<T, E>;
+    fn next(&mut self) -> Option<Result<T, E>> {
+        match self.0.as_mut().and_then(Iterator::next) {
+            Some(Err(err)) => {
+                self.0 = None;
+                Some(Err(err))
+            },
+            result => result,
+        }
+    }
+}
+
+impl<I> UntilError<I> {
+    pub fn new(iterator: I) -> Self {
+        UntilError(Some(iterator))
+    }
+}
+
+// The bytes of a reader, buffered, counting how many have been consumed.
+
+pub struct ReadBytes<R>(Peekable<UntilError<io::Bytes<io::BufReader<R>>>>, usize) where R: io::Read;
+
+impl<R> Iterator for ReadBytes<R>
+    where R: io::Read,
+{
+    type Item = io::Result<u8>;
+    fn next(&mut self) -> Option<io::Result<u8>> {
+        let result = self.0.next();
+        if result.is_some() {
+            self.1 += 1;
+        }
+        result
+    }
+}
+
+impl<R> PeekableIterator for ReadBytes<R>
+    where R: io::Read,
+{
+    fn is_empty(&mut self) -> bool {
+        self.0.is_empty()
+    }
+
+    fn next_if_ref<F>(&mut self, f: F) -> Option<io::Result<u8>>
+        where F: for<'a> Function<&'a io::Result<u8>, Output = bool>
+    {
+        let result = self.0.next_if_ref(f);
+        if result.is_some() {
+            self.1 += 1;
+        }
+        result
+    }
+
+    fn progress(&self) -> Option<usize> {
+        Some(self.1)
+    }
+}
+
+impl<R> ReadBytes<R>
+    where R: io::Read,
+{
+    pub fn new(reader: R) -> Self {
+        ReadBytes(UntilError::new(io::Read::bytes(io::BufReader::new(reader))).peekable(), 0)
+    }
+}
+
+#[derive(Copy,Clone,Debug)]
+pub struct AnyCharacter;
+
+impl Parser for AnyCharacter {}
+
+impl<Ch, Str> Stateful<Ch, Str, Option<Ch>> for AnyCharacter
+    where Str: Iterator<Item = Ch>,
+{
+    fn more(self, string: &mut Str) -> ParseResult<Self, Option<Ch>> {
+        match string.next() {
+            None => Continue(self),
+            Some(ch) => Done(Some(ch)),
+        }
+    }
+
+    fn done(self) -> Option<Ch> {
+        None
+    }
+}
+
+impl<Ch, Str> HasOutput<Ch, Str> for AnyCharacter
+    where Str: Iterator<Item = Ch>,
+{
+    type Output = Option<Ch>;
+}
+
+impl<Ch, Str> Uncommitted<Ch, Str, Option<Ch>> for AnyCharacter
+    where Str: Iterator<Item = Ch>,
+{
+    type State = AnyCharacter;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<Ch>>> {
+        match string.next() {
+            None => None,
+            Some(ch) => Some(Done(Some(ch))),
+        }
+    }
+
+}
+
+impl<Ch, Str> Committed<Ch, Str, Option<Ch>> for AnyCharacter
+    where Str: Iterator<Item = Ch>,
+{
+
+    fn empty(&self) -> Option<Ch> {
+        None
+    }
+
+}
+
+// ----------- Buffering -------------
+
+// If p is a UncommittedInfer<char, Chars<'a>>, then
+// m.buffer() is a UncommittedInfer<char, Chars<'a>> with Output (char, Cow<'a,str>).
+// It does as little buffering as it can, but it does allocate as buffer for the case
+// where the boundary marker of the input is misaligned with that of the parser.
+// For example, m is matching string literals, and the input is '"abc' followed by 'def"'
+// we have to buffer up '"abc'.
+
+// TODO(ajeffrey): make this code generic.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Buffered<P>(P);
+
+impl<P> Parser for Buffered<P> where P: Parser {}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for Buffered<P>
+{
+    type Output = Cow<'a, str>;
+}
+
+impl<'a, P> Uncommitted<char, Chars<'a>, Cow<'a, str>> for Buffered<P>
+    where P: UncommittedInfer<char, Chars<'a>>,
+{
+    type State = BufferedState<P::State>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Cow<'a, str>>> {
+        let string0 = string.as_str();
+        match self.0.init(string) {
+            Some(Done(_)) => Some(Done(Borrowed(&string0[..(string0.len() - string.as_str().len())]))),
+            Some(Continue(state)) => {
+                #[cfg(feature = "metrics")]
+                metrics::buffered(&String::new(), string0);
+                Some(Continue(BufferedState(state, String::from(string0))))
+            },
+            None => None,
+        }
+    }
+}
+
+impl<'a, P> Committed<char, Chars<'a>, Cow<'a, str>> for Buffered<P>
+    where P: CommittedInfer<char, Chars<'a>>,
+{
+    fn empty(&self) -> Cow<'a, str> { Borrowed("") }
+}
+
+impl<P> Buffered<P> {
+    pub fn new(parser: P) -> Self {
+        Buffered(parser)
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct BufferedState<P>(P, String);
+
+impl<'a, P> Stateful<char, Chars<'a>, Cow<'a, str>> for BufferedState<P>
+    where P: StatefulInfer<char, Chars<'a>>
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Cow<'a, str>> {
+        let string0 = string.as_str();
+        match self.0.more(string) {
+            Done(_) => {
+                push_buffer(&mut self.1, &string0[..(string0.len() - string.as_str().len())]);
+                Done(Owned(self.1))
+            },
+            Continue(state) => {
+                push_buffer(&mut self.1, string0);
+                Continue(BufferedState(state, self.1))
+            },
+        }
+    }
+
+    fn done(self) -> Cow<'a, str> {
+        Owned(self.1)
+    }
+
+    fn finish(mut self, string: &mut Chars<'a>) -> Cow<'a, str> {
+        let string0 = string.as_str();
+        self.0.finish(string);
+        push_buffer(&mut self.1, &string0[..(string0.len() - string.as_str().len())]);
+        Owned(self.1)
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("Buffered: {} bytes", self.1.len()));
+        outline.nested(|outline| self.0.dump(outline));
+    }
+
+}
+
+fn push_buffer(buffer: &mut String, data: &str) {
+    #[cfg(feature = "metrics")]
+    metrics::buffered(buffer, data);
+    buffer.push_str(data);
+}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for BufferedState<P>
+    where P: HasOutput<char, Chars<'a>>
+{
+
+    type Output = Cow<'a, str>;
+
+}
+
+// If p is a UncommittedInfer<char, Chars<'a>>, then m.buffer_segments() is like
+// m.buffer(), but with output Vec<Cow<'a,str>>, the input from each chunk as a separate
+// segment. Input from earlier chunks is copied, but not reallocated as the match grows,
+// and input from the last chunk is borrowed.
+
+#[derive(Copy, Clone, Debug)]
+pub struct BufferedSegments<P>(P);
+
+impl<P> Parser for BufferedSegments<P> where P: Parser {}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for BufferedSegments<P>
+{
+    type Output = Vec<Cow<'a, str>>;
+}
+
+impl<'a, P> Uncommitted<char, Chars<'a>, Vec<Cow<'a, str>>> for BufferedSegments<P>
+    where P: UncommittedInfer<char, Chars<'a>>,
+{
+    type State = BufferedSegmentsState<P::State>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Vec<Cow<'a, str>>>> {
+        let string0 = string.as_str();
+        match self.0.init(string) {
+            Some(Done(_)) => Some(Done(vec![Borrowed(&string0[..(string0.len() - string.as_str().len())])])),
+            Some(Continue(state)) => Some(Continue(BufferedSegmentsState(state, vec![segment(string0)]))),
+            None => None,
+        }
+    }
+}
+
+impl<'a, P> Committed<char, Chars<'a>, Vec<Cow<'a, str>>> for BufferedSegments<P>
+    where P: CommittedInfer<char, Chars<'a>>,
+{
+    fn empty(&self) -> Vec<Cow<'a, str>> { Vec::new() }
+}
+
+impl<P> BufferedSegments<P> {
+    pub fn new(parser: P) -> Self {
+        BufferedSegments(parser)
+    }
+}
+
+#[derive(Clone,Debug)]
+pub struct BufferedSegmentsState<P>(P, Vec<String>);
+
+fn segment(data: &str) -> String {
+    #[cfg(feature = "metrics")]
+    metrics::buffered(&String::new(), data);
+    String::from(data)
+}
+
+impl<'a, P> Stateful<char, Chars<'a>, Vec<Cow<'a, str>>> for BufferedSegmentsState<P>
+    where P: StatefulInfer<char, Chars<'a>>
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Vec<Cow<'a, str>>> {
+        let string0 = string.as_str();
+        match self.0.more(string) {
+            Done(_) => {
+                let mut segments: Vec<Cow<'a, str>> = self.1.into_iter().map(Owned).collect();
+                let last = &string0[..(string0.len() - string.as_str().len())];
+                if !last.is_empty() {
+                    segments.push(Borrowed(last));
+                }
+                Done(segments)
+            },
+            Continue(state) => {
+                if !string0.is_empty() {
+                    self.1.push(segment(string0));
+                }
+                Continue(BufferedSegmentsState(state, self.1))
+            },
+        }
+    }
+
+    fn done(self) -> Vec<Cow<'a, str>> {
+        self.1.into_iter().map(Owned).collect()
+    }
+
+    fn finish(self, string: &mut Chars<'a>) -> Vec<Cow<'a, str>> {
+        let string0 = string.as_str();
+        self.0.finish(string);
+        let mut segments: Vec<Cow<'a, str>> = self.1.into_iter().map(Owned).collect();
+        let last = &string0[..(string0.len() - string.as_str().len())];
+        if !last.is_empty() {
+            segments.push(Borrowed(last));
+        }
+        segments
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        let length: usize = self.1.iter().map(String::len).sum();
+        outline.line(format!("Buffered: {} bytes in {} segments", length, self.1.len()));
+        outline.nested(|outline| self.0.dump(outline));
+    }
+
+}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for BufferedSegmentsState<P>
+    where P: HasOutput<char, Chars<'a>>
+{
+
+    type Output = Vec<Cow<'a, str>>;
+
+}
+
+// If p is a UncommittedInfer<char, Chars<'a>>, then m.buffer_with(policy) is like
+// m.buffer(), but with output Result<Cow<'a,str>, BufferOverflow>. The policy says
+// whether the result is always owned, and how much input may be buffered. If more would
+// be buffered, the buffer is dropped, and m keeps running without buffering,
+// so the input stays in sync, then produces an error.
+
+#[derive(Copy, Clone, Debug)]
+pub struct BufferedWith<P>(P, BufferPolicy);
+
+impl<P> Parser for BufferedWith<P> where P: Parser {}
+
+/// How `parser.buffer_with(policy)` buffers its input.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum BufferPolicy {
+    /// Borrow the input if it arrives in one chunk, and otherwise buffer it, like `parser.buffer()`.
+    Adaptive,
+    /// Always produce owned data, copying the input even if it arrives in one chunk.
+    AlwaysOwned,
+    /// Never copy the input, failing if it does not arrive in one chunk.
+    NeverCopy,
+    /// Borrow the input if it arrives in one chunk, and otherwise buffer at most this many bytes.
+    Limit(usize),
+}
+
+impl BufferPolicy {
+    fn limit(self) -> usize {
+        match self {
+            BufferPolicy::NeverCopy => 0,
+            BufferPolicy::Limit(limit) => limit,
+            _ => usize::MAX,
+        }
+    }
+}
+
+/// The error produced by `parser.buffer_with(policy)` when its input would need more buffering than the policy allows.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BufferOverflow {
+    /// The maximum number of bytes which could be buffered.
+    pub limit: usize,
+}
+
+impl std::fmt::Display for BufferOverflow {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Buffered input exceeded {} bytes", self.limit)
+    }
+}
+
+impl std::error::Error for BufferOverflow {}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for BufferedWith<P>
+{
+    type Output = Result<Cow<'a, str>, BufferOverflow>;
+}
+
+impl<'a, P> Uncommitted<char, Chars<'a>, Result<Cow<'a, str>, BufferOverflow>> for BufferedWith<P>
+    where P: UncommittedInfer<char, Chars<'a>>,
+{
+    type State = BufferedWithState<P::State>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Result<Cow<'a, str>, BufferOverflow>>> {
+        let string0 = string.as_str();
         match self.0.init(string) {
-            None => if string.is_empty() {
-                None
-            } else {
-                Some(Done(None))
+            Some(Done(_)) => {
+                let result = &string0[..(string0.len() - string.as_str().len())];
+                if self.1 == BufferPolicy::AlwaysOwned {
+                    Some(Done(Ok(Owned(String::from(result)))))
+                } else {
+                    Some(Done(Ok(Borrowed(result))))
+                }
+            },
+            Some(Continue(state)) => {
+                let mut buffer = Some(String::new());
+                push_limited(&mut buffer, string0, self.1.limit());
+                Some(Continue(BufferedWithState(state, buffer, self.1.limit())))
+            },
+            None => None,
+        }
+    }
+}
+
+impl<'a, P> Committed<char, Chars<'a>, Result<Cow<'a, str>, BufferOverflow>> for BufferedWith<P>
+    where P: CommittedInfer<char, Chars<'a>>,
+{
+    fn empty(&self) -> Result<Cow<'a, str>, BufferOverflow> {
+        if self.1 == BufferPolicy::AlwaysOwned {
+            Ok(Owned(String::new()))
+        } else {
+            Ok(Borrowed(""))
+        }
+    }
+}
+
+impl<P> BufferedWith<P> {
+    pub fn new(parser: P, policy: BufferPolicy) -> Self {
+        BufferedWith(parser, policy)
+    }
+}
+
+// The buffer is None once it has overflowed.
+
+#[derive(Clone,Debug)]
+pub struct BufferedWithState<P>(P, Option<String>, usize);
+
+fn push_limited(buffer: &mut Option<String>, data: &str, limit: usize) {
+    let overflowed = match *buffer {
+        Some(ref mut buffer) if buffer.len() + data.len() <= limit => {
+            push_buffer(buffer, data);
+            false
+        },
+        _ => true,
+    };
+    if overflowed {
+        *buffer = None;
+    }
+}
+
+fn limited_result<'a>(buffer: Option<String>, limit: usize) -> Result<Cow<'a, str>, BufferOverflow> {
+    match buffer {
+        Some(buffer) => Ok(Owned(buffer)),
+        None => Err(BufferOverflow { limit }),
+    }
+}
+
+impl<'a, P> Stateful<char, Chars<'a>, Result<Cow<'a, str>, BufferOverflow>> for BufferedWithState<P>
+    where P: StatefulInfer<char, Chars<'a>>
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Result<Cow<'a, str>, BufferOverflow>> {
+        let string0 = string.as_str();
+        match self.0.more(string) {
+            Done(_) => {
+                push_limited(&mut self.1, &string0[..(string0.len() - string.as_str().len())], self.2);
+                Done(limited_result(self.1, self.2))
+            },
+            Continue(state) => {
+                push_limited(&mut self.1, string0, self.2);
+                Continue(BufferedWithState(state, self.1, self.2))
+            },
+        }
+    }
+
+    fn done(self) -> Result<Cow<'a, str>, BufferOverflow> {
+        limited_result(self.1, self.2)
+    }
+
+    fn finish(mut self, string: &mut Chars<'a>) -> Result<Cow<'a, str>, BufferOverflow> {
+        let string0 = string.as_str();
+        self.0.finish(string);
+        push_limited(&mut self.1, &string0[..(string0.len() - string.as_str().len())], self.2);
+        limited_result(self.1, self.2)
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match self.1 {
+            Some(ref buffer) => outline.line(format!("Buffered: {} of {} bytes", buffer.len(), self.2)),
+            None => outline.line(format!("Buffered: overflowed {} bytes", self.2)),
+        }
+        outline.nested(|outline| self.0.dump(outline));
+    }
+
+}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for BufferedWithState<P>
+    where P: HasOutput<char, Chars<'a>>
+{
+
+    type Output = Result<Cow<'a, str>, BufferOverflow>;
+
+}
+
+// If p is a UncommittedInfer<char, Chars<'a>>, then m.buffer_in(&arena) is like
+// m.buffer(), but with output &'bump str. If the input arrives in one chunk the result
+// is borrowed from it, otherwise the input is buffered in the arena.
+
+#[cfg(feature = "bumpalo")]
+#[derive(Copy, Clone)]
+pub struct BufferedIn<'bump, P>(P, &'bump bumpalo::Bump);
+
+#[cfg(feature = "bumpalo")]
+impl<'bump, P> Parser for BufferedIn<'bump, P> where P: Parser {}
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump, P> HasOutput<char, Chars<'a>> for BufferedIn<'bump, P>
+{
+    type Output = &'bump str;
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump, P> Uncommitted<char, Chars<'a>, &'bump str> for BufferedIn<'bump, P>
+    where P: UncommittedInfer<char, Chars<'a>>,
+          'a: 'bump,
+{
+    type State = BufferedInState<'bump, P::State>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, &'bump str>> {
+        let string0 = string.as_str();
+        match self.0.init(string) {
+            Some(Done(_)) => Some(Done(&string0[..(string0.len() - string.as_str().len())])),
+            Some(Continue(state)) => Some(Continue(BufferedInState(state, bumpalo::collections::String::from_str_in(string0, self.1)))),
+            None => None,
+        }
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump, P> Committed<char, Chars<'a>, &'bump str> for BufferedIn<'bump, P>
+    where P: CommittedInfer<char, Chars<'a>>,
+          'a: 'bump,
+{
+    fn empty(&self) -> &'bump str { "" }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'bump, P> BufferedIn<'bump, P> {
+    pub fn new(parser: P, arena: &'bump bumpalo::Bump) -> Self {
+        BufferedIn(parser, arena)
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+pub struct BufferedInState<'bump, P>(P, bumpalo::collections::String<'bump>);
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump, P> Stateful<char, Chars<'a>, &'bump str> for BufferedInState<'bump, P>
+    where P: StatefulInfer<char, Chars<'a>>
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, &'bump str> {
+        let string0 = string.as_str();
+        match self.0.more(string) {
+            Done(_) => {
+                self.1.push_str(&string0[..(string0.len() - string.as_str().len())]);
+                Done(self.1.into_bump_str())
+            },
+            Continue(state) => {
+                self.1.push_str(string0);
+                Continue(BufferedInState(state, self.1))
+            },
+        }
+    }
+
+    fn done(self) -> &'bump str {
+        self.1.into_bump_str()
+    }
+
+    fn finish(mut self, string: &mut Chars<'a>) -> &'bump str {
+        let string0 = string.as_str();
+        self.0.finish(string);
+        self.1.push_str(&string0[..(string0.len() - string.as_str().len())]);
+        self.1.into_bump_str()
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("Buffered: {} bytes", self.1.len()));
+        outline.nested(|outline| self.0.dump(outline));
+    }
+
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump, P> HasOutput<char, Chars<'a>> for BufferedInState<'bump, P>
+{
+
+    type Output = &'bump str;
+
+}
+
+// ----------- Scanning to a terminator -------------
+
+// The parser take_until(terminator) consumes input up to the terminator, producing it
+// as a Cow<'a,str>, and take_through(terminator) consumes the terminator as well.
+// Like m.buffer(), the result is borrowed when the input arrives in one chunk.
+// A terminator which is split across chunks is tracked by the length of the prefix
+// of the terminator matched so far, which has been consumed with the earlier chunk.
+
+#[derive(Copy, Clone, Debug)]
+pub struct TakeUntil(&'static str, bool);
+
+impl Parser for TakeUntil {}
+
+#[derive(Clone, Debug)]
+pub struct TakeUntilState(&'static str, bool, String, usize);
+
+// The longest prefix of `terminator` which is a suffix of `terminator[..matched]` followed by `ch`.
+
+fn extend_match(terminator: &str, matched: usize, ch: char) -> usize {
+    let width = ch.len_utf8();
+    for length in (width..(matched + width + 1)).rev() {
+        if let Some(candidate) = terminator.get(..length) {
+            if candidate.ends_with(ch) && terminator[..matched].ends_with(&candidate[..(length - width)]) {
+                return length;
+            }
+        }
+    }
+    0
+}
+
+// Scan `text`, having already matched `terminator[..matched]`. Returns either
+// the length of `text` up to the end of the terminator, or the new length of the match.
+
+fn scan_terminator(terminator: &str, mut matched: usize, text: &str) -> Result<usize, usize> {
+    if matched == terminator.len() {
+        return Ok(0);
+    }
+    for (index, ch) in text.char_indices() {
+        matched = extend_match(terminator, matched, ch);
+        if matched == terminator.len() {
+            return Ok(index + ch.len_utf8());
+        }
+    }
+    Err(matched)
+}
+
+impl<'a> Stateful<char, Chars<'a>, Cow<'a, str>> for TakeUntilState {
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Cow<'a, str>> {
+        let text = string.as_str();
+        match scan_terminator(self.0, self.3, text) {
+            Ok(end) if !self.1 && self.0.len() <= end => {
+                *string = text[(end - self.0.len())..].chars();
+                push_buffer(&mut self.2, &text[..(end - self.0.len())]);
+                Done(Owned(self.2))
+            },
+            Ok(end) => {
+                *string = text[end..].chars();
+                push_buffer(&mut self.2, &text[..end]);
+                let length = self.2.len() - self.0.len();
+                self.2.truncate(length);
+                Done(Owned(self.2))
+            },
+            Err(matched) => {
+                *string = text[text.len()..].chars();
+                push_buffer(&mut self.2, text);
+                Continue(TakeUntilState(self.0, self.1, self.2, matched))
+            },
+        }
+    }
+
+    fn done(self) -> Cow<'a, str> {
+        Owned(self.2)
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("TakeUntil {:?}: {} bytes", self.0, self.2.len()));
+    }
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for TakeUntilState {
+
+    type Output = Cow<'a, str>;
+
+}
+
+impl<'a> HasOutput<char, Chars<'a>> for TakeUntil {
+
+    type Output = Cow<'a, str>;
+
+}
+
+impl<'a> Uncommitted<char, Chars<'a>, Cow<'a, str>> for TakeUntil {
+
+    type State = TakeUntilState;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Cow<'a, str>>> {
+        let text = string.as_str();
+        if text.is_empty() {
+            return None;
+        }
+        match scan_terminator(self.0, 0, text) {
+            Ok(end) => {
+                let start = end - self.0.len();
+                *string = text[if self.1 { end } else { start }..].chars();
+                Some(Done(Borrowed(&text[..start])))
+            },
+            Err(matched) => {
+                *string = text[text.len()..].chars();
+                let mut buffer = String::new();
+                push_buffer(&mut buffer, text);
+                Some(Continue(TakeUntilState(self.0, self.1, buffer, matched)))
+            },
+        }
+    }
+
+}
+
+impl<'a> Committed<char, Chars<'a>, Cow<'a, str>> for TakeUntil {
+
+    fn empty(&self) -> Cow<'a, str> {
+        Borrowed("")
+    }
+
+}
+
+impl TakeUntil {
+    pub fn new(terminator: &'static str) -> Self {
+        TakeUntil(terminator, false)
+    }
+    pub fn through(terminator: &'static str) -> Self {
+        TakeUntil(terminator, true)
+    }
+}
+
+// ----------- Scanning while a predicate holds -------------
+
+// The parser take_while(f) consumes the characters satisfying f, producing them
+// as a Cow<'a,str>. Unlike character(f).plus(ignore).buffer(), which passes each character
+// through the Chars iterator, it scans the underlying string slice directly.
+
+pub struct TakeWhile<F>(F);
+
+// A work around for functions implmenting copy but not clone
+// https://github.com/rust-lang/rust/issues/28229
+impl<F> Copy for TakeWhile<F> where F: Copy {}
+impl<F> Clone for TakeWhile<F> where F: Copy
+{
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+// A work around for named functions not implmenting Debug
+// https://github.com/rust-lang/rust/issues/31522
+impl<F> Debug for TakeWhile<F>
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "TakeWhile(...)")
+    }
+}
+
+impl<F> Parser for TakeWhile<F> {}
+
+pub struct TakeWhileState<F>(F, String);
+
+impl<F> Clone for TakeWhileState<F> where F: Copy
+{
+    fn clone(&self) -> Self {
+        TakeWhileState(self.0, self.1.clone())
+    }
+}
+
+impl<F> Debug for TakeWhileState<F>
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "TakeWhileState(..., {:?})", self.1)
+    }
+}
+
+// The length of the prefix of `text` whose characters satisfy `f`.
+
+fn scan_while<F>(f: &F, text: &str) -> usize
+    where F: Function<char, Output = bool>,
+{
+    text.find(|ch| !f.apply(ch)).unwrap_or(text.len())
+}
+
+impl<'a, F> Stateful<char, Chars<'a>, Cow<'a, str>> for TakeWhileState<F>
+    where F: Function<char, Output = bool>,
+{
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Cow<'a, str>> {
+        let text = string.as_str();
+        let end = scan_while(&self.0, text);
+        *string = text[end..].chars();
+        push_buffer(&mut self.1, &text[..end]);
+        if end < text.len() {
+            Done(Owned(self.1))
+        } else {
+            Continue(self)
+        }
+    }
+
+    fn done(self) -> Cow<'a, str> {
+        Owned(self.1)
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("TakeWhile: {} bytes", self.1.len()));
+    }
+
+}
+
+impl<'a, F> HasOutput<char, Chars<'a>> for TakeWhileState<F> {
+
+    type Output = Cow<'a, str>;
+
+}
+
+impl<'a, F> HasOutput<char, Chars<'a>> for TakeWhile<F> {
+
+    type Output = Cow<'a, str>;
+
+}
+
+impl<'a, F> Uncommitted<char, Chars<'a>, Cow<'a, str>> for TakeWhile<F>
+    where F: Copy + Function<char, Output = bool>,
+{
+
+    type State = TakeWhileState<F>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Cow<'a, str>>> {
+        let text = string.as_str();
+        let end = scan_while(&self.0, text);
+        if end == 0 {
+            None
+        } else if end < text.len() {
+            *string = text[end..].chars();
+            Some(Done(Borrowed(&text[..end])))
+        } else {
+            *string = text[end..].chars();
+            let mut buffer = String::new();
+            push_buffer(&mut buffer, text);
+            Some(Continue(TakeWhileState(self.0, buffer)))
+        }
+    }
+
+}
+
+impl<F> TakeWhile<F> {
+    pub fn new(function: F) -> Self {
+        TakeWhile(function)
+    }
+}
+
+// ----------- Requiring complete input -------------
+
+#[derive(Copy, Clone, Debug)]
+pub struct Complete<P>(P);
+
+impl<P> Parser for Complete<P> where P: Parser {}
+
+/// The error produced by `parser.complete()` when there is input left after the parse.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Trailing {
+    /// The byte offset of the first trailing character, from the start of the parse.
+    pub position: usize,
+}
+
+impl std::fmt::Display for Trailing {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Unexpected trailing input at byte {}", self.position)
+    }
+}
+
+impl std::error::Error for Trailing {}
+
+// The parser is still running, or has finished and is waiting for the end of input.
+// Either way, we track the number of bytes consumed.
+
+#[derive(Clone, Debug)]
+pub enum CompleteState<P, T> {
+    Parsing(P, usize),
+    Finished(T, usize),
+}
+
+fn finish_complete<P, T>(result: T, position: usize, string: &mut Chars) -> ParseResult<CompleteState<P, T>, Result<T, Trailing>> {
+    if string.is_empty() {
+        Continue(CompleteState::Finished(result, position))
+    } else {
+        Done(Err(Trailing { position }))
+    }
+}
+
+impl<'a, P, T> Stateful<char, Chars<'a>, Result<T, Trailing>> for CompleteState<P, T>
+    where P: Stateful<char, Chars<'a>, T>,
+{
+
+    fn more(self, string: &mut Chars<'a>) -> ParseResult<Self, Result<T, Trailing>> {
+        match self {
+            CompleteState::Parsing(state, position) => {
+                let string0 = string.as_str();
+                match state.more(string) {
+                    Done(result) => finish_complete(result, position + string0.len() - string.as_str().len(), string),
+                    Continue(state) => Continue(CompleteState::Parsing(state, position + string0.len())),
+                }
+            },
+            CompleteState::Finished(result, position) => finish_complete(result, position, string),
+        }
+    }
+
+    fn done(self) -> Result<T, Trailing> {
+        match self {
+            CompleteState::Parsing(state, _) => Ok(state.done()),
+            CompleteState::Finished(result, _) => Ok(result),
+        }
+    }
+
+    fn finish(self, string: &mut Chars<'a>) -> Result<T, Trailing> {
+        let (result, position) = match self {
+            CompleteState::Parsing(state, position) => {
+                let string0 = string.as_str();
+                let result = state.finish(string);
+                (result, position + string0.len() - string.as_str().len())
             },
-            Some(Done(result)) => Some(Done(Some(result))),
-            Some(Continue(parsing)) => Some(Continue(Opt(parsing))),
+            CompleteState::Finished(result, position) => (result, position),
+        };
+        if string.is_empty() {
+            Ok(result)
+        } else {
+            Err(Trailing { position })
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match *self {
+            CompleteState::Parsing(ref state, _) => state.dump(outline),
+            CompleteState::Finished(..) => outline.line("Complete: waiting for end of input"),
         }
     }
 
 }
 
-impl<P, Ch, Str, Output> Committed<Ch, Str, Option<Output>> for Opt<P>
-    where Str: PeekableIterator,
-          P: Uncommitted<Ch, Str, Output>,
+impl<'a, P, T> HasOutput<char, Chars<'a>> for CompleteState<P, T>
 {
 
-    fn empty(&self) -> Option<Output>{
-        None
+    type Output = Result<T, Trailing>;
+
+}
+
+impl<'a, P> HasOutput<char, Chars<'a>> for Complete<P>
+    where P: HasOutput<char, Chars<'a>>,
+{
+
+    type Output = Result<P::Output, Trailing>;
+
+}
+
+impl<'a, P, T> Uncommitted<char, Chars<'a>, Result<T, Trailing>> for Complete<P>
+    where P: Uncommitted<char, Chars<'a>, T>,
+{
+
+    type State = CompleteState<P::State, T>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Result<T, Trailing>>> {
+        let string0 = string.as_str();
+        match self.0.init(string) {
+            None => None,
+            Some(Done(result)) => Some(finish_complete(result, string0.len() - string.as_str().len(), string)),
+            Some(Continue(state)) => Some(Continue(CompleteState::Parsing(state, string0.len()))),
+        }
     }
 
 }
 
-impl<P> Opt<P> {
+impl<'a, P, T> Committed<char, Chars<'a>, Result<T, Trailing>> for Complete<P>
+    where P: Committed<char, Chars<'a>, T>,
+{
+
+    fn empty(&self) -> Result<T, Trailing> {
+        Ok(self.0.empty())
+    }
+
+}
+
+impl<P> Complete<P> {
     pub fn new(parser: P) -> Self {
-        Opt(parser)
+        Complete(parser)
     }
 }
 
-// ----------- Parse but discard the result -------------
+// ----------- Measuring input -------------
 
 #[derive(Copy, Clone, Debug)]
-pub struct Discard<P>(P);
+pub struct Measure<P>(P);
 
-impl<P> Parser for Discard<P> where P: Parser {}
+impl<P> Parser for Measure<P> where P: Parser {}
 
-impl<P, Ch, Str> Stateful<Ch, Str, ()> for Discard<P>
-    where P: StatefulInfer<Ch, Str>,
+// The state of the parser, and the number of characters consumed so far.
+
+#[derive(Copy, Clone, Debug)]
+pub struct MeasureState<P>(P, usize);
+
+// The number of characters consumed from `string`, which was `before`.
+
+fn consumed(before: &str, string: &Chars) -> usize {
+    before[..(before.len() - string.as_str().len())].chars().count()
+}
+
+impl<'a, P, T> Stateful<char, Chars<'a>, (T, usize)> for MeasureState<P>
+    where P: Stateful<char, Chars<'a>, T>,
 {
 
-    fn more(self, string: &mut Str) -> ParseResult<Self, ()> {
+    fn more(self, string: &mut Chars<'a>) -> ParseResult<Self, (T, usize)> {
+        let before = string.as_str();
         match self.0.more(string) {
-            Done(_) => Done(()),
-            Continue(parsing) => Continue(Discard(parsing)),
+            Done(result) => Done((result, self.1 + consumed(before, string))),
+            Continue(state) => Continue(MeasureState(state, self.1 + consumed(before, string))),
         }
     }
 
-    fn done(self) -> () {
-        ()
+    fn done(self) -> (T, usize) {
+        (self.0.done(), self.1)
+    }
+
+    fn finish(self, string: &mut Chars<'a>) -> (T, usize) {
+        let before = string.as_str();
+        let result = self.0.finish(string);
+        (result, self.1 + consumed(before, string))
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("Measure: {} characters", self.1));
+        outline.nested(|outline| self.0.dump(outline));
     }
 
 }
 
-impl<P, Ch, Str> HasOutput<Ch, Str> for Discard<P>
+impl<'a, P> HasOutput<char, Chars<'a>> for MeasureState<P>
+    where P: HasOutput<char, Chars<'a>>,
 {
 
-    type Output = ();
+    type Output = (P::Output, usize);
 
 }
 
-impl<P, Ch, Str> Uncommitted<Ch, Str, ()> for Discard<P>
-    where P: UncommittedInfer<Ch, Str>,
+impl<'a, P> HasOutput<char, Chars<'a>> for Measure<P>
+    where P: HasOutput<char, Chars<'a>>,
 {
 
-    type State = Discard<P::State>;
+    type Output = (P::Output, usize);
 
-    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, ()>> {
+}
+
+impl<'a, P, T> Uncommitted<char, Chars<'a>, (T, usize)> for Measure<P>
+    where P: Uncommitted<char, Chars<'a>, T>,
+{
+
+    type State = MeasureState<P::State>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, (T, usize)>> {
+        let before = string.as_str();
         match self.0.init(string) {
             None => None,
-            Some(Done(_)) => Some(Done(())),
-            Some(Continue(parsing)) => Some(Continue(Discard(parsing))),
+            Some(Done(result)) => Some(Done((result, consumed(before, string)))),
+            Some(Continue(state)) => Some(Continue(MeasureState(state, consumed(before, string)))),
         }
     }
 
 }
 
-impl<P, Ch, Str> Committed<Ch, Str, ()> for Discard<P>
-    where P: CommittedInfer<Ch, Str>,
+impl<'a, P, T> Committed<char, Chars<'a>, (T, usize)> for Measure<P>
+    where P: Committed<char, Chars<'a>, T>,
 {
 
-    fn empty(&self) -> () {
-        ()
+    fn empty(&self) -> (T, usize) {
+        (self.0.empty(), 0)
     }
 
 }
 
-impl<P> Discard<P> {
+impl<P> Measure<P> {
     pub fn new(parser: P) -> Self {
-        Discard(parser)
+        Measure(parser)
     }
 }
 
-// ----------- A type for parsers which immediately emit a result -------------
+// ----------- Longest match -------------
 
-#[derive(Copy, Clone, Debug)]
-pub struct Emit<F>(F);
+pub struct AltLongest<'a, Output>(Vec<Box<DynUncommitted<char, Chars<'a>, Output>>>);
 
-impl<F> Parser for Emit<F> {}
+impl<'a, Output> Debug for AltLongest<'a, Output> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "AltLongest({} alternatives)", self.0.len())
+    }
+}
 
-impl<F, Ch, Str> Stateful<Ch, Str, F::Output> for Emit<F>
-    where F: Factory,
-{
+// The alternatives which are still running, with their index, the best finished
+// alternative so far, with its length and index, and the number of bytes consumed.
 
-    fn more(self, _: &mut Str) -> ParseResult<Self, F::Output> {
-        Done(self.0.build())
+pub struct AltLongestState<'a, Output> {
+    running: Vec<(usize, DynState<char, Chars<'a>, Output>)>,
+    best: Option<(usize, usize, Output)>,
+    consumed: usize,
+}
+
+impl<'a, Output> Debug for AltLongestState<'a, Output> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "AltLongestState({} running)", self.running.len())
     }
+}
 
-    fn done(self) -> F::Output {
-        self.0.build()
+impl<'a, Output> AltLongestState<'a, Output> {
+    // Offer the output of an alternative, keeping it if it is the longest so far,
+    // preferring earlier alternatives if there is a tie.
+    fn offer(&mut self, length: usize, index: usize, output: Output) {
+        let better = match self.best {
+            None => true,
+            Some((best_length, best_index, _)) => (length, best_index) > (best_length, index),
+        };
+        if better {
+            self.best = Some((length, index, output));
+        }
     }
 
+    fn finish(self) -> ParseResult<Self, Output> {
+        if self.running.is_empty() {
+            Done(self.best.expect("no alternatives").2)
+        } else {
+            Continue(self)
+        }
+    }
 }
 
-impl<F, Ch, Str> HasOutput<Ch, Str> for Emit<F>
-    where F: Factory,
-{
+impl<'a, Output> Parser for AltLongest<'a, Output> {}
+
+impl<'a, Output> Stateful<char, Chars<'a>, Output> for AltLongestState<'a, Output> {
+
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Output> {
+        let start = string.as_str();
+        let mut rest = start;
+        let running = std::mem::take(&mut self.running);
+        for (index, mut state) in running {
+            let mut data = start.chars();
+            match state.more_boxable(&mut data) {
+                Done(output) => {
+                    let length = self.consumed + start.len() - data.as_str().len();
+                    self.offer(length, index, output);
+                },
+                Continue(()) => self.running.push((index, state)),
+            }
+            if data.as_str().len() < rest.len() {
+                rest = data.as_str();
+            }
+        }
+        self.consumed += start.len() - rest.len();
+        *string = rest.chars();
+        self.finish()
+    }
 
-    type Output = F::Output;
+    fn done(mut self) -> Output {
+        let running = std::mem::take(&mut self.running);
+        let consumed = self.consumed;
+        for (index, mut state) in running {
+            let output = state.done_boxable();
+            self.offer(consumed, index, output);
+        }
+        self.best.expect("no alternatives").2
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.line(format!("AltLongest: {} running", self.running.len()));
+        outline.nested(|outline| for (_, state) in &self.running {
+            state.dump_boxable(outline);
+        });
+    }
 
 }
 
-impl<F, Ch, Str> Uncommitted<Ch, Str, F::Output> for Emit<F>
-    where Str: PeekableIterator,
-          F: 'static + Copy + Factory,
-{
+impl<'a, Output> HasOutput<char, Chars<'a>> for AltLongestState<'a, Output> {
 
-    type State = Self;
+    type Output = Output;
 
-    fn init(&self, string: &mut Str) -> Option<ParseResult<Self, F::Output>> {
-        if string.is_empty() {
-            None
-        } else {
-            Some(Done(self.0.build()))
+}
+
+impl<'a, Output> HasOutput<char, Chars<'a>> for AltLongest<'a, Output> {
+
+    type Output = Output;
+
+}
+
+impl<'a, Output> Uncommitted<char, Chars<'a>, Output> for AltLongest<'a, Output> {
+
+    type State = AltLongestState<'a, Output>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Output>> {
+        let start = string.as_str();
+        let mut rest = start;
+        let mut state = AltLongestState { running: Vec::new(), best: None, consumed: 0 };
+        for (index, parser) in self.0.iter().enumerate() {
+            let mut data = start.chars();
+            match (**parser).init_dyn(&mut data) {
+                None => continue,
+                Some(Done(output)) => state.offer(start.len() - data.as_str().len(), index, output),
+                Some(Continue(parsing)) => state.running.push((index, parsing)),
+            }
+            if data.as_str().len() < rest.len() {
+                rest = data.as_str();
+            }
+        }
+        if state.running.is_empty() && state.best.is_none() {
+            return None;
         }
+        state.consumed = start.len() - rest.len();
+        *string = rest.chars();
+        Some(state.finish())
     }
 
 }
 
-impl<F, Ch, Str> Committed<Ch, Str, F::Output> for Emit<F>
-    where Str: PeekableIterator,
-          F: 'static + Copy + Factory,
-{
+impl<'a, Output> AltLongest<'a, Output> {
+    pub fn new(parsers: Vec<Box<DynUncommitted<char, Chars<'a>, Output>>>) -> Self {
+        AltLongest(parsers)
+    }
+}
 
-    fn empty(&self) -> F::Output {
-        self.0.build()
+// ----------- Dispatching on a key -------------
+
+// The parser switch(key, table) reads a key, then runs the parser for it in the table.
+// The table is a handle, such as a &HashMap or Rc<HashMap>, which is shared with the state,
+// since the key may only be known after the parser has returned its state.
+
+pub type SwitchTable<K, Ch, Str, Output> = HashMap<K, Box<DynUncommitted<Ch, Str, Output>>>;
+
+/// The error produced by `switch(key, table)` when the payload of a key cannot be parsed.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SwitchError<K> {
+    /// The key is not in the table.
+    Unknown(K),
+    /// The parser for the key backtracked, or the input ended before the payload.
+    Rejected(K),
+}
+
+impl<K> std::fmt::Display for SwitchError<K> where K: Debug {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            SwitchError::Unknown(ref key) => write!(fmt, "Unknown key {:?}", key),
+            SwitchError::Rejected(ref key) => write!(fmt, "Malformed payload for key {:?}", key),
+        }
     }
 }
 
-impl<T> Emit<T> {
-    pub fn new(t: T) -> Self {
-        Emit(t)
+impl<K> std::error::Error for SwitchError<K> where K: Debug + Send + Sync + 'static {}
+
+impl<K> SwitchError<K> {
+    fn map<F, L>(self, f: F) -> SwitchError<L> where F: FnOnce(K) -> L {
+        match self {
+            SwitchError::Unknown(key) => SwitchError::Unknown(f(key)),
+            SwitchError::Rejected(key) => SwitchError::Rejected(f(key)),
+        }
     }
 }
 
-// ----------- Character parsers -------------
+impl<K> ToStatic for SwitchError<K> where K: ToStatic {
+    type Static = SwitchError<K::Static>;
+}
 
-#[derive(Copy, Clone, Debug)]
-pub enum CharacterState {}
+impl<K, L> Upcast<SwitchError<L>> for SwitchError<K> where K: Upcast<L> {
+    fn upcast(self) -> SwitchError<L> { self.map(Upcast::upcast) }
+}
 
-impl<Ch, Str> Stateful<Ch, Str, Ch> for CharacterState
-{
-    fn more(self, _: &mut Str) -> ParseResult<Self, Ch> { 
-        match self {}
-   }
+impl<K, L> Downcast<SwitchError<L>> for SwitchError<K> where K: Downcast<L> {
+    fn downcast(self) -> SwitchError<L> { self.map(Downcast::downcast) }
+}
 
-    fn done(self) -> Ch {
-        match self {}
+#[derive(Copy, Clone)]
+pub struct Switch<P, T>(P, T);
+
+impl<P, T, K, Ch, Str, Output> Debug for Switch<P, T>
+    where P: Debug,
+          T: Deref<Target = SwitchTable<K, Ch, Str, Output>>,
+{
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Switch({:?}, {} keys)", self.0, self.1.len())
     }
 }
 
-impl<Ch, Str> HasOutput<Ch, Str> for CharacterState
+pub enum SwitchState<PState, K, T, Ch, Str, Output> {
+    InKey(PState, T),
+    // The key has been read, but there was no input left to start its parser.
+    Selected(K, T),
+    InPayload(DynState<Ch, Str, Output>),
+}
+
+impl<PState, K, T, Ch, Str, Output> Debug for SwitchState<PState, K, T, Ch, Str, Output>
+    where PState: Debug,
+          K: Debug,
 {
-    type Output = Ch;
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            SwitchState::InKey(ref state, _) => write!(fmt, "InKey({:?}, ...)", state),
+            SwitchState::Selected(ref key, _) => write!(fmt, "Selected({:?}, ...)", key),
+            SwitchState::InPayload(_) => write!(fmt, "InPayload(...)"),
+        }
+    }
 }
 
-pub struct Character<F>(F);
+type SwitchResult<PState, K, T, Ch, Str, Output> = ParseResult<SwitchState<PState, K, T, Ch, Str, Output>, Result<Output, SwitchError<K>>>;
 
-// A work around for functions implmenting copy but not clone
-// https://github.com/rust-lang/rust/issues/28229
-impl<F> Copy for Character<F> where F: Copy {}
-impl<F> Clone for Character<F> where F: Copy
+// Start the parser for a key, once there is input for it.
+
+fn select<PState, K, T, Ch, Str, Output>(key: K, table: T, string: &mut Str) -> SwitchResult<PState, K, T, Ch, Str, Output>
+    where K: Eq + Hash,
+          T: Deref<Target = SwitchTable<K, Ch, Str, Output>>,
+          Str: PeekableIterator,
 {
-    fn clone(&self) -> Self {
-        Character(self.0)
+    if !table.contains_key(&key) {
+        return Done(Err(SwitchError::Unknown(key)));
+    }
+    if string.is_empty() {
+        return Continue(SwitchState::Selected(key, table));
+    }
+    match (*table[&key]).init_dyn(string) {
+        None => Done(Err(SwitchError::Rejected(key))),
+        Some(Done(result)) => Done(Ok(result)),
+        Some(Continue(state)) => Continue(SwitchState::InPayload(state)),
     }
 }
 
-// A work around for named functions not implmenting Debug
-// https://github.com/rust-lang/rust/issues/31522
-impl<F> Debug for Character<F>
+impl<P, T> Parser for Switch<P, T> {}
+
+impl<PState, K, T, Ch, Str, Output> Stateful<Ch, Str, Result<Output, SwitchError<K>>> for SwitchState<PState, K, T, Ch, Str, Output>
+    where PState: Stateful<Ch, Str, K>,
+          K: Eq + Hash,
+          T: Deref<Target = SwitchTable<K, Ch, Str, Output>>,
+          Str: PeekableIterator,
 {
-    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
-        write!(fmt, "Character(...)")
+
+    fn more(self, string: &mut Str) -> ParseResult<Self, Result<Output, SwitchError<K>>> {
+        match self {
+            SwitchState::InKey(state, table) => match state.more(string) {
+                Done(key) => select(key, table, string),
+                Continue(state) => Continue(SwitchState::InKey(state, table)),
+            },
+            SwitchState::Selected(key, table) => select(key, table, string),
+            SwitchState::InPayload(mut state) => match state.more_boxable(string) {
+                Done(result) => Done(Ok(result)),
+                Continue(()) => Continue(SwitchState::InPayload(state)),
+            },
+        }
     }
+
+    fn done(self) -> Result<Output, SwitchError<K>> {
+        match self {
+            SwitchState::InKey(state, table) => {
+                let key = state.done();
+                if table.contains_key(&key) {
+                    Err(SwitchError::Rejected(key))
+                } else {
+                    Err(SwitchError::Unknown(key))
+                }
+            },
+            SwitchState::Selected(key, _) => Err(SwitchError::Rejected(key)),
+            SwitchState::InPayload(mut state) => Ok(state.done_boxable()),
+        }
+    }
+
+    fn finish(self, string: &mut Str) -> Result<Output, SwitchError<K>> {
+        let (key, table) = match self {
+            SwitchState::InKey(state, table) => (state.finish(string), table),
+            SwitchState::Selected(key, table) => (key, table),
+            SwitchState::InPayload(mut state) => return Ok(state.finish_boxable(string)),
+        };
+        if !table.contains_key(&key) {
+            return Err(SwitchError::Unknown(key));
+        }
+        match (*table[&key]).init_dyn(string) {
+            None => Err(SwitchError::Rejected(key)),
+            Some(Done(result)) => Ok(result),
+            Some(Continue(mut state)) => Ok(state.finish_boxable(string)),
+        }
+    }
+
+    fn dump(&self, outline: &mut Outline) {
+        match *self {
+            SwitchState::InKey(ref state, _) => {
+                outline.line("Switch: key");
+                outline.nested(|outline| state.dump(outline));
+            },
+            SwitchState::Selected(..) => outline.line("Switch: selected"),
+            SwitchState::InPayload(ref state) => {
+                outline.line("Switch: payload");
+                outline.nested(|outline| state.dump_boxable(outline));
+            },
+        }
+    }
+
 }
 
-impl<F> Parser for Character<F> {}
+impl<PState, K, T, Ch, Str, Output> HasOutput<Ch, Str> for SwitchState<PState, K, T, Ch, Str, Output> {
 
-impl<F, Ch, Str> HasOutput<Ch, Str> for Character<F>
+    type Output = Result<Output, SwitchError<K>>;
+
+}
+
+impl<P, T, K, Ch, Str, Output> HasOutput<Ch, Str> for Switch<P, T>
+    where T: Deref<Target = SwitchTable<K, Ch, Str, Output>>,
 {
-    type Output = Ch;
+
+    type Output = Result<Output, SwitchError<K>>;
+
 }
 
-impl<F, Ch, Str> Uncommitted<Ch, Str, Ch> for Character<F>
-    where Str: PeekableIterator<Item = Ch>,
-          F: Copy + Function<Ch, Output = bool>,
-          Ch: Copy,
+impl<P, T, K, Ch, Str, Output> Uncommitted<Ch, Str, Result<Output, SwitchError<K>>> for Switch<P, T>
+    where P: Uncommitted<Ch, Str, K>,
+          K: Eq + Hash,
+          T: Clone + Deref<Target = SwitchTable<K, Ch, Str, Output>>,
+          Str: PeekableIterator,
 {
-    type State = CharacterState;
 
-    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Ch>> {
-        match string.next_if(self.0) {
+    type State = SwitchState<P::State, K, T, Ch, Str, Output>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<Output, SwitchError<K>>>> {
+        match self.0.init(string) {
             None => None,
-            Some(ch) => Some(Done(ch)),
+            Some(Done(key)) => Some(select(key, self.1.clone(), string)),
+            Some(Continue(state)) => Some(Continue(SwitchState::InKey(state, self.1.clone()))),
         }
     }
 
 }
 
-impl<F> Character<F> {
-    pub fn new(function: F) -> Self {
-        Character(function)
+impl<P, T> Switch<P, T> {
+    pub fn new(key: P, table: T) -> Self {
+        Switch(key, table)
     }
 }
 
-pub struct CharacterRef<F>(F);
+// ----------- Operators -------------
 
-// A work around for functions implmenting copy but not clone
-// https://github.com/rust-lang/rust/issues/28229
-impl<F> Copy for CharacterRef<F> where F: Copy {}
-impl<F> Clone for CharacterRef<F> where F: Copy
+#[derive(Copy, Clone, Debug)]
+pub struct Rule<P>(P);
+
+impl<P> Parser for Rule<P> where P: Parser {}
+
+impl<P, Ch, Str> HasOutput<Ch, Str> for Rule<P>
+    where P: HasOutput<Ch, Str>,
 {
-    fn clone(&self) -> Self {
-        CharacterRef(self.0)
-    }
+    type Output = P::Output;
 }
 
-// A work around for named functions not implmenting Debug
-// https://github.com/rust-lang/rust/issues/31522
-impl<F> Debug for CharacterRef<F>
+impl<P, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Rule<P>
+    where P: Uncommitted<Ch, Str, Output>,
 {
-    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
-        write!(fmt, "CharacterRef(...)")
+    type State = P::State;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        self.0.init(string)
     }
 }
 
-impl<F> Parser for CharacterRef<F> {}
-
-impl<F, Ch, Str> HasOutput<Ch, Str> for CharacterRef<F>
+impl<P, Ch, Str, Output> Committed<Ch, Str, Output> for Rule<P>
+    where P: Committed<Ch, Str, Output>,
 {
-    type Output = Ch;
+    fn empty(&self) -> Output {
+        self.0.empty()
+    }
 }
 
-impl<F, Ch, Str> Uncommitted<Ch, Str, Ch> for CharacterRef<F>
-    where Str: PeekableIterator<Item = Ch>,
-          F: Copy + for<'a> Function<&'a Ch, Output = bool>,
+impl<P, Q> std::ops::BitOr<Q> for Rule<P>
+    where P: Parser,
+          Q: IntoParser,
 {
-    type State = CharacterState;
-
-    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Ch>> {
-        match string.next_if_ref(self.0) {
-            None => None,
-            Some(ch) => Some(Done(ch)),
-        }
+    type Output = Rule<OrElse<P, Q::Parser>>;
+    fn bitor(self, other: Q) -> Self::Output {
+        Rule(self.0.or_else(other))
     }
-
 }
 
-impl<F> CharacterRef<F> {
-    pub fn new(function: F) -> Self {
-        CharacterRef(function)
+impl<P, Q> std::ops::Shr<Q> for Rule<P>
+    where P: Parser,
+          Q: IntoParser,
+{
+    type Output = Rule<VariantMap<AndThen<Discard<P>, Q::Parser>, Second>>;
+    fn shr(self, other: Q) -> Self::Output {
+        Rule(self.0.discard_and_then(other))
     }
 }
 
-#[derive(Copy,Clone,Debug)]
-pub struct AnyCharacter;
-
-impl Parser for AnyCharacter {}
-
-impl<Ch, Str> Stateful<Ch, Str, Option<Ch>> for AnyCharacter
-    where Str: Iterator<Item = Ch>,
+impl<P, Q> std::ops::Shl<Q> for Rule<P>
+    where P: Parser,
+          Q: IntoParser,
 {
-    fn more(self, string: &mut Str) -> ParseResult<Self, Option<Ch>> {
-        match string.next() {
-            None => Continue(self),
-            Some(ch) => Done(Some(ch)),
-        }
+    type Output = Rule<VariantMap<AndThen<P, Discard<Q::Parser>>, First>>;
+    fn shl(self, other: Q) -> Self::Output {
+        Rule(self.0.and_then_discard(other))
     }
+}
 
-    fn done(self) -> Option<Ch> {
-        None
+impl<P> Rule<P> {
+    pub fn new(parser: P) -> Self {
+        Rule(parser)
+    }
+
+    /// The parser wrapped by this rule.
+    pub fn into_inner(self) -> P {
+        self.0
     }
 }
 
-impl<Ch, Str> HasOutput<Ch, Str> for AnyCharacter
-    where Str: Iterator<Item = Ch>,
+// ----------- Shared parsers -------------
+
+impl<P: ?Sized> Parser for &P where P: Parser {}
+
+impl<P: ?Sized, Ch, Str> HasOutput<Ch, Str> for &P
+    where P: HasOutput<Ch, Str>,
 {
-    type Output = Option<Ch>;
+    type Output = P::Output;
 }
 
-impl<Ch, Str> Uncommitted<Ch, Str, Option<Ch>> for AnyCharacter
-    where Str: Iterator<Item = Ch>,
+impl<P: ?Sized, Ch, Str, Output> Uncommitted<Ch, Str, Output> for &P
+    where P: Uncommitted<Ch, Str, Output>,
 {
-    type State = AnyCharacter;
+    type State = P::State;
 
-    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Option<Ch>>> {
-        match string.next() {
-            None => None,
-            Some(ch) => Some(Done(Some(ch))),
-        }
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        (**self).init(string)
     }
-
 }
 
-impl<Ch, Str> Committed<Ch, Str, Option<Ch>> for AnyCharacter
-    where Str: Iterator<Item = Ch>,
+impl<P: ?Sized, Ch, Str, Output> Committed<Ch, Str, Output> for &P
+    where P: Committed<Ch, Str, Output>,
 {
-
-    fn empty(&self) -> Option<Ch> {
-        None
+    fn empty(&self) -> Output {
+        (**self).empty()
     }
-
 }
 
-// ----------- Buffering -------------
-
-// If p is a UncommittedInfer<char, Chars<'a>>, then
-// m.buffer() is a UncommittedInfer<char, Chars<'a>> with Output (char, Cow<'a,str>).
-// It does as little buffering as it can, but it does allocate as buffer for the case
-// where the boundary marker of the input is misaligned with that of the parser.
-// For example, m is matching string literals, and the input is '"abc' followed by 'def"'
-// we have to buffer up '"abc'.
-
-// TODO(ajeffrey): make this code generic.
-
-#[derive(Copy, Clone, Debug)]
-pub struct Buffered<P>(P);
-
-impl<P> Parser for Buffered<P> where P: Parser {}
+impl<P: ?Sized> Parser for Rc<P> where P: Parser {}
 
-impl<'a, P> HasOutput<char, Chars<'a>> for Buffered<P>
+impl<P: ?Sized, Ch, Str> HasOutput<Ch, Str> for Rc<P>
+    where P: HasOutput<Ch, Str>,
 {
-    type Output = Cow<'a, str>;
+    type Output = P::Output;
 }
 
-impl<'a, P> Uncommitted<char, Chars<'a>, Cow<'a, str>> for Buffered<P>
-    where P: UncommittedInfer<char, Chars<'a>>,
+impl<P: ?Sized, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Rc<P>
+    where P: Uncommitted<Ch, Str, Output>,
 {
-    type State = BufferedState<P::State>;
+    type State = P::State;
 
-    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Cow<'a, str>>> {
-        let string0 = string.as_str();
-        match self.0.init(string) {
-            Some(Done(_)) => Some(Done(Borrowed(&string0[..(string0.len() - string.as_str().len())]))),
-            Some(Continue(state)) => Some(Continue(BufferedState(state, String::from(string0)))),
-            None => None,
-        }
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        (**self).init(string)
     }
 }
 
-impl<'a, P> Committed<char, Chars<'a>, Cow<'a, str>> for Buffered<P>
-    where P: CommittedInfer<char, Chars<'a>>,
+impl<P: ?Sized, Ch, Str, Output> Committed<Ch, Str, Output> for Rc<P>
+    where P: Committed<Ch, Str, Output>,
 {
-    fn empty(&self) -> Cow<'a, str> { Borrowed("") }
-}
-
-impl<P> Buffered<P> {
-    pub fn new(parser: P) -> Self {
-        Buffered(parser)
+    fn empty(&self) -> Output {
+        (**self).empty()
     }
 }
 
-#[derive(Clone,Debug)]
-pub struct BufferedState<P>(P, String);
+impl<P: ?Sized> Parser for Arc<P> where P: Parser {}
 
-impl<'a, P> Stateful<char, Chars<'a>, Cow<'a, str>> for BufferedState<P>
-    where P: StatefulInfer<char, Chars<'a>>
+impl<P: ?Sized, Ch, Str> HasOutput<Ch, Str> for Arc<P>
+    where P: HasOutput<Ch, Str>,
 {
+    type Output = P::Output;
+}
 
-    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Cow<'a, str>> {
-        let string0 = string.as_str();
-        match self.0.more(string) {
-            Done(_) => {
-                self.1.push_str(&string0[..(string0.len() - string.as_str().len())]);
-                Done(Owned(self.1))
-            },
-            Continue(state) => {
-                self.1.push_str(string0);
-                Continue(BufferedState(state, self.1))
-            },
-        }
+impl<P: ?Sized, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Arc<P>
+    where P: Uncommitted<Ch, Str, Output>,
+{
+    type State = P::State;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        (**self).init(string)
     }
+}
 
-    fn done(self) -> Cow<'a, str> {
-        Owned(self.1)
+impl<P: ?Sized, Ch, Str, Output> Committed<Ch, Str, Output> for Arc<P>
+    where P: Committed<Ch, Str, Output>,
+{
+    fn empty(&self) -> Output {
+        (**self).empty()
     }
+}
+
+impl<P: ?Sized> Parser for Box<P> where P: Parser {}
 
+impl<P: ?Sized, Ch, Str> HasOutput<Ch, Str> for Box<P>
+    where P: HasOutput<Ch, Str>,
+{
+    type Output = P::Output;
 }
 
-impl<'a, P> HasOutput<char, Chars<'a>> for BufferedState<P>
-    where P: HasOutput<char, Chars<'a>>
+impl<P: ?Sized, Ch, Str, Output> Uncommitted<Ch, Str, Output> for Box<P>
+    where P: Uncommitted<Ch, Str, Output>,
 {
+    type State = P::State;
 
-    type Output = Cow<'a, str>;
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        (**self).init(string)
+    }
+}
 
+impl<P: ?Sized, Ch, Str, Output> Committed<Ch, Str, Output> for Box<P>
+    where P: Committed<Ch, Str, Output>,
+{
+    fn empty(&self) -> Output {
+        (**self).empty()
+    }
 }
 
 // ----------- Parsers which are boxable -------------
@@ -1361,6 +4811,14 @@ impl<P, Ch, Str, Output> Boxable<Ch, Str, Output> for BoxableState<P>
     fn done_boxable(&mut self) -> Output {
         self.0.take().unwrap().done()
     }
+    fn finish_boxable(&mut self, string: &mut Str) -> Output {
+        self.0.take().unwrap().finish(string)
+    }
+    fn dump_boxable(&self, outline: &mut Outline) {
+        if let Some(ref state) = self.0 {
+            state.dump(outline)
+        }
+    }
 }
 
 impl<P, Ch, Str> HasOutput<Ch, Str> for BoxableState<P>
@@ -1381,6 +4839,12 @@ impl<P: ?Sized, Ch, Str, Output> Stateful<Ch, Str, Output> for Box<P>
     fn done(mut self) -> Output {
         self.done_boxable()
     }
+    fn finish(mut self, string: &mut Str) -> Output {
+        self.finish_boxable(string)
+    }
+    fn dump(&self, outline: &mut Outline) {
+        (**self).dump_boxable(outline)
+    }
 }
 
 impl<P> BoxableState<P> {
@@ -1432,6 +4896,144 @@ impl<P, F> Boxed<P, F> {
     }
 }
 
+// ----------- Parsers whose type is erased -------------
+
+impl<P, Output> Erasable<Output> for P
+    where P: for<'a> Uncommitted<char, Chars<'a>, Output>,
+          for<'a, 'b> <P as Uncommitted<char, Chars<'a>, Output>>::State: 'static + Stateful<char, Chars<'b>, Output>,
+{
+    fn init_erasable<'a>(&self, string: &mut Chars<'a>) -> Option<ParseResult<BoxedState<Output>, Output>> {
+        match self.init(string) {
+            None => None,
+            Some(Done(result)) => Some(Done(result)),
+            Some(Continue(parsing)) => Some(Continue(Box::new(BoxableState::new(parsing)))),
+        }
+    }
+}
+
+impl<P, Output> ErasableCommitted<Output> for P
+    where P: for<'a> Committed<char, Chars<'a>, Output>,
+{
+    fn empty_erasable(&self) -> Output {
+        self.empty()
+    }
+}
+
+impl<P, Ch, Str, Output> DynUncommitted<Ch, Str, Output> for P
+    where P: Uncommitted<Ch, Str, Output>,
+          P::State: 'static + Stateful<Ch, Str, Output>,
+{
+    fn init_dyn(&self, string: &mut Str) -> Option<ParseResult<DynState<Ch, Str, Output>, Output>> {
+        match self.init(string) {
+            None => None,
+            Some(Done(result)) => Some(Done(result)),
+            Some(Continue(parsing)) => Some(Continue(Box::new(BoxableState::new(parsing)))),
+        }
+    }
+}
+
+impl<Ch, Str, Output> Parser for DynUncommitted<Ch, Str, Output> {}
+
+impl<Ch, Str, Output> HasOutput<Ch, Str> for DynUncommitted<Ch, Str, Output> {
+    type Output = Output;
+}
+
+impl<Ch, Str, Output> HasOutput<Ch, Str> for Boxable<Ch, Str, Output> {
+    type Output = Output;
+}
+
+impl<Ch, Str, Output> Uncommitted<Ch, Str, Output> for DynUncommitted<Ch, Str, Output> {
+    type State = Box<Boxable<Ch, Str, Output>>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>> {
+        self.init_dyn(string)
+    }
+}
+
+pub struct Erased<Output: 'static>(Rc<Erasable<Output>>);
+
+// A work around for Output not necessarily implementing Clone or Debug
+impl<Output> Clone for Erased<Output> {
+    fn clone(&self) -> Self {
+        Erased(self.0.clone())
+    }
+}
+
+impl<Output> Debug for Erased<Output> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Erased(...)")
+    }
+}
+
+impl<Output> Parser for Erased<Output> {}
+
+impl<'a, Output> HasOutput<char, Chars<'a>> for Erased<Output> {
+    type Output = Output;
+}
+
+impl<'a, Output> Uncommitted<char, Chars<'a>, Output> for Erased<Output> {
+    type State = ErasedState<Output>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Output>> {
+        match self.0.init_erasable(string) {
+            None => None,
+            Some(Done(result)) => Some(Done(result)),
+            Some(Continue(parsing)) => Some(Continue(self.clone().in_state(parsing))),
+        }
+    }
+}
+
+impl<Output> Erased<Output> {
+    pub fn new<P>(parser: P) -> Self
+        where P: 'static + Erasable<Output>,
+    {
+        Erased(Rc::new(parser))
+    }
+}
+
+pub struct ErasedCommitted<Output: 'static>(Erased<Output>, Rc<ErasableCommitted<Output>>);
+
+impl<Output> Clone for ErasedCommitted<Output> {
+    fn clone(&self) -> Self {
+        ErasedCommitted(self.0.clone(), self.1.clone())
+    }
+}
+
+impl<Output> Debug for ErasedCommitted<Output> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "ErasedCommitted(...)")
+    }
+}
+
+impl<Output> Parser for ErasedCommitted<Output> {}
+
+impl<'a, Output> HasOutput<char, Chars<'a>> for ErasedCommitted<Output> {
+    type Output = Output;
+}
+
+impl<'a, Output> Uncommitted<char, Chars<'a>, Output> for ErasedCommitted<Output> {
+    type State = ErasedState<Output>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Output>> {
+        self.0.init(string)
+    }
+}
+
+impl<'a, Output> Committed<char, Chars<'a>, Output> for ErasedCommitted<Output> {
+    fn empty(&self) -> Output {
+        self.1.empty_erasable()
+    }
+}
+
+impl<Output> ErasedCommitted<Output> {
+    pub fn new<P>(parser: P) -> Self
+        where P: 'static + Erasable<Output> + ErasableCommitted<Output>,
+    {
+        let parser = Rc::new(parser);
+        ErasedCommitted(Erased(parser.clone()), parser)
+    }
+}
+
 // // ----------- Iterate over parse results -------------
 
 // #[derive(Copy, Clone, Debug)]
@@ -1513,7 +5115,7 @@ impl<P, F> Boxed<P, F> {
 // }
 
 // impl<P, Q, Str> CommittedInfer<Str> for PipeParser<P, Q>
-//     where P: 'static + Copy + CommittedInfer<Str>,
+//     where P: 'static + Clone + CommittedInfer<Str>,
 //           Q: for<'a> CommittedInfer<Peekable<&'a mut IterParser<P, P::State, Str>>>,
 //           Str: IntoPeekable,
 //           Str::Item: ToStatic,
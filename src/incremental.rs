@@ -0,0 +1,178 @@
+//! Incremental re-parsing of edited documents.
+//!
+//! A `Document` runs a parser repeatedly over a string, like a `Session`, and keeps
+//! the output of each run as a `Record` with its position. When the document is edited,
+//! only the records near the edit are re-parsed: parsing restarts at the first record which
+//! could have been affected, and once it reaches the start of a record after the edit,
+//! the remaining records are reused, since the rest of the input is unchanged.
+//! This suits editors, which re-parse the same large document after small changes,
+//! for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::incremental::Document;
+//! fn ignore() {}
+//! let line = character(|ch| ch != '\n').star(String::new)
+//!     .and_then_discard(character(|ch| ch == '\n').opt());
+//! let mut document = Document::new(line, "one\ntwo\nthree\n");
+//! assert_eq!(document.edit(4..7, "2"), 0..2);
+//! assert_eq!(document.text(), "one\n2\nthree\n");
+//! assert_eq!(document.records()[2].output, "three");
+//! assert_eq!(document.records()[2].start, 6);
+//! ```
+//!
+//! Parsing looks ahead one character past the end of each record, so the record
+//! ending just before an edit is also re-parsed.
+
+use super::{Erasable, Stateful};
+use super::ParseResult::{Done, Continue};
+use super::session::Error;
+
+use std::ops::Range;
+
+/// The output of one run of the parser, with the byte range of the input it consumed.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Record<Output> {
+    /// The byte position of the start of the record.
+    pub start: usize,
+    /// The byte position of the end of the record.
+    pub end: usize,
+    /// The output of the parser.
+    pub output: Output,
+}
+
+/// A document, whose records are re-parsed when it is edited.
+///
+/// If the parser rejects the input, the records stop at the rejected input.
+/// Parsers which succeed without consuming input are treated as rejecting it,
+/// since otherwise they would succeed forever.
+
+pub struct Document<Output> {
+    parser: Box<Erasable<Output>>,
+    text: String,
+    records: Vec<Record<Output>>,
+    error: Option<Error>,
+}
+
+impl<Output> Document<Output> {
+    /// A document containing `text`, parsed by running `parser` repeatedly.
+    pub fn new<P>(parser: P, text: &str) -> Self where P: 'static + Erasable<Output> {
+        let mut document = Document {
+            parser: Box::new(parser),
+            text: String::from(text),
+            records: Vec::new(),
+            error: None,
+        };
+        document.reparse(0, Vec::new(), 0, 0);
+        document
+    }
+
+    /// The text of the document.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// The records parsed from the document, in order.
+    pub fn records(&self) -> &[Record<Output>] {
+        &self.records
+    }
+
+    /// The error, if the parser rejected the document.
+    pub fn error(&self) -> Option<Error> {
+        self.error
+    }
+
+    /// Replace the bytes in `range` with `replacement`, and re-parse the records which
+    /// could have been affected, returning the indexes of the records which were re-parsed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds, or does not lie on character boundaries.
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> Range<usize> {
+        self.text.replace_range(range.clone(), replacement);
+        let first = self.records.iter().position(|record| record.end >= range.start).unwrap_or(self.records.len());
+        let old = self.records.split_off(first);
+        let reused = old.iter().position(|record| record.start >= range.end).unwrap_or(old.len());
+        let old = old.into_iter().skip(reused).collect();
+        let start = self.records.last().map(|record| record.end).unwrap_or(0);
+        let reparsed = self.reparse(start, old, range.end, range.start + replacement.len());
+        first..(first + reparsed)
+    }
+
+    // Parse records from `start`, until reaching the start of one of the `old` records,
+    // which follow the edit, whose end moved from `old_end` to `new_end`.
+    // Returns the number of records which were parsed.
+    fn reparse(&mut self, start: usize, old: Vec<Record<Output>>, old_end: usize, new_end: usize) -> usize {
+        let old_error = self.error.take();
+        let mut old = old.into_iter().peekable();
+        let mut position = start;
+        let mut reparsed = 0;
+        while position < self.text.len() {
+            if position >= new_end {
+                while old.peek().map(|record| record.start + new_end < position + old_end) == Some(true) {
+                    old.next();
+                }
+                if old.peek().map(|record| record.start + new_end == position + old_end) == Some(true) {
+                    for record in old {
+                        self.records.push(Record {
+                            start: record.start + new_end - old_end,
+                            end: record.end + new_end - old_end,
+                            output: record.output,
+                        });
+                    }
+                    self.error = old_error.map(|error| Error { position: error.position + new_end - old_end });
+                    return reparsed;
+                }
+            }
+            let rest = &self.text[position..];
+            let mut data = rest.chars();
+            let output = match self.parser.init_erasable(&mut data) {
+                Some(Done(output)) if data.as_str().len() < rest.len() => output,
+                Some(Continue(state)) => state.done(),
+                _ => {
+                    self.error = Some(Error { position });
+                    return reparsed;
+                },
+            };
+            let end = self.text.len() - data.as_str().len();
+            self.records.push(Record { start: position, end, output });
+            reparsed += 1;
+            position = end;
+        }
+        reparsed
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_document() {
+    use super::{character, Parser};
+    fn ignore() {}
+    let WORD = character(char::is_alphanumeric).plus(String::new)
+        .and_then_discard(character(char::is_whitespace).star(ignore));
+    fn words(document: &Document<String>) -> Vec<&str> {
+        document.records().iter().map(|record| &*record.output).collect()
+    }
+    let mut document = Document::new(WORD, "ab cd ef gh");
+    assert_eq!(words(&document), vec!["ab", "cd", "ef", "gh"]);
+    assert_eq!(document.edit(4..4, "x"), 1..2);
+    assert_eq!(words(&document), vec!["ab", "cxd", "ef", "gh"]);
+    assert_eq!(document.records()[3], Record { start: 10, end: 12, output: String::from("gh") });
+    assert_eq!(document.edit(5..7, ""), 1..2);
+    assert_eq!(words(&document), vec!["ab", "cxef", "gh"]);
+    assert_eq!(document.edit(10..10, "ij"), 2..3);
+    assert_eq!(words(&document), vec!["ab", "cxef", "ghij"]);
+    assert_eq!(document.edit(2..3, "!"), 0..1);
+    assert_eq!(document.error(), Some(Error { position: 2 }));
+    assert_eq!(words(&document), vec!["ab"]);
+    assert_eq!(document.edit(0..0, "z "), 0..1);
+    assert_eq!(words(&document), vec!["z", "ab"]);
+    assert_eq!(document.error(), Some(Error { position: 4 }));
+    assert_eq!(document.edit(4..5, " "), 1..4);
+    assert_eq!(document.error(), None);
+    assert_eq!(words(&document), vec!["z", "ab", "cxef", "ghij"]);
+    assert_eq!(document.text(), "z ab cxef ghij");
+}
@@ -0,0 +1,147 @@
+//! String interning for identifier-heavy grammars.
+//!
+//! Grammars for programming languages produce many copies of the same identifiers.
+//! Rather than producing an owned string for each of them, `parser.interned(&interner)`
+//! passes each string output through the interner, and produces the interner's symbol
+//! instead, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr};
+//! # use parsell::intern::Symbols;
+//! # use std::cell::RefCell;
+//! # use std::rc::Rc;
+//! fn ignore() {}
+//! let interner = Rc::new(RefCell::new(Symbols::new()));
+//! let identifier = character(char::is_alphabetic).plus(ignore).buffer().interned(interner.clone());
+//! let space = character(char::is_whitespace).plus(ignore);
+//! let identifiers = identifier.and_then_discard(space.opt()).star(Vec::new);
+//! let symbols = identifiers.init_str("x y x!").unwrap().unDone();
+//! assert_eq!(symbols[0], symbols[2]);
+//! assert_eq!(interner.borrow().resolve(symbols[1]), Some("y"));
+//! assert_eq!(interner.borrow().len(), 2);
+//! ```
+//!
+//! Since parsers are copied, the interner is passed as a shared handle, such as a
+//! `&RefCell<I>` or `Rc<RefCell<I>>`, so it can be used by every parser in a grammar.
+//! Parsers which are repeated or sequenced are `'static`, so an interned parser inside
+//! them needs an `Rc<RefCell<I>>`.
+
+use super::StaticMarker;
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// An interner, which maps strings to symbols.
+///
+/// Interning equal strings should produce equal symbols. For an interned parser to be
+/// the left hand side of `and_then`, its symbols should implement `StaticMarker`.
+
+pub trait Interner {
+    /// The type of symbols.
+    type Symbol;
+
+    /// The symbol for `name`.
+    fn intern(&mut self, name: &str) -> Self::Symbol;
+}
+
+/// A symbol produced by `Symbols`.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct Symbol(u32);
+
+impl StaticMarker for Symbol {}
+
+impl Symbol {
+    /// The index of the symbol, counting from 0 in the order the symbols were interned.
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// A simple interner, which numbers strings in the order they are first seen.
+
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    symbols: HashMap<String, Symbol>,
+    names: Vec<String>,
+}
+
+impl Symbols {
+    /// An empty interner.
+    pub fn new() -> Self {
+        Symbols::default()
+    }
+
+    /// The string which was interned as `symbol`.
+    pub fn resolve(&self, symbol: Symbol) -> Option<&str> {
+        self.names.get(symbol.index()).map(|name| &**name)
+    }
+
+    /// The number of distinct strings which have been interned.
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Whether no strings have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+impl Interner for Symbols {
+    type Symbol = Symbol;
+
+    fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(&symbol) = self.symbols.get(name) {
+            return symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.symbols.insert(String::from(name), symbol);
+        self.names.push(String::from(name));
+        symbol
+    }
+}
+
+impl<I> Interner for &RefCell<I> where I: Interner {
+    type Symbol = I::Symbol;
+
+    fn intern(&mut self, name: &str) -> I::Symbol {
+        self.borrow_mut().intern(name)
+    }
+}
+
+impl<I> Interner for Rc<RefCell<I>> where I: Interner {
+    type Symbol = I::Symbol;
+
+    fn intern(&mut self, name: &str) -> I::Symbol {
+        self.borrow_mut().intern(name)
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_interned() {
+    use super::{character, Parser, UncommittedStr, StatefulStr};
+    struct Lengths;
+    impl Interner for Lengths {
+        type Symbol = usize;
+        fn intern(&mut self, name: &str) -> usize { name.len() }
+    }
+    let symbols = RefCell::new(Symbols::new());
+    let word = character(char::is_alphabetic).plus(String::new).interned(&symbols);
+    assert_eq!(word.init_str("ab ").unwrap().unDone(), Symbol(0));
+    assert_eq!(word.init_str("cd").unwrap().unContinue().last_str(""), Symbol(1));
+    assert_eq!(word.init_str("a").unwrap().unContinue().last_str("b"), Symbol(0));
+    assert_eq!(symbols.borrow().len(), 2);
+    let symbols = RefCell::new(Symbols::new());
+    let buffered = character(char::is_alphabetic).plus(|| ()).buffer().interned(&symbols);
+    assert_eq!(buffered.init_str("cd").unwrap().unContinue().last_str("e"), Symbol(0));
+    let symbols = symbols.into_inner();
+    assert_eq!(symbols.resolve(Symbol(0)), Some("cde"));
+    assert_eq!(symbols.resolve(Symbol(1)), None);
+    let lengths = Rc::new(RefCell::new(Lengths));
+    let length = character(char::is_alphabetic).plus(String::new).interned(lengths);
+    assert_eq!(length.init_str("abc ").unwrap().unDone(), 3);
+}
@@ -21,14 +21,87 @@
 
 #![feature(unboxed_closures)]
 
+#[cfg(feature = "arrayvec")]
+extern crate arrayvec;
+#[cfg(feature = "bitflags")]
+extern crate bitflags;
+#[cfg(feature = "bumpalo")]
+extern crate bumpalo;
+#[cfg(feature = "chumsky")]
+extern crate chumsky;
+#[cfg(feature = "combine")]
+extern crate combine;
+#[cfg(feature = "async")]
+extern crate futures;
+#[cfg(feature = "nom")]
+extern crate nom;
+#[cfg(feature = "num-bigint")]
+extern crate num_bigint;
+#[cfg(feature = "rust_decimal")]
+extern crate rust_decimal;
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+#[macro_use]
+extern crate serde_derive;
+#[cfg(feature = "smallvec")]
+extern crate smallvec;
+#[cfg(feature = "tracing")]
+extern crate tracing;
+#[cfg(feature = "unicode-normalization")]
+extern crate unicode_normalization;
+#[cfg(feature = "unicode-segmentation")]
+extern crate unicode_segmentation;
+#[cfg(feature = "uuid")]
+extern crate uuid;
+#[cfg(feature = "wasm")]
+extern crate wasm_bindgen;
+
 use self::ParseResult::{Done, Continue};
 
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
 use std::str::Chars;
 use std::iter::Peekable;
-use std::fmt::{Debug, Formatter};
+use std::marker::PhantomData;
+use std::rc::Rc;
+use std::io;
+use std::fmt::{Debug, Display, Formatter};
 
 pub mod impls;
+pub mod compat;
+pub mod coverage;
+#[cfg(feature = "serde")]
+pub mod de;
+#[cfg(feature = "debugger")]
+pub mod debugger;
+pub mod events;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod formats;
+pub mod grammar;
+pub mod incremental;
+pub mod intern;
+pub mod machine;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+pub mod numeric;
+pub mod pipeline;
+pub mod recover;
+#[cfg(feature = "async")]
+pub mod sink;
+pub mod session;
+pub mod snapshot;
+pub mod split;
+pub mod testing;
+mod trace;
+pub mod unicode;
+pub mod utf16;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 // ----------- Types for parsers ------------
 
@@ -165,6 +238,113 @@ pub trait Stateful<Ch, Str, Output> {
         }
     }
 
+    /// Provides the last data to the parser, treating the end of the data as the end of input.
+    ///
+    /// If `parser: StatefulInfer<Ch, Str>` and `data: Str`, then `parser.finish(&mut data)`
+    /// is like `parser.last(&mut data)`, but parsers which treat the end of input as
+    /// a terminator can finish in one call. By default this is the same as `last`,
+    /// but repetitions complete the item in progress rather than discarding it,
+    /// so the last line of a file without a final newline is kept, for example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,Stateful,UncommittedStr};
+    /// let line = character(|ch| ch != '\n').star(String::new)
+    ///     .and_then_discard(character(|ch| ch == '\n').opt());
+    /// let parser = line.plus(Vec::new);
+    /// let parsing = parser.init_str("one\ntw").unwrap().unContinue();
+    /// assert_eq!(parsing.clone().last(&mut "o".chars()), vec!["one"]);
+    /// assert_eq!(parsing.finish(&mut "o".chars()), vec!["one", "two"]);
+    /// ```
+
+    fn finish(self, string: &mut Str) -> Output
+        where Self: Sized
+    {
+        self.last(string)
+    }
+
+    /// Provides each of a sequence of chunks of data to the parser.
+    ///
+    /// The result is `Done` as soon as the parser finishes, and any remaining data is ignored,
+    /// otherwise it is `Continue` with the parser's state after the last chunk. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,Stateful,UncommittedStr};
+    /// let parser = character(char::is_alphabetic).star(String::new);
+    /// let parsing = parser.init_str("ab").unwrap().unContinue();
+    /// let chunks = vec!["cd".chars(), "ef!".chars(), "gh".chars()];
+    /// assert_eq!(parsing.feed_all(chunks).unDone(), "abcdef");
+    /// ```
+
+    fn feed_all<I>(self, chunks: I) -> ParseResult<Self, Output>
+        where Self: Sized,
+              I: IntoIterator<Item = Str>,
+    {
+        let mut parsing = self;
+        for mut chunk in chunks {
+            match parsing.more(&mut chunk) {
+                Continue(state) => parsing = state,
+                Done(result) => return Done(result),
+            }
+        }
+        Continue(parsing)
+    }
+
+    /// Describes the state of the parser, as part of an outline for debugging.
+    ///
+    /// By default, this is the name of the type of the state. Combinators describe
+    /// the states they are waiting on, nested below them. Named parsers report
+    /// which rule is waiting, and buffering parsers report how much they have buffered.
+
+    fn dump(&self, outline: &mut Outline) {
+        outline.type_name::<Self>();
+    }
+
+}
+
+/// An indented outline describing the state of a parser.
+///
+/// This is produced by `state.dump_state()`, using `Stateful::dump`.
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Outline {
+    text: String,
+    depth: usize,
+}
+
+impl Outline {
+    /// An empty outline.
+    pub fn new() -> Self {
+        Outline::default()
+    }
+
+    /// Add a line at the current depth.
+    pub fn line<T>(&mut self, line: T) where T: Display {
+        for _ in 0..self.depth {
+            self.text.push_str("  ");
+        }
+        self.text.push_str(&line.to_string());
+        self.text.push('\n');
+    }
+
+    /// Add a line with the name of type `T`, without its path or type parameters.
+    pub fn type_name<T: ?Sized>(&mut self) {
+        let name = std::any::type_name::<T>();
+        let name = name.split('<').next().unwrap_or(name);
+        self.line(name.rsplit("::").next().unwrap_or(name));
+    }
+
+    /// Add the lines produced by `f` one level deeper.
+    pub fn nested<F>(&mut self, f: F) where F: FnOnce(&mut Outline) {
+        self.depth += 1;
+        f(self);
+        self.depth -= 1;
+    }
+}
+
+impl Display for Outline {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        fmt.write_str(&self.text)
+    }
 }
 
 /// A trait for stateful parsers which can infer their output type from their input types.
@@ -209,7 +389,29 @@ pub trait StatefulStr<'a>: StatefulInfer<char, Chars<'a>> {
     fn more_str(self, string: &'a str) -> ParseResult<Self, Self::Output>
         where Self: Sized,
     {
-        self.more(&mut string.chars())
+        on_str(string, |data| self.more(data))
+    }
+
+    /// Provides a string to the parser, returning the unconsumed remainder of the string.
+    ///
+    /// This is like `more_str`, but if the parser finishes part way through the string,
+    /// the rest of the string is returned rather than discarded.
+    ///
+    /// For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// let parser = character(char::is_alphabetic).star(String::new);
+    /// let parsing = parser.init_str("ab").unwrap().unContinue();
+    /// let (result, rest) = parsing.more_str_rest("cd!ef");
+    /// assert_eq!(result.unDone(), "abcd");
+    /// assert_eq!(rest, "!ef");
+    /// ```
+
+    fn more_str_rest(self, string: &'a str) -> (ParseResult<Self, Self::Output>, &'a str)
+        where Self: Sized,
+    {
+        on_str(string, |data| (self.more(data), data.as_str()))
     }
 
     /// Provides the last string to the parser.
@@ -235,7 +437,70 @@ pub trait StatefulStr<'a>: StatefulInfer<char, Chars<'a>> {
     fn last_str(self, string: &'a str) -> Self::Output
         where Self: Sized,
     {
-        self.last(&mut string.chars())
+        on_str(string, |data| self.last(data))
+    }
+
+    /// Provides the last string to the parser, treating its end as the end of input.
+    ///
+    /// If `parser: StatefulInfer<char, Chars<'a>>` and `data: &'a str`, then `parser.finish_str(data)`
+    /// is short-hand for `parser.finish(&mut data.chars())`.
+
+    fn finish_str(self, string: &'a str) -> Self::Output
+        where Self: Sized,
+    {
+        on_str(string, |data| self.finish(data))
+    }
+
+    /// Provides each of a sequence of strings to the parser.
+    ///
+    /// If `parser: StatefulInfer<char, Chars<'a>>` and `chunks` is an iterator of `&'a str`,
+    /// then `parser.feed_all_str(chunks)` is short-hand for
+    /// `parser.feed_all(chunks.into_iter().map(str::chars))`.
+    ///
+    /// For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// let parser = character(char::is_alphabetic).star(String::new);
+    /// let parsing = parser.init_str("ab").unwrap().unContinue();
+    /// let parsing = parsing.feed_all_str(vec!["cd", "", "ef"]).unContinue();
+    /// assert_eq!(parsing.feed_all_str(vec!["gh!", "ij"]).unDone(), "abcdefgh");
+    /// ```
+
+    fn feed_all_str<I>(self, chunks: I) -> ParseResult<Self, Self::Output>
+        where Self: Sized,
+              I: IntoIterator<Item = &'a str>,
+    {
+        let mut parsing = self;
+        for chunk in chunks {
+            match parsing.more_str(chunk) {
+                Continue(state) => parsing = state,
+                Done(result) => return Done(result),
+            }
+        }
+        Continue(parsing)
+    }
+
+    /// An indented outline of the state of the parser, for debugging.
+    ///
+    /// Unlike the `Debug` output of a state, which includes every parser and function
+    /// the state is built from, the outline shows which rules are waiting for input,
+    /// and how much input has been buffered. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// let word = character(char::is_alphabetic).plus(String::new).named("word");
+    /// let parser = word.and_then(character(char::is_numeric).star(String::new).buffer());
+    /// let parsing = parser.init_str("abc12").unwrap().unContinue();
+    /// assert_eq!(parsing.dump_state(), "AndThen: in rhs\n  Buffered: 2 bytes\n    Star\n");
+    /// let parsing = parser.init_str("abc").unwrap().unContinue();
+    /// assert_eq!(parsing.dump_state(), "AndThen: in lhs\n  Rule \"word\" waiting\n    Star\n");
+    /// ```
+
+    fn dump_state(&self) -> String {
+        let mut outline = Outline::new();
+        self.dump(&mut outline);
+        outline.to_string()
     }
 
 }
@@ -245,6 +510,22 @@ impl<'a, P> StatefulStr<'a> for P
 {
 }
 
+// Run a parser on a string, recording metrics if they are enabled.
+
+#[cfg(not(feature = "metrics"))]
+fn on_str<'a, F, T>(string: &'a str, f: F) -> T
+    where F: FnOnce(&mut Chars<'a>) -> T,
+{
+    f(&mut string.chars())
+}
+
+#[cfg(feature = "metrics")]
+fn on_str<'a, F, T>(string: &'a str, f: F) -> T
+    where F: FnOnce(&mut Chars<'a>) -> T,
+{
+    metrics::measure(string, f)
+}
+
 /// The result of parsing
 #[derive(Copy, Clone)]
 pub enum ParseResult<State, Output> {
@@ -279,6 +560,59 @@ impl<P,S> PartialEq for ParseResult<P, S> where S: PartialEq {
     }
 }
 
+// Combinators for the results of `init` and `more`.
+
+impl<State, Output> ParseResult<State, Output> {
+    /// The result of a finished parse, panicking with `msg` if the parse is unfinished.
+    pub fn unwrap_done(self, msg: &str) -> Output {
+        match self {
+            Done(result) => result,
+            Continue(_) => panic!("{}", msg),
+        }
+    }
+
+    /// Apply a function to the result of a finished parse.
+    pub fn map<F, T>(self, f: F) -> ParseResult<State, T>
+        where F: FnOnce(Output) -> T,
+    {
+        match self {
+            Done(result) => Done(f(result)),
+            Continue(state) => Continue(state),
+        }
+    }
+
+    /// Apply a function to the state of an unfinished parse.
+    pub fn map_continue<F, S>(self, f: F) -> ParseResult<S, Output>
+        where F: FnOnce(State) -> S,
+    {
+        match self {
+            Done(result) => Done(result),
+            Continue(state) => Continue(f(state)),
+        }
+    }
+
+    /// Continue from the result of a finished parse.
+    ///
+    /// For example:
+    ///
+    /// ```
+    /// # use parsell::ParseResult;
+    /// # use parsell::ParseResult::{Continue,Done};
+    /// fn positive(n: i32) -> ParseResult<&'static str, i32> { if n > 0 { Done(n) } else { Continue("more") } }
+    /// assert_eq!(Done(5).and_then(positive).unDone(), 5);
+    /// assert_eq!(Done(-5).and_then(positive).unContinue(), "more");
+    /// assert_eq!(Continue("parsing").and_then(positive).unContinue(), "parsing");
+    /// ```
+    pub fn and_then<F, T>(self, f: F) -> ParseResult<State, T>
+        where F: FnOnce(Output) -> ParseResult<State, T>,
+    {
+        match self {
+            Done(result) => f(result),
+            Continue(state) => Continue(state),
+        }
+    }
+}
+
 /// A trait for stateless parsers.
 ///
 /// Parsers are implemented either as committed parsers, which cannot backtrack,
@@ -286,23 +620,28 @@ impl<P,S> PartialEq for ParseResult<P, S> where S: PartialEq {
 /// For example `character(char::is_alphabetic)` is uncommitted because
 /// it will backtrack on any non-alphabetic character, but
 /// `CHARACTER` is not, because it will produce `None` rather than backtracking.
+///
+/// An uncommitted parser `p` is made committed by saying what to produce instead of
+/// backtracking: `p.opt()` produces `None`, `p.or_emit(f)` produces `f()`,
+/// `p.or_default()` produces the default value, and `p.or_else_emit(f)` produces `Err(f())`.
+/// Repetition is already committed, since `p.star(f)` produces an empty collection.
 
 pub trait Parser {
 
     /// Choice between parsers
-    fn or_else<P>(self, other: P) -> impls::OrElse<Self, P>
+    fn or_else<P>(self, other: P) -> impls::OrElse<Self, P::Parser>
         where Self: Sized,
-              P: Parser,
+              P: IntoParser,
     {
-        impls::OrElse::new(self, other)
+        impls::OrElse::new(self, other.parser())
     }
 
     /// Sequencing with a committed parser
-    fn and_then<P>(self, other: P) -> impls::AndThen<Self, P>
+    fn and_then<P>(self, other: P) -> impls::AndThen<Self, P::Parser>
         where Self: Sized,
-              P: Parser,
+              P: IntoParser,
     {
-        impls::AndThen::new(self, other)
+        impls::AndThen::new(self, other.parser())
     }
 
     /// Sequencing with a committed parser (bubble any errors from this parser).
@@ -329,6 +668,40 @@ pub trait Parser {
         self.and_then(other).variant_map(impls::TryZipTry)
     }
 
+    /// Sequencing with a committed parser (bubble any errors from either parser),
+    /// skipping to a synchronization point after errors from this parser.
+    ///
+    /// If this parser produces an error, `other` is not run. Instead, input is skipped
+    /// up to and including the next item satisfying `sync`, and the error is produced.
+    /// This avoids parsing the rest of a record which has already failed. For example:
+    ///
+    /// ```
+    /// # use parsell::{CHARACTER,Parser,Uncommitted};
+    /// # use parsell::ParseResult::Done;
+    /// fn digit(ch: Option<char>) -> Result<char, String> {
+    ///     ch.and_then(|ch| ch.to_digit(10)).map(|_| ch.unwrap()).ok_or(String::from("not a digit"))
+    /// }
+    /// fn is_semicolon(ch: char) -> bool { ch == ';' }
+    /// let parser = CHARACTER.map(digit).try_and_then_try_or_skip(CHARACTER.map(digit), is_semicolon);
+    /// let mut data = "x23;45;".chars();
+    /// match parser.init(&mut data).unwrap() {
+    ///     Done(result) => assert_eq!(result, Err(String::from("not a digit"))),
+    ///     _ => panic!("Can't happen"),
+    /// }
+    /// assert_eq!(data.as_str(), "45;");
+    /// match parser.init(&mut data).unwrap() {
+    ///     Done(result) => assert_eq!(result, Ok(('4', '5'))),
+    ///     _ => panic!("Can't happen"),
+    /// }
+    /// assert_eq!(data.as_str(), ";");
+    /// ```
+    fn try_and_then_try_or_skip<P, F>(self, other: P, sync: F) -> impls::TryAndThenOrSkip<Self, P, F>
+        where Self: Sized,
+              P: Parser,
+    {
+        impls::TryAndThenOrSkip::new(self, other, sync)
+    }
+
     /// Iterate one or more times (returns an uncommitted parser).
     fn plus<F>(self, factory: F) -> impls::Plus<Self, F>
         where Self: Sized,
@@ -337,7 +710,34 @@ pub trait Parser {
         impls::Plus::new(self, factory)
     }
 
+    /// Iterate exactly `N` times, collecting the results in an array (returns a committed parser).
+    ///
+    /// The parser being repeated must be committed. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,CHARACTER,Parser,UncommittedStr,StatefulStr};
+    /// # use parsell::ParseResult::{Continue,Done};
+    /// let parser = CHARACTER.count::<3>();
+    /// match parser.init_str("ab").unwrap() {
+    ///     Continue(parsing) => match parsing.more_str("cd") {
+    ///         Done(result) => assert_eq!(result, [Some('a'), Some('b'), Some('c')]),
+    ///         _ => panic!("Can't happen"),
+    ///     },
+    ///     _ => panic!("Can't happen"),
+    /// }
+    /// ```
+    fn count<const N: usize>(self) -> impls::Count<Self, N>
+        where Self: Sized,
+    {
+        impls::Count::new(self)
+    }
+
     /// Iterate zero or more times (returns a committed parser).
+    ///
+    /// The repeated parser should consume input whenever it succeeds, otherwise it would
+    /// be repeated forever. When repeating a parser such as `p.opt()`, the repetition ends
+    /// at the first match which consumed no input, as long as the input can tell
+    /// (see `PeekableIterator::progress`).
     fn star<F>(self, factory: F) -> impls::Star<Self, F>
         where Self: Sized,
               F: Factory,
@@ -345,6 +745,81 @@ pub trait Parser {
         impls::Star::new(self, factory)
     }
 
+    /// Skip input until this parser matches (returns a committed parser).
+    ///
+    /// Items which this parser backtracks on are discarded, one at a time, until it commits.
+    /// The result is `None` if the input ends without a match. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,Uncommitted};
+    /// # use parsell::ParseResult::Done;
+    /// let parser = character(char::is_numeric).plus(String::new).find();
+    /// let mut data = "abc123def".chars();
+    /// match parser.init(&mut data).unwrap() {
+    ///     Done(result) => assert_eq!(result, Some(String::from("123"))),
+    ///     _ => panic!("Can't happen"),
+    /// }
+    /// assert_eq!(data.as_str(), "def");
+    /// ```
+    fn find(self) -> impls::Map<impls::Find<Self>, impls::DropCount>
+        where Self: Sized,
+    {
+        self.find_counted().map(impls::DropCount)
+    }
+
+    /// Skip input until this parser matches, also returning the number of items skipped.
+    fn find_counted(self) -> impls::Find<Self>
+        where Self: Sized,
+    {
+        impls::Find::new(self)
+    }
+
+    /// Iterate zero or more times, stopping as soon as `until` matches (returns a committed parser).
+    ///
+    /// Before each iteration, `until` is tried, and if it commits, the iteration stops.
+    /// The output of `until` is discarded. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,Uncommitted};
+    /// # use parsell::ParseResult::Done;
+    /// fn is_any(_: char) -> bool { true }
+    /// fn is_semicolon(ch: char) -> bool { ch == ';' }
+    /// let parser = character(is_any).star_lazy(character(is_semicolon), String::new);
+    /// let mut data = "abc;def;".chars();
+    /// match parser.init(&mut data).unwrap() {
+    ///     Done(result) => assert_eq!(result, "abc"),
+    ///     _ => panic!("Can't happen"),
+    /// }
+    /// assert_eq!(data.as_str(), "def;");
+    /// ```
+    fn star_lazy<Q, F>(self, until: Q, factory: F) -> impls::Map<impls::StarLazy<Self, Q, F>, impls::First>
+        where Self: Sized,
+              Q: Parser,
+              F: Factory,
+    {
+        self.star_lazy_with(until, factory).map(impls::First)
+    }
+
+    /// Iterate zero or more times, stopping as soon as `until` matches, keeping the output of `until`.
+    ///
+    /// This is the same as `star_lazy`, but the result is paired with the output of `until`,
+    /// or `None` if the input ended, or this parser backtracked, before `until` matched. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr};
+    /// fn is_any(_: char) -> bool { true }
+    /// fn is_terminator(ch: char) -> bool { ch == ';' || ch == '.' }
+    /// let parser = character(is_any).star_lazy_with(character(is_terminator), String::new);
+    /// assert_eq!(parser.init_str("abc.def").unwrap().unDone(), (String::from("abc"), Some('.')));
+    /// ```
+    fn star_lazy_with<Q, F>(self, until: Q, factory: F) -> impls::StarLazy<Self, Q, F>
+        where Self: Sized,
+              Q: Parser,
+              F: Factory,
+    {
+        impls::StarLazy::new(self, until, factory)
+    }
+
     /// Apply a function to the result
     fn map<F>(self, f: F) -> impls::Map<Self, F>
         where Self: Sized,
@@ -436,6 +911,44 @@ pub trait Parser {
         self.map(impls::Dereference::new(f))
     }
 
+    /// Pass a copy of each result to a side consumer, as well as producing it.
+    ///
+    /// Since parsers are copied, the consumer is a shared handle which is cloned
+    /// when a result is passed to it, such as a `&RefCell<C>` or `Rc<RefCell<C>>`.
+    /// This taps the results of part of a grammar, for example to count or log them,
+    /// without changing the rest of the grammar. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// # use std::cell::RefCell;
+    /// let seen = RefCell::new(Vec::new());
+    /// let word = character(char::is_alphabetic).plus(String::new).tee(&seen);
+    /// let parsing = word.init_str("te").unwrap().unContinue();
+    /// assert!(seen.borrow().is_empty());
+    /// assert_eq!(parsing.last_str("e!"), "tee");
+    /// assert_eq!(*seen.borrow(), vec!["tee"]);
+    /// ```
+    ///
+    /// Parsers which are repeated or sequenced are `'static`, so a tee inside
+    /// them needs a consumer which is too, such as an `Rc<RefCell<C>>`.
+    fn tee<C>(self, consumer: C) -> impls::Tee<Self, C>
+        where Self: Sized,
+    {
+        impls::Tee::new(self, consumer)
+    }
+
+    /// Intern the string result, producing the interner's symbol.
+    ///
+    /// Since parsers are copied, the interner is a shared handle which is cloned
+    /// when a result is interned, such as a `&RefCell<I>` or `Rc<RefCell<I>>`.
+    /// See the `intern` module for an example.
+    fn interned<I>(self, interner: I) -> impls::Interned<Self, I>
+        where Self: Sized,
+              I: Clone + intern::Interner,
+    {
+        impls::Interned::new(self, interner)
+    }
+
     /// Apply a variant function to the result
     fn variant_map<F>(self, f: F) -> impls::VariantMap<Self, F>
         where Self: Sized,
@@ -444,19 +957,19 @@ pub trait Parser {
     }
 
     /// Sequencing, discard the output of the first parse
-    fn discard_and_then<P>(self, other: P) -> impls::VariantMap<impls::AndThen<impls::Discard<Self>, P>, impls::Second>
+    fn discard_and_then<P>(self, other: P) -> impls::VariantMap<impls::AndThen<impls::Discard<Self>, P::Parser>, impls::Second>
         where Self: Sized,
-              P: Parser,
+              P: IntoParser,
     {
         self.discard().and_then(other).variant_map(impls::Second)
     }
 
     /// Sequencing, discard the output of the second parse
-    fn and_then_discard<P>(self, other: P) -> impls::VariantMap<impls::AndThen<Self, impls::Discard<P>>, impls::First>
+    fn and_then_discard<P>(self, other: P) -> impls::VariantMap<impls::AndThen<Self, impls::Discard<P::Parser>>, impls::First>
         where Self: Sized,
-              P: Parser,
+              P: IntoParser,
     {
-        self.and_then(other.discard()).variant_map(impls::First)
+        self.and_then(other.parser().discard()).variant_map(impls::First)
     }
 
     /// Sequencing, discard the output of the first parse, bubble errors from the first parser
@@ -491,6 +1004,28 @@ pub trait Parser {
         self.try_and_then_try(other.try_discard()).variant_map(impls::Try::new(impls::First))
     }
 
+    /// Sequencing of three parsers, bubble errors from any of them.
+    ///
+    /// This is like `try_and_then_try` twice, but produces a flat triple. For example:
+    ///
+    /// ```
+    /// # use parsell::{CHARACTER,Parser,UncommittedStr};
+    /// fn digit(ch: Option<char>) -> Result<u32, String> {
+    ///     ch.and_then(|ch| ch.to_digit(10)).ok_or(format!("Not a digit: {:?}", ch))
+    /// }
+    /// let digit = CHARACTER.map(digit);
+    /// let parser = digit.try_seq3(digit, digit);
+    /// assert_eq!(parser.init_str("123").unwrap().unDone(), Ok((1, 2, 3)));
+    /// assert_eq!(parser.init_str("1a3").unwrap().unDone(), Err(String::from("Not a digit: Some('a')")));
+    /// ```
+    fn try_seq3<P, Q>(self, second: P, third: Q) -> impls::TrySeq3<Self, P, Q>
+        where Self: Sized,
+              P: Parser,
+              Q: Parser,
+    {
+        self.try_and_then_try(second).try_and_then_try(third).variant_map(impls::Try::new(impls::Triple))
+    }
+
     /// Optional parse
     fn opt(self) -> impls::Opt<Self>
         where Self: Sized,
@@ -505,6 +1040,66 @@ pub trait Parser {
         self.opt().variant_map(impls::TryOpt)
     }
 
+    /// Optional parse, producing a value from `factory` if this parser backtracks.
+    ///
+    /// Like `opt()`, this makes an uncommitted parser committed, but without changing its
+    /// output type. The value is also produced at the end of input. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// fn unnamed() -> String { String::from("unnamed") }
+    /// let name = character(char::is_alphabetic).plus(String::new).or_emit(unnamed);
+    /// assert_eq!(name.init_str("bob!").unwrap().unDone(), "bob");
+    /// assert_eq!(name.init_str("!").unwrap().unDone(), "unnamed");
+    /// let mention = character(|ch| ch == '@').and_then(name);
+    /// assert_eq!(mention.init_str("@").unwrap().unContinue().last_str(""), ('@', String::from("unnamed")));
+    /// ```
+    ///
+    /// As with `opt()`, the result succeeds without consuming input, so should not
+    /// be repeated with `star` or `plus`. The factory is called each time a value is produced.
+    fn or_emit<F>(self, factory: F) -> impls::Map<impls::Opt<Self>, impls::UnwrapOrElse<F>>
+        where Self: Sized,
+              F: Factory,
+    {
+        self.opt().map(impls::UnwrapOrElse::new(factory))
+    }
+
+    /// Optional parse, producing the default value if this parser backtracks.
+    ///
+    /// This is `or_emit` using `Default::default`. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr};
+    /// let digits = character(char::is_numeric).plus(String::new).or_default();
+    /// assert_eq!(digits.init_str("12!").unwrap().unDone(), "12");
+    /// assert_eq!(digits.init_str("!").unwrap().unDone(), "");
+    /// ```
+    fn or_default(self) -> impls::Map<impls::Opt<Self>, impls::UnwrapOrDefault>
+        where Self: Sized,
+    {
+        self.opt().map(impls::UnwrapOrDefault)
+    }
+
+    /// Optional parse, producing an error from `factory` if this parser backtracks.
+    ///
+    /// The output is `Ok(result)` if this parser matches, and `Err(factory())` if it backtracks
+    /// or the input ends, so a missing part of a grammar can be reported as a value
+    /// rather than by backtracking. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr};
+    /// fn expected_digit() -> &'static str { "expected a digit" }
+    /// let digit = character(char::is_numeric).or_else_emit(expected_digit);
+    /// assert_eq!(digit.init_str("1").unwrap().unDone(), Ok('1'));
+    /// assert_eq!(digit.init_str("x").unwrap().unDone(), Err("expected a digit"));
+    /// ```
+    fn or_else_emit<F>(self, factory: F) -> impls::Map<impls::Opt<Self>, impls::OkOrElse<F>>
+        where Self: Sized,
+              F: Factory,
+    {
+        self.opt().map(impls::OkOrElse::new(factory))
+    }
+
     /// Discard the output
     fn discard(self) -> impls::Discard<Self>
         where Self: Sized,
@@ -533,6 +1128,55 @@ pub trait Parser {
         impls::Boxed::new(self, f)
     }
 
+    /// Erase the type of this string parser.
+    ///
+    /// The parser is moved to the heap, and its state is boxed, so the type of the result
+    /// is just `Erased<Output>`. This is the recommended way to keep type-checking times
+    /// and type names under control in large grammars: erase each rule as it is defined,
+    /// and build other rules from the erased ones. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr};
+    /// # use parsell::impls::Erased;
+    /// # use parsell::ParseResult::Done;
+    /// let digit: Erased<char> = character(char::is_numeric).erase();
+    /// let digits: Erased<(char, String)> = digit.clone().and_then(digit.star(String::new)).erase();
+    /// match digits.init_str("123!").unwrap() {
+    ///     Done(result) => assert_eq!(result, ('1', String::from("23"))),
+    ///     _ => panic!("Can't happen"),
+    /// }
+    /// ```
+    ///
+    /// The parser is reference counted, so erased parsers are `Clone` but not `Copy`,
+    /// and cloning one is cheap.
+    fn erase<Output>(self) -> impls::Erased<Output>
+        where Self: 'static + Sized + Erasable<Output>,
+              Output: 'static,
+    {
+        impls::Erased::new(self)
+    }
+
+    /// Erase the type of this committed string parser.
+    ///
+    /// This is the same as `erase()`, but the result is committed.
+    fn erase_committed<Output>(self) -> impls::ErasedCommitted<Output>
+        where Self: 'static + Sized + Erasable<Output> + ErasableCommitted<Output>,
+              Output: 'static,
+    {
+        impls::ErasedCommitted::new(self)
+    }
+
+    /// Label this parser, for example for coverage instrumentation.
+    ///
+    /// With the `tracing` feature, named parsers also emit `tracing` events with target
+    /// `parsell` when they enter, suspend, resume, complete or reject, inside a span
+    /// whose `rule` field is the name.
+    fn named(self, name: &'static str) -> impls::Named<Self>
+        where Self: Sized
+    {
+        impls::Named::new(self, name)
+    }
+
     /// Set the state of this parser
     fn in_state<State>(self, state: State) -> InState<Self, State>
         where Self: Sized
@@ -571,11 +1215,245 @@ pub trait Parser {
         impls::Buffered::new(self)
     }
 
-}
-
-/// A trait for committed parsers.
-///
-/// A parser is committed if it is guaranteed only to backtrack on empty input.
+    /// A parser which produces its input as a list of segments.
+    ///
+    /// This is like `buffer()`, but rather than appending each chunk of input to one `String`,
+    /// the input from each chunk is kept as a separate segment, which avoids reallocating
+    /// a very long match as it grows. The segment from the last chunk is borrowed.
+    /// The segments can be joined with `concat()`, which allocates once. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// # use std::borrow::Cow::{Borrowed,Owned};
+    /// fn ignore() {}
+    /// let parser = character(char::is_alphabetic).plus(ignore).buffer_segments();
+    /// assert_eq!(parser.init_str("abc!").unwrap().unDone(), vec![Borrowed("abc")]);
+    /// let parsing = parser.init_str("ab").unwrap().unContinue();
+    /// let parsing = parsing.more_str("cd").unContinue();
+    /// let segments = parsing.more_str("e!").unDone();
+    /// assert_eq!(segments, vec![Owned(String::from("ab")), Owned(String::from("cd")), Borrowed("e")]);
+    /// assert_eq!(segments.concat(), "abcde");
+    /// ```
+    fn buffer_segments(self) -> impls::BufferedSegments<Self>
+        where Self: Sized
+    {
+        impls::BufferedSegments::new(self)
+    }
+
+    /// A parser which produces its input, buffering it according to `policy`.
+    ///
+    /// This is like `buffer()`, but the policy says whether the result is always owned,
+    /// as needed by a pipeline of `'static` data, and how much input may be buffered.
+    /// If more input would be buffered than the policy allows, the result is an error.
+    /// The parser keeps consuming its input without buffering it, so that parsing can carry
+    /// on after the error. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// # use parsell::impls::{BufferOverflow,BufferPolicy};
+    /// # use std::borrow::Cow::Owned;
+    /// fn ignore() {}
+    /// let word = character(char::is_alphabetic).plus(ignore);
+    /// let parser = word.buffer_with(BufferPolicy::AlwaysOwned);
+    /// assert_eq!(parser.init_str("abc!").unwrap().unDone(), Ok(Owned(String::from("abc"))));
+    /// let parser = word.buffer_with(BufferPolicy::NeverCopy);
+    /// assert_eq!(parser.init_str("abc!").unwrap().unDone().unwrap(), "abc");
+    /// let parsing = parser.init_str("ab").unwrap().unContinue();
+    /// assert_eq!(parsing.more_str("c!").unDone(), Err(BufferOverflow { limit: 0 }));
+    /// ```
+    fn buffer_with(self, policy: impls::BufferPolicy) -> impls::BufferedWith<Self>
+        where Self: Sized
+    {
+        impls::BufferedWith::new(self, policy)
+    }
+
+    /// A parser which produces its input, buffering at most `limit` bytes.
+    ///
+    /// This is `buffer_with(BufferPolicy::Limit(limit))`, which bounds the memory used
+    /// by a match which is split across many chunks. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// # use parsell::impls::BufferOverflow;
+    /// fn ignore() {}
+    /// let parser = character(char::is_alphabetic).plus(ignore).buffer_limit(4);
+    /// assert_eq!(parser.init_str("abcdef!").unwrap().unDone().unwrap(), "abcdef");
+    /// let parsing = parser.init_str("abc").unwrap().unContinue();
+    /// assert_eq!(parsing.clone().more_str("d!").unDone().unwrap(), "abcd");
+    /// assert_eq!(parsing.more_str("de!").unDone(), Err(BufferOverflow { limit: 4 }));
+    /// ```
+    fn buffer_limit(self, limit: usize) -> impls::BufferedWith<Self>
+        where Self: Sized
+    {
+        self.buffer_with(impls::BufferPolicy::Limit(limit))
+    }
+
+    /// A parser which produces its input, buffering it in an arena.
+    ///
+    /// This is like `buffer()`, but when the input was provided in chunks, it is buffered
+    /// in `arena` rather than in a `String`, and the result is a string slice which lives
+    /// as long as the arena. This is only available with the `bumpalo` feature. For example:
+    ///
+    /// ```text
+    /// let arena = Bump::new();
+    /// let parser = character(char::is_alphabetic).plus(ignore).buffer_in(&arena);
+    /// let parsing = parser.init_str("abc").unwrap().unContinue();
+    /// assert_eq!(parsing.last_str("def!"), "abcdef");
+    /// ```
+    #[cfg(feature = "bumpalo")]
+    fn buffer_in<'bump>(self, arena: &'bump bumpalo::Bump) -> impls::BufferedIn<'bump, Self>
+        where Self: Sized
+    {
+        impls::BufferedIn::new(self, arena)
+    }
+
+    /// A parser which pairs the output of this parser with the number of characters it consumed.
+    ///
+    /// The characters are counted as they are parsed, so no input is buffered. For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// let parser = character(char::is_alphabetic).star(String::new).measure();
+    /// let parsing = parser.init_str("ab").unwrap().unContinue();
+    /// assert_eq!(parsing.more_str("\u{e9}!").unDone(), (String::from("ab\u{e9}"), 3));
+    /// ```
+    ///
+    /// This is only available for string parsers.
+    fn measure(self) -> impls::Measure<Self>
+        where Self: Sized
+    {
+        impls::Measure::new(self)
+    }
+
+    /// Wrap this parser to allow grammars to be written using operators.
+    ///
+    /// For rules `p` and `q`, `p | q` is `p.or_else(q)`, `p >> q` is `p.discard_and_then(q)`,
+    /// which keeps the output of `q`, and `p << q` is `p.and_then_discard(q)`, which keeps
+    /// the output of `p`. The right hand side of an operator can be any parser, `char` or
+    /// `&'static str`, and the result is another rule, for example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,IntoParser,UncommittedStr};
+    /// fn ignore() {}
+    /// let ws = character(char::is_whitespace).star(ignore);
+    /// let name = character(char::is_alphanumeric).star(String::new);
+    /// let number = character(char::is_numeric).plus(String::new);
+    /// let token = '$'.parser().rule() >> name << ws | number;
+    /// assert_eq!(token.init_str("$abc  123").unwrap().unDone(), "abc");
+    /// assert_eq!(token.init_str("123 abc").unwrap().unDone(), "123");
+    /// ```
+    ///
+    /// As usual in Rust, `>>` and `<<` bind more tightly than `|`.
+    fn rule(self) -> impls::Rule<Self>
+        where Self: Sized
+    {
+        impls::Rule::new(self)
+    }
+
+    /// A parser which requires its input to end when this parser does.
+    ///
+    /// The result is `Ok(result)` if there is no input after the parse, and otherwise an
+    /// error with the byte offset of the first trailing character, rather than silently
+    /// ignoring the rest of the input. Since the end of input is only known once there is
+    /// no more data, the parser continues until `done()`, for example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+    /// let parser = character(char::is_alphabetic).plus(String::new).complete();
+    /// let parsing = parser.init_str("abc").unwrap().unContinue();
+    /// assert_eq!(parsing.last_str("def").unwrap(), "abcdef");
+    /// let parsing = parser.init_str("abc").unwrap().unContinue();
+    /// assert_eq!(parsing.last_str("def!").unwrap_err().position, 6);
+    /// ```
+    ///
+    /// This is only available for string parsers.
+    fn complete(self) -> impls::Complete<Self>
+        where Self: Sized
+    {
+        impls::Complete::new(self)
+    }
+
+}
+
+/// A trait for values which can be converted into parsers.
+///
+/// This is implemented by every parser, by `char`, which converts to a parser for
+/// that character, by `&'static str`, which converts to a parser for that string,
+/// by ranges such as `'0'..='9'`, which convert to a parser for one item in the range,
+/// and by byte arrays such as `[b'\r', b'\n']`, which convert to a parser for those bytes.
+/// The string and byte parsers backtrack if the first item does not match, and otherwise
+/// produce an `impls::Expected` error if the rest does not match.
+/// The arguments of `or_else` and `and_then` are converted using this trait, for example:
+///
+/// ```
+/// # use parsell::{character,Parser,IntoParser,Uncommitted,UncommittedStr};
+/// # use parsell::impls::Expected;
+/// let parser = '+'.parser().or_else('-');
+/// assert_eq!(parser.init_str("+").unwrap().unDone(), '+');
+/// assert_eq!(parser.init_str("-").unwrap().unDone(), '-');
+/// let parser = "=>".parser().or_else("==");
+/// assert_eq!(parser.init_str("=>x").unwrap().unDone(), Ok("=>"));
+/// assert_eq!(parser.init_str("=<x").unwrap().unDone(), Err(Expected("=>")));
+/// let digit = ('0'..='9').parser();
+/// assert_eq!(digit.init_str("7").unwrap().unDone(), '7');
+/// let newline = [b'\r', b'\n'].parser();
+/// let mut data = b"\r\nabc".iter().cloned().peekable();
+/// assert_eq!(newline.init(&mut data).unwrap().unDone(), Ok(*b"\r\n"));
+/// ```
+///
+/// Literals are uncommitted, so after `and_then` they are made committed, for example with `')'.parser().opt()`.
+/// The conversions are also available using `From`, for example `let plus: impls::Character<_> = '+'.into()`.
+
+pub trait IntoParser {
+    type Parser: Parser;
+    fn parser(self) -> Self::Parser;
+}
+
+impl<P> IntoParser for P where P: Parser {
+    type Parser = P;
+    fn parser(self) -> P {
+        self
+    }
+}
+
+impl IntoParser for char {
+    type Parser = impls::Character<impls::Equals<char>>;
+    fn parser(self) -> Self::Parser {
+        impls::Character::from(self)
+    }
+}
+
+impl IntoParser for &'static str {
+    type Parser = impls::Tag;
+    fn parser(self) -> impls::Tag {
+        impls::Tag::from(self)
+    }
+}
+
+impl<T> IntoParser for std::ops::RangeInclusive<T> where T: Copy {
+    type Parser = impls::Character<impls::InRange<T>>;
+    fn parser(self) -> Self::Parser {
+        impls::Character::from(self)
+    }
+}
+
+impl<T> IntoParser for std::ops::Range<T> {
+    type Parser = impls::Character<impls::InRange<T>>;
+    fn parser(self) -> Self::Parser {
+        impls::Character::from(self)
+    }
+}
+
+impl<const N: usize> IntoParser for [u8; N] {
+    type Parser = impls::ByteTag<N>;
+    fn parser(self) -> impls::ByteTag<N> {
+        impls::ByteTag::from(self)
+    }
+}
+
+/// A trait for committed parsers.
+///
+/// A parser is committed if it is guaranteed only to backtrack on empty input.
 /// CommittedInfer parsers are typically constructed by calling the methods of the library,
 /// for example:
 ///
@@ -658,6 +1536,17 @@ pub trait Uncommitted<Ch, Str, Output>
     /// Parse a string of data.
     fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Output>>;
 
+    /// Run the parser repeatedly, passing each output to `consumer`, for as long as
+    /// it can do so without needing more input.
+    ///
+    /// This is a fast path for `star` and `plus`, which then carry on one run at a time.
+    /// By default it does nothing, and `character(f)` overrides it to scan string slices directly.
+    fn init_repeat<C>(&self, _string: &mut Str, _consumer: &mut C)
+        where Self: Sized,
+              C: Consumer<Output>,
+    {
+    }
+
 }
 
 /// A trait for uncommitted parsers which can infer their output type from their input types.
@@ -672,6 +1561,10 @@ impl<P, Ch, Str> UncommittedInfer<Ch, Str> for P
 {
 }
 
+/// The result of `parser.init_str_rest(string)`: the result of parsing, and the unconsumed rest of `string`.
+
+pub type StrRest<'a, State, Output> = (Option<ParseResult<State, Output>>, &'a str);
+
 /// A trait for uncommitted string parsers.
 
 pub trait UncommittedStr<'a>: UncommittedInfer<char, Chars<'a>> {
@@ -702,7 +1595,31 @@ pub trait UncommittedStr<'a>: UncommittedInfer<char, Chars<'a>> {
     fn init_str(&self, string: &'a str) -> Option<ParseResult<Self::State, Self::Output>>
         where Self: Sized,
     {
-        self.init(&mut string.chars())
+        on_str(string, |data| self.init(data))
+    }
+
+    /// Provides string data to the parser, returning the unconsumed remainder of the string.
+    ///
+    /// This is like `init_str`, but if the parser finishes part way through the string,
+    /// the rest of the string is returned rather than discarded.
+    ///
+    /// For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser,UncommittedStr};
+    /// let parser = character(char::is_alphabetic).star(String::new);
+    /// let (result, rest) = parser.init_str_rest("abc!de");
+    /// assert_eq!(result.unwrap().unDone(), "abc");
+    /// assert_eq!(rest, "!de");
+    /// ```
+
+    fn init_str_rest(&self, string: &'a str) -> StrRest<'a, Self::State, Self::Output>
+        where Self: Sized,
+    {
+        on_str(string, |data| {
+            let result = self.init(data);
+            (result, data.as_str())
+        })
     }
 
 }
@@ -899,8 +1816,74 @@ pub trait Boxable<Ch, Str, Output>
 {
     fn more_boxable(&mut self, string: &mut Str) -> ParseResult<(), Output>;
     fn done_boxable(&mut self) -> Output;
+    fn finish_boxable(&mut self, string: &mut Str) -> Output {
+        match self.more_boxable(string) {
+            Done(result) => result,
+            Continue(()) => self.done_boxable(),
+        }
+    }
+    fn dump_boxable(&self, outline: &mut Outline) {
+        outline.type_name::<Self>();
+    }
+}
+
+/// A trait for string parsers whose type can be erased.
+///
+/// This is an object-safe version of `Uncommitted<char, Chars<'a>, Output>`, whose state is boxed.
+/// It is implemented by any string parser whose state is `'static` and does not depend on
+/// the lifetime of the input, and is used by `parser.erase()`.
+
+pub trait Erasable<Output> {
+    fn init_erasable<'a>(&self, string: &mut Chars<'a>) -> Option<ParseResult<BoxedState<Output>, Output>>;
+}
+
+/// A trait for committed string parsers whose type can be erased.
+///
+/// This is an object-safe version of `Committed<char, Chars<'a>, Output>`,
+/// and is used by `parser.erase_committed()`.
+
+pub trait ErasableCommitted<Output> {
+    fn empty_erasable(&self) -> Output;
+}
+
+/// A trait for uncommitted parsers which can be used as trait objects.
+///
+/// This is an object-safe version of `Uncommitted<Ch, Str, Output>`, whose state is boxed.
+/// It is implemented by any uncommitted parser whose state is `'static`, and
+/// `Box<DynUncommitted<Ch, Str, Output>>` is itself a parser, which allows grammars
+/// to be assembled at run time, for example:
+///
+/// ```
+/// # use parsell::{character,Parser,DynUncommitted,UncommittedStr};
+/// # use std::str::Chars;
+/// type Grammar<'a> = Box<DynUncommitted<char, Chars<'a>, String>>;
+/// fn grammar<'a>(numeric: bool) -> Grammar<'a> {
+///     if numeric {
+///         Box::new(character(char::is_numeric).plus(String::new))
+///     } else {
+///         Box::new(character(char::is_alphabetic).plus(String::new))
+///     }
+/// }
+/// assert_eq!(grammar(true).init_str("123abc").unwrap().unDone(), "123");
+/// assert!(grammar(false).init_str("123abc").is_none());
+/// ```
+
+pub trait DynUncommitted<Ch, Str, Output> {
+    fn init_dyn(&self, string: &mut Str) -> Option<ParseResult<DynState<Ch, Str, Output>, Output>>;
 }
 
+/// The boxed state of a string parser whose type has been erased.
+
+pub type BoxedState<Output> = Box<for<'a> Boxable<char, Chars<'a>, Output>>;
+
+/// The boxed state of an uncommitted parser used as a trait object.
+
+pub type DynState<Ch, Str, Output> = Box<Boxable<Ch, Str, Output>>;
+
+/// The state of a string parser whose type has been erased.
+
+pub type ErasedState<Output> = InState<impls::Erased<Output>, BoxedState<Output>>;
+
 /// A parser that knows its current state.
 ///
 /// This is produced by `parser.in_state(state)`.
@@ -925,6 +1908,12 @@ impl<P, PState, Ch, Str, Output> Stateful<Ch, Str, Output> for InState<P,PState>
     fn done(self) -> Output {
         self.1.done()
     }
+    fn finish(self, string: &mut Str) -> Output {
+        self.1.finish(string)
+    }
+    fn dump(&self, outline: &mut Outline) {
+        self.1.dump(outline)
+    }
 }
 
 /// A trait for one-argument functions.
@@ -949,6 +1938,36 @@ impl<P, PState, Ch, Str, Output> Stateful<Ch, Str, Output> for InState<P,PState>
 /// since `typeof` is not implemented in Rust.
 /// At some point, Rust will probably get abstract return types,
 /// at which point the main need for this type will go away.
+///
+/// Some common conversions are provided in `impls`: `DigitsToU64(radix)` converts
+/// a string of digits to a number, `HexPairToByte` converts a pair of hex digits
+/// (the second of which may be missing) to a byte, and `CharsToString` collects characters into a string. These produce `None`
+/// if the digits are invalid or overflow, for example:
+///
+/// ```
+/// # use parsell::{character,Parser,UncommittedStr};
+/// # use parsell::impls::{DigitsToU64,HexPairToByte};
+/// let number = character(|ch: char| ch.is_digit(16)).plus(String::new).map(DigitsToU64(16));
+/// assert_eq!(number.init_str("ff!").unwrap().unDone(), Some(255));
+/// let hex = character(|ch: char| ch.is_digit(16));
+/// let byte = hex.and_then(hex.opt()).map(HexPairToByte);
+/// assert_eq!(byte.init_str("7f").unwrap().unDone(), Some(127));
+/// assert_eq!(byte.init_str("7g").unwrap().unDone(), None);
+/// ```
+///
+/// Functions can be built from smaller pieces: `Compose::new(f, g)` applies `g` then `f`,
+/// `AndThenFn::new(f, g)` applies `f` then `g`, `Identity` returns its argument,
+/// and `Const(value)` ignores its argument and returns `value`, for example:
+///
+/// ```
+/// # use parsell::{character,Parser,UncommittedStr};
+/// # use parsell::impls::{AndThenFn,CharsToString,Const,DigitsToU64,Unwrap};
+/// let digits = character(|ch: char| ch.is_digit(10)).plus(Vec::new);
+/// let number = digits.map(AndThenFn::new(CharsToString, Unwrap::new(DigitsToU64(10))));
+/// assert_eq!(number.init_str("42;").unwrap().unDone(), 42);
+/// let zero = character(|ch: char| ch == '-').map(Const(0));
+/// assert_eq!(zero.init_str("-").unwrap().unDone(), 0);
+/// ```
 
 pub trait Function<S> {
     type Output;
@@ -1011,6 +2030,32 @@ impl<F, T> Factory for F where F: Fn() -> T
 /// assert_eq!(buffer,&[1,2,3,4]);
 /// ```
 ///
+/// With the `smallvec` feature, `SmallVec<A>` is a consumer of its items, and with the
+/// `arrayvec` feature, so is `ArrayVec<T, CAP>`. These avoid heap allocation for short repetitions,
+/// for example `parser.star(SmallVec::<[T; 4]>::new)`. Once an `ArrayVec` is full, the rest of
+/// the values are dropped, so to detect this it should be wrapped in a `BoundedConsumer`,
+/// for example `BoundedConsumer::items(ArrayVec::<T, 4>::new(), 4, QuotaPolicy::Fail)`.
+///
+/// With the `bumpalo` feature, the `Vec` and `String` collections of `bumpalo` are consumers,
+/// which allocate from an arena, for example `parser.star(|| Vec::new_in(&arena))`.
+/// The outputs of a parse can then be freed all at once by dropping the arena.
+///
+/// `HashMap<K, V>` and `BTreeMap<K, V>` are consumers of key-value pairs,
+/// where later values replace earlier values with the same key. Other ways of handling
+/// duplicate keys are given by `MapConsumer`.
+///
+/// ```
+/// # use parsell::Consumer;
+/// # use std::collections::HashMap;
+/// let mut map = HashMap::new();
+/// map.accept(("a", 1));
+/// map.accept(("a", 2));
+/// assert_eq!(map["a"], 2);
+/// ```
+///
+/// A `&RefCell<C>` or `Rc<RefCell<C>>` is a consumer when `C` is, which passes data
+/// to the shared consumer, for example for use with `parser.tee(consumer)`.
+///
 /// The unit type `()` is a trivial consumer that discards data.
 ///
 /// ```
@@ -1030,6 +2075,18 @@ impl<T> Consumer<T> for () {
     fn accept(&mut self, _: T) {}
 }
 
+impl<T, C> Consumer<T> for &RefCell<C> where C: Consumer<T> {
+    fn accept(&mut self, arg: T) {
+        self.borrow_mut().accept(arg);
+    }
+}
+
+impl<T, C> Consumer<T> for Rc<RefCell<C>> where C: Consumer<T> {
+    fn accept(&mut self, arg: T) {
+        self.borrow_mut().accept(arg);
+    }
+}
+
 impl Consumer<String> for String {
     fn accept(&mut self, arg: String) {
         self.push_str(&*arg);
@@ -1095,6 +2152,69 @@ impl<T> Consumer<T> for Vec<T> {
     }
 }
 
+impl<K, V> Consumer<(K, V)> for HashMap<K, V> where K: Eq + Hash
+{
+    fn accept(&mut self, (key, value): (K, V)) {
+        self.insert(key, value);
+    }
+}
+
+impl<K, V> Consumer<(K, V)> for BTreeMap<K, V> where K: Ord
+{
+    fn accept(&mut self, (key, value): (K, V)) {
+        self.insert(key, value);
+    }
+}
+
+#[cfg(feature = "smallvec")]
+impl<A> Consumer<A::Item> for smallvec::SmallVec<A> where A: smallvec::Array {
+    fn accept(&mut self, x: A::Item) {
+        self.push(x);
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'bump, T> Consumer<T> for bumpalo::collections::Vec<'bump, T> {
+    fn accept(&mut self, x: T) {
+        self.push(x);
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'bump> Consumer<char> for bumpalo::collections::String<'bump> {
+    fn accept(&mut self, x: char) {
+        self.push(x);
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump> Consumer<&'a str> for bumpalo::collections::String<'bump> {
+    fn accept(&mut self, arg: &'a str) {
+        self.push_str(arg);
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'a, 'bump> Consumer<Cow<'a, str>> for bumpalo::collections::String<'bump> {
+    fn accept(&mut self, arg: Cow<'a, str>) {
+        self.push_str(&arg);
+    }
+}
+
+#[cfg(feature = "bumpalo")]
+impl<'bump> Consumer<String> for bumpalo::collections::String<'bump> {
+    fn accept(&mut self, arg: String) {
+        self.push_str(&arg);
+    }
+}
+
+#[cfg(feature = "arrayvec")]
+impl<T, const CAP: usize> Consumer<T> for arrayvec::ArrayVec<T, CAP> {
+    fn accept(&mut self, x: T) {
+        let _ = self.try_push(x);
+    }
+}
+
 impl<C, T, E> Consumer<Result<T, E>> for Result<C, E> where C: Consumer<T>
 {
     fn accept(&mut self, value: Result<T, E>) {
@@ -1111,6 +2231,429 @@ impl<C, T, E> Consumer<Result<T, E>> for Result<C, E> where C: Consumer<T>
     }
 }
 
+/// A consumer which collects exactly `N` values into an array, without heap allocation.
+///
+/// This is used by `parser.count()`, for example:
+///
+/// ```
+/// # use parsell::{Consumer,ArrayConsumer};
+/// let mut buffer = ArrayConsumer::new();
+/// assert!(buffer.is_empty());
+/// buffer.accept(1);
+/// buffer.accept(2);
+/// assert_eq!(buffer.len(), 2);
+/// assert!(!buffer.is_full());
+/// buffer.accept(3);
+/// assert_eq!(buffer.into_array(), Some([1,2,3]));
+/// ```
+
+#[derive(Clone, Debug)]
+pub struct ArrayConsumer<T, const N: usize>([Option<T>; N], usize);
+
+impl<T, const N: usize> ArrayConsumer<T, N> {
+    /// An empty array consumer.
+    pub fn new() -> Self {
+        ArrayConsumer(std::array::from_fn(|_| None), 0)
+    }
+
+    /// The number of values accepted so far.
+    pub fn len(&self) -> usize {
+        self.1
+    }
+
+    /// Have no values been accepted yet?
+    pub fn is_empty(&self) -> bool {
+        self.1 == 0
+    }
+
+    /// Have all `N` values been accepted?
+    pub fn is_full(&self) -> bool {
+        self.1 == N
+    }
+
+    /// The array of accepted values, or `None` if fewer than `N` values were accepted.
+    pub fn into_array(self) -> Option<[T; N]> {
+        if self.is_full() {
+            Some(self.0.map(|value| value.unwrap()))
+        } else {
+            None
+        }
+    }
+}
+
+impl<T, const N: usize> Default for ArrayConsumer<T, N> {
+    fn default() -> Self {
+        ArrayConsumer::new()
+    }
+}
+
+impl<T, const N: usize> Consumer<T> for ArrayConsumer<T, N> {
+    fn accept(&mut self, value: T) {
+        assert!(self.1 < N, "ArrayConsumer accepted more than {} values.", N);
+        self.0[self.1] = Some(value);
+        self.1 += 1;
+    }
+}
+
+/// A trait for maps, which are used by `MapConsumer`.
+
+pub trait KeyValueMap<K, V> {
+    /// The value for `key`, if there is one.
+    fn get_mut(&mut self, key: &K) -> Option<&mut V>;
+    /// Add a value for `key`, which is not already in the map.
+    fn insert(&mut self, key: K, value: V);
+}
+
+impl<K, V> KeyValueMap<K, V> for HashMap<K, V> where K: Eq + Hash
+{
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        HashMap::get_mut(self, key)
+    }
+    fn insert(&mut self, key: K, value: V) {
+        HashMap::insert(self, key, value);
+    }
+}
+
+impl<K, V> KeyValueMap<K, V> for BTreeMap<K, V> where K: Ord
+{
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        BTreeMap::get_mut(self, key)
+    }
+    fn insert(&mut self, key: K, value: V) {
+        BTreeMap::insert(self, key, value);
+    }
+}
+
+/// What a `MapConsumer` does with a value whose key is already in the map.
+
+#[derive(Copy, Clone, Debug)]
+pub enum Duplicates<F> {
+    /// Keep the earlier value.
+    KeepFirst,
+    /// Replace the earlier value, as the `HashMap` and `BTreeMap` consumers do.
+    KeepLast,
+    /// Keep the earlier value, and report the key as an error.
+    Reject,
+    /// Merge the value into the earlier value, using a function `F: FnMut(&mut V, V)`.
+    Merge(F),
+}
+
+/// A consumer of key-value pairs, which adds them to a map with a policy for duplicate keys.
+///
+/// The map can be a `HashMap` or `BTreeMap`, for example:
+///
+/// ```
+/// # use parsell::{Consumer,MapConsumer,Duplicates};
+/// # use std::collections::HashMap;
+/// fn join(old: &mut String, new: String) { old.push_str(", "); old.push_str(&new); }
+/// let mut map = MapConsumer::new(HashMap::new(), Duplicates::Merge(join));
+/// map.accept(("Accept", String::from("text/html")));
+/// map.accept(("Accept", String::from("text/plain")));
+/// assert_eq!(map.into_map()["Accept"], "text/html, text/plain");
+///
+/// let mut map = MapConsumer::new(HashMap::new(), Duplicates::Reject);
+/// map.accept(("a", 1));
+/// map.accept(("b", 2));
+/// map.accept(("a", 3));
+/// assert_eq!(map.into_result(), Err("a"));
+/// ```
+///
+/// A parser builds one with a factory such as `|| MapConsumer::new(HashMap::new(), Duplicates::KeepFirst)`.
+/// To merge values using a closure which captures its environment, use `MapConsumer::with_policy`.
+
+#[derive(Clone, Debug)]
+pub struct MapConsumer<M, K, V, F = fn(&mut V, V)> {
+    map: M,
+    policy: Duplicates<F>,
+    duplicate: Option<K>,
+    marker: PhantomData<fn(V)>,
+}
+
+impl<M, K, V> MapConsumer<M, K, V> {
+    /// A consumer which adds pairs to `map`, handling duplicate keys according to `policy`.
+    pub fn new(map: M, policy: Duplicates<fn(&mut V, V)>) -> Self {
+        MapConsumer::with_policy(map, policy)
+    }
+}
+
+impl<M, K, V, F> MapConsumer<M, K, V, F> {
+    /// A consumer like `MapConsumer::new`, whose policy can merge values using any function.
+    pub fn with_policy(map: M, policy: Duplicates<F>) -> Self {
+        MapConsumer {
+            map,
+            policy,
+            duplicate: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// The map of pairs accepted so far.
+    pub fn map(&self) -> &M {
+        &self.map
+    }
+
+    /// The map, ignoring any rejected duplicate keys.
+    pub fn into_map(self) -> M {
+        self.map
+    }
+
+    /// The map, or the first rejected duplicate key.
+    pub fn into_result(self) -> Result<M, K> {
+        match self.duplicate {
+            Some(key) => Err(key),
+            None => Ok(self.map),
+        }
+    }
+}
+
+impl<M, K, V, F> Consumer<(K, V)> for MapConsumer<M, K, V, F>
+    where M: KeyValueMap<K, V>,
+          F: FnMut(&mut V, V),
+{
+    fn accept(&mut self, (key, value): (K, V)) {
+        match self.map.get_mut(&key) {
+            None => self.map.insert(key, value),
+            Some(old) => match self.policy {
+                Duplicates::KeepFirst => (),
+                Duplicates::KeepLast => *old = value,
+                Duplicates::Reject => if self.duplicate.is_none() {
+                    self.duplicate = Some(key);
+                },
+                Duplicates::Merge(ref mut merge) => merge(old, value),
+            },
+        }
+    }
+}
+
+/// A consumer which applies a function to values before passing them on to another consumer.
+///
+/// This transforms the items of a repetition on their way into its collection,
+/// rather than mapping the repeated parser, for example:
+///
+/// ```
+/// # use parsell::{character,map_consumer,Parser,UncommittedStr};
+/// let parser = character(char::is_alphabetic)
+///     .star(|| map_consumer(String::new(), |ch: char| ch.to_ascii_lowercase()));
+/// let letters = parser.init_str("aBcD!").unwrap().unDone().into_inner();
+/// assert_eq!(letters, "abcd");
+/// ```
+
+#[derive(Copy, Clone, Debug)]
+pub struct MappedConsumer<C, F>(C, F);
+
+impl<C, F> MappedConsumer<C, F> {
+    /// A consumer which passes values mapped by `function` on to `consumer`.
+    pub fn new(consumer: C, function: F) -> Self {
+        MappedConsumer(consumer, function)
+    }
+
+    /// The consumer of the mapped values.
+    pub fn inner(&self) -> &C {
+        &self.0
+    }
+
+    /// The consumer of the mapped values.
+    pub fn into_inner(self) -> C {
+        self.0
+    }
+}
+
+impl<C, F, T> Consumer<T> for MappedConsumer<C, F>
+    where F: Function<T>,
+          C: Consumer<F::Output>,
+{
+    fn accept(&mut self, value: T) {
+        self.0.accept(self.1.apply(value));
+    }
+}
+
+/// A consumer which applies `function` to values before passing them on to `consumer`.
+
+pub fn map_consumer<C, F>(consumer: C, function: F) -> MappedConsumer<C, F> {
+    MappedConsumer::new(consumer, function)
+}
+
+/// What a `BoundedConsumer` does once its quota is exceeded.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum QuotaPolicy {
+    /// Drop the rest of the values, and produce an error.
+    Fail,
+    /// Drop the rest of the values, and keep the ones accepted before the quota was exceeded.
+    Truncate,
+}
+
+/// The error produced by a `BoundedConsumer` whose quota was exceeded.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct QuotaExceeded {
+    /// The quota, in items or bytes.
+    pub limit: usize,
+}
+
+impl Display for QuotaExceeded {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Consumer exceeded its quota of {}", self.limit)
+    }
+}
+
+impl std::error::Error for QuotaExceeded {}
+
+/// A consumer which limits how much is passed on to another consumer.
+///
+/// The consumer `BoundedConsumer::items(consumer, limit, policy)` passes at most `limit`
+/// values on to `consumer`, and `BoundedConsumer::bytes(consumer, limit, policy)` passes on
+/// values of at most `limit` bytes in total. Values after the quota is exceeded are dropped,
+/// so a repetition can not exhaust memory even if the grammar has no limit on its length,
+/// for example:
+///
+/// ```
+/// # use parsell::{character,BoundedConsumer,Parser,QuotaExceeded,QuotaPolicy,UncommittedStr};
+/// let parser = character(char::is_alphabetic)
+///     .star(|| BoundedConsumer::bytes(String::new(), 3, QuotaPolicy::Fail));
+/// let letters = parser.init_str("abc!").unwrap().unDone();
+/// assert_eq!(letters.into_result(), Ok(String::from("abc")));
+/// let letters = parser.init_str("abcd!").unwrap().unDone();
+/// assert_eq!(letters.into_result(), Err(QuotaExceeded { limit: 3 }));
+/// ```
+///
+/// With `QuotaPolicy::Truncate`, the result is the values accepted before the quota was exceeded,
+/// and `is_truncated()` reports whether any were dropped. Other measures of size are
+/// given by `BoundedConsumer::new(consumer, measure, limit, policy)`.
+
+#[derive(Copy, Clone, Debug)]
+pub struct BoundedConsumer<C, F> {
+    consumer: C,
+    measure: F,
+    limit: usize,
+    used: usize,
+    policy: QuotaPolicy,
+    exceeded: bool,
+}
+
+impl<C> BoundedConsumer<C, impls::CountItems> {
+    /// A consumer which passes at most `limit` values on to `consumer`.
+    pub fn items(consumer: C, limit: usize, policy: QuotaPolicy) -> Self {
+        BoundedConsumer::new(consumer, impls::CountItems, limit, policy)
+    }
+}
+
+impl<C> BoundedConsumer<C, impls::CountBytes> {
+    /// A consumer which passes at most `limit` bytes of strings or characters on to `consumer`.
+    pub fn bytes(consumer: C, limit: usize, policy: QuotaPolicy) -> Self {
+        BoundedConsumer::new(consumer, impls::CountBytes, limit, policy)
+    }
+}
+
+impl<C, F> BoundedConsumer<C, F> {
+    /// A consumer which passes values on to `consumer` while their total size,
+    /// as given by `measure`, is at most `limit`.
+    pub fn new(consumer: C, measure: F, limit: usize, policy: QuotaPolicy) -> Self {
+        BoundedConsumer {
+            consumer,
+            measure,
+            limit,
+            used: 0,
+            policy,
+            exceeded: false,
+        }
+    }
+
+    /// Were any values dropped?
+    pub fn is_truncated(&self) -> bool {
+        self.exceeded
+    }
+
+    /// The consumer of the values which were passed on.
+    pub fn inner(&self) -> &C {
+        &self.consumer
+    }
+
+    /// The consumer of the values which were passed on, or an error if the quota
+    /// was exceeded and the policy is `QuotaPolicy::Fail`.
+    pub fn into_result(self) -> Result<C, QuotaExceeded> {
+        if self.exceeded && self.policy == QuotaPolicy::Fail {
+            Err(QuotaExceeded { limit: self.limit })
+        } else {
+            Ok(self.consumer)
+        }
+    }
+}
+
+impl<C, F, T> Consumer<T> for BoundedConsumer<C, F>
+    where F: for<'a> Function<&'a T, Output = usize>,
+          C: Consumer<T>,
+{
+    fn accept(&mut self, value: T) {
+        if !self.exceeded {
+            let used = self.used.saturating_add(self.measure.apply(&value));
+            if used <= self.limit {
+                self.used = used;
+                self.consumer.accept(value);
+            } else {
+                self.exceeded = true;
+            }
+        }
+    }
+}
+
+/// A function which classifies identifiers as keywords.
+///
+/// The function `keyword_map(&keywords, other)` takes a string `name`, and if
+/// `keywords` contains `(name, keyword)` then it returns `keyword`, otherwise it
+/// returns `other(name)`. The keywords are a static table, sorted by name, which is
+/// searched without comparing `name` against each keyword in turn. It is used with `map`,
+/// for example:
+///
+/// ```
+/// # use parsell::{character,keyword_map,Keywords,Parser,UncommittedStr};
+/// #[derive(Clone, Debug, PartialEq)]
+/// enum Token { Else, If, Then, Identifier(String) }
+/// static KEYWORDS: Keywords<Token> = Keywords::new(&[
+///     ("else", Token::Else),
+///     ("if", Token::If),
+///     ("then", Token::Then),
+/// ]);
+/// let parser = character(char::is_alphabetic).plus(String::new)
+///     .map(keyword_map(&KEYWORDS, Token::Identifier));
+/// assert_eq!(parser.init_str("then ").unwrap().unDone(), Token::Then);
+/// assert_eq!(parser.init_str("thence ").unwrap().unDone(), Token::Identifier(String::from("thence")));
+/// ```
+
+pub fn keyword_map<K, S>(keywords: &'static Keywords<K>, other: fn(S) -> K) -> impls::KeywordMap<K, S> {
+    impls::KeywordMap::new(keywords, other)
+}
+
+/// A table of keywords for `keyword_map`, sorted by name.
+///
+/// The table is checked when it is constructed, so a table in a `static` or `const`
+/// which is not sorted, or has a name which appears more than once, is a compile-time error:
+///
+/// ```compile_fail
+/// # use parsell::Keywords;
+/// static KEYWORDS: Keywords<u8> = Keywords::new(&[("then", 0), ("if", 1)]);
+/// ```
+
+#[derive(Debug)]
+pub struct Keywords<K: 'static>(&'static [(&'static str, K)]);
+
+impl<K> Keywords<K> {
+    /// A table of keywords.
+    ///
+    /// # Panics
+    ///
+    /// If the keywords are not sorted by name, or a name appears more than once.
+    pub const fn new(keywords: &'static [(&'static str, K)]) -> Self {
+        let mut index = 1;
+        while index < keywords.len() {
+            assert!(impls::keyword_less(keywords[index - 1].0, keywords[index].0),
+                    "Keywords must be sorted and distinct.");
+            index += 1;
+        }
+        Keywords(keywords)
+    }
+}
+
 /// A trait for subtyping
 
 pub trait Upcast<T:?Sized> {
@@ -1267,6 +2810,37 @@ pub trait PeekableIterator: Iterator {
         self.next_if_ref(ByRef(f))
     }
 
+    /// Consume items while `f` is `true`, passing each of them to `consumer`.
+    ///
+    /// String slices override this to scan the slice directly, rather than one item at a time.
+    fn next_while<F, C>(&mut self, f: F, consumer: &mut C)
+        where F: Function<Self::Item, Output = bool>,
+              C: Consumer<Self::Item>,
+              Self::Item: Copy,
+    {
+        while let Some(item) = self.next_if(|item: Self::Item| f.apply(item)) {
+            consumer.accept(item);
+        }
+    }
+
+    /// If the input is a string slice, consume the longest common prefix of it and `prefix`,
+    /// returning its length in bytes.
+    ///
+    /// Other inputs return `None`, and are read one item at a time.
+    fn next_prefix(&mut self, _prefix: &str) -> Option<usize> {
+        None
+    }
+
+    /// A measure of progress through the input, which changes whenever an item is consumed,
+    /// if one is known.
+    ///
+    /// This is used to detect repeated parsers which succeed without consuming input.
+    /// By default it is the upper bound of the size hint, which for most iterators shrinks
+    /// as items are consumed. Inputs whose size hint does not should override this.
+    fn progress(&self) -> Option<usize> {
+        self.size_hint().1
+    }
+
 }
 
 impl<I> PeekableIterator for Peekable<I>
@@ -1301,6 +2875,74 @@ impl<'a> PeekableIterator for Chars<'a>
             _ => None
         }
     }
+
+    fn next_while<F, C>(&mut self, f: F, consumer: &mut C)
+        where F: Function<char, Output = bool>,
+              C: Consumer<char>,
+    {
+        let text = self.as_str();
+        let end = text.find(|ch| !f.apply(ch)).unwrap_or(text.len());
+        for ch in text[..end].chars() {
+            consumer.accept(ch);
+        }
+        *self = text[end..].chars();
+    }
+
+    fn next_prefix(&mut self, prefix: &str) -> Option<usize> {
+        let text = self.as_str();
+        let mut end = text.bytes().zip(prefix.bytes()).take_while(|&(lhs, rhs)| lhs == rhs).count();
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        *self = text[end..].chars();
+        Some(end)
+    }
+
+    fn progress(&self) -> Option<usize> {
+        Some(self.as_str().len())
+    }
+}
+
+/// A trait for inputs which are string slices, giving access to the remaining input.
+///
+/// This lets combinators written outside this crate avoid copying, as `parser.buffer()`
+/// does, by working with the underlying `&str` rather than one character at a time.
+/// It is implemented by `Chars<'a>`. For example, a parser for a run of spaces
+/// which borrows its result:
+///
+/// ```
+/// # use parsell::AsStrRemainder;
+/// fn spaces<'a, Str: AsStrRemainder<'a>>(string: &mut Str) -> &'a str {
+///     let text = string.remainder();
+///     let length = text.len() - text.trim_start_matches(' ').len();
+///     string.advance(length);
+///     &text[..length]
+/// }
+/// let mut data = "   abc".chars();
+/// assert_eq!(spaces(&mut data), "   ");
+/// assert_eq!(data.as_str(), "abc");
+/// ```
+
+pub trait AsStrRemainder<'a> {
+    /// The remaining input.
+    fn remainder(&self) -> &'a str;
+
+    /// Skips `length` bytes of the remaining input.
+    ///
+    /// This panics if `length` is past the end of the input, or is not on a character boundary.
+    fn advance(&mut self, length: usize);
+}
+
+impl<'a> AsStrRemainder<'a> for Chars<'a>
+{
+    fn remainder(&self) -> &'a str {
+        self.as_str()
+    }
+
+    fn advance(&mut self, length: usize) {
+        let text = self.as_str();
+        *self = text[length..].chars();
+    }
 }
 
 /// An uncommitted parser that reads one character.
@@ -1309,36 +2951,288 @@ impl<'a> PeekableIterator for Chars<'a>
 /// if `f(ch)` is `true` then it commits and the result is `ch`,
 /// otherwise it backtracks.
 ///
+/// The predicate `f` can be a function such as `char::is_alphabetic`, or a closure,
+/// which may capture data that is not copyable. For example:
+///
+/// ```
+/// # use parsell::{character,Parser,UncommittedStr};
+/// let vowels = String::from("aeiou");
+/// let vowel = character(move |ch: char| vowels.contains(ch));
+/// assert_eq!(vowel.init_str("abc").unwrap().unDone(), 'a');
+/// assert!(vowel.init_str("xyz").is_none());
+/// let syllable = (&vowel).and_then(character(char::is_alphabetic).star(String::new));
+/// assert_eq!(syllable.init_str("ebb!").unwrap().unDone(), ('e', String::from("bb")));
+/// ```
+///
+/// A parser whose predicate is not `Copy` is not `Copy` itself, so it is shared
+/// with combinators by reference, or using `Rc` or `Arc`.
+///
+/// This requires characters to be copyable.
+
+pub fn character<F>(f: F) -> impls::Character<F> {
+    impls::Character::new(f)
+}
+
+/// An uncommitted parser that reads one character by reference.
+///
+/// The parser `character_ref(f)` reads one character `ch` from the input,
+/// if `f(&ch)` is `true` then it commits and the result is `ch`,
+/// otherwise it backtracks.
+///
+/// This does not require characters to be copyable.
+
+pub fn character_ref<F>(f: F) -> impls::CharacterRef<F> {
+    impls::CharacterRef::new(f)
+}
+
+/// An uncommitted parser that reads one character from fallible input.
+///
+/// The parser `try_character(f)` reads one item from an input whose items are `Result<Ch, E>`.
+/// If the item is `Ok(ch)` and `f(ch)` is `true` then it commits and the result is `Ok(ch)`,
+/// if the item is `Err(err)` then it commits and the result is `Err(err)`,
+/// otherwise it backtracks. Errors can then be bubbled using the `try_` combinators.
+///
+/// Together with `read_bytes(reader)`, this allows parsing directly from a `Read`, for example:
+///
+/// ```
+/// # use parsell::{try_character,read_bytes,Parser,Uncommitted};
+/// # use parsell::ParseResult::Done;
+/// # use std::io;
+/// fn is_digit(byte: u8) -> bool { byte.is_ascii_digit() }
+/// fn mk_buffer() -> io::Result<Vec<u8>> { Ok(Vec::new()) }
+/// let parser = try_character(is_digit).plus(mk_buffer);
+/// let mut data = read_bytes("123abc".as_bytes());
+/// match parser.init(&mut data).unwrap() {
+///     Done(result) => assert_eq!(result.unwrap(), b"123"),
+///     _ => panic!("Can't happen"),
+/// }
+/// ```
+///
 /// This requires characters to be copyable.
 
-pub fn character<F>(f: F) -> impls::Character<F> {
-    impls::Character::new(f)
+pub fn try_character<F>(f: F) -> impls::TryCharacter<F> {
+    impls::TryCharacter::new(f)
+}
+
+/// The bytes of a reader, as input for parsers such as `try_character(f)`.
+///
+/// The input ends after the first I/O error, so that a reader which keeps failing
+/// does not produce an endless stream of errors. A blocking reader only reports
+/// the end of input at end of file, so parsers will not suspend until then.
+/// The reader is buffered internally, so need not be wrapped in a `BufReader`.
+
+pub fn read_bytes<R>(reader: R) -> impls::ReadBytes<R>
+    where R: io::Read,
+{
+    impls::ReadBytes::new(reader)
+}
+
+/// An uncommitted parser that reads one character by reference and applies a function to it.
+///
+/// The parser `character_ref_map(f)` reads one character `ch` from the input,
+/// if `f(&ch)` is `Some(value)` then it commits and the result is `value`,
+/// otherwise it backtracks.
+///
+/// This does not require characters to be copyable.
+
+pub fn character_map_ref<F>(f: F) -> impls::Map<impls::CharacterRef<impls::IsSome<F>>, impls::Dereference<impls::Unwrap<F>>>
+    where F: Copy,
+{
+    character_ref(impls::IsSome::new(f)).map_ref(impls::Unwrap::new(f))
+}
+
+/// An uncommitted parser which chooses the alternative matching the longest input.
+///
+/// The parser `alt_longest_of(parsers)` runs each of the type-erased `parsers` side by side,
+/// and once they have all finished, the result is the output of the one which consumed the
+/// most input, or the first such parser if there is a tie. It backtracks if every parser
+/// backtracks. Since the alternatives are chosen at run time, this is useful for grammars
+/// which are extended by plugins, for example:
+///
+/// ```
+/// # use parsell::{alt_longest_of,character,Parser,IntoParser,DynUncommitted,UncommittedStr};
+/// # use std::str::Chars;
+/// let mut keywords: Vec<Box<DynUncommitted<char, Chars, String>>> = Vec::new();
+/// keywords.push(Box::new("for".parser().map(|_| String::from("keyword"))));
+/// keywords.push(Box::new(character(char::is_alphabetic).plus(String::new)));
+/// let parser = alt_longest_of(keywords);
+/// assert_eq!(parser.init_str("for ").unwrap().unDone(), "keyword");
+/// assert_eq!(parser.init_str("format ").unwrap().unDone(), "format");
+/// ```
+///
+/// This is only available for string parsers.
+
+pub fn alt_longest_of<'a, Output>(parsers: Vec<Box<DynUncommitted<char, Chars<'a>, Output>>>) -> impls::AltLongest<'a, Output> {
+    impls::AltLongest::new(parsers)
+}
+
+/// An uncommitted parser which reads a key, then parses the rest of the input with the parser for that key.
+///
+/// The parser `switch(key, table)` runs `key`, and looks up its output in `table`,
+/// a map from keys to type-erased parsers, which parses the payload. This is the usual
+/// pattern for tagged unions, such as the type byte of a binary message, or the name of a
+/// directive in a configuration file. The result is `Ok` of the payload's output,
+/// or an error if the key is not in the table, or its parser backtracks. For example:
+///
+/// ```
+/// # use parsell::{character,switch,Parser,DynUncommitted,UncommittedStr};
+/// # use parsell::impls::SwitchError;
+/// # use std::collections::HashMap;
+/// # use std::str::Chars;
+/// let mut table: HashMap<String, Box<DynUncommitted<char, Chars, String>>> = HashMap::new();
+/// table.insert(String::from("name"), Box::new(character(char::is_alphabetic).plus(String::new)));
+/// table.insert(String::from("size"), Box::new(character(char::is_numeric).plus(String::new)));
+/// let name = character(char::is_alphabetic).plus(String::new);
+/// let directive = switch(name.and_then_discard(character(|ch| ch == ' ').opt()), &table);
+/// assert_eq!(directive.init_str("size 12;").unwrap().unDone(), Ok(String::from("12")));
+/// assert_eq!(directive.init_str("name 12;").unwrap().unDone(), Err(SwitchError::Rejected(String::from("name"))));
+/// assert_eq!(directive.init_str("color red;").unwrap().unDone(), Err(SwitchError::Unknown(String::from("color"))));
+/// ```
+///
+/// The table is a shared handle to the map, which is cloned when the parser is run.
+/// A `&HashMap` makes the parser `Copy`, and an `Rc<HashMap>` makes it `'static`,
+/// so it can be repeated or sequenced.
+
+pub fn switch<P, T>(key: P, table: T) -> impls::Switch<P, T> {
+    impls::Switch::new(key, table)
+}
+
+/// A parser that reads up to a terminator.
+///
+/// The parser `take_until(terminator)` consumes input up to, but not including, the first
+/// occurrence of `terminator`, and produces it. At the end of input, the result is all of
+/// the remaining input. The result is borrowed if the input arrived in one chunk,
+/// and owned otherwise. For example:
+///
+/// ```
+/// # use parsell::{take_until,UncommittedStr,StatefulStr};
+/// # use std::borrow::Cow::{Borrowed,Owned};
+/// let comment = take_until("-->");
+/// let (result, rest) = comment.init_str_rest("hello -->world");
+/// assert_eq!(result.unwrap().unDone(), Borrowed("hello "));
+/// assert_eq!(rest, "-->world");
+/// let parsing = comment.init_str("hello -").unwrap().unContinue();
+/// let (result, rest) = parsing.more_str_rest("- -->world");
+/// assert_eq!(result.unDone(), Owned::<str>(String::from("hello -- ")));
+/// assert_eq!(rest, "-->world");
+/// ```
+///
+/// A terminator which is split across chunks has been partly consumed by the time it is
+/// recognized, so in that case the whole terminator is consumed. Grammars which go on
+/// to read the terminator should use `take_through(terminator)`, which always consumes it.
+///
+/// This is only available for string parsers.
+
+pub fn take_until(terminator: &'static str) -> impls::TakeUntil {
+    impls::TakeUntil::new(terminator)
+}
+
+/// A parser that reads up to and including a terminator.
+///
+/// The parser `take_through(terminator)` is like `take_until(terminator)`, but it also
+/// consumes the terminator, which is not included in the result. For example:
+///
+/// ```
+/// # use parsell::{take_through,UncommittedStr,StatefulStr};
+/// # use std::borrow::Cow::{Borrowed,Owned};
+/// let comment = take_through("-->");
+/// let (result, rest) = comment.init_str_rest("hello -->world");
+/// assert_eq!(result.unwrap().unDone(), Borrowed("hello "));
+/// assert_eq!(rest, "world");
+/// let parsing = comment.init_str("hello --").unwrap().unContinue();
+/// let (result, rest) = parsing.more_str_rest("-->world");
+/// assert_eq!(result.unDone(), Owned::<str>(String::from("hello --")));
+/// assert_eq!(rest, "world");
+/// ```
+///
+/// This is only available for string parsers.
+
+pub fn take_through(terminator: &'static str) -> impls::TakeUntil {
+    impls::TakeUntil::through(terminator)
+}
+
+/// An uncommitted parser that reads the characters satisfying a predicate.
+///
+/// The parser `take_while(f)` reads characters `ch` while `f(ch)` is `true`, and produces
+/// the input it read. It commits if the first character satisfies `f`, and otherwise backtracks.
+/// It is like `character(f).plus(ignore).buffer()`, but rather than reading one character
+/// at a time, it scans each chunk of input as a string slice, which is much faster
+/// for long runs of characters. For example:
+///
+/// ```
+/// # use parsell::{take_while,UncommittedStr,StatefulStr};
+/// # use std::borrow::Cow::{Borrowed,Owned};
+/// let word = take_while(char::is_alphanumeric);
+/// assert_eq!(word.init_str("abc123 def").unwrap().unDone(), Borrowed("abc123"));
+/// assert!(word.init_str(" def").is_none());
+/// let parsing = word.init_str("abc").unwrap().unContinue();
+/// assert_eq!(parsing.more_str("123 def").unDone(), Owned::<str>(String::from("abc123")));
+/// ```
+///
+/// This is only available for string parsers.
+
+pub fn take_while<F>(f: F) -> impls::TakeWhile<F> {
+    impls::TakeWhile::new(f)
+}
+
+/// A parser that chooses between two parsers each time it starts.
+///
+/// The parser `when(flag, p, q)` calls `flag()` when it is initialized, and behaves like `p`
+/// if the result is `true`, and like `q` otherwise. The flag typically reads some state
+/// found earlier in the stream, such as a protocol version, so a grammar which depends
+/// on it can be written once. It is committed if `p` and `q` are. For example:
+///
+/// ```
+/// # use parsell::{character,when,Parser,UncommittedStr};
+/// # use std::cell::Cell;
+/// let version = Cell::new(1);
+/// let decimal = character(|ch: char| ch.is_digit(10)).plus(String::new);
+/// let hex = character(|ch: char| ch.is_digit(16)).plus(String::new);
+/// let number = when(|| version.get() >= 2, hex, decimal);
+/// assert_eq!(number.init_str("12ab ").unwrap().unDone(), "12");
+/// version.set(2);
+/// assert_eq!(number.init_str("12ab ").unwrap().unDone(), "12ab");
+/// ```
+
+pub fn when<F, P, Q>(flag: F, lhs: P, rhs: Q) -> impls::When<F, P, Q> {
+    impls::When::new(flag, lhs, rhs)
 }
 
-/// An uncommitted parser that reads one character by reference.
+/// A committed parser that copies its input to a consumer, except for the regions matched by a parser.
 ///
-/// The parser `character_ref(f)` reads one character `ch` from the input,
-/// if `f(&ch)` is `true` then it commits and the result is `ch`,
-/// otherwise it backtracks.
+/// The parser `strip(p, factory)` reads all of its input, and passes each item to a consumer
+/// built by `factory`, except for the regions which `p` matches, which are discarded.
+/// Only the state of `p` is kept between chunks, so if the consumer writes its items out,
+/// streams of any length can be filtered in constant memory. A region which is unfinished
+/// at the end of input is discarded. For example, to remove the colors from a log:
 ///
-/// This does not require characters to be copyable.
+/// ```
+/// # use parsell::{strip,UncommittedStr,StatefulStr};
+/// # use parsell::formats::ansi::escape_sequence;
+/// let parsing = strip(escape_sequence(), String::new).init_str("\x1b[31mError\x1b[").unwrap().unContinue();
+/// assert_eq!(parsing.last_str("0m: disk full"), "Error: disk full");
+/// ```
 
-pub fn character_ref<F>(f: F) -> impls::CharacterRef<F> {
-    impls::CharacterRef::new(f)
+pub fn strip<P, F>(parser: P, factory: F) -> impls::Strip<P, F> {
+    impls::Strip::new(parser, factory)
 }
 
-/// An uncommitted parser that reads one character by reference and applies a function to it.
+/// An uncommitted parser that reads one item if a function maps it to a value.
 ///
-/// The parser `character_ref_map(f)` reads one character `ch` from the input,
-/// if `f(&ch)` is `Some(value)` then it commits and the result is `value`,
-/// otherwise it backtracks.
+/// The parser `satisfy_map(f)` peeks at one item `ch` of the input,
+/// if `f(ch)` is `Some(value)` then it consumes `ch` and the result is `value`,
+/// otherwise it backtracks. Unlike `character_map(f)`, the function is only called once
+/// per item, and the input can be any items which can be cloned. For example:
 ///
-/// This does not require characters to be copyable.
+/// ```
+/// # use parsell::{satisfy_map,Parser,UncommittedStr};
+/// let digit = satisfy_map(|ch: char| ch.to_digit(16));
+/// assert_eq!(digit.init_str("f0").unwrap().unDone(), 15);
+/// assert!(digit.init_str("g0").is_none());
+/// ```
 
-pub fn character_map_ref<F>(f: F) -> impls::Map<impls::CharacterRef<impls::IsSome<F>>, impls::Dereference<impls::Unwrap<F>>>
-    where F: Copy,
-{
-    character_ref(impls::IsSome::new(f)).map_ref(impls::Unwrap::new(f))
+pub fn satisfy_map<F>(f: F) -> impls::SatisfyMap<F> {
+    impls::SatisfyMap::new(f)
 }
 
 /// An uncommitted parser that reads one character and applies a function to it.
@@ -1368,6 +3262,28 @@ pub fn emit<T>(t: T) -> impls::Emit<T> {
     impls::Emit::new(t)
 }
 
+/// A committed parser that reads zero characters, and produces a fresh value each time.
+///
+/// The parser `emit_with(f)` produces `f()`. Unlike `emit(f)`, the function does not
+/// need to be `'static` or `Copy`, so it can produce values derived from borrowed
+/// configuration, for example as the last alternative of a choice:
+///
+/// ```
+/// # use parsell::{character,emit_with,Parser,UncommittedStr};
+/// let placeholder = String::from("anonymous");
+/// let name = character(char::is_alphabetic).plus(String::new)
+///     .or_else(emit_with(|| placeholder.clone()));
+/// assert_eq!(name.init_str("bob!").unwrap().unDone(), "bob");
+/// assert_eq!(name.init_str("!").unwrap().unDone(), "anonymous");
+/// ```
+///
+/// Since its function is not stored in the state of the parser, it can be used
+/// in choices, but not on the right of `and_then`, which stores its right-hand parser.
+
+pub fn emit_with<F>(f: F) -> impls::EmitWith<F> {
+    impls::EmitWith::new(f)
+}
+
 // ----------- Tests -------------
 
 #[allow(non_snake_case)]
@@ -1389,6 +3305,23 @@ impl<State, Output> ParseResult<State, Output>
 
 }
 
+#[test]
+fn test_parse_result() {
+    let done: ParseResult<&str, u32> = Done(1);
+    assert_eq!(done.map(|n| n + 1).unwrap_done("unfinished"), 2);
+    assert_eq!(done.map_continue(str::len).unwrap_done("unfinished"), 1);
+    let parsing: ParseResult<&str, u32> = Continue("abc");
+    assert_eq!(parsing.map(|n| n + 1).unContinue(), "abc");
+    assert_eq!(parsing.map_continue(str::len).unContinue(), 3);
+}
+
+#[test]
+#[should_panic(expected = "unfinished")]
+fn test_unwrap_done() {
+    let parsing: ParseResult<&str, u32> = Continue("abc");
+    parsing.unwrap_done("unfinished");
+}
+
 #[test]
 fn test_character() {
     let parser = character(char::is_alphabetic);
@@ -1418,6 +3351,19 @@ fn test_character_ref() {
     assert_eq!(data.as_str(), "bcd");
 }
 
+#[test]
+fn test_character_not_copy() {
+    // Closures which capture a Vec are Clone but not Copy.
+    let excluded = ['b', 'c'];
+    let parser = character(move |ch: char| !excluded.contains(&ch));
+    assert_eq!(parser.init_str("abc").unwrap().unDone(), 'a');
+    assert!(parser.init_str("bcd").is_none());
+    let excluded = ['b', 'c'];
+    let parser = character_ref(move |ch: &char| !excluded.contains(ch));
+    assert_eq!(parser.init_str("abc").unwrap().unDone(), 'a');
+    assert!(parser.init_str("bcd").is_none());
+}
+
 #[test]
 fn test_character_map() {
     fn to_decimal_digit(ch: char) -> Option<u32> { ch.to_digit(10) }
@@ -1448,6 +3394,28 @@ fn test_character_map_ref() {
     assert_eq!(data.as_str(), "abcd");
 }
 
+#[test]
+fn test_satisfy_map() {
+    use std::cell::Cell;
+    #[derive(Clone, Debug, PartialEq)]
+    enum Token { Word(String), Number(u32) }
+    fn number(token: Token) -> Option<u32> { match token { Token::Number(n) => Some(n), _ => None } }
+    let tokens = vec![Token::Number(3), Token::Word(String::from("x"))];
+    let mut data = tokens.into_iter().peekable();
+    let parser = satisfy_map(number);
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), 3);
+    assert!(parser.init(&mut data).is_none());
+    assert_eq!(data.next(), Some(Token::Word(String::from("x"))));
+    assert!(parser.init(&mut data).is_none());
+    let calls = Cell::new(0);
+    let parser = satisfy_map(|ch: char| { calls.set(calls.get() + 1); ch.to_digit(10) });
+    let mut data = "7x".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), 7);
+    assert!(parser.init(&mut data).is_none());
+    assert_eq!(data.as_str(), "x");
+    assert_eq!(calls.get(), 2);
+}
+
 #[test]
 #[allow(non_snake_case)]
 fn test_CHARACTER() {
@@ -1715,6 +3683,55 @@ fn test_try_discard_and_then_try() {
     assert_eq!(data.as_str(), "b");
 }
 
+#[test]
+#[allow(non_snake_case)]
+fn test_try_seq3() {
+    fn mk_err<T>(_: Option<char>) -> Result<T, String> { Err(String::from("oh")) }
+    fn mk_ok<T>(ok: T) -> Result<T, String> { Ok(ok) }
+    let ALPHANUMERIC = character(char::is_alphanumeric).map(mk_ok).or_else(CHARACTER.map(mk_err));
+    let parser = ALPHANUMERIC.try_seq3(ALPHANUMERIC, ALPHANUMERIC);
+    let mut data = "".chars();
+    assert!(parser.init(&mut data).is_none());
+    let mut data = "abcd".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Ok(('a', 'b', 'c')));
+    assert_eq!(data.as_str(), "d");
+    let mut data = "ab!d".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Err(String::from("oh")));
+    assert_eq!(data.as_str(), "d");
+    let parsing = parser.init_str("a").unwrap().unContinue();
+    assert_eq!(parsing.more_str("bc").unDone(), Ok(('a', 'b', 'c')));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_try_and_then_try_or_skip() {
+    fn mk_err<T>(_: Option<char>) -> Result<T, String> { Err(String::from("oh")) }
+    fn mk_ok<T>(ok: T) -> Result<T, String> { Ok(ok) }
+    fn is_semicolon(ch: char) -> bool { ch == ';' }
+    let ALPHANUMERIC = character(char::is_alphanumeric).map(mk_ok).or_else(CHARACTER.map(mk_err));
+    let parser = ALPHANUMERIC.try_and_then_try_or_skip(ALPHANUMERIC, is_semicolon);
+    let mut data = "".chars();
+    assert!(parser.init(&mut data).is_none());
+    let mut data = "abc".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Ok(('a', 'b')));
+    assert_eq!(data.as_str(), "c");
+    let mut data = "a!c".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Err(String::from("oh")));
+    assert_eq!(data.as_str(), "c");
+    let mut data = "!bc;d".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Err(String::from("oh")));
+    assert_eq!(data.as_str(), "d");
+    let parsing = parser.init_str("!b").unwrap().unContinue();
+    let parsing = parsing.more_str("c").unContinue();
+    let mut data = "d;e".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), Err(String::from("oh")));
+    assert_eq!(data.as_str(), "e");
+    let parsing = parser.init_str("!b").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), Err(String::from("oh")));
+    let parsing = parser.init_str("a").unwrap().unContinue();
+    assert_eq!(parsing.more_str("b").unDone(), Ok(('a', 'b')));
+}
+
 #[test]
 #[allow(non_snake_case)]
 fn test_or_else() {
@@ -1782,6 +3799,193 @@ fn test_star() {
     assert_eq!(data.as_str(), "!");
 }
 
+#[test]
+fn test_try_character() {
+    use std::io;
+    use std::io::Read;
+    struct Failing<'a>(&'a [u8]);
+    impl<'a> Read for Failing<'a> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                Err(io::Error::other("oops"))
+            } else {
+                let size = try!(self.0.read(buffer));
+                Ok(size)
+            }
+        }
+    }
+    fn is_digit(byte: u8) -> bool { byte.is_ascii_digit() }
+    fn mk_buffer() -> io::Result<Vec<u8>> { Ok(Vec::new()) }
+    let parser = try_character(is_digit).plus(mk_buffer);
+    let mut data = read_bytes("12a".as_bytes());
+    assert_eq!(parser.init(&mut data).unwrap().unDone().unwrap(), b"12");
+    assert_eq!(data.next().unwrap().unwrap(), b'a');
+    let mut data = read_bytes("a".as_bytes());
+    assert!(parser.init(&mut data).is_none());
+    let mut data = read_bytes(Failing(b"12"));
+    let parsing = parser.init(&mut data).unwrap().unContinue();
+    assert_eq!(parsing.last(&mut data).unwrap_err().to_string(), "oops");
+    assert!(data.next().is_none());
+    let mut data = read_bytes(Failing(b""));
+    let parsing = parser.init(&mut data).unwrap().unContinue();
+    assert!(parsing.last(&mut data).is_err());
+}
+
+#[test]
+#[cfg(feature = "arrayvec")]
+fn test_arrayvec() {
+    use arrayvec::ArrayVec;
+    let parser = character(char::is_alphabetic).star(ArrayVec::<char, 3>::new);
+    assert_eq!(&parser.init_str("abcd!").unwrap().unDone()[..], &['a', 'b', 'c']);
+    let parser = character(char::is_alphabetic)
+        .star(|| BoundedConsumer::items(ArrayVec::<char, 3>::new(), 3, QuotaPolicy::Fail));
+    let letters = parser.init_str("abc!").unwrap().unDone().into_result().unwrap();
+    assert_eq!(&letters[..], &['a', 'b', 'c']);
+    let letters = parser.init_str("abcd!").unwrap().unDone();
+    assert_eq!(letters.into_result().unwrap_err(), QuotaExceeded { limit: 3 });
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert!(parsing.last_str("cde").into_result().is_err());
+}
+
+#[test]
+fn test_count() {
+    let parser = character(char::is_numeric).map(Some).or_else(CHARACTER).count::<3>();
+    assert_eq!(parser.init_str(""), None);
+    let mut data = "12a4".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), [Some('1'), Some('2'), Some('a')]);
+    assert_eq!(data.as_str(), "4");
+    let parsing = parser.init_str("1").unwrap().unContinue();
+    assert_eq!(parsing.last_str("2"), [Some('1'), Some('2'), None]);
+    assert_eq!(Committed::<char, Chars, _>::empty(&parser), [None, None, None]);
+    let parser = CHARACTER.count::<0>();
+    let mut data = "abc".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), []);
+    assert_eq!(data.as_str(), "abc");
+}
+
+#[test]
+fn test_find() {
+    let parser = character(char::is_numeric).plus(String::new).find_counted();
+    assert_eq!(parser.init_str(""), None);
+    let mut data = "ab12c".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), Some((2, String::from("12"))));
+    assert_eq!(data.as_str(), "c");
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("c1").unContinue();
+    assert_eq!(parsing.last_str("2d"), Some((3, String::from("12"))));
+    let parsing = parser.init_str("abc").unwrap().unContinue();
+    assert_eq!(parsing.last_str("de"), None);
+    assert_eq!(Committed::<char, Chars, _>::empty(&parser), None);
+    let parser = character(char::is_numeric).find();
+    assert_eq!(parser.init_str("ab3").unwrap().unDone(), Some('3'));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_erase() {
+    fn ignore() {}
+    let ALPHANUMERIC = character(char::is_alphanumeric).erase();
+    let WORD = ALPHANUMERIC.plus(String::new).erase();
+    let parser = WORD.and_then(character(char::is_whitespace).star(ignore)).plus(Vec::new).erase();
+    assert_eq!(parser.init_str("!"), None);
+    assert_eq!(parser.init_str("abc def!").unwrap().unDone(), vec![(String::from("abc"), ()), (String::from("def"), ())]);
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("c d").unContinue();
+    assert_eq!(parsing.last_str("ef!"), vec![(String::from("abc"), ()), (String::from("def"), ())]);
+}
+
+#[test]
+fn test_star_no_progress() {
+    let parser = character(char::is_alphanumeric).opt().star(Vec::new);
+    let mut data = "ab!".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), vec![Some('a'), Some('b')]);
+    assert_eq!(data.as_str(), "!");
+}
+
+#[test]
+fn test_star_lazy_no_progress() {
+    let parser = character(char::is_alphanumeric).opt().star_lazy(character(char::is_whitespace), Vec::new);
+    let mut data = "ab!".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), vec![Some('a'), Some('b')]);
+    assert_eq!(data.as_str(), "!");
+}
+
+#[test]
+fn test_star_no_progress_peekable() {
+    let parser = character(char::is_alphanumeric).opt().star(Vec::new);
+    let mut data = vec!['a', 'b', '!'].into_iter().peekable();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), vec![Some('a'), Some('b')]);
+    assert_eq!(data.next(), Some('!'));
+    // An input whose size is not known exactly.
+    fn is_a(ch: char) -> bool { ch == 'a' }
+    let parser = character(is_a).opt().star(Vec::new);
+    let mut data = "bbb".chars().filter(|_| true).peekable();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), vec![]);
+    assert_eq!(data.next(), Some('b'));
+    fn is_a_byte(byte: u8) -> bool { byte == b'a' }
+    let bytes = try_character(is_a_byte).opt().star(Vec::new);
+    let mut data = read_bytes("aab".as_bytes());
+    assert_eq!(bytes.init(&mut data).unwrap().unDone().len(), 2);
+    assert_eq!(data.next().unwrap().unwrap(), b'b');
+}
+
+#[test]
+fn test_star_unbounded_size_hint() {
+    // An input which does not know how much is left, so only the end of input is checked.
+    struct Unbounded(::std::vec::IntoIter<char>);
+    impl Iterator for Unbounded {
+        type Item = char;
+        fn next(&mut self) -> Option<char> { self.0.next() }
+        fn size_hint(&self) -> (usize, Option<usize>) { (0, None) }
+    }
+    let parser = character(char::is_alphanumeric).star(String::new);
+    let mut data = Unbounded(vec!['a', 'b', '!'].into_iter()).peekable();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), "ab");
+    assert_eq!(data.next(), Some('!'));
+    let parser = character(char::is_alphanumeric).opt().star(Vec::new);
+    let mut data = Unbounded(vec!['a', 'b'].into_iter()).peekable();
+    assert_eq!(parser.init(&mut data).unwrap().unContinue().finish(&mut data), vec![Some('a'), Some('b')]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_star_lazy() {
+    fn is_semicolon(ch: char) -> bool { ch == ';' }
+    let parser = CHARACTER.and_then(character(char::is_alphanumeric).star_lazy(character(char::is_numeric), String::new));
+    let mut data = "".chars();
+    assert!(parser.init(&mut data).is_none());
+    let mut data = "!?".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), (Some('!'), String::from("")));
+    assert_eq!(data.as_str(), "?");
+    let mut data = "!abc1def".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), (Some('!'), String::from("abc")));
+    assert_eq!(data.as_str(), "def");
+    let mut data1 = "!ab".chars();
+    let mut data2 = "c!".chars();
+    assert_eq!(parser.init(&mut data1).unwrap().unContinue().more(&mut data2).unDone(), (Some('!'), String::from("abc")));
+    assert_eq!(data2.as_str(), "!");
+    let mut data = "!ab".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unContinue().last(&mut "".chars()), (Some('!'), String::from("ab")));
+    let parser = CHARACTER.star_lazy(character(is_semicolon).and_then(CHARACTER), Vec::new);
+    testing::assert_chunk_invariant(parser, "ab;cd;");
+    testing::assert_chunk_invariant(parser, "ab");
+    testing::assert_chunk_invariant(parser, "ab;");
+}
+
+#[test]
+fn test_star_lazy_with() {
+    fn is_semicolon(ch: char) -> bool { ch == ';' }
+    let parser = character(char::is_alphanumeric).star_lazy_with(character(is_semicolon).and_then(CHARACTER), String::new);
+    assert_eq!(parser.init_str("ab;cd").unwrap().unDone(), (String::from("ab"), Some((';', Some('c')))));
+    assert_eq!(parser.init_str("ab!").unwrap().unDone(), (String::from("ab"), None));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), (String::from("ab"), None));
+    let parsing = parser.init_str("ab;").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), (String::from("ab"), Some((';', None))));
+    assert_eq!(Committed::<char, Chars, _>::empty(&parser), (String::from(""), None));
+    testing::assert_chunk_invariant(parser, "ab;cd");
+}
+
 #[test]
 #[allow(non_snake_case)]
 fn test_buffer() {
@@ -1809,6 +4013,655 @@ fn test_buffer() {
     assert_eq!(data.as_str(), "!");
 }
 
+#[test]
+#[allow(non_snake_case)]
+fn test_complete() {
+    use impls::Trailing;
+    let ALPHABETIC = character(char::is_alphabetic);
+    let parser = ALPHABETIC.plus(String::new).complete();
+    assert!(parser.init_str("").is_none());
+    assert!(parser.init_str("!").is_none());
+    assert_eq!(parser.init_str("ab!").unwrap().unDone(), Err(Trailing { position: 2 }));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.clone().last_str(""), Ok(String::from("ab")));
+    assert_eq!(parsing.clone().more_str("cd!").unDone(), Err(Trailing { position: 4 }));
+    let parsing = parsing.more_str("c").unContinue();
+    assert_eq!(parsing.more_str(";").unDone(), Err(Trailing { position: 3 }));
+    let parser = ALPHABETIC.star(String::new).complete();
+    assert_eq!(Committed::<char, Chars, _>::empty(&parser), Ok(String::new()));
+    assert_eq!(format!("{}", Trailing { position: 3 }), "Unexpected trailing input at byte 3");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_shared() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+    let ALPHABETIC = character(char::is_alphabetic);
+    let WORD = ALPHABETIC.plus(String::new);
+    let shared = Rc::new(WORD);
+    let parser = shared.clone().and_then(character(char::is_numeric).star(String::new));
+    assert_eq!(parser.init_str("ab1;").unwrap().unDone(), (String::from("ab"), String::from("1")));
+    let parsing = shared.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.last_str("c"), "abc");
+    let parser = (&WORD).or_else(Arc::new(character(char::is_numeric).plus(String::new)));
+    assert_eq!(parser.init_str("12!").unwrap().unDone(), "12");
+    assert_eq!(parser.init_str("ab!").unwrap().unDone(), "ab");
+    let parser = Arc::new(ALPHABETIC.star(String::new));
+    assert_eq!(Committed::<char, Chars, _>::empty(&parser), "");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_boxed_parser() {
+    let ALPHABETIC = character(char::is_alphabetic);
+    let NUMERIC = character(char::is_numeric);
+    let parser = Box::new(ALPHABETIC.plus(String::new)).and_then(NUMERIC.star(String::new));
+    assert_eq!(parser.init_str("ab12;").unwrap().unDone(), (String::from("ab"), String::from("12")));
+    let grammars: Vec<Box<DynUncommitted<char, Chars, String>>> = vec![
+        Box::new(NUMERIC.plus(String::new)),
+        Box::new(ALPHABETIC.plus(String::new)),
+    ];
+    assert_eq!(grammars[0].init_str("12!").unwrap().unDone(), "12");
+    assert!(grammars[0].init_str("ab!").is_none());
+    let parsing = grammars[1].init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("cd").unContinue();
+    assert_eq!(parsing.last_str("e"), "abcde");
+    let parser = (&grammars[1]).or_else(&grammars[0]);
+    assert_eq!(parser.init_str("12!").unwrap().unDone(), "12");
+}
+
+#[test]
+fn test_literals() {
+    let plus: impls::Character<_> = '+'.into();
+    assert_eq!(plus.init_str("+1").unwrap().unDone(), '+');
+    assert!(plus.init_str("-1").is_none());
+    let parser = "\u{e9}t\u{e9}".parser();
+    assert!(parser.init_str("").is_none());
+    assert!(parser.init_str("ete").is_none());
+    assert_eq!(parser.init_str("\u{e9}t\u{e9}!").unwrap().unDone(), Ok("\u{e9}t\u{e9}"));
+    assert_eq!(parser.init_str("\u{e9}te").unwrap().unDone(), Err(impls::Expected("\u{e9}t\u{e9}")));
+    let parsing = parser.init_str("\u{e9}").unwrap().unContinue();
+    assert_eq!(parsing.more_str("t\u{e9}").unDone(), Ok("\u{e9}t\u{e9}"));
+    let parsing = parser.init_str("\u{e9}t").unwrap().unContinue();
+    assert!(parsing.last_str("").is_err());
+    let parser = '('.parser().and_then(character(char::is_alphabetic).star(String::new)).and_then_discard(')'.parser().opt());
+    assert_eq!(parser.init_str("(ab)").unwrap().unDone(), ('(', String::from("ab")));
+    testing::assert_chunk_invariant("=>".parser().or_else("=="), "=>x");
+    let digit = ('0'..'5').parser();
+    assert_eq!(digit.init_str("4").unwrap().unDone(), '4');
+    assert!(digit.init_str("5").is_none());
+    let hex = ('0'..='9').parser().or_else('a'..='f');
+    assert_eq!(hex.init_str("f").unwrap().unDone(), 'f');
+    assert!(hex.init_str("g").is_none());
+    let crlf = (*b"\r\n").parser();
+    let mut data = b"\r".iter().cloned().peekable();
+    let parsing = crlf.init(&mut data).unwrap().unContinue();
+    let mut data = b"\nx".iter().cloned().peekable();
+    assert_eq!(parsing.more(&mut data).unDone(), Ok(*b"\r\n"));
+    assert_eq!(data.next(), Some(b'x'));
+    let mut data = b"\r\r".iter().cloned().peekable();
+    assert!(crlf.init(&mut data).unwrap().unDone().is_err());
+    let mut data = b"\n".iter().cloned().peekable();
+    assert!(crlf.init(&mut data).is_none());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_rule() {
+    fn ignore() {}
+    let WHITESPACE = character(char::is_whitespace).star(ignore);
+    let DIGITS = character(char::is_numeric).star(String::new);
+    let parser = '('.parser().rule() >> WHITESPACE >> DIGITS << WHITESPACE << ')'.parser().opt();
+    assert_eq!(parser.init_str("( 12 )").unwrap().unDone(), "12");
+    assert_eq!(parser.init_str("(3!").unwrap().unDone(), "3");
+    assert!(parser.init_str("12").is_none());
+    let parsing = parser.init_str("( 1").unwrap().unContinue();
+    assert_eq!(parsing.last_str("2"), "12");
+    let parser = "<=".parser().rule() | "<>" | ">=";
+    assert_eq!(parser.init_str(">=").unwrap().unDone(), Ok(">="));
+    assert_eq!(parser.into_inner().init_str("<>").unwrap().unDone(), Err(impls::Expected("<=")));
+    testing::assert_chunk_invariant('-'.parser().rule() >> DIGITS, "-42;");
+}
+
+#[test]
+fn test_feed_all() {
+    let parser = character(char::is_alphabetic).star(String::new);
+    let parsing = parser.init_str("a").unwrap().unContinue();
+    let parsing = parsing.feed_all(Vec::<Chars>::new()).unContinue();
+    let parsing = parsing.feed_all(vec!["b".chars(), "".chars()]).unContinue();
+    assert_eq!(parsing.clone().feed_all_str(vec!["c", "d;e", "f"]).unDone(), "abcd");
+    assert_eq!(parsing.feed_all_str(vec!["c", "d"]).unContinue().last_str(""), "abcd");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_measure() {
+    fn ignore() {}
+    let WORD = character(char::is_alphabetic).plus(String::new).measure();
+    let SPACE = character(char::is_whitespace).star(ignore).measure();
+    let parser = WORD.and_then(SPACE);
+    assert!(parser.init_str("").is_none());
+    assert!(parser.init_str("1").is_none());
+    assert_eq!(parser.init_str("\u{e9}t\u{e9}  x").unwrap().unDone(), ((String::from("\u{e9}t\u{e9}"), 3), ((), 2)));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("c ").unContinue();
+    assert_eq!(parsing.last_str(" "), ((String::from("abc"), 3), ((), 2)));
+    assert_eq!(Committed::<char, Chars, _>::empty(&SPACE), ((), 0));
+    testing::assert_chunk_invariant(parser, "abc  def");
+}
+
+#[test]
+fn test_alt_longest_of() {
+    fn name(tag: Result<&'static str, impls::Expected<&'static str>>) -> String { String::from(tag.unwrap_or("?")) }
+    let operators: Vec<Box<DynUncommitted<char, Chars, String>>> = vec![
+        Box::new("<".parser().map(name)),
+        Box::new("<<=".parser().map(name)),
+        Box::new("<=".parser().map(name)),
+        Box::new(character(char::is_numeric).plus(String::new)),
+    ];
+    let parser = alt_longest_of(operators);
+    assert!(parser.init_str("").is_none());
+    assert!(parser.init_str("x").is_none());
+    assert_eq!(parser.init_str("<x").unwrap().unDone(), "<");
+    assert_eq!(parser.init_str("<<=x").unwrap().unDone(), "<<=");
+    assert_eq!(parser.init_str("<<x").unwrap().unDone(), "?");
+    let mut data = "<=1".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), "<=");
+    assert_eq!(data.as_str(), "1");
+    let parsing = parser.init_str("12").unwrap().unContinue();
+    assert_eq!(parsing.last_str("3"), "123");
+    let parsing = parser.init_str("<").unwrap().unContinue();
+    let mut data = "<3".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), "?");
+    assert_eq!(data.as_str(), "3");
+    testing::assert_chunk_invariant(&parser, "<<=1");
+    testing::assert_chunk_invariant(&parser, "<=<");
+}
+
+#[test]
+fn test_take_until() {
+    use std::borrow::Cow::{Borrowed, Owned};
+    let parser = take_until("aab");
+    assert!(parser.init_str("").is_none());
+    let (result, rest) = parser.init_str_rest("xaaab!");
+    assert_eq!(result.unwrap().unDone(), Borrowed("xa"));
+    assert_eq!(rest, "aab!");
+    assert_eq!(parser.init_str("aab").unwrap().unDone(), Borrowed(""));
+    let parsing = parser.init_str("xa").unwrap().unContinue();
+    let (result, rest) = parsing.more_str_rest("xaab!");
+    assert_eq!(result.unDone(), Owned::<str>(String::from("xax")));
+    assert_eq!(rest, "aab!");
+    let parsing = parser.init_str("xaa").unwrap().unContinue();
+    let (result, rest) = parsing.more_str_rest("ab!");
+    assert_eq!(result.unDone(), Owned::<str>(String::from("xa")));
+    assert_eq!(rest, "!");
+    let parsing = parser.init_str("xaa").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), "xaa");
+    assert_eq!(take_until("\u{e9}t\u{e9}").init_str("\u{e9}\u{e9}t\u{e9}").unwrap().unDone(), "\u{e9}");
+    assert_eq!(take_until("").init_str("abc").unwrap().unDone(), "");
+    let quoted = take_until("\"").and_then_discard("\"".parser().opt());
+    assert_eq!(quoted.init_str_rest("ab\"c").0.unwrap().unDone(), "ab");
+}
+
+#[test]
+fn test_take_through() {
+    use std::borrow::Cow::{Borrowed, Owned};
+    let parser = take_through("aab");
+    assert!(parser.init_str("").is_none());
+    let (result, rest) = parser.init_str_rest("xaaab!");
+    assert_eq!(result.unwrap().unDone(), Borrowed("xa"));
+    assert_eq!(rest, "!");
+    let parsing = parser.init_str("xaa").unwrap().unContinue();
+    assert_eq!(parsing.more_str("ab!").unDone(), Owned::<str>(String::from("xa")));
+    let parsing = parser.init_str("xa").unwrap().unContinue();
+    let parsing = parsing.more_str("a").unContinue();
+    assert_eq!(parsing.more_str("b").unDone(), Owned::<str>(String::from("x")));
+    testing::assert_chunk_invariant(parser, "xaaaxaab!");
+    testing::assert_chunk_invariant(take_through("abab"), "ababaababab");
+}
+
+#[test]
+fn test_dump_state() {
+    let tag = "abc".parser();
+    assert_eq!(tag.init_str("ab").unwrap().unContinue().dump_state(), "TagState\n");
+    fn owned(text: Cow<str>) -> String { text.into_owned() }
+    let comment = take_until("-->").map(owned).named("comment").erase();
+    let parsing = comment.init_str("hello -").unwrap().unContinue();
+    assert_eq!(parsing.dump_state(), "Rule \"comment\" waiting\n  TakeUntil \"-->\": 7 bytes\n");
+    let word = character(char::is_alphabetic).plus(String::new).named("word");
+    let parser = word.and_then(word.opt()).star(Vec::new).measure();
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.dump_state(), "Measure: 2 characters\n  Star\n    AndThen: in lhs\n      Rule \"word\" waiting\n        Star\n");
+}
+
+#[test]
+fn test_emit_with() {
+    use std::cell::Cell;
+    let counter = Cell::new(0);
+    let next = emit_with(|| { counter.set(counter.get() + 1); counter.get() });
+    let parser = character(char::is_numeric).map(|ch: char| ch.to_digit(10).unwrap() as usize).or_else(&next);
+    assert_eq!(parser.init_str("7").unwrap().unDone(), 7);
+    assert_eq!(parser.init_str("x").unwrap().unDone(), 1);
+    assert_eq!(parser.init_str("x").unwrap().unDone(), 2);
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(Committed::<char, Chars, usize>::empty(&next), 3);
+}
+
+#[test]
+fn test_or_emit() {
+    fn zero() -> u32 { 0 }
+    fn missing() -> &'static str { "missing" }
+    let digit = character_map(|ch: char| ch.to_digit(10));
+    let parser = digit.or_emit(zero);
+    assert_eq!(parser.init_str("7").unwrap().unDone(), 7);
+    assert_eq!(parser.init_str("x").unwrap().unDone(), 0);
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(Committed::<char, Chars, u32>::empty(&parser), 0);
+    let parser = digit.or_default();
+    assert_eq!(parser.init_str("x").unwrap().unDone(), 0);
+    let parser = digit.or_else_emit(missing);
+    assert_eq!(parser.init_str("7").unwrap().unDone(), Ok(7));
+    assert_eq!(parser.init_str("x").unwrap().unDone(), Err("missing"));
+    let parser = digit.opt().or_default();
+    assert_eq!(parser.init_str("x").unwrap().unDone(), None);
+    let parser = digit.star(Vec::new).or_else_emit(missing);
+    assert_eq!(parser.init_str("x").unwrap().unDone(), Ok(vec![]));
+    let parser = character(char::is_alphabetic).and_then(digit.or_else_emit(missing));
+    assert_eq!(parser.init_str("a7").unwrap().unDone(), ('a', Ok(7)));
+    assert_eq!(parser.init_str("a").unwrap().unContinue().last_str(""), ('a', Err("missing")));
+}
+
+#[test]
+#[cfg(feature = "bumpalo")]
+fn test_buffer_in() {
+    use bumpalo::Bump;
+    fn ignore() {}
+    let arena = Bump::new();
+    let word = character(char::is_alphabetic).plus(ignore).buffer_in(&arena);
+    assert_eq!(word.init_str("abc!").unwrap().unDone(), "abc");
+    let parsing = word.init_str("abc").unwrap().unContinue();
+    assert_eq!(parsing.last_str("def!"), "abcdef");
+    let words = character(char::is_alphabetic).plus(|| bumpalo::collections::String::new_in(&arena));
+    assert_eq!(words.init_str("ab").unwrap().unContinue().last_str("c").as_str(), "abc");
+}
+
+#[test]
+fn test_conversions() {
+    use impls::{DigitsToU64, HexPairToByte, CharsToString};
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    assert_eq!(DigitsToU64(10).apply("123"), Some(123));
+    assert_eq!(DigitsToU64(2).apply(String::from("101")), Some(5));
+    assert_eq!(DigitsToU64(36).apply(Cow::Borrowed("zz")), Some(1295));
+    assert_eq!(DigitsToU64(10).apply("18446744073709551616"), None);
+    assert_eq!(DigitsToU64(10).apply("+1"), None);
+    assert_eq!(DigitsToU64(10).apply(""), None);
+    assert_eq!(HexPairToByte.apply(('F', 'e')), Some(254));
+    assert_eq!(HexPairToByte.apply(('0', 'x')), None);
+    assert_eq!(HexPairToByte.apply(('0', Some('a'))), Some(10));
+    assert_eq!(HexPairToByte.apply(('0', None)), None);
+    assert_eq!(CharsToString.apply(vec!['a', 'b']), "ab");
+    let parser = character(is_digit).star(Vec::new).map(CharsToString);
+    assert_eq!(parser.init_str("42;").unwrap().unDone(), "42");
+    assert_eq!(parser.init_str("4").unwrap().unContinue().last_str("2"), "42");
+}
+
+#[test]
+fn test_compose() {
+    use impls::{AndThenFn, Compose, Const, Identity, MkSome};
+    fn double(x: u32) -> u32 { x * 2 }
+    fn increment(x: u32) -> u32 { x + 1 }
+    assert_eq!(Identity.apply("x"), "x");
+    assert_eq!(Const('c').apply(17), 'c');
+    assert_eq!(Compose::new(double, increment).apply(3), 8);
+    assert_eq!(AndThenFn::new(double, increment).apply(3), 7);
+    assert_eq!(Compose::new(MkSome, Identity).apply(5), Some(5));
+    let parser = character(char::is_alphabetic).map(AndThenFn::new(Const(5), Compose::new(double, increment)));
+    assert_eq!(parser.init_str("a").unwrap().unDone(), 12);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_buffer_segments() {
+    fn ignore() {}
+    fn concat(segments: Vec<Cow<str>>) -> String { segments.concat() }
+    let ALPHABETIC = character(char::is_alphabetic);
+    let parser = ALPHABETIC.plus(ignore).buffer_segments();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("a").unwrap().unContinue().last_str(""), vec![Cow::<str>::Owned(String::from("a"))]);
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let parsing = parsing.more_str("").unContinue();
+    let mut data = "!c".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), vec![Cow::<str>::Owned(String::from("ab"))]);
+    assert_eq!(data.as_str(), "!c");
+    testing::assert_chunk_invariant(ALPHABETIC.plus(ignore).buffer_segments().map(concat), "abcdef!");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_buffer_limit() {
+    use impls::BufferOverflow;
+    fn ignore() {}
+    let ALPHABETIC = character(char::is_alphabetic);
+    let parser = ALPHABETIC.plus(ignore).buffer_limit(3);
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("abcde").unwrap().unContinue().last_str(""), Err(BufferOverflow { limit: 3 }));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.clone().last_str("c"), Ok(Cow::Owned(String::from("abc"))));
+    let mut data = "cdef;g".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), Err(BufferOverflow { limit: 3 }));
+    assert_eq!(data.as_str(), ";g");
+    let parser = ALPHABETIC.plus(ignore).and_then_discard(character(|ch| ch == ';').opt()).buffer_limit(3);
+    let parsing = parser.init_str("a").unwrap().unContinue();
+    let parsing = parsing.more_str("bcd").unContinue();
+    assert_eq!(parsing.more_str("e;").unDone(), Err(BufferOverflow { limit: 3 }));
+    assert_eq!(format!("{}", BufferOverflow { limit: 3 }), "Buffered input exceeded 3 bytes");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_buffer_with() {
+    use impls::{BufferOverflow, BufferPolicy};
+    fn ignore() {}
+    let WORD = character(char::is_alphabetic).plus(ignore);
+    let SPACE = character(char::is_whitespace).star(ignore);
+    let parser = WORD.buffer_with(BufferPolicy::Adaptive);
+    match parser.init_str("ab!").unwrap().unDone() {
+        Ok(Cow::Borrowed("ab")) => (),
+        result => panic!("Unexpected {:?}", result),
+    }
+    assert_eq!(parser.init_str("a").unwrap().unContinue().last_str("b!"), Ok(Cow::Owned(String::from("ab"))));
+    let parser = WORD.buffer_with(BufferPolicy::AlwaysOwned);
+    match parser.init_str("ab!").unwrap().unDone() {
+        Ok(Cow::Owned(ref result)) if result == "ab" => (),
+        result => panic!("Unexpected {:?}", result),
+    }
+    match SPACE.buffer_with(BufferPolicy::AlwaysOwned).init_str("!").unwrap().unDone() {
+        Ok(Cow::Owned(ref result)) if result.is_empty() => (),
+        result => panic!("Unexpected {:?}", result),
+    }
+    let parser = WORD.buffer_with(BufferPolicy::NeverCopy);
+    assert_eq!(parser.init_str("ab!").unwrap().unDone(), Ok(Cow::Borrowed("ab")));
+    assert_eq!(parser.init_str("ab").unwrap().unContinue().last_str(""), Err(BufferOverflow { limit: 0 }));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_finish() {
+    fn ignore() {}
+    fn len(text: String) -> usize { text.len() }
+    let ALPHABETIC = character(char::is_alphabetic);
+    let SPACE = character(char::is_whitespace);
+    let WORD = ALPHABETIC.plus(String::new).and_then_discard(SPACE.star(ignore));
+    let parser = WORD.star(Vec::new);
+    let parsing = parser.init_str("ab c").unwrap().unContinue();
+    assert_eq!(parsing.clone().last_str("d"), vec!["ab"]);
+    assert_eq!(parsing.clone().finish_str("d"), vec!["ab", "cd"]);
+    assert_eq!(parsing.clone().finish_str(""), vec!["ab", "c"]);
+    assert_eq!(parsing.finish_str("d e f"), vec!["ab", "cd", "e", "f"]);
+    let mut data = "d!g".chars();
+    let parsing = parser.init_str("ab c").unwrap().unContinue();
+    assert_eq!(parsing.finish(&mut data), vec!["ab", "cd"]);
+    assert_eq!(data.as_str(), "!g");
+    let parser = WORD.map(len).and_then(WORD.star(Vec::new));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.finish_str("c d"), (3, vec![String::from("d")]));
+    let parsing = parser.init_str("ab c").unwrap().unContinue();
+    assert_eq!(parsing.finish_str("d"), (2, vec![String::from("cd")]));
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    assert_eq!(parsing.finish_str(""), (2, Vec::<String>::new()));
+    let WORDS = WORD.star(Vec::new);
+    let words = vec![String::from("ab"), String::from("cd")];
+    assert_eq!(WORDS.opt().init_str("ab c").unwrap().unContinue().finish_str("d"), Some(words.clone()));
+    assert_eq!(WORDS.or_else(WORDS).init_str("ab c").unwrap().unContinue().finish_str("d"), words);
+    assert_eq!(WORDS.named("words").init_str("ab c").unwrap().unContinue().finish_str("d"), words);
+    assert_eq!(WORDS.erase().init_str("ab c").unwrap().unContinue().finish_str("d"), words);
+    assert_eq!(WORDS.measure().init_str("ab c").unwrap().unContinue().finish_str("d"), (words.clone(), 5));
+    assert_eq!(WORDS.complete().init_str("ab c").unwrap().unContinue().finish_str("d"), Ok(words));
+    assert_eq!(WORDS.buffer().init_str("ab c").unwrap().unContinue().finish_str("d"), "ab cd");
+    assert_eq!(WORDS.discard().init_str("ab c").unwrap().unContinue().finish_str("d"), ());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_take_while() {
+    fn ignore() {}
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    let parser = take_while(is_digit);
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("x1"), None);
+    let mut data = "12\u{e9}".chars();
+    assert_eq!(parser.init(&mut data).unwrap().unDone(), "12");
+    assert_eq!(data.as_str(), "\u{e9}");
+    let parsing = parser.init_str("1").unwrap().unContinue();
+    let parsing = parsing.more_str("").unContinue();
+    let parsing = parsing.more_str("23").unContinue();
+    assert_eq!(parsing.clone().last_str(""), "123");
+    let mut data = "4x5".chars();
+    assert_eq!(parsing.more(&mut data).unDone(), "1234");
+    assert_eq!(data.as_str(), "x5");
+    let ALPHABETIC = character(char::is_alphabetic);
+    fn own(text: Cow<str>) -> String { text.into_owned() }
+    let inputs = testing::random_strings("ab\u{e9}1 ", 8).take(200);
+    testing::assert_equivalent(take_while(char::is_alphabetic).map(own), ALPHABETIC.plus(ignore).buffer().map(own), inputs);
+    testing::assert_chunk_invariant(take_while(char::is_alphabetic).and_then(take_while(is_digit).opt()), "ab\u{e9}cd1234 ");
+    let mut data = "ab\u{e9}1".chars().peekable();
+    assert_eq!(ALPHABETIC.plus(String::new).init(&mut data).unwrap().unDone(), "ab\u{e9}");
+    assert_eq!(data.next(), Some('1'));
+    testing::assert_chunk_invariant(ALPHABETIC.plus(String::new), "ab\u{e9}cd1234");
+    let mut data = "ab\u{e9}x".chars();
+    assert_eq!("ab\u{e8}".parser().init(&mut data).unwrap().unDone(), Err(impls::Expected("ab\u{e8}")));
+    assert_eq!(data.as_str(), "\u{e9}x");
+    let parsing = "ab\u{e9}".parser().init_str("a").unwrap().unContinue();
+    assert_eq!(parsing.more_str("b\u{e9}!").unDone(), Ok("ab\u{e9}"));
+    testing::assert_chunk_invariant("ab\u{e9}c".parser(), "ab\u{e9}c");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_when() {
+    use std::cell::Cell;
+    thread_local!(static VERSION: Cell<u8> = const { Cell::new(1) });
+    fn is_v2() -> bool { VERSION.with(Cell::get) >= 2 }
+    let ALPHABETIC = character(char::is_alphabetic);
+    let NUMERIC = character(char::is_numeric);
+    let parser = when(is_v2, ALPHABETIC.star(String::new), NUMERIC.star(String::new));
+    assert_eq!(parser.init_str("1a!").unwrap().unDone(), "1");
+    let parsing = parser.init_str("1").unwrap().unContinue();
+    VERSION.with(|version| version.set(2));
+    assert_eq!(parsing.last_str("2a!"), "12");
+    assert_eq!(parser.init_str("1a!").unwrap().unDone(), "");
+    assert_eq!(Committed::<char, Chars, String>::empty(&parser), "");
+    let records = when(is_v2, ALPHABETIC, NUMERIC).and_then(CHARACTER).star(Vec::new);
+    assert_eq!(records.init_str("a1b2!").unwrap().unDone(), vec![('a', Some('1')), ('b', Some('2'))]);
+    VERSION.with(|version| version.set(1));
+    assert_eq!(records.init_str("1ab!").unwrap().unDone(), vec![('1', Some('a'))]);
+    assert_eq!(when(is_v2, ALPHABETIC, NUMERIC).init_str("a"), None);
+}
+
+#[test]
+fn test_switch() {
+    use impls::SwitchError::{Unknown, Rejected};
+    use std::collections::HashMap;
+    use std::rc::Rc;
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    fn is_key(ch: char) -> bool { ch.is_alphabetic() }
+    fn own(text: Cow<str>) -> String { text.into_owned() }
+    let mut table: HashMap<String, Box<DynUncommitted<char, Chars<'static>, String>>> = HashMap::new();
+    table.insert(String::from("n"), Box::new(take_while(is_digit).map(own)));
+    table.insert(String::from("s"), Box::new(character(|ch| ch == '"').and_then(take_through("\"")).map(|(_, text)| own(text))));
+    let key = take_while(is_key).map(own).and_then_discard(character(|ch| ch == ':').opt());
+    let table = Rc::new(table);
+    let parser = switch(key, &*table);
+    let copy = parser;
+    assert_eq!(copy.init_str(""), None);
+    assert_eq!(parser.init_str("1"), None);
+    assert_eq!(parser.init_str("n:12,").unwrap().unDone(), Ok(String::from("12")));
+    assert_eq!(parser.init_str("n:x").unwrap().unDone(), Err(Rejected(String::from("n"))));
+    assert_eq!(parser.init_str("x:1").unwrap().unDone(), Err(Unknown(String::from("x"))));
+    let parsing = parser.init_str("s:").unwrap().unContinue();
+    let parsing = parsing.more_str("").unContinue();
+    let parsing = parsing.more_str("\"a").unContinue();
+    assert_eq!(parsing.more_str("b\"").unDone(), Ok(String::from("ab")));
+    assert_eq!(parser.init_str("n").unwrap().unContinue().last_str(""), Err(Rejected(String::from("n"))));
+    assert_eq!(parser.init_str("n:").unwrap().unContinue().last_str(""), Err(Rejected(String::from("n"))));
+    assert_eq!(parser.init_str("nn").unwrap().unContinue().last_str(""), Err(Unknown(String::from("nn"))));
+    let parser = switch(key, table);
+    let records = parser.and_then_discard(character(|ch| ch == ',').opt()).star(Vec::new);
+    let parsing = records.init_str("n:1,s:\"x").unwrap().unContinue();
+    assert_eq!(parsing.finish_str("y\",n:2"), vec![Ok(String::from("1")), Ok(String::from("xy")), Ok(String::from("2"))]);
+}
+
+#[test]
+fn test_strip() {
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    let parser = strip(take_while(is_digit), String::new);
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("a1b22c").unwrap().unContinue().last_str(""), "abc");
+    let parsing = parser.init_str("a1").unwrap().unContinue();
+    let parsing = parsing.more_str("2").unContinue();
+    assert_eq!(parsing.last_str("3b4"), "ab");
+    let bytes = strip(character(|byte: u8| byte == 0), Vec::new);
+    let mut data = b"\0a\0\0b".iter().cloned().peekable();
+    assert_eq!(bytes.init(&mut data).unwrap().unContinue().finish(&mut data), b"ab".to_vec());
+    testing::assert_chunk_invariant(parser, "a12b\u{e9}345c6");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_tee() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let seen = Rc::new(RefCell::new(Vec::new()));
+    let ALPHABETIC = character(char::is_alphabetic).tee(seen.clone());
+    let parser = ALPHABETIC.star(String::new);
+    assert_eq!(parser.init_str("ab").unwrap().unContinue().last_str("c1"), "abc");
+    assert_eq!(*seen.borrow(), vec!['a', 'b', 'c']);
+    let counts = Rc::new(RefCell::new(Vec::new()));
+    let DIGITS = character(char::is_numeric).star(String::new).tee(counts.clone());
+    assert_eq!(Committed::<char, Chars, String>::empty(&DIGITS), "");
+    let parsing = DIGITS.init_str("12").unwrap().unContinue();
+    assert_eq!(parsing.last_str(""), "12");
+    assert_eq!(*counts.borrow(), vec![String::new(), String::from("12")]);
+    assert_eq!(DIGITS.init_str(""), None);
+    assert_eq!(counts.borrow().len(), 2);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_keyword_map() {
+    use std::panic;
+    #[derive(Clone, Debug, PartialEq)]
+    enum Token { Do, For, In, Identifier(String) }
+    impl StaticMarker for Token {}
+    static KEYWORDS: Keywords<Token> = Keywords::new(&[("do", Token::Do), ("for", Token::For), ("in", Token::In)]);
+    let WORD = character(char::is_alphabetic).plus(String::new);
+    let SPACE = character(char::is_whitespace).plus(String::new);
+    let TOKENS = WORD.map(keyword_map(&KEYWORDS, Token::Identifier)).and_then_discard(SPACE.opt()).star(Vec::new);
+    assert_eq!(TOKENS.init_str("for x in xs do d!").unwrap().unDone(),
+               vec![Token::For, Token::Identifier(String::from("x")), Token::In,
+                    Token::Identifier(String::from("xs")), Token::Do, Token::Identifier(String::from("d"))]);
+    static NONE: Keywords<String> = Keywords::new(&[]);
+    assert_eq!(keyword_map(&NONE, String::from).apply("for"), "for");
+    let unsorted = panic::catch_unwind(|| Keywords::new(&[("for", 0), ("do", 1)]));
+    assert!(unsorted.is_err());
+    let duplicated = panic::catch_unwind(|| Keywords::new(&[("do", 0), ("do", 1)]));
+    assert!(duplicated.is_err());
+    let prefixed = panic::catch_unwind(|| Keywords::new(&[("do", 0), ("done", 1), ("e", 2)]));
+    assert!(prefixed.is_ok());
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_bounded_consumer() {
+    let ALPHABETIC = character(char::is_alphabetic);
+    let WORD = ALPHABETIC.plus(String::new).and_then_discard(character(|ch| ch == ' ').opt());
+    let parser = WORD.star(|| BoundedConsumer::items(Vec::new(), 2, QuotaPolicy::Truncate));
+    let words = parser.init_str("ab cd!").unwrap().unDone();
+    assert!(!words.is_truncated());
+    assert_eq!(words.into_result().unwrap(), vec!["ab", "cd"]);
+    let words = parser.init_str("ab cd ef!").unwrap().unDone();
+    assert!(words.is_truncated());
+    assert_eq!(words.into_result().unwrap(), vec!["ab", "cd"]);
+    let parser = WORD.star(|| BoundedConsumer::bytes(Vec::new(), 4, QuotaPolicy::Truncate));
+    let words = parser.init_str("ab cde f!").unwrap().unDone();
+    assert!(words.is_truncated());
+    assert_eq!(words.inner(), &vec!["ab"]);
+    let parser = ALPHABETIC.star(|| BoundedConsumer::bytes(String::new(), 3, QuotaPolicy::Fail));
+    assert_eq!(parser.init_str("\u{e9}a!").unwrap().unDone().into_result(), Ok(String::from("\u{e9}a")));
+    assert_eq!(parser.init_str("\u{e9}\u{e9}!").unwrap().unDone().into_result(), Err(QuotaExceeded { limit: 3 }));
+    let mut words = BoundedConsumer::new(Vec::new(), String::len, 0, QuotaPolicy::Fail);
+    words.accept(String::new());
+    assert_eq!(words.into_result(), Ok(vec![String::new()]));
+    assert_eq!(QuotaExceeded { limit: 3 }.to_string(), "Consumer exceeded its quota of 3");
+}
+
+#[test]
+fn test_as_str_remainder() {
+    let mut data = "a\u{e9}b".chars();
+    assert_eq!(data.remainder(), "a\u{e9}b");
+    data.advance(0);
+    assert_eq!(data.remainder(), "a\u{e9}b");
+    data.advance(3);
+    assert_eq!(data.remainder(), "b");
+    assert_eq!(data.next(), Some('b'));
+    data.advance(0);
+    assert_eq!(data.remainder(), "");
+}
+
+#[test]
+#[should_panic]
+fn test_as_str_remainder_boundary() {
+    "a\u{e9}b".chars().advance(2);
+}
+
+#[test]
+fn test_map_consumer() {
+    use formats::headers::header_block;
+    fn add(old: &mut u32, new: u32) { *old += new; }
+    let block = "A: 1\nB: 2\nA: 3\n\n";
+    let first = header_block(|| MapConsumer::new(HashMap::new(), Duplicates::KeepFirst)).init_str(block).unwrap().unDone();
+    assert_eq!(first.map()["A"], "1");
+    let last = header_block(|| MapConsumer::new(BTreeMap::new(), Duplicates::KeepLast)).init_str(block).unwrap().unDone();
+    assert_eq!(last.into_map().into_iter().collect::<Vec<_>>(), vec![
+        (String::from("A"), String::from("3")),
+        (String::from("B"), String::from("2")),
+    ]);
+    let rejected = header_block(|| MapConsumer::new(HashMap::new(), Duplicates::Reject)).init_str(block).unwrap().unDone();
+    assert_eq!(rejected.map()["A"], "1");
+    assert_eq!(rejected.into_result().unwrap_err(), "A");
+    let accepted = header_block(|| MapConsumer::new(HashMap::new(), Duplicates::Reject)).init_str("A: 1\n\n").unwrap().unDone();
+    assert_eq!(accepted.into_result().unwrap().len(), 1);
+    let mut sums = MapConsumer::new(BTreeMap::new(), Duplicates::Merge(add));
+    sums.accept(('x', 1));
+    sums.accept(('y', 2));
+    sums.accept(('x', 3));
+    assert_eq!(sums.map()[&'x'], 4);
+    assert_eq!(sums.map()[&'y'], 2);
+    let separator = String::from("; ");
+    let join = move |old: &mut String, new: String| {
+        old.push_str(&separator);
+        old.push_str(&new);
+    };
+    let joined = header_block(|| MapConsumer::with_policy(HashMap::new(), Duplicates::Merge(join.clone()))).init_str(block).unwrap().unDone();
+    assert_eq!(joined.map()["A"], "1; 3");
+}
+
+#[test]
+fn test_mapped_consumer() {
+    use impls::DigitsToU64;
+    fn trim(text: String) -> String { String::from(text.trim()) }
+    let mut buffer = map_consumer(Vec::new(), trim);
+    buffer.accept(String::from(" a "));
+    buffer.accept(String::from("b\n"));
+    assert_eq!(buffer.inner(), &["a", "b"]);
+    let number = character(char::is_numeric).plus(String::new).and_then_discard(character(|ch| ch == ',').opt());
+    let parser = number.star(|| map_consumer(Vec::new(), DigitsToU64(10)));
+    assert_eq!(parser.init_str("1,23,4;").unwrap().unDone().into_inner(), vec![Some(1), Some(23), Some(4)]);
+    let parsing = parser.init_str("5,6").unwrap().unContinue();
+    assert_eq!(parsing.more_str("7;").unDone().into_inner(), vec![Some(5), Some(67)]);
+}
+
 #[test]
 #[allow(non_snake_case)]
 fn test_cow() {
@@ -1908,6 +4761,36 @@ fn test_boxable() {
     assert!(!is_owned(thd));
 }
 
+#[test]
+fn test_errors_send_sync() {
+    // Checked at compile time, so errors can be boxed as `Box<Error + Send + Sync>`.
+    fn _assert<T: std::error::Error + Send + Sync + 'static>() {}
+    _assert::<QuotaExceeded>();
+    _assert::<impls::BufferOverflow>();
+    _assert::<impls::Trailing>();
+    _assert::<impls::SwitchError<String>>();
+    _assert::<impls::Expected<&'static str>>();
+    _assert::<impls::Expected<[u8; 2]>>();
+    _assert::<numeric::MissingDigits>();
+    _assert::<numeric::Overflow>();
+    _assert::<numeric::ReservedBits<u32>>();
+    _assert::<recover::RecordError>();
+    _assert::<session::Error>();
+    _assert::<pipeline::Error>();
+    _assert::<snapshot::Mismatch>();
+    _assert::<machine::Rejected>();
+    _assert::<formats::ber::Error>();
+    _assert::<formats::chunks::Error>();
+    #[cfg(feature = "cbor")]
+    _assert::<formats::cbor::Error>();
+    #[cfg(feature = "yaml")]
+    _assert::<formats::yaml::Error>();
+    #[cfg(feature = "serde")]
+    _assert::<de::Error>();
+    #[cfg(feature = "async")]
+    _assert::<sink::Error<io::Error>>();
+}
+
 // #[test]
 // #[allow(non_snake_case)]
 // fn test_iter() {
@@ -0,0 +1,460 @@
+//! Grammars which run on an explicit work stack.
+//!
+//! A grammar built from combinators has a state whose type nests as deeply as the
+//! grammar, and a recursive grammar (using `boxed()`) recurses natively in `more()`
+//! once per level of nesting in the input, so deeply nested input can overflow the
+//! call stack. A `Machine` instead describes the grammar as a table of nodes, and
+//! runs it in a loop, keeping the nodes in progress on a heap-allocated stack.
+//! This trades a little dispatch cost for stack usage which does not depend on the input,
+//! and the types involved do not depend on the grammar, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+//! # use parsell::machine::MachineBuilder;
+//! fn zero(_: char) -> usize { 0 }
+//! fn deeper(depths: Vec<usize>) -> usize { depths[1] + 1 }
+//! fn deepest(depths: Vec<usize>) -> usize { depths.into_iter().max().unwrap_or(0) }
+//! // nested ::= ( "(" nested ")" )*
+//! let mut builder = MachineBuilder::new();
+//! let nested = builder.rule();
+//! let open = builder.parser(character(|ch| ch == '(').map(zero));
+//! let close = builder.parser(character(|ch| ch == ')').map(zero));
+//! let group = builder.seq(vec![open, nested, close], deeper);
+//! let groups = builder.star(group, deepest);
+//! builder.define(nested, groups);
+//! let machine = builder.build(nested);
+//! let input = format!("{}{}", "(".repeat(100_000), ")".repeat(100_000));
+//! assert_eq!(machine.init_str(&input).unwrap().unContinue().last_str(""), Ok(100_000));
+//! ```
+//!
+//! The leaves of a machine are uncommitted string parsers whose type can be erased,
+//! and every node has the same output type. A sequence fails (rather than backtracking)
+//! if any node after its first rejects, and a repetition stops at the first item which
+//! makes no progress. Left-recursive rules are not supported.
+
+use super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, Erasable, Boxable};
+use super::ParseResult::{Done, Continue};
+
+use std::error::Error;
+use std::fmt::{Debug, Display, Formatter};
+use std::rc::Rc;
+use std::str::Chars;
+use std;
+
+/// A node of a machine under construction.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Node(usize);
+
+enum Op<T> {
+    Parser(Box<Erasable<T>>),
+    Seq(Vec<Node>, fn(Vec<T>) -> T),
+    Alt(Vec<Node>),
+    Star(Node, fn(Vec<T>) -> T),
+    Rule(Option<Node>),
+}
+
+/// A builder for machines.
+///
+/// Each method adds a node, and nodes are combined by later nodes.
+/// Recursive grammars are built by declaring a `rule()` before it is used,
+/// and giving its definition with `define()`.
+
+pub struct MachineBuilder<T: 'static> {
+    ops: Vec<Op<T>>,
+}
+
+impl<T> MachineBuilder<T> {
+    /// A builder with no nodes.
+    pub fn new() -> Self {
+        MachineBuilder { ops: Vec::new() }
+    }
+
+    fn push(&mut self, op: Op<T>) -> Node {
+        self.ops.push(op);
+        Node(self.ops.len() - 1)
+    }
+
+    /// A node which runs a parser.
+    pub fn parser<P>(&mut self, parser: P) -> Node
+        where P: 'static + Erasable<T>,
+    {
+        self.push(Op::Parser(Box::new(parser)))
+    }
+
+    /// A node which runs each of `nodes` in turn, and combines their outputs.
+    pub fn seq(&mut self, nodes: Vec<Node>, combine: fn(Vec<T>) -> T) -> Node {
+        self.push(Op::Seq(nodes, combine))
+    }
+
+    /// A node which runs the first of `nodes` which does not reject its input.
+    pub fn alt(&mut self, nodes: Vec<Node>) -> Node {
+        self.push(Op::Alt(nodes))
+    }
+
+    /// A node which runs `node` repeatedly, and combines their outputs.
+    pub fn star(&mut self, node: Node, combine: fn(Vec<T>) -> T) -> Node {
+        self.push(Op::Star(node, combine))
+    }
+
+    /// A node which is defined later by `define()`.
+    pub fn rule(&mut self) -> Node {
+        self.push(Op::Rule(None))
+    }
+
+    /// Define a node declared by `rule()`.
+    ///
+    /// # Panics
+    ///
+    /// If `rule` was not declared by `rule()`.
+    pub fn define(&mut self, rule: Node, node: Node) {
+        match self.ops[rule.0] {
+            Op::Rule(ref mut definition) => *definition = Some(node),
+            _ => panic!("Node {} is not a rule", rule.0),
+        }
+    }
+
+    /// The machine which starts by running `start`.
+    ///
+    /// # Panics
+    ///
+    /// If a rule was declared but never defined.
+    pub fn build(self, start: Node) -> Machine<T> {
+        for (index, op) in self.ops.iter().enumerate() {
+            if let Op::Rule(None) = *op {
+                panic!("Rule {} is not defined", index);
+            }
+        }
+        Machine {
+            ops: Rc::new(self.ops),
+            start,
+        }
+    }
+}
+
+impl<T> Default for MachineBuilder<T> {
+    fn default() -> Self {
+        MachineBuilder::new()
+    }
+}
+
+/// A parser which runs a grammar on an explicit work stack.
+///
+/// Its output is `Ok(result)`, or an error if a sequence failed part way through.
+
+pub struct Machine<T: 'static> {
+    ops: Rc<Vec<Op<T>>>,
+    start: Node,
+}
+
+// A work around for T not necessarily implementing Clone or Debug
+impl<T> Clone for Machine<T> {
+    fn clone(&self) -> Self {
+        Machine {
+            ops: self.ops.clone(),
+            start: self.start,
+        }
+    }
+}
+
+impl<T> Debug for Machine<T> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Machine(...)")
+    }
+}
+
+impl<T> Parser for Machine<T> {}
+
+impl<'a, T> HasOutput<char, Chars<'a>> for Machine<T> {
+    type Output = Result<T, Rejected>;
+}
+
+impl<'a, T> Uncommitted<char, Chars<'a>, Result<T, Rejected>> for Machine<T> {
+    type State = MachineState<T>;
+
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<Self::State, Result<T, Rejected>>> {
+        if string.as_str().is_empty() {
+            return None;
+        }
+        let mut state = MachineState {
+            ops: self.ops.clone(),
+            stack: Vec::new(),
+            resume: Resume::Start(self.start),
+            position: 0,
+        };
+        match state.run(Step::Start(self.start), string, false) {
+            Outcome::Backtracked => None,
+            Outcome::Finished(result) => Some(Done(result)),
+            Outcome::Suspended => Some(Continue(state)),
+        }
+    }
+}
+
+/// The error returned when a sequence fails part way through.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Rejected {
+    /// The byte position of the rejected input, counted from the start of the parse.
+    pub position: usize,
+}
+
+impl Display for Rejected {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "Unexpected input at byte {}", self.position)
+    }
+}
+
+impl Error for Rejected {}
+
+// The nodes in progress. Sequences and repetitions keep the outputs so far,
+// and repetitions keep the position at which the current item started.
+
+enum Frame<T> {
+    Seq(Node, usize, Vec<T>),
+    Alt(Node, usize),
+    Star(Node, usize, Vec<T>),
+}
+
+// How to continue once more input arrives: either a suspended leaf,
+// or a node which was about to start when the input ran out.
+
+enum Resume<T> {
+    Leaf(Box<for<'b> Boxable<char, Chars<'b>, T>>),
+    Start(Node),
+}
+
+enum Step<T> {
+    Start(Node),
+    Return(T),
+    Reject,
+}
+
+enum Outcome<T> {
+    Backtracked,
+    Finished(Result<T, Rejected>),
+    Suspended,
+}
+
+/// The state of a machine which is waiting for more input.
+
+pub struct MachineState<T: 'static> {
+    ops: Rc<Vec<Op<T>>>,
+    stack: Vec<Frame<T>>,
+    resume: Resume<T>,
+    position: usize,
+}
+
+impl<T> Debug for MachineState<T> {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        write!(fmt, "MachineState(depth: {}, position: {})", self.stack.len(), self.position)
+    }
+}
+
+impl<T> MachineState<T> {
+    // The interpreter loop. Starting a node pushes a frame, and returning or rejecting
+    // pops one, so the native stack depth does not depend on the input.
+    fn run(&mut self, mut step: Step<T>, string: &mut Chars, eof: bool) -> Outcome<T> {
+        let ops = self.ops.clone();
+        loop {
+            step = match step {
+                Step::Start(node) => match ops[node.0] {
+                    Op::Parser(ref parser) => {
+                        if string.as_str().is_empty() && !eof {
+                            self.resume = Resume::Start(node);
+                            return Outcome::Suspended;
+                        }
+                        let before = string.as_str().len();
+                        let result = parser.init_erasable(string);
+                        self.position += before - string.as_str().len();
+                        match result {
+                            None => Step::Reject,
+                            Some(Done(result)) => Step::Return(result),
+                            Some(Continue(parsing)) => {
+                                self.resume = Resume::Leaf(parsing);
+                                return Outcome::Suspended;
+                            }
+                        }
+                    }
+                    Op::Seq(ref nodes, combine) => match nodes.first() {
+                        None => Step::Return(combine(Vec::new())),
+                        Some(&first) => {
+                            self.stack.push(Frame::Seq(node, 0, Vec::new()));
+                            Step::Start(first)
+                        }
+                    },
+                    Op::Alt(ref nodes) => match nodes.first() {
+                        None => Step::Reject,
+                        Some(&first) => {
+                            self.stack.push(Frame::Alt(node, 0));
+                            Step::Start(first)
+                        }
+                    },
+                    Op::Star(item, _) => {
+                        self.stack.push(Frame::Star(node, self.position, Vec::new()));
+                        Step::Start(item)
+                    }
+                    Op::Rule(definition) => Step::Start(definition.expect("Rules are defined")),
+                },
+                Step::Return(result) => match self.stack.pop() {
+                    None => return Outcome::Finished(Ok(result)),
+                    Some(Frame::Seq(node, index, mut results)) => match ops[node.0] {
+                        Op::Seq(ref nodes, combine) => {
+                            results.push(result);
+                            match nodes.get(index + 1) {
+                                None => Step::Return(combine(results)),
+                                Some(&next) => {
+                                    self.stack.push(Frame::Seq(node, index + 1, results));
+                                    Step::Start(next)
+                                }
+                            }
+                        }
+                        _ => unreachable!(),
+                    },
+                    Some(Frame::Alt(..)) => Step::Return(result),
+                    Some(Frame::Star(node, start, mut results)) => match ops[node.0] {
+                        // An item which made no progress ends the repetition.
+                        Op::Star(_, combine) if start == self.position => Step::Return(combine(results)),
+                        Op::Star(item, _) => {
+                            results.push(result);
+                            self.stack.push(Frame::Star(node, self.position, results));
+                            Step::Start(item)
+                        }
+                        _ => unreachable!(),
+                    },
+                },
+                Step::Reject => match self.stack.pop() {
+                    None => return Outcome::Backtracked,
+                    Some(Frame::Seq(_, 0, _)) => Step::Reject,
+                    Some(Frame::Seq(..)) => return Outcome::Finished(Err(Rejected { position: self.position })),
+                    Some(Frame::Alt(node, index)) => match ops[node.0] {
+                        Op::Alt(ref nodes) => match nodes.get(index + 1) {
+                            None => Step::Reject,
+                            Some(&next) => {
+                                self.stack.push(Frame::Alt(node, index + 1));
+                                Step::Start(next)
+                            }
+                        },
+                        _ => unreachable!(),
+                    },
+                    Some(Frame::Star(node, _, results)) => match ops[node.0] {
+                        Op::Star(_, combine) => Step::Return(combine(results)),
+                        _ => unreachable!(),
+                    },
+                },
+            };
+        }
+    }
+
+    // Since the machine has consumed input by the time it suspends, a rejection
+    // can only reach the bottom of the stack during `init`.
+    fn finish_outcome(self, outcome: Outcome<T>) -> ParseResult<Self, Result<T, Rejected>> {
+        match outcome {
+            Outcome::Backtracked => Done(Err(Rejected { position: self.position })),
+            Outcome::Finished(result) => Done(result),
+            Outcome::Suspended => Continue(self),
+        }
+    }
+}
+
+impl<'a, T> HasOutput<char, Chars<'a>> for MachineState<T> {
+    type Output = Result<T, Rejected>;
+}
+
+impl<'a, T> Stateful<char, Chars<'a>, Result<T, Rejected>> for MachineState<T> {
+    fn more(mut self, string: &mut Chars<'a>) -> ParseResult<Self, Result<T, Rejected>> {
+        let step = match self.resume {
+            Resume::Start(node) => Step::Start(node),
+            Resume::Leaf(ref mut parsing) => {
+                let before = string.as_str().len();
+                let result = parsing.more_boxable(string);
+                self.position += before - string.as_str().len();
+                match result {
+                    Done(result) => Step::Return(result),
+                    Continue(()) => return Continue(self),
+                }
+            }
+        };
+        let outcome = self.run(step, string, false);
+        self.finish_outcome(outcome)
+    }
+
+    fn done(mut self) -> Result<T, Rejected> {
+        let step = match self.resume {
+            Resume::Start(node) => Step::Start(node),
+            Resume::Leaf(ref mut parsing) => Step::Return(parsing.done_boxable()),
+        };
+        let outcome = self.run(step, &mut "".chars(), true);
+        match self.finish_outcome(outcome) {
+            Done(result) => result,
+            Continue(_) => unreachable!(),
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[cfg(test)]
+fn zero(_: char) -> usize {
+    0
+}
+
+#[cfg(test)]
+fn deeper(depths: Vec<usize>) -> usize {
+    depths[1] + 1
+}
+
+#[cfg(test)]
+fn deepest(depths: Vec<usize>) -> usize {
+    depths.into_iter().max().unwrap_or(0)
+}
+
+#[cfg(test)]
+fn nested() -> Machine<usize> {
+    use super::character;
+    // nested ::= ( "(" nested ")" | "[" nested "]" )*
+    let mut builder = MachineBuilder::new();
+    let nested = builder.rule();
+    let open = builder.parser(character(|ch| ch == '(').map(zero));
+    let close = builder.parser(character(|ch| ch == ')').map(zero));
+    let parens = builder.seq(vec![open, nested, close], deeper);
+    let open = builder.parser(character(|ch| ch == '[').map(zero));
+    let close = builder.parser(character(|ch| ch == ']').map(zero));
+    let brackets = builder.seq(vec![open, nested, close], deeper);
+    let group = builder.alt(vec![parens, brackets]);
+    let groups = builder.star(group, deepest);
+    builder.define(nested, groups);
+    builder.build(nested)
+}
+
+#[test]
+fn test_machine() {
+    use super::{UncommittedStr, StatefulStr};
+    use super::testing::assert_chunk_invariant;
+    let machine = nested();
+    assert!(machine.init_str("").is_none());
+    assert_eq!(machine.init_str("!").unwrap().unDone(), Ok(0));
+    assert_eq!(machine.init_str("()!").unwrap().unDone(), Ok(1));
+    assert_eq!(machine.init_str("([])[]").unwrap().unContinue().last_str("!"), Ok(2));
+    assert_eq!(machine.init_str("([").unwrap().unContinue().last_str("])"), Ok(2));
+    assert_eq!(machine.init_str("((]").unwrap().unDone(), Err(Rejected { position: 2 }));
+    assert_eq!(machine.init_str("((").unwrap().unContinue().done(), Err(Rejected { position: 2 }));
+    for input in &["", "!", "()", "([])[]!", "[[(]", "(()[[]])((", "(((())))[]"] {
+        assert_chunk_invariant(machine.clone(), input);
+    }
+}
+
+#[test]
+fn test_machine_deep() {
+    use super::{UncommittedStr, StatefulStr};
+    // Deep enough to overflow the stack if parsing were recursive.
+    let depth = 100_000;
+    let input = format!("{}{}", "([".repeat(depth), "])".repeat(depth));
+    let machine = nested();
+    assert_eq!(machine.init_str(&input).unwrap().unContinue().last_str(""), Ok(2 * depth));
+    let mut parsing = machine.init_str("(").unwrap().unContinue();
+    for ch in input[1..].chars() {
+        let mut chunk = [0; 4];
+        parsing = parsing.more_str(ch.encode_utf8(&mut chunk)).unContinue();
+    }
+    assert_eq!(parsing.done(), Ok(2 * depth));
+}
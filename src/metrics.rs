@@ -0,0 +1,153 @@
+//! Throughput and allocation metrics.
+//!
+//! This module is only available with the `metrics` feature.
+//! While metrics are being recorded, the string drivers (`init_str`, `more_str` and `last_str`)
+//! record how much input each chunk consumed and how long it took,
+//! and `Buffered` parsers record how much data they copied, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+//! # use parsell::metrics;
+//! let parser = character(char::is_alphanumeric).plus(String::new).buffer();
+//! metrics::start();
+//! let parsing = parser.init_str("abc").unwrap().unContinue();
+//! assert_eq!(parsing.more_str("def!").unDone(), "abcdef");
+//! let report = metrics::finish();
+//! assert_eq!(report.chunks, 2);
+//! assert_eq!(report.bytes, 6);
+//! assert_eq!(report.buffered_bytes, 6);
+//! ```
+//!
+//! Metrics are recorded per thread. Chunk times are not recorded on `wasm32-unknown-unknown`,
+//! which has no clock.
+
+use std::cell::RefCell;
+use std::mem;
+use std::str::Chars;
+use std::time::{Duration, Instant};
+
+/// Metrics recorded during a parse, produced by `finish()`.
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Metrics {
+    /// The number of chunks given to the parser.
+    pub chunks: usize,
+    /// The number of bytes of input the parser consumed.
+    pub bytes: usize,
+    /// The number of characters of input the parser consumed.
+    pub chars: usize,
+    /// The time spent parsing each chunk, if the platform has a clock.
+    pub chunk_times: Vec<Duration>,
+    /// The number of times a buffer was allocated or reallocated by `Buffered`.
+    pub buffer_allocations: usize,
+    /// The number of bytes copied into buffers by `Buffered`.
+    pub buffered_bytes: usize,
+    /// The size (in bytes) of the largest type of result of a chunk, including any suspended state.
+    /// This is the static size of the type, so it does not include any heap data the state owns.
+    pub state_type_size: usize,
+}
+
+impl Metrics {
+    /// The total time spent parsing.
+    pub fn total_time(&self) -> Duration {
+        self.chunk_times.iter().fold(Duration::new(0, 0), |total, &time| total + time)
+    }
+
+    /// The number of bytes consumed per second.
+    pub fn throughput(&self) -> f64 {
+        let time = self.total_time();
+        let seconds = time.as_secs() as f64 + time.subsec_nanos() as f64 * 1e-9;
+        if seconds == 0.0 { 0.0 } else { self.bytes as f64 / seconds }
+    }
+}
+
+thread_local!(static METRICS: RefCell<Option<Metrics>> = const { RefCell::new(None) });
+
+/// Start recording metrics on this thread, discarding any previous metrics.
+
+pub fn start() {
+    METRICS.with(|metrics| *metrics.borrow_mut() = Some(Metrics::default()));
+}
+
+/// Stop recording metrics on this thread, and return them.
+
+pub fn finish() -> Metrics {
+    METRICS.with(|metrics| metrics.borrow_mut().take().unwrap_or_default())
+}
+
+fn update<F>(f: F) where F: FnOnce(&mut Metrics) {
+    METRICS.with(|metrics| {
+        if let Some(ref mut metrics) = *metrics.borrow_mut() {
+            f(metrics)
+        }
+    })
+}
+
+// `Instant::now()` panics on platforms without a clock.
+
+#[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+fn now() -> Option<Instant> {
+    Some(Instant::now())
+}
+
+#[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+fn now() -> Option<Instant> {
+    None
+}
+
+// Run a parser on a chunk, recording its metrics.
+
+pub(crate) fn measure<'a, F, T>(string: &'a str, f: F) -> T
+    where F: FnOnce(&mut Chars<'a>) -> T,
+{
+    let mut data = string.chars();
+    let start = now();
+    let result = f(&mut data);
+    let time = start.map(|start| start.elapsed());
+    let consumed = &string[..(string.len() - data.as_str().len())];
+    update(|metrics| {
+        metrics.chunks += 1;
+        metrics.bytes += consumed.len();
+        metrics.chars += consumed.chars().count();
+        metrics.chunk_times.extend(time);
+        metrics.state_type_size = metrics.state_type_size.max(mem::size_of::<T>());
+    });
+    result
+}
+
+// Record data being copied into a buffer.
+
+pub(crate) fn buffered(buffer: &String, data: &str) {
+    let allocates = buffer.capacity() < buffer.len() + data.len();
+    update(|metrics| {
+        metrics.buffered_bytes += data.len();
+        if allocates { metrics.buffer_allocations += 1; }
+    });
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_metrics() {
+    use super::{character, Parser, UncommittedStr, StatefulStr};
+    fn ignore() {}
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let parser = ALPHANUMERIC.plus(ignore).buffer();
+    parser.init_str("abc!");
+    assert_eq!(finish(), Metrics::default());
+    start();
+    assert_eq!(parser.init_str("abc!").unwrap().unDone(), "abc");
+    let parsing = parser.init_str("\u{e9}").unwrap().unContinue();
+    let parsing = parsing.more_str("").unContinue();
+    assert_eq!(parsing.last_str("f"), "\u{e9}f");
+    let report = finish();
+    assert_eq!(report.chunks, 4);
+    assert_eq!(report.bytes, 6);
+    assert_eq!(report.chars, 5);
+    assert_eq!(report.chunk_times.len(), 4);
+    assert_eq!(report.buffered_bytes, 3);
+    assert_eq!(report.buffer_allocations, 2);
+    assert!(report.state_type_size > 0);
+    assert!(report.throughput() >= 0.0);
+}
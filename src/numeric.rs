@@ -0,0 +1,195 @@
+//! Parsers for numbers.
+//!
+//! The recognizers match the syntax of a number, and produce the text that matched,
+//! without converting it. This is useful for callers who need full precision,
+//! want to defer conversion, or must preserve formatting, for example:
+//!
+//! ```
+//! # use parsell::{UncommittedStr,StatefulStr};
+//! # use parsell::numeric::recognize_float;
+//! let parser = recognize_float();
+//! assert_eq!(parser.init_str("-1.50e+3,").unwrap().unDone(), "-1.50e+3");
+//! let parsing = parser.init_str("3.14159").unwrap().unContinue();
+//! assert_eq!(parsing.last_str("26535897932384626433832795!"), "3.1415926535897932384626433832795");
+//! ```
+//!
+//! Like all parsell parsers, the recognizers only look one character ahead, so they commit
+//! to a sign or exponent marker before seeing whether it is followed by a digit. For example,
+//! `recognize_float()` matches `1e` in `1e!`, which conversion to a number would then reject.
+//! Similarly, the converting parsers produce a `MissingDigits` error for a sign which is
+//! not followed by a digit.
+//!
+//! The parser `integer(policy)` converts integers to `i64`, where the policy says what
+//! happens on overflow. With the `num-bigint` feature, `bigint()` parses integers of any size,
+//! and with the `rust_decimal` feature, `decimal()` parses exact decimals.
+//! Parsed integers can be mapped to typed flags with `flags()`, or `bitflags()`
+//! with the `bitflags` feature.
+
+use super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::impls::Buffered;
+use super::ParseResult::{Done, Continue};
+
+use std::error;
+use std::fmt;
+
+#[cfg(feature = "num-bigint")]
+mod bigint;
+#[cfg(feature = "num-bigint")]
+pub use self::bigint::{bigint, BigIntParser, BigIntState};
+#[cfg(feature = "rust_decimal")]
+mod decimal;
+#[cfg(feature = "rust_decimal")]
+pub use self::decimal::{decimal, DecimalParser, DecimalState};
+mod flags;
+pub use self::flags::{flags, FlagLayout, ReservedBits};
+#[cfg(feature = "bitflags")]
+pub use self::flags::{bitflags, BitFlags};
+mod integer;
+pub use self::integer::{integer, IntegerParser, IntegerState, Overflow, OverflowPolicy, Accumulated, Saturate, Wrap, Checked};
+#[cfg(feature = "num-bigint")]
+pub use self::integer::{Widen, Widened};
+
+/// The error produced when a sign is not followed by any digits.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct MissingDigits;
+
+impl fmt::Display for MissingDigits {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Expected digits after the sign")
+    }
+}
+
+impl error::Error for MissingDigits {}
+
+/// A parser which matches integer syntax, producing the matched text.
+///
+/// Integers are an optional `+` or `-` followed by decimal digits.
+
+pub fn recognize_integer() -> Buffered<NumberSyntax> {
+    NumberSyntax(false).buffer()
+}
+
+/// A parser which matches floating point syntax, producing the matched text.
+///
+/// Floating point numbers are an integer, optionally followed by `.` and zero or more digits,
+/// optionally followed by `e` or `E`, an optional sign, and digits.
+
+pub fn recognize_float() -> Buffered<NumberSyntax> {
+    NumberSyntax(true).buffer()
+}
+
+// How much of a number has been read.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Position {
+    Start,
+    Sign,
+    Integer,
+    Fraction,
+    Exponent,
+    ExponentSign,
+    ExponentDigits,
+}
+
+impl Position {
+    fn step(self, float: bool, ch: char) -> Option<Position> {
+        use self::Position::*;
+        match (self, ch) {
+            (Start, '+') | (Start, '-') => Some(Sign),
+            (Start, '0'..='9') | (Sign, '0'..='9') | (Integer, '0'..='9') => Some(Integer),
+            (Integer, '.') if float => Some(Fraction),
+            (Fraction, '0'..='9') => Some(Fraction),
+            (Integer, 'e') | (Integer, 'E') | (Fraction, 'e') | (Fraction, 'E') if float => Some(Exponent),
+            (Exponent, '+') | (Exponent, '-') => Some(ExponentSign),
+            (Exponent, '0'..='9') | (ExponentSign, '0'..='9') | (ExponentDigits, '0'..='9') => Some(ExponentDigits),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct NumberSyntax(bool);
+
+#[derive(Copy, Clone, Debug)]
+pub struct NumberSyntaxState(bool, Position);
+
+impl Parser for NumberSyntax {}
+
+impl<Str> Stateful<char, Str, ()> for NumberSyntaxState
+    where Str: PeekableIterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, ()> {
+        let float = self.0;
+        loop {
+            let position = self.1;
+            match string.next_if(move |ch| position.step(float, ch).is_some()) {
+                Some(ch) => self.1 = position.step(float, ch).unwrap(),
+                None if string.is_empty() => return Continue(self),
+                None => return Done(()),
+            }
+        }
+    }
+
+    fn done(self) {}
+
+}
+
+impl<Str> HasOutput<char, Str> for NumberSyntaxState {
+
+    type Output = ();
+
+}
+
+impl<Str> HasOutput<char, Str> for NumberSyntax {
+
+    type Output = ();
+
+}
+
+impl<Str> Uncommitted<char, Str, ()> for NumberSyntax
+    where Str: PeekableIterator<Item = char>,
+{
+
+    type State = NumberSyntaxState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, ()>> {
+        let float = self.0;
+        match string.next_if(move |ch| Position::Start.step(float, ch).is_some()) {
+            Some(ch) => Some(NumberSyntaxState(float, Position::Start.step(float, ch).unwrap()).more(string)),
+            None => None,
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_recognize_integer() {
+    use super::{UncommittedStr, StatefulStr};
+    use std::borrow::Cow::{Borrowed, Owned};
+    let parser = recognize_integer();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("a"), None);
+    assert_eq!(parser.init_str("-0012.5").unwrap().unDone(), Borrowed("-0012"));
+    assert_eq!(parser.init_str("+!").unwrap().unDone(), Borrowed("+"));
+    let parsing = parser.init_str("12345678901234567890").unwrap().unContinue();
+    assert_eq!(parsing.last_str("12345678901234567890 "), Owned::<str>(String::from("1234567890123456789012345678901234567890")));
+}
+
+#[test]
+fn test_recognize_float() {
+    use super::{UncommittedStr, StatefulStr};
+    use super::testing;
+    let parser = recognize_float();
+    assert_eq!(parser.init_str("."), None);
+    assert_eq!(parser.init_str("1.").unwrap().unContinue().last_str(""), "1.");
+    assert_eq!(parser.init_str("1.e5,").unwrap().unDone(), "1.e5");
+    assert_eq!(parser.init_str("1E-07x").unwrap().unDone(), "1E-07");
+    assert_eq!(parser.init_str("1e!").unwrap().unDone(), "1e");
+    assert_eq!(parser.init_str("1.2.3").unwrap().unDone(), "1.2");
+    assert_eq!(parser.init_str("1e2.3").unwrap().unDone(), "1e2");
+    testing::assert_chunk_invariant(parser, "-123.456e+789;");
+}
@@ -0,0 +1,152 @@
+// Arbitrary-precision integers, using the `num-bigint` crate.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+use super::MissingDigits;
+
+use num_bigint::BigInt;
+
+use std::mem;
+
+// The largest number of decimal digits which always fit in a u64.
+const CHUNK_DIGITS: u32 = 19;
+
+/// A parser for integers of any size, producing a `BigInt`.
+///
+/// Integers are an optional `+` or `-` followed by decimal digits. Digits are accumulated
+/// as they are read, so literals which are split across chunks are never buffered as text.
+/// The result is an error if there is a sign but no digits.
+/// This is only available with the `num-bigint` feature.
+
+pub fn bigint() -> BigIntParser {
+    BigIntParser
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct BigIntParser;
+
+#[derive(Clone, Debug)]
+pub struct BigIntState {
+    negative: bool,
+    value: BigInt,
+    chunk: u64,
+    digits: u32,
+    empty: bool,
+}
+
+impl BigIntState {
+    fn new(negative: bool) -> Self {
+        BigIntState {
+            negative,
+            value: BigInt::from(0u64),
+            chunk: 0,
+            digits: 0,
+            empty: true,
+        }
+    }
+
+    fn accept(&mut self, digit: u32) {
+        self.chunk = self.chunk * 10 + digit as u64;
+        self.digits += 1;
+        self.empty = false;
+        if self.digits == CHUNK_DIGITS {
+            self.flush();
+        }
+    }
+
+    // Accumulating digits in a u64 saves a bignum multiplication for most digits.
+    fn flush(&mut self) {
+        let value = mem::replace(&mut self.value, BigInt::from(0u64));
+        self.value = value * 10u64.pow(self.digits) + self.chunk;
+        self.chunk = 0;
+        self.digits = 0;
+    }
+}
+
+fn is_digit(ch: char) -> bool {
+    ch.is_ascii_digit()
+}
+
+fn is_sign_or_digit(ch: char) -> bool {
+    ch == '+' || ch == '-' || ch.is_ascii_digit()
+}
+
+impl Parser for BigIntParser {}
+
+impl<Str> Stateful<char, Str, Result<BigInt, MissingDigits>> for BigIntState
+    where Str: PeekableIterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Result<BigInt, MissingDigits>> {
+        while let Some(ch) = string.next_if(is_digit) {
+            self.accept(ch.to_digit(10).unwrap());
+        }
+        if string.is_empty() {
+            Continue(self)
+        } else {
+            Done(Stateful::<char, Str, _>::done(self))
+        }
+    }
+
+    fn done(mut self) -> Result<BigInt, MissingDigits> {
+        if self.empty {
+            return Err(MissingDigits);
+        }
+        self.flush();
+        Ok(if self.negative { -self.value } else { self.value })
+    }
+
+}
+
+impl<Str> HasOutput<char, Str> for BigIntState {
+
+    type Output = Result<BigInt, MissingDigits>;
+
+}
+
+impl<Str> HasOutput<char, Str> for BigIntParser {
+
+    type Output = Result<BigInt, MissingDigits>;
+
+}
+
+impl<Str> Uncommitted<char, Str, Result<BigInt, MissingDigits>> for BigIntParser
+    where Str: PeekableIterator<Item = char>,
+{
+
+    type State = BigIntState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<BigInt, MissingDigits>>> {
+        match string.next_if(is_sign_or_digit) {
+            None => None,
+            Some('+') => Some(BigIntState::new(false).more(string)),
+            Some('-') => Some(BigIntState::new(true).more(string)),
+            Some(ch) => {
+                let mut state = BigIntState::new(false);
+                state.accept(ch.to_digit(10).unwrap());
+                Some(state.more(string))
+            },
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_bigint() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    fn big(string: &str) -> Result<BigInt, MissingDigits> { Ok(string.parse().unwrap()) }
+    let parser = bigint();
+    assert_eq!(parser.init_str(""), None);
+    assert_eq!(parser.init_str("x"), None);
+    assert_eq!(parser.init_str("0!").unwrap().unDone(), big("0"));
+    assert_eq!(parser.init_str("-!").unwrap().unDone(), Err(MissingDigits));
+    assert_eq!(parser.init_str("+").unwrap().unContinue().last_str("x"), Err(MissingDigits));
+    assert_eq!(parser.init_str("-42!").unwrap().unDone(), big("-42"));
+    let parsing = parser.init_str("+1234567890123456").unwrap().unContinue();
+    let parsing = parsing.more_str("78901234567890").unContinue();
+    assert_eq!(parsing.last_str("12345;"), big("12345678901234567890123456789012345"));
+    testing::assert_chunk_invariant(parser, "-98765432109876543210987654321;");
+}
@@ -0,0 +1,147 @@
+// Exact decimals, using the `rust_decimal` crate.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+use super::MissingDigits;
+
+use rust_decimal::{Decimal, Error};
+
+/// A parser for exact decimal numbers, producing a `Decimal`.
+///
+/// Decimals are an optional `+` or `-`, followed by decimal digits,
+/// optionally followed by `.` and zero or more digits. No binary floating point is involved,
+/// so `0.1` is exactly one tenth. The result is an error if the number has more
+/// significant digits than a `Decimal` can represent, or if there is a sign but no digits.
+/// This is only available with the `rust_decimal` feature.
+
+pub fn decimal() -> DecimalParser {
+    DecimalParser
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct DecimalParser;
+
+#[derive(Copy, Clone, Debug)]
+pub struct DecimalState {
+    negative: bool,
+    mantissa: Option<u128>,
+    scale: u32,
+    fraction: bool,
+    empty: bool,
+}
+
+impl DecimalState {
+    fn new(negative: bool) -> Self {
+        DecimalState {
+            negative,
+            mantissa: Some(0),
+            scale: 0,
+            fraction: false,
+            empty: true,
+        }
+    }
+
+    fn accept(&mut self, ch: char) {
+        match ch.to_digit(10) {
+            None => self.fraction = true,
+            Some(digit) => {
+                self.empty = false;
+                self.mantissa = self.mantissa
+                    .and_then(|mantissa| mantissa.checked_mul(10))
+                    .and_then(|mantissa| mantissa.checked_add(digit as u128));
+                if self.fraction {
+                    self.scale += 1;
+                }
+            },
+        }
+    }
+}
+
+impl Parser for DecimalParser {}
+
+impl<Str> Stateful<char, Str, Result<Decimal, Error>> for DecimalState
+    where Str: PeekableIterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Result<Decimal, Error>> {
+        loop {
+            let fraction = self.fraction;
+            match string.next_if(move |ch: char| ch.is_ascii_digit() || (ch == '.' && !fraction)) {
+                Some(ch) => self.accept(ch),
+                None if string.is_empty() => return Continue(self),
+                None => return Done(Stateful::<char, Str, _>::done(self)),
+            }
+        }
+    }
+
+    fn done(self) -> Result<Decimal, Error> {
+        if self.empty {
+            return Err(Error::from(MissingDigits.to_string()));
+        }
+        let mantissa = try!(self.mantissa.ok_or(Error::ExceedsMaximumPossibleValue));
+        let mantissa = try!(if mantissa > i128::MAX as u128 {
+            Err(Error::ExceedsMaximumPossibleValue)
+        } else {
+            Ok(mantissa as i128)
+        });
+        let mantissa = if self.negative { -mantissa } else { mantissa };
+        Decimal::try_from_i128_with_scale(mantissa, self.scale)
+    }
+
+}
+
+impl<Str> HasOutput<char, Str> for DecimalState {
+
+    type Output = Result<Decimal, Error>;
+
+}
+
+impl<Str> HasOutput<char, Str> for DecimalParser {
+
+    type Output = Result<Decimal, Error>;
+
+}
+
+impl<Str> Uncommitted<char, Str, Result<Decimal, Error>> for DecimalParser
+    where Str: PeekableIterator<Item = char>,
+{
+
+    type State = DecimalState;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<Decimal, Error>>> {
+        match string.next_if(|ch: char| ch == '+' || ch == '-' || ch.is_ascii_digit()) {
+            None => None,
+            Some('+') => Some(DecimalState::new(false).more(string)),
+            Some('-') => Some(DecimalState::new(true).more(string)),
+            Some(ch) => {
+                let mut state = DecimalState::new(false);
+                state.accept(ch);
+                Some(state.more(string))
+            },
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_decimal() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    fn dec(string: &str) -> Decimal { string.parse().unwrap() }
+    let parser = decimal();
+    assert!(parser.init_str("").is_none());
+    assert!(parser.init_str(".5").is_none());
+    assert_eq!(parser.init_str("0.1,").unwrap().unDone(), Ok(dec("0.1")));
+    assert_eq!(parser.init_str("-12.50.").unwrap().unDone(), Ok(dec("-12.50")));
+    assert_eq!(parser.init_str("7.!").unwrap().unDone(), Ok(dec("7")));
+    let parsing = parser.init_str("+123.4").unwrap().unContinue();
+    assert_eq!(parsing.last_str("56"), Ok(dec("123.456")));
+    let huge = "1234567890123456789012345678901234567890;";
+    assert_eq!(parser.init_str(huge).unwrap().unDone(), Err(Error::ExceedsMaximumPossibleValue));
+    let missing = Err(Error::from(MissingDigits.to_string()));
+    assert_eq!(parser.init_str("-;").unwrap().unDone(), missing);
+    assert_eq!(parser.init_str("+.;").unwrap().unDone(), missing);
+    testing::assert_chunk_invariant(parser, "-3.14159;");
+}
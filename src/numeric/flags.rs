@@ -0,0 +1,134 @@
+// Flags packed into parsed integers.
+
+use super::super::Function;
+
+use std::error;
+use std::fmt;
+#[cfg(feature = "bitflags")]
+use std::marker::PhantomData;
+
+#[cfg(feature = "bitflags")]
+use bitflags::{Bits, Flags};
+
+/// An error for an integer with reserved bits set.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ReservedBits<B>(pub B);
+
+impl<B> fmt::Display for ReservedBits<B> where B: fmt::LowerHex {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Reserved bits set: {:#x}", self.0)
+    }
+}
+
+impl<B> error::Error for ReservedBits<B> where B: fmt::Debug + fmt::LowerHex + Send + Sync + 'static {}
+
+/// A function from a parsed integer to a flags type, checking reserved bits.
+///
+/// The function `flags(defined, build)` takes an integer `bits`, and
+/// if `bits` only has bits set which are also set in `defined`, returns `Ok(build(bits))`,
+/// otherwise returns an error with the reserved bits that were set. It is used with `map`,
+/// for example:
+///
+/// ```
+/// # use parsell::{character_map,Parser,UncommittedStr};
+/// # use parsell::numeric::{flags,ReservedBits};
+/// #[derive(Debug, PartialEq)]
+/// struct Mode { read: bool, write: bool }
+/// fn mode(bits: u32) -> Mode { Mode { read: bits & 4 != 0, write: bits & 2 != 0 } }
+/// let parser = character_map(|ch: char| ch.to_digit(8)).map(flags(0o6, mode));
+/// assert_eq!(parser.init_str("6").unwrap().unDone(), Ok(Mode { read: true, write: true }));
+/// assert_eq!(parser.init_str("5").unwrap().unDone(), Err(ReservedBits(1)));
+/// ```
+///
+/// With the `bitflags` feature, `bitflags()` converts to a type defined with the `bitflags!` macro.
+
+pub fn flags<B, T>(defined: B, build: fn(B) -> T) -> FlagLayout<B, T> {
+    FlagLayout(defined, build)
+}
+
+/// A function from a parsed integer to a `bitflags` type, checking reserved bits.
+///
+/// The reserved bits are those which are not part of any declared flag.
+/// This is only available with the `bitflags` feature.
+
+#[cfg(feature = "bitflags")]
+pub fn bitflags<F>() -> BitFlags<F> {
+    BitFlags(PhantomData)
+}
+
+pub struct FlagLayout<B, T>(B, fn(B) -> T);
+
+impl<B, T> Copy for FlagLayout<B, T> where B: Copy {}
+impl<B, T> Clone for FlagLayout<B, T> where B: Copy {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<B, T> Function<B> for FlagLayout<B, T>
+    where B: Copy + PartialEq + Default + ::std::ops::BitAnd<Output = B> + ::std::ops::Not<Output = B>,
+{
+    type Output = Result<T, ReservedBits<B>>;
+    fn apply(&self, bits: B) -> Result<T, ReservedBits<B>> {
+        let reserved = bits & !self.0;
+        if reserved == B::default() {
+            Ok((self.1)(bits))
+        } else {
+            Err(ReservedBits(reserved))
+        }
+    }
+}
+
+#[cfg(feature = "bitflags")]
+pub struct BitFlags<F>(PhantomData<fn() -> F>);
+
+#[cfg(feature = "bitflags")]
+impl<F> Copy for BitFlags<F> {}
+#[cfg(feature = "bitflags")]
+impl<F> Clone for BitFlags<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+#[cfg(feature = "bitflags")]
+impl<F> Function<F::Bits> for BitFlags<F>
+    where F: Flags,
+{
+    type Output = Result<F, ReservedBits<F::Bits>>;
+    fn apply(&self, bits: F::Bits) -> Result<F, ReservedBits<F::Bits>> {
+        let reserved = bits & !F::all().bits();
+        if reserved == F::Bits::EMPTY {
+            Ok(F::from_bits_retain(bits))
+        } else {
+            Err(ReservedBits(reserved))
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_flags() {
+    fn low(bits: u8) -> u8 { bits }
+    let layout = flags(0x0f, low);
+    assert_eq!(layout.apply(0x05), Ok(0x05));
+    assert_eq!(layout.apply(0x35), Err(ReservedBits(0x30)));
+    assert_eq!(format!("{}", ReservedBits(0x30u8)), "Reserved bits set: 0x30");
+}
+
+#[cfg(feature = "bitflags")]
+#[test]
+fn test_bitflags() {
+    ::bitflags::bitflags! {
+        #[derive(Debug, PartialEq)]
+        struct Header: u16 {
+            const QUERY = 0x8000;
+            const RECURSIVE = 0x0001;
+        }
+    }
+    let layout = bitflags::<Header>();
+    assert_eq!(layout.apply(0x8000), Ok(Header::QUERY));
+    assert_eq!(layout.apply(0x8002), Err(ReservedBits(0x0002)));
+}
@@ -0,0 +1,270 @@
+// Machine integers, with a choice of behaviour on overflow.
+
+use super::super::{Parser, ParseResult, HasOutput, Stateful, Uncommitted, PeekableIterator};
+use super::super::ParseResult::{Done, Continue};
+use super::MissingDigits;
+
+use std::error;
+use std::fmt;
+
+#[cfg(feature = "num-bigint")]
+use num_bigint::BigInt;
+
+/// A parser for integers, producing an `i64`, with a policy for integers which overflow.
+///
+/// Integers are an optional `+` or `-` followed by decimal digits. The policy is one of
+/// `Saturate`, `Wrap` or `Checked`, or `Widen` with the `num-bigint` feature.
+/// The result is an error if there is a sign but no digits. For example:
+///
+/// ```
+/// # use parsell::UncommittedStr;
+/// # use parsell::numeric::{integer,Saturate,Wrap,Checked,Overflow,MissingDigits};
+/// assert_eq!(integer(Saturate).init_str("-99999999999999999999 ").unwrap().unDone(), Ok(i64::min_value()));
+/// assert_eq!(integer(Wrap).init_str("9223372036854775808 ").unwrap().unDone(), Ok(i64::min_value()));
+/// assert_eq!(integer(Checked).init_str("9223372036854775808 ").unwrap().unDone(), Ok(Err(Overflow)));
+/// assert_eq!(integer(Checked).init_str("-42 ").unwrap().unDone(), Ok(Ok(-42)));
+/// assert_eq!(integer(Checked).init_str("- ").unwrap().unDone(), Err(MissingDigits));
+/// ```
+
+pub fn integer<P>(policy: P) -> IntegerParser<P> {
+    IntegerParser(policy)
+}
+
+/// An error for an integer which overflows.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Overflow;
+
+impl fmt::Display for Overflow {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Integer overflow")
+    }
+}
+
+impl error::Error for Overflow {}
+
+/// An integer as it has been read so far.
+
+#[derive(Clone, Debug)]
+pub struct Accumulated {
+    /// Whether the integer is negative.
+    pub negative: bool,
+    /// The value of the integer, or `None` if it has overflowed.
+    pub checked: Option<i64>,
+    /// The value of the integer, wrapping on overflow.
+    pub wrapped: i64,
+    /// The digits of the integer, if the policy keeps them.
+    pub digits: String,
+}
+
+/// A policy for integers which overflow.
+
+pub trait OverflowPolicy: Copy {
+    /// The output of the parser.
+    type Output;
+
+    /// Whether the policy needs the digits of the integer.
+    fn keeps_digits(&self) -> bool { false }
+
+    /// Convert an integer which has been read.
+    fn convert(&self, integer: Accumulated) -> Self::Output;
+}
+
+/// Saturate to the largest or smallest `i64` on overflow.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Saturate;
+
+impl OverflowPolicy for Saturate {
+    type Output = i64;
+    fn convert(&self, integer: Accumulated) -> i64 {
+        match integer.checked {
+            Some(value) => value,
+            None if integer.negative => i64::MIN,
+            None => i64::MAX,
+        }
+    }
+}
+
+/// Wrap around on overflow, using two's complement arithmetic.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Wrap;
+
+impl OverflowPolicy for Wrap {
+    type Output = i64;
+    fn convert(&self, integer: Accumulated) -> i64 {
+        integer.wrapped
+    }
+}
+
+/// Produce an error on overflow.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Checked;
+
+impl OverflowPolicy for Checked {
+    type Output = Result<i64, Overflow>;
+    fn convert(&self, integer: Accumulated) -> Result<i64, Overflow> {
+        integer.checked.ok_or(Overflow)
+    }
+}
+
+/// An integer which may have been widened to a `BigInt`.
+///
+/// This is only available with the `num-bigint` feature.
+
+#[cfg(feature = "num-bigint")]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Widened {
+    /// An integer which fits in an `i64`.
+    Small(i64),
+    /// An integer which does not.
+    Big(BigInt),
+}
+
+/// Fall back to a `BigInt` on overflow.
+///
+/// This is only available with the `num-bigint` feature.
+
+#[cfg(feature = "num-bigint")]
+#[derive(Copy, Clone, Debug)]
+pub struct Widen;
+
+#[cfg(feature = "num-bigint")]
+impl OverflowPolicy for Widen {
+    type Output = Widened;
+    fn keeps_digits(&self) -> bool { true }
+    fn convert(&self, integer: Accumulated) -> Widened {
+        match integer.checked {
+            Some(value) => Widened::Small(value),
+            None => {
+                let sign = if integer.negative { "-" } else { "" };
+                Widened::Big(format!("{}{}", sign, integer.digits).parse().unwrap())
+            },
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct IntegerParser<P>(P);
+
+#[derive(Clone, Debug)]
+pub struct IntegerState<P>(P, Accumulated, bool);
+
+impl<P> IntegerState<P> where P: OverflowPolicy {
+    fn new(policy: P, negative: bool) -> Self {
+        IntegerState(policy, Accumulated { negative, checked: Some(0), wrapped: 0, digits: String::new() }, false)
+    }
+
+    fn accept(&mut self, ch: char) {
+        let digit = ch.to_digit(10).unwrap() as i64;
+        let integer = &mut self.1;
+        self.2 = true;
+        if integer.negative {
+            integer.checked = integer.checked.and_then(|value| value.checked_mul(10)).and_then(|value| value.checked_sub(digit));
+            integer.wrapped = integer.wrapped.wrapping_mul(10).wrapping_sub(digit);
+        } else {
+            integer.checked = integer.checked.and_then(|value| value.checked_mul(10)).and_then(|value| value.checked_add(digit));
+            integer.wrapped = integer.wrapped.wrapping_mul(10).wrapping_add(digit);
+        }
+        if self.0.keeps_digits() {
+            integer.digits.push(ch);
+        }
+    }
+}
+
+impl<P> Parser for IntegerParser<P> {}
+
+impl<P, Str> Stateful<char, Str, Result<P::Output, MissingDigits>> for IntegerState<P>
+    where P: OverflowPolicy,
+          Str: PeekableIterator<Item = char>,
+{
+
+    fn more(mut self, string: &mut Str) -> ParseResult<Self, Result<P::Output, MissingDigits>> {
+        loop {
+            match string.next_if(|ch: char| ch.is_ascii_digit()) {
+                Some(ch) => self.accept(ch),
+                None if string.is_empty() => return Continue(self),
+                None => return Done(Stateful::<char, Str, _>::done(self)),
+            }
+        }
+    }
+
+    fn done(self) -> Result<P::Output, MissingDigits> {
+        if self.2 {
+            Ok(self.0.convert(self.1))
+        } else {
+            Err(MissingDigits)
+        }
+    }
+
+}
+
+impl<P, Str> HasOutput<char, Str> for IntegerState<P>
+    where P: OverflowPolicy,
+{
+
+    type Output = Result<P::Output, MissingDigits>;
+
+}
+
+impl<P, Str> HasOutput<char, Str> for IntegerParser<P>
+    where P: OverflowPolicy,
+{
+
+    type Output = Result<P::Output, MissingDigits>;
+
+}
+
+impl<P, Str> Uncommitted<char, Str, Result<P::Output, MissingDigits>> for IntegerParser<P>
+    where P: OverflowPolicy,
+          Str: PeekableIterator<Item = char>,
+{
+
+    type State = IntegerState<P>;
+
+    fn init(&self, string: &mut Str) -> Option<ParseResult<Self::State, Result<P::Output, MissingDigits>>> {
+        match string.next_if(|ch: char| ch == '+' || ch == '-' || ch.is_ascii_digit()) {
+            None => None,
+            Some('+') => Some(IntegerState::new(self.0, false).more(string)),
+            Some('-') => Some(IntegerState::new(self.0, true).more(string)),
+            Some(ch) => {
+                let mut state = IntegerState::new(self.0, false);
+                state.accept(ch);
+                Some(state.more(string))
+            },
+        }
+    }
+
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_integer() {
+    use super::super::{UncommittedStr, StatefulStr};
+    use super::super::testing;
+    assert_eq!(integer(Checked).init_str(""), None);
+    assert_eq!(integer(Checked).init_str("x"), None);
+    assert_eq!(integer(Checked).init_str("-9223372036854775808;").unwrap().unDone(), Ok(Ok(i64::MIN)));
+    assert_eq!(integer(Checked).init_str("-9223372036854775809;").unwrap().unDone(), Ok(Err(Overflow)));
+    assert_eq!(integer(Saturate).init_str("+18446744073709551616;").unwrap().unDone(), Ok(i64::MAX));
+    assert_eq!(integer(Wrap).init_str("18446744073709551617;").unwrap().unDone(), Ok(1));
+    assert_eq!(integer(Wrap).init_str("-9223372036854775809;").unwrap().unDone(), Ok(i64::MAX));
+    assert_eq!(integer(Checked).init_str("-;").unwrap().unDone(), Err(MissingDigits));
+    assert_eq!(integer(Wrap).init_str("+").unwrap().unContinue().last_str(""), Err(MissingDigits));
+    let parsing = integer(Checked).init_str("92233720368").unwrap().unContinue();
+    assert_eq!(parsing.last_str("54775807"), Ok(Ok(i64::MAX)));
+    testing::assert_chunk_invariant(integer(Saturate), "-123456789012345678901234567890!");
+    testing::assert_chunk_invariant(integer(Wrap), "123456789012345678901234567890!");
+}
+
+#[cfg(feature = "num-bigint")]
+#[test]
+fn test_integer_widen() {
+    use super::super::UncommittedStr;
+    assert_eq!(integer(Widen).init_str("-12;").unwrap().unDone(), Ok(Widened::Small(-12)));
+    let big = "-123456789012345678901234567890";
+    assert_eq!(integer(Widen).init_str("-123456789012345678901234567890;").unwrap().unDone(), Ok(Widened::Big(big.parse().unwrap())));
+}
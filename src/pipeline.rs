@@ -0,0 +1,608 @@
+//! Pipelines of input layers feeding a grammar.
+//!
+//! Input often needs preparing before it is parsed: it may be compressed, it arrives as bytes
+//! rather than text, and its line endings may vary. A `Layer` transforms chunks of input
+//! one stage at a time, keeping any data which is split across chunks, such as part of a
+//! UTF-8 sequence. A `PipelineBuilder` stacks layers, and then builds a `Pipeline`
+//! which feeds their output to a `Session`, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::pipeline::PipelineBuilder;
+//! let line = character(|ch| ch != '\n').star(String::new)
+//!     .and_then_discard(character(|ch| ch == '\n').opt());
+//! let mut pipeline = PipelineBuilder::bytes()
+//!     .decode_utf8()
+//!     .normalize_newlines()
+//!     .parse(line);
+//! assert_eq!(pipeline.push(b"caf\xc3".to_vec()).unwrap(), Vec::<String>::new());
+//! assert_eq!(pipeline.push(b"\xa9\r".to_vec()).unwrap(), Vec::<String>::new());
+//! assert_eq!(pipeline.push(b"\nbar\r".to_vec()).unwrap(), vec!["caf\u{e9}"]);
+//! assert_eq!(pipeline.finish().unwrap(), vec!["bar"]);
+//! ```
+//!
+//! Bytes which are run-length or delta encoded, as is common in telemetry formats, are decoded
+//! with `decode_run_length()` and `decode_delta(distance)`, or `decode_delta_with_width(distance, width)`
+//! for integers wider than a byte.
+//! Layers of other kinds, such as decompression, are added with `builder.layer(layer)`.
+//! However many layers there are, the type of a pipeline only depends on the type of its
+//! input chunks and of the grammar's output. Positions in errors from the grammar are
+//! counted in the text it is given, after every layer has been applied.
+
+use super::Erasable;
+use super::session::{self, Session};
+use super::testing::Recording;
+
+use std::cell::RefCell;
+use std::error;
+use std::fmt;
+use std::marker::PhantomData;
+use std::mem;
+use std::rc::Rc;
+use std::str;
+use std::fmt::{Display, Formatter};
+
+/// The error returned by a pipeline.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// A layer could not transform its input.
+    Layer(String),
+    /// The grammar rejected its input.
+    Parse(session::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Layer(ref message) => write!(fmt, "{}", message),
+            Error::Parse(ref err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+/// A stage of a pipeline, which transforms chunks of input.
+
+pub trait Layer {
+    /// The type of input chunks.
+    type Input;
+    /// The type of output chunks.
+    type Output;
+
+    /// Transform a chunk, keeping any part of it which cannot be transformed yet.
+    fn push(&mut self, chunk: Self::Input) -> Result<Self::Output, Error>;
+
+    /// End the input, returning the chunks which were kept.
+    fn finish(&mut self) -> Result<Vec<Self::Output>, Error>;
+}
+
+/// A layer which passes its input through unchanged.
+
+#[derive(Debug)]
+pub struct Identity<T>(PhantomData<T>);
+
+impl<T> Layer for Identity<T> {
+    type Input = T;
+    type Output = T;
+
+    fn push(&mut self, chunk: T) -> Result<T, Error> {
+        Ok(chunk)
+    }
+
+    fn finish(&mut self) -> Result<Vec<T>, Error> {
+        Ok(Vec::new())
+    }
+}
+
+/// A layer which runs one layer, then another.
+
+#[derive(Debug)]
+pub struct Then<A, B>(A, B);
+
+impl<A, B> Layer for Then<A, B>
+    where A: Layer,
+          B: Layer<Input = A::Output>,
+{
+    type Input = A::Input;
+    type Output = B::Output;
+
+    fn push(&mut self, chunk: A::Input) -> Result<B::Output, Error> {
+        let chunk = try!(self.0.push(chunk));
+        self.1.push(chunk)
+    }
+
+    fn finish(&mut self) -> Result<Vec<B::Output>, Error> {
+        let mut chunks = Vec::new();
+        for chunk in try!(self.0.finish()) {
+            chunks.push(try!(self.1.push(chunk)));
+        }
+        chunks.extend(try!(self.1.finish()));
+        Ok(chunks)
+    }
+}
+
+/// A layer which decodes UTF-8, including characters which are split across chunks.
+///
+/// If the input is not valid UTF-8, the buffered bytes and the rest of the chunk are discarded,
+/// so that decoding can resume with the next chunk.
+
+#[derive(Debug, Default)]
+pub struct Utf8 {
+    partial: Vec<u8>,
+    position: usize,
+}
+
+impl Layer for Utf8 {
+    type Input = Vec<u8>;
+    type Output = String;
+
+    fn push(&mut self, chunk: Vec<u8>) -> Result<String, Error> {
+        self.partial.extend_from_slice(&chunk);
+        let valid = match str::from_utf8(&self.partial) {
+            Ok(string) => string.len(),
+            Err(err) => match err.error_len() {
+                None => err.valid_up_to(),
+                Some(_) => {
+                    let position = self.position + err.valid_up_to();
+                    self.position += self.partial.len();
+                    self.partial.clear();
+                    return Err(Error::Layer(format!("Invalid UTF-8 at byte {}", position)));
+                },
+            },
+        };
+        let rest = self.partial.split_off(valid);
+        let text = mem::replace(&mut self.partial, rest);
+        self.position += valid;
+        Ok(String::from_utf8(text).expect("valid UTF-8"))
+    }
+
+    fn finish(&mut self) -> Result<Vec<String>, Error> {
+        if self.partial.is_empty() {
+            Ok(Vec::new())
+        } else {
+            Err(Error::Layer(format!("Incomplete UTF-8 at byte {}", self.position)))
+        }
+    }
+}
+
+/// A layer which replaces `\r\n` and `\r` line endings by `\n`.
+
+#[derive(Debug, Default)]
+pub struct Newlines {
+    // Whether the last chunk ended with `\r`, which may be followed by `\n`.
+    pending: bool,
+}
+
+impl Layer for Newlines {
+    type Input = String;
+    type Output = String;
+
+    fn push(&mut self, chunk: String) -> Result<String, Error> {
+        if !self.pending && !chunk.contains('\r') {
+            return Ok(chunk);
+        }
+        let mut result = String::with_capacity(chunk.len() + 1);
+        for ch in chunk.chars() {
+            if mem::replace(&mut self.pending, false) {
+                result.push('\n');
+                if ch == '\n' {
+                    continue;
+                }
+            }
+            if ch == '\r' {
+                self.pending = true;
+            } else {
+                result.push(ch);
+            }
+        }
+        Ok(result)
+    }
+
+    fn finish(&mut self) -> Result<Vec<String>, Error> {
+        if mem::replace(&mut self.pending, false) {
+            Ok(vec![String::from("\n")])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
+
+/// A layer which decodes run-length encoded bytes.
+///
+/// The input is pairs of a count followed by a byte, which is repeated count times.
+
+#[derive(Debug, Default)]
+pub struct RunLength {
+    // The count of a pair which was split across chunks.
+    count: Option<u8>,
+    position: usize,
+}
+
+impl Layer for RunLength {
+    type Input = Vec<u8>;
+    type Output = Vec<u8>;
+
+    fn push(&mut self, chunk: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::with_capacity(chunk.len());
+        for byte in chunk {
+            match self.count.take() {
+                None => self.count = Some(byte),
+                Some(count) => result.extend((0..count).map(|_| byte)),
+            }
+            self.position += 1;
+        }
+        Ok(result)
+    }
+
+    fn finish(&mut self) -> Result<Vec<Vec<u8>>, Error> {
+        match self.count.take() {
+            None => Ok(Vec::new()),
+            Some(_) => Err(Error::Layer(format!("Incomplete run at byte {}", self.position - 1))),
+        }
+    }
+}
+
+/// The byte order of integers wider than a byte.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ByteOrder {
+    /// The least significant byte first.
+    LittleEndian,
+    /// The most significant byte first.
+    BigEndian,
+}
+
+/// The integers which are delta encoded.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Width {
+    /// Bytes.
+    U8,
+    /// Integers of two bytes.
+    U16(ByteOrder),
+    /// Integers of four bytes.
+    U32(ByteOrder),
+    /// Integers of eight bytes.
+    U64(ByteOrder),
+}
+
+impl Width {
+    // The number of bytes in an integer.
+    fn size(self) -> usize {
+        match self {
+            Width::U8 => 1,
+            Width::U16(_) => 2,
+            Width::U32(_) => 4,
+            Width::U64(_) => 8,
+        }
+    }
+
+    fn order(self) -> ByteOrder {
+        match self {
+            Width::U8 => ByteOrder::LittleEndian,
+            Width::U16(order) | Width::U32(order) | Width::U64(order) => order,
+        }
+    }
+
+    fn read(self, bytes: &[u8]) -> u64 {
+        match self.order() {
+            ByteOrder::LittleEndian => bytes.iter().rev().fold(0, |value, &byte| (value << 8) | byte as u64),
+            ByteOrder::BigEndian => bytes.iter().fold(0, |value, &byte| (value << 8) | byte as u64),
+        }
+    }
+
+    fn write(self, value: u64, bytes: &mut [u8]) {
+        let size = bytes.len();
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            let shift = match self.order() {
+                ByteOrder::LittleEndian => index,
+                ByteOrder::BigEndian => size - 1 - index,
+            };
+            *byte = (value >> (8 * shift)) as u8;
+        }
+    }
+}
+
+/// A layer which decodes delta encoded integers.
+///
+/// Each integer of the output is the sum of the integer of the input and the integer of the output
+/// `distance` integers before it (or zero), wrapping on overflow. A distance of one decodes
+/// a sequence of integers, and the number of integers in a record decodes records of fixed size.
+/// By default the integers are bytes, and wider integers are decoded by `Delta::with_width`.
+
+#[derive(Debug)]
+pub struct Delta {
+    width: Width,
+    // The last `distance` integers of output, as a ring buffer.
+    previous: Vec<u64>,
+    index: usize,
+    // The bytes of an integer which was split across chunks.
+    partial: Vec<u8>,
+    position: usize,
+}
+
+impl Delta {
+    /// A layer which decodes deltas of bytes from the byte `distance` bytes before.
+    ///
+    /// # Panics
+    ///
+    /// If `distance` is zero.
+    pub fn new(distance: usize) -> Self {
+        Delta::with_width(distance, Width::U8)
+    }
+
+    /// A layer which decodes deltas of integers of the given width, from the integer
+    /// `distance` integers before.
+    ///
+    /// # Panics
+    ///
+    /// If `distance` is zero.
+    pub fn with_width(distance: usize, width: Width) -> Self {
+        assert!(distance > 0, "Delta distance must be positive.");
+        Delta {
+            width,
+            previous: vec![0; distance],
+            index: 0,
+            partial: Vec::new(),
+            position: 0,
+        }
+    }
+}
+
+impl Layer for Delta {
+    type Input = Vec<u8>;
+    type Output = Vec<u8>;
+
+    fn push(&mut self, chunk: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let size = self.width.size();
+        let mut result = if self.partial.is_empty() {
+            chunk
+        } else {
+            let mut result = mem::take(&mut self.partial);
+            result.extend_from_slice(&chunk);
+            result
+        };
+        let complete = result.len() - result.len() % size;
+        self.partial = result.split_off(complete);
+        let mask = if size == 8 { !0 } else { (1 << (8 * size)) - 1 };
+        for integer in result.chunks_mut(size) {
+            let value = self.width.read(integer).wrapping_add(self.previous[self.index]) & mask;
+            self.width.write(value, integer);
+            self.previous[self.index] = value;
+            self.index = (self.index + 1) % self.previous.len();
+        }
+        self.position += complete;
+        Ok(result)
+    }
+
+    fn finish(&mut self) -> Result<Vec<Vec<u8>>, Error> {
+        if self.partial.is_empty() {
+            Ok(Vec::new())
+        } else {
+            Err(Error::Layer(format!("Incomplete integer at byte {}", self.position)))
+        }
+    }
+}
+
+/// A builder for pipelines, which adds one layer at a time.
+
+#[derive(Debug)]
+pub struct PipelineBuilder<L>(L, Option<Rc<RefCell<Recording>>>);
+
+impl PipelineBuilder<Identity<Vec<u8>>> {
+    /// A builder for a pipeline whose input is chunks of bytes.
+    pub fn bytes() -> Self {
+        PipelineBuilder(Identity(PhantomData), None)
+    }
+}
+
+impl PipelineBuilder<Identity<String>> {
+    /// A builder for a pipeline whose input is chunks of text.
+    pub fn text() -> Self {
+        PipelineBuilder(Identity(PhantomData), None)
+    }
+}
+
+impl<L> PipelineBuilder<L> where L: Layer {
+    /// Add a layer, whose input is the output of the layers so far.
+    pub fn layer<M>(self, layer: M) -> PipelineBuilder<Then<L, M>>
+        where M: Layer<Input = L::Output>,
+    {
+        PipelineBuilder(Then(self.0, layer), self.1)
+    }
+
+    /// Decode bytes as UTF-8.
+    pub fn decode_utf8(self) -> PipelineBuilder<Then<L, Utf8>>
+        where L: Layer<Output = Vec<u8>>,
+    {
+        self.layer(Utf8::default())
+    }
+
+    /// Decode run-length encoded bytes.
+    pub fn decode_run_length(self) -> PipelineBuilder<Then<L, RunLength>>
+        where L: Layer<Output = Vec<u8>>,
+    {
+        self.layer(RunLength::default())
+    }
+
+    /// Decode delta encoded bytes, whose deltas are from the byte `distance` bytes before.
+    pub fn decode_delta(self, distance: usize) -> PipelineBuilder<Then<L, Delta>>
+        where L: Layer<Output = Vec<u8>>,
+    {
+        self.layer(Delta::new(distance))
+    }
+
+    /// Decode delta encoded integers of the given width, whose deltas are from the integer
+    /// `distance` integers before.
+    pub fn decode_delta_with_width(self, distance: usize, width: Width) -> PipelineBuilder<Then<L, Delta>>
+        where L: Layer<Output = Vec<u8>>,
+    {
+        self.layer(Delta::with_width(distance, width))
+    }
+
+    /// Replace `\r\n` and `\r` line endings by `\n`.
+    pub fn normalize_newlines(self) -> PipelineBuilder<Then<L, Newlines>>
+        where L: Layer<Output = String>,
+    {
+        self.layer(Newlines::default())
+    }
+
+    /// Record the text produced by the layers, as it is fed to the grammar.
+    ///
+    /// The recording can be replayed with `Recording::replay_session`,
+    /// which reproduces the run without the layers.
+    pub fn record(self, recording: Rc<RefCell<Recording>>) -> Self {
+        PipelineBuilder(self.0, Some(recording))
+    }
+
+    /// Build a pipeline which runs `parser` repeatedly on the text produced by the layers.
+    pub fn parse<P, Output>(self, parser: P) -> Pipeline<L::Input, Output>
+        where L: 'static + Layer<Output = String>,
+              P: 'static + Erasable<Output>,
+    {
+        let session = match self.1 {
+            Some(recording) => Session::new(parser).record(recording),
+            None => Session::new(parser),
+        };
+        Pipeline {
+            layers: Box::new(self.0),
+            session,
+        }
+    }
+}
+
+/// A pipeline, which passes chunks of input through its layers, then parses them.
+
+pub struct Pipeline<Input, Output> {
+    layers: Box<Layer<Input = Input, Output = String>>,
+    session: Session<Output>,
+}
+
+impl<Input, Output> Pipeline<Input, Output> {
+    /// Feed a chunk of input to the pipeline, returning the outputs it completed.
+    ///
+    /// Like `Session::push`, if the input is rejected, the error is returned along with
+    /// the outputs completed before it.
+    pub fn push(&mut self, chunk: Input) -> Result<Vec<Output>, (Vec<Output>, Error)> {
+        let text = try!(self.layers.push(chunk).map_err(|err| (Vec::new(), err)));
+        self.session.push(&text).map_err(|(outputs, err)| (outputs, Error::Parse(err)))
+    }
+
+    /// End the input, returning the outputs completed by the rest of it.
+    pub fn finish(mut self) -> Result<Vec<Output>, (Vec<Output>, Error)> {
+        let mut outputs = Vec::new();
+        let texts = match self.layers.finish() {
+            Ok(texts) => texts,
+            Err(err) => return Err((outputs, err)),
+        };
+        for text in texts {
+            match self.session.push(&text) {
+                Ok(completed) => outputs.extend(completed),
+                Err((completed, err)) => {
+                    outputs.extend(completed);
+                    return Err((outputs, Error::Parse(err)));
+                },
+            }
+        }
+        outputs.extend(self.session.finish());
+        Ok(outputs)
+    }
+
+    /// The number of bytes of text parsed so far.
+    pub fn position(&self) -> usize {
+        self.session.position()
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_pipeline() {
+    use super::{character, Parser};
+    let ALPHABETIC = character(char::is_alphabetic);
+    let SPACE = character(|ch| ch == ' ' || ch == '\n');
+    let word = ALPHABETIC.plus(String::new).and_then_discard(SPACE.opt());
+    struct Upper;
+    impl Layer for Upper {
+        type Input = String;
+        type Output = String;
+        fn push(&mut self, chunk: String) -> Result<String, Error> { Ok(chunk.to_uppercase()) }
+        fn finish(&mut self) -> Result<Vec<String>, Error> { Ok(Vec::new()) }
+    }
+    let mut pipeline = PipelineBuilder::text().layer(Upper).parse(word);
+    assert_eq!(pipeline.push(String::from("ab c")).unwrap(), vec!["AB"]);
+    assert_eq!(pipeline.position(), 4);
+    assert_eq!(pipeline.finish().unwrap(), vec!["C"]);
+    let mut pipeline = PipelineBuilder::bytes().decode_utf8().parse(word);
+    assert_eq!(pipeline.push(b"ab \xff".to_vec()), Err((vec![], Error::Layer(String::from("Invalid UTF-8 at byte 3")))));
+    assert_eq!(pipeline.push(b"cd \xc3".to_vec()).unwrap(), vec!["cd"]);
+    assert_eq!(pipeline.push(b"\xa9".to_vec()).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.push(b"\xfe".to_vec()), Err((vec![], Error::Layer(String::from("Invalid UTF-8 at byte 9")))));
+    assert_eq!(pipeline.finish().unwrap(), vec!["\u{e9}"]);
+    let mut pipeline = PipelineBuilder::bytes().decode_utf8().parse(word);
+    assert_eq!(pipeline.push(b"ab\xc3".to_vec()).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.finish(), Err((vec![], Error::Layer(String::from("Incomplete UTF-8 at byte 2")))));
+    let mut pipeline = PipelineBuilder::text().normalize_newlines().parse(SPACE.star(String::new));
+    assert_eq!(pipeline.push(String::from("\r")).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.push(String::from("\n \r\r")).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.finish().unwrap(), vec!["\n \n\n"]);
+    let mut pipeline = PipelineBuilder::text().parse(word);
+    assert_eq!(pipeline.push(String::from("a!")), Err((vec![String::from("a")], Error::Parse(session::Error { position: 1 }))));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_pipeline_record() {
+    use super::{character, Parser};
+    let ALPHABETIC = character(char::is_alphabetic);
+    let word = ALPHABETIC.plus(String::new).and_then_discard(character(|ch| ch == ' ').opt());
+    let recording = Rc::new(RefCell::new(Recording::new()));
+    let mut pipeline = PipelineBuilder::bytes().decode_utf8().record(recording.clone()).parse(word);
+    assert_eq!(pipeline.push(b"caf\xc3".to_vec()).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.push(b"\xa9 ab".to_vec()).unwrap(), vec!["caf\u{e9}"]);
+    assert_eq!(pipeline.finish().unwrap(), vec!["ab"]);
+    assert_eq!(recording.borrow().chunks(), ["caf", "\u{e9} ab"]);
+    assert_eq!(recording.borrow().replay_session(word).unwrap(), vec!["caf\u{e9}", "ab"]);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_run_length_and_delta() {
+    use super::{character, Parser};
+    let LINE = character(|ch| ch != '\n').star(String::new).and_then_discard(character(|ch| ch == '\n').opt());
+    let mut pipeline = PipelineBuilder::bytes().decode_run_length().decode_utf8().parse(LINE);
+    assert_eq!(pipeline.push(b"\x03a\x00b\x01".to_vec()).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.push(b"\n\x02c".to_vec()).unwrap(), vec!["aaa"]);
+    assert_eq!(pipeline.finish().unwrap(), vec!["cc"]);
+    let mut pipeline = PipelineBuilder::bytes().decode_run_length().decode_utf8().parse(LINE);
+    assert_eq!(pipeline.push(b"\x01a\x02".to_vec()).unwrap(), Vec::<String>::new());
+    assert_eq!(pipeline.finish(), Err((vec![], Error::Layer(String::from("Incomplete run at byte 2")))));
+    let mut delta = Delta::new(1);
+    assert_eq!(delta.push(vec![b'a', 1, 1]).unwrap(), b"abc");
+    assert_eq!(delta.push(vec![0xff, 0]).unwrap(), b"bb");
+    let mut delta = Delta::new(2);
+    assert_eq!(delta.push(vec![1, 10, 1]).unwrap(), vec![1, 10, 2]);
+    assert_eq!(delta.push(vec![0xff, 1]).unwrap(), vec![9, 3]);
+    let mut pipeline = PipelineBuilder::bytes().decode_delta(1).decode_utf8().parse(LINE);
+    assert_eq!(pipeline.push(vec![b'a', 1, 0xa8]).unwrap(), vec!["ab"]);
+    assert_eq!(pipeline.finish().unwrap(), Vec::<String>::new());
+}
+
+#[test]
+fn test_delta_width() {
+    let mut delta = Delta::with_width(1, Width::U16(ByteOrder::LittleEndian));
+    assert_eq!(delta.push(vec![0xff, 0x00, 0x01]).unwrap(), vec![0xff, 0x00]);
+    assert_eq!(delta.push(vec![0x00, 0x02, 0x00]).unwrap(), vec![0x00, 0x01, 0x02, 0x01]);
+    assert_eq!(delta.push(vec![0xfe, 0xff]).unwrap(), vec![0x00, 0x01]);
+    assert_eq!(delta.finish().unwrap(), Vec::<Vec<u8>>::new());
+    let mut delta = Delta::with_width(2, Width::U32(ByteOrder::BigEndian));
+    assert_eq!(delta.push(vec![0, 0, 1, 0, 0, 0, 0, 7, 0, 0, 0, 1]).unwrap(), vec![0, 0, 1, 0, 0, 0, 0, 7, 0, 0, 1, 1]);
+    assert_eq!(delta.push(vec![0xff, 0xff, 0xff, 0xff, 0]).unwrap(), vec![0, 0, 0, 6]);
+    assert_eq!(delta.finish(), Err(Error::Layer(String::from("Incomplete integer at byte 16"))));
+    let mut delta = Delta::with_width(1, Width::U64(ByteOrder::LittleEndian));
+    assert_eq!(delta.push(vec![0xff; 8]).unwrap(), vec![0xff; 8]);
+    assert_eq!(delta.push(vec![1, 0, 0, 0, 0, 0, 0, 0]).unwrap(), vec![0; 8]);
+}
@@ -0,0 +1,208 @@
+//! Parsing streams of records, recovering from malformed records.
+//!
+//! A `Recover` runs a record parser repeatedly over chunks of input, like a `Session`,
+//! and produces an iterator of its outputs. When the parser rejects a record, rather than
+//! ending the stream, the rest of the record is skipped up to and including the next record
+//! delimiter, an error is produced in place of the record, and parsing carries on with the
+//! next record. One malformed line of a huge log does not stop the rest being read, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::recover::{Recover,RecordError};
+//! let number = character(char::is_numeric).plus(String::new)
+//!     .and_then_discard(character(|ch| ch == '\n').opt());
+//! let records: Vec<_> = Recover::new(number, '\n', vec!["12\nx", "y\n3", "4\n"]).collect();
+//! assert_eq!(records, vec![
+//!     Ok(String::from("12")),
+//!     Err(RecordError { start: 3, end: 6 }),
+//!     Ok(String::from("34")),
+//! ]);
+//! ```
+//!
+//! Only rejected input is skipped: a parser which stops part way through a record
+//! is run again on the rest of it. Parsers which succeed without consuming input are
+//! treated as rejecting it, since otherwise they would succeed forever.
+
+use super::{Erasable, BoxedState, Stateful};
+use super::ParseResult::{Done, Continue};
+
+use std::error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+
+/// The error produced in place of a record which the parser rejected.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RecordError {
+    /// The byte position of the rejected input, counted from the start of the stream.
+    pub start: usize,
+    /// The byte position after the skipped input, including the delimiter.
+    pub end: usize,
+}
+
+impl Display for RecordError {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "Malformed record at bytes {}..{}", self.start, self.end)
+    }
+}
+
+impl error::Error for RecordError {}
+
+/// An iterator over the records parsed from chunks of input, with errors for malformed records.
+
+pub struct Recover<Output, I> {
+    parser: Box<Erasable<Output>>,
+    state: Option<BoxedState<Output>>,
+    delimiter: char,
+    chunks: I,
+    chunk: String,
+    // The byte position in the current chunk.
+    offset: usize,
+    // The byte position of the start of the current chunk.
+    position: usize,
+    // The start of the rejected input being skipped.
+    skipping: Option<usize>,
+    finished: bool,
+}
+
+impl<Output, I> Recover<Output, I>
+    where I: Iterator,
+          I::Item: Into<String>,
+{
+    /// Parse the records in `chunks` by running `parser` repeatedly,
+    /// skipping to after the next `delimiter` when it rejects its input.
+    pub fn new<P, J>(parser: P, delimiter: char, chunks: J) -> Self
+        where P: 'static + Erasable<Output>,
+              J: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
+        Recover {
+            parser: Box::new(parser),
+            state: None,
+            delimiter,
+            chunks: chunks.into_iter(),
+            chunk: String::new(),
+            offset: 0,
+            position: 0,
+            skipping: None,
+            finished: false,
+        }
+    }
+
+    /// The number of bytes of input consumed so far.
+    pub fn position(&self) -> usize {
+        self.position + self.offset
+    }
+
+    // Skip the rest of a rejected record, returning whether the delimiter was found.
+    fn skip(&mut self) -> bool {
+        let rest = &self.chunk[self.offset..];
+        match rest.find(self.delimiter) {
+            Some(index) => {
+                self.offset += index + self.delimiter.len_utf8();
+                true
+            },
+            None => {
+                self.offset = self.chunk.len();
+                false
+            },
+        }
+    }
+
+    // Run the parser on the current chunk until it completes an output or rejects its input.
+    fn step(&mut self) -> Option<Result<Output, RecordError>> {
+        let mut data = self.chunk[self.offset..].chars();
+        while !data.as_str().is_empty() {
+            let before = data.as_str().len();
+            let output = match self.state.take() {
+                Some(state) => match state.more(&mut data) {
+                    Done(output) => Some(output),
+                    Continue(state) => {
+                        self.state = Some(state);
+                        None
+                    },
+                },
+                None => match self.parser.init_erasable(&mut data) {
+                    Some(Done(output)) if data.as_str().len() < before => Some(output),
+                    Some(Continue(state)) => {
+                        self.state = Some(state);
+                        None
+                    },
+                    _ => {
+                        self.skipping = Some(self.position + self.offset);
+                        return None;
+                    },
+                },
+            };
+            self.offset += before - data.as_str().len();
+            if let Some(output) = output {
+                return Some(Ok(output));
+            }
+        }
+        None
+    }
+}
+
+impl<Output, I> Iterator for Recover<Output, I>
+    where I: Iterator,
+          I::Item: Into<String>,
+{
+    type Item = Result<Output, RecordError>;
+
+    fn next(&mut self) -> Option<Result<Output, RecordError>> {
+        while !self.finished {
+            if self.offset == self.chunk.len() {
+                match self.chunks.next() {
+                    Some(chunk) => {
+                        self.position += self.chunk.len();
+                        self.chunk = chunk.into();
+                        self.offset = 0;
+                    },
+                    None => {
+                        self.finished = true;
+                        if let Some(start) = self.skipping.take() {
+                            return Some(Err(RecordError { start, end: self.position + self.offset }));
+                        }
+                        return self.state.take().map(|state| Ok(state.done()));
+                    },
+                }
+            } else if let Some(start) = self.skipping {
+                if self.skip() {
+                    self.skipping = None;
+                    return Some(Err(RecordError { start, end: self.position + self.offset }));
+                }
+            } else if let Some(result) = self.step() {
+                return Some(result);
+            }
+        }
+        None
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_recover() {
+    use super::{character, Parser};
+    fn ignore() {}
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let SEMICOLON = character(|ch| ch == ';');
+    let word = ALPHANUMERIC.plus(String::new).and_then_discard(SEMICOLON.star(ignore));
+    let records: Vec<_> = Recover::new(word, ';', vec!["ab;!", "", "?;", "c\u{e9}", "d;", "!!"]).collect();
+    assert_eq!(records, vec![
+        Ok(String::from("ab")),
+        Err(RecordError { start: 3, end: 6 }),
+        Ok(String::from("c\u{e9}d")),
+        Err(RecordError { start: 11, end: 13 }),
+    ]);
+    let mut records = Recover::new(word, ';', vec!["ab", "c"]);
+    assert_eq!(records.next(), Some(Ok(String::from("abc"))));
+    assert_eq!(records.position(), 3);
+    assert_eq!(records.next(), None);
+    assert_eq!(records.next(), None);
+    let mut records = Recover::new(SEMICOLON.star(String::new), ';', vec!["x;;"]);
+    assert_eq!(records.next(), Some(Err(RecordError { start: 0, end: 2 })));
+    assert_eq!(records.next(), Some(Ok(String::from(";"))));
+    assert_eq!(records.next(), None);
+    assert_eq!(Recover::new(word, ';', Vec::<String>::new()).next(), None);
+}
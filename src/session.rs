@@ -0,0 +1,293 @@
+//! Parsing sessions which are fed chunks of input.
+//!
+//! A `Session` runs a parser repeatedly over chunks of input, returning every output
+//! which was completed by each chunk, and keeping any output which is split across
+//! chunks as suspended state. Since the type of the parser is erased, sessions have
+//! a simple type, which makes them suitable for exposing to code outside Rust,
+//! for example:
+//!
+//! ```
+//! # use parsell::{character,Parser};
+//! # use parsell::session::Session;
+//! fn ignore() {}
+//! let word = character(char::is_alphanumeric).plus(String::new)
+//!     .and_then_discard(character(char::is_whitespace).star(ignore));
+//! let mut session = Session::new(word);
+//! assert_eq!(session.push("abc de").unwrap(), vec!["abc"]);
+//! assert_eq!(session.push("f gh").unwrap(), vec!["def"]);
+//! assert_eq!(session.finish(), Some(String::from("gh")));
+//! ```
+
+use super::{Erasable, BoxedState, Stateful};
+use super::ParseResult::{Done, Continue};
+use super::testing::Recording;
+
+use std::cell::RefCell;
+use std::error;
+use std::fmt;
+use std::rc::Rc;
+use std::str::Chars;
+use std::fmt::{Display, Formatter};
+
+/// The error returned when a session's parser rejects its input.
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Error {
+    /// The byte position of the rejected input, counted from the start of the session.
+    pub position: usize,
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        write!(fmt, "Unexpected input at byte {}", self.position)
+    }
+}
+
+impl error::Error for Error {}
+
+// The loop shared by sessions and the other drivers which run a parser over chunks of input:
+// it resumes the suspended state if there is one, and otherwise starts the parser again.
+
+pub(crate) struct Driver<Output> {
+    state: Option<BoxedState<Output>>,
+    position: usize,
+}
+
+impl<Output> Driver<Output> {
+    pub(crate) fn new() -> Self {
+        Driver {
+            state: None,
+            position: 0,
+        }
+    }
+
+    // The number of bytes of input consumed so far.
+    pub(crate) fn position(&self) -> usize {
+        self.position
+    }
+
+    // Whether the parser is waiting for more input.
+    pub(crate) fn is_waiting(&self) -> bool {
+        self.state.is_some()
+    }
+
+    // Run the parser on the start of `data`, returning its output if it completes one,
+    // or the position of the input if the parser rejects it. A parser which succeeds
+    // without consuming input is treated as rejecting it.
+    pub(crate) fn step<P>(&mut self, parser: &P, data: &mut Chars) -> Result<Option<Output>, usize>
+        where P: ?Sized + Erasable<Output>,
+    {
+        let before = data.as_str().len();
+        let output = match self.state.take() {
+            Some(state) => match state.more(data) {
+                Done(output) => Some(output),
+                Continue(state) => {
+                    self.state = Some(state);
+                    None
+                },
+            },
+            None => match parser.init_erasable(data) {
+                Some(Done(output)) if data.as_str().len() < before => Some(output),
+                Some(Continue(state)) => {
+                    self.state = Some(state);
+                    None
+                },
+                _ => return Err(self.position),
+            },
+        };
+        self.position += before - data.as_str().len();
+        Ok(output)
+    }
+
+    // End the input, completing any output which is waiting for more input.
+    pub(crate) fn finish(&mut self) -> Option<Output> {
+        self.state.take().map(Stateful::done)
+    }
+}
+
+/// A parsing session, which runs a parser repeatedly over chunks of input.
+
+pub struct Session<Output> {
+    parser: Box<Erasable<Output>>,
+    driver: Driver<Output>,
+    consumed: usize,
+    recording: Option<Rc<RefCell<Recording>>>,
+}
+
+impl<Output> Session<Output> {
+    /// A session which runs `parser` repeatedly.
+    pub fn new<P>(parser: P) -> Self where P: 'static + Erasable<Output> {
+        Session {
+            parser: Box::new(parser),
+            driver: Driver::new(),
+            consumed: 0,
+            recording: None,
+        }
+    }
+
+    /// Record every chunk of input fed to the session, for example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser};
+    /// # use parsell::session::Session;
+    /// # use parsell::testing::Recording;
+    /// # use std::cell::RefCell;
+    /// # use std::rc::Rc;
+    /// let word = character(char::is_alphanumeric).plus(String::new);
+    /// let recording = Rc::new(RefCell::new(Recording::new()));
+    /// let mut session = Session::new(word).record(recording.clone());
+    /// session.push("ab").unwrap();
+    /// session.push("c").unwrap();
+    /// assert_eq!(session.finish(), Some(String::from("abc")));
+    /// assert_eq!(recording.borrow().chunks(), ["ab", "c"]);
+    /// ```
+    ///
+    /// The recording can be written to a file with `Recording::write_to`,
+    /// and the session reproduced with `Recording::replay_session`.
+    pub fn record(mut self, recording: Rc<RefCell<Recording>>) -> Self {
+        self.recording = Some(recording);
+        self
+    }
+
+    /// The number of bytes of input consumed so far.
+    pub fn position(&self) -> usize {
+        self.driver.position()
+    }
+
+    /// The number of characters of input consumed so far.
+    pub fn consumed(&self) -> usize {
+        self.consumed
+    }
+
+    /// Feed a chunk of input to the session, returning the outputs it completed.
+    ///
+    /// If the parser rejects the input, the rest of the chunk is discarded, and the error
+    /// is returned along with the outputs completed earlier in the chunk.
+    /// Parsers which succeed without consuming input are treated as rejecting it,
+    /// since otherwise they would succeed forever.
+    pub fn push(&mut self, chunk: &str) -> Result<Vec<Output>, (Vec<Output>, Error)> {
+        if let Some(ref recording) = self.recording {
+            recording.borrow_mut().record(chunk);
+        }
+        let mut outputs = Vec::new();
+        let mut chunk = chunk;
+        while !chunk.is_empty() {
+            match self.step(chunk) {
+                Ok((output, rest)) => {
+                    outputs.extend(output);
+                    chunk = rest;
+                },
+                Err(err) => return Err((outputs, err)),
+            }
+        }
+        Ok(outputs)
+    }
+
+    /// Feed a chunk of input to the session until it completes an output,
+    /// returning the output and the unconsumed remainder of the chunk.
+    ///
+    /// If the chunk ends before an output is complete, the result is `None` and an empty remainder.
+    /// For example:
+    ///
+    /// ```
+    /// # use parsell::{character,Parser};
+    /// # use parsell::session::Session;
+    /// let mut session = Session::new(character(char::is_alphabetic).plus(String::new));
+    /// assert_eq!(session.push_once("ab").unwrap(), (None, ""));
+    /// assert_eq!(session.push_once("c\u{e9}!rest").unwrap(), (Some(String::from("abc\u{e9}")), "!rest"));
+    /// assert_eq!((session.position(), session.consumed()), (5, 4));
+    /// ```
+    ///
+    /// If the session is recording, only the consumed part of the chunk is recorded,
+    /// since the remainder is returned to the caller.
+    pub fn push_once<'a>(&mut self, chunk: &'a str) -> Result<(Option<Output>, &'a str), Error> {
+        let result = self.step(chunk);
+        if let Some(ref recording) = self.recording {
+            match result {
+                Ok((_, rest)) => recording.borrow_mut().record(&chunk[..(chunk.len() - rest.len())]),
+                Err(_) => recording.borrow_mut().record(chunk),
+            };
+        }
+        result
+    }
+
+    // Feed a chunk of input to the parser until it completes an output, without recording it.
+    fn step<'a>(&mut self, chunk: &'a str) -> Result<(Option<Output>, &'a str), Error> {
+        let mut data = chunk.chars();
+        while !data.as_str().is_empty() {
+            let before = data.as_str();
+            let output = match self.driver.step(&*self.parser, &mut data) {
+                Ok(output) => output,
+                Err(position) => return Err(Error { position }),
+            };
+            self.consumed += before[..(before.len() - data.as_str().len())].chars().count();
+            if output.is_some() {
+                return Ok((output, data.as_str()));
+            }
+        }
+        Ok((None, data.as_str()))
+    }
+
+    /// End the session, completing any output which is split across the end of the input.
+    pub fn finish(mut self) -> Option<Output> {
+        self.driver.finish()
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_session() {
+    use super::{character, Parser};
+    fn ignore() {}
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let WHITESPACE = character(char::is_whitespace);
+    let mut session = Session::new(ALPHANUMERIC.plus(String::new).and_then_discard(WHITESPACE.star(ignore)));
+    assert_eq!(session.push("").unwrap(), Vec::<String>::new());
+    assert_eq!(session.push("ab cd e").unwrap(), vec!["ab", "cd"]);
+    assert_eq!(session.push("f").unwrap(), Vec::<String>::new());
+    assert_eq!(session.push(" g!h").unwrap_err(), (vec![String::from("ef"), String::from("g")], Error { position: 10 }));
+    assert_eq!(session.position(), 10);
+    assert_eq!(session.finish(), None);
+    let session = Session::new(ALPHANUMERIC.plus(String::new));
+    assert_eq!(session.finish(), None);
+    let mut session = Session::new(WHITESPACE.star(String::new));
+    assert_eq!(session.push("a").unwrap_err(), (Vec::new(), Error { position: 0 }));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_session_remainder() {
+    use super::{character, Parser};
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let mut session = Session::new(ALPHANUMERIC.plus(String::new));
+    assert_eq!(session.push_once("").unwrap(), (None, ""));
+    assert_eq!(session.push_once("\u{e9}t\u{e9} ab").unwrap(), (Some(String::from("\u{e9}t\u{e9}")), " ab"));
+    assert_eq!((session.position(), session.consumed()), (5, 3));
+    assert_eq!(session.push_once(" ab").unwrap_err(), Error { position: 5 });
+    assert_eq!(session.push_once("ab").unwrap(), (None, ""));
+    assert_eq!(session.push_once("c;d").unwrap(), (Some(String::from("abc")), ";d"));
+    assert_eq!((session.position(), session.consumed()), (8, 6));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_session_record() {
+    use super::{character, Parser};
+    fn ignore() {}
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let WHITESPACE = character(char::is_whitespace);
+    let word = ALPHANUMERIC.plus(String::new).and_then_discard(WHITESPACE.star(ignore));
+    let recording = Rc::new(RefCell::new(Recording::new()));
+    let mut session = Session::new(word).record(recording.clone());
+    assert_eq!(session.push("ab c").unwrap(), vec!["ab"]);
+    assert_eq!(session.push("").unwrap(), Vec::<String>::new());
+    assert_eq!(session.push_once("d e").unwrap(), (Some(String::from("cd")), "e"));
+    assert_eq!(session.push("e!").unwrap_err(), (vec![String::from("e")], Error { position: 7 }));
+    assert_eq!(recording.borrow().chunks(), ["ab c", "", "d ", "e!"]);
+    let mut file = Vec::new();
+    recording.borrow().write_to(&mut file).unwrap();
+    let replayed = Recording::read_from(&file[..]).unwrap().replay_session(word);
+    assert_eq!(replayed, Err((vec![String::from("ab"), String::from("cd"), String::from("e")], Error { position: 7 })));
+}
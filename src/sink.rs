@@ -0,0 +1,232 @@
+//! Forwarding parser output into an asynchronous sink.
+//!
+//! This module is only available with the `async` feature.
+//! A `ParseSink` is a `futures::Sink` of chunks of input, which runs a parser repeatedly
+//! and forwards each output into another sink. It only accepts another chunk once every
+//! output parsed from the previous chunk has been accepted by the downstream sink,
+//! so a slow consumer throttles the parser, and the parser in turn throttles its producer.
+//! For example, with a parser for whitespace-terminated words:
+//!
+//! ```
+//! # extern crate futures;
+//! # extern crate parsell;
+//! # use futures::{executor, stream, StreamExt};
+//! # use parsell::{character,Parser};
+//! # use parsell::sink::ParseSink;
+//! fn ignore() {}
+//! let words = character(char::is_alphanumeric).plus(String::new)
+//!     .and_then_discard(character(char::is_whitespace).star(ignore));
+//! let chunks = stream::iter(vec!["ab", "c de"]).map(|chunk| Ok(String::from(chunk)));
+//! let mut sink = ParseSink::new(words, Vec::new());
+//! executor::block_on(chunks.forward(&mut sink)).unwrap();
+//! assert_eq!(sink.into_inner(), vec!["abc", "de"]);
+//! ```
+//!
+//! When the sink is closed, any output which is split across the end of the input
+//! is completed using `done()` before the downstream sink is closed.
+
+use super::Erasable;
+use super::session::Driver;
+
+use futures::Sink;
+
+use std::error;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::fmt::{Debug, Display, Formatter};
+
+/// An error produced by a `ParseSink`.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error<E> {
+    /// The parser rejected the input at the given byte position.
+    Parse(usize),
+    /// The downstream sink returned an error.
+    Sink(E),
+}
+
+impl<E> Display for Error<E> where E: Display {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
+        match *self {
+            Error::Parse(position) => write!(fmt, "Unexpected input at byte {}", position),
+            Error::Sink(ref err) => Display::fmt(err, fmt),
+        }
+    }
+}
+
+impl<E> error::Error for Error<E> where E: error::Error + Send + Sync + 'static {
+    fn source(&self) -> Option<&(error::Error + 'static)> {
+        match *self {
+            Error::Parse(_) => None,
+            Error::Sink(ref err) => Some(err),
+        }
+    }
+}
+
+/// A sink of chunks, which forwards the outputs of a parser into another sink.
+
+pub struct ParseSink<P, S, Output> {
+    parser: P,
+    sink: S,
+    chunk: String,
+    offset: usize,
+    driver: Driver<Output>,
+    pending: Option<Output>,
+}
+
+impl<P, S, Output> ParseSink<P, S, Output>
+    where P: Erasable<Output>,
+          S: Sink<Output> + Unpin,
+{
+    /// A sink which runs `parser` on its input, forwarding each output into `sink`.
+    pub fn new(parser: P, sink: S) -> Self {
+        ParseSink {
+            parser,
+            sink,
+            chunk: String::new(),
+            offset: 0,
+            driver: Driver::new(),
+            pending: None,
+        }
+    }
+
+    /// The downstream sink.
+    pub fn get_ref(&self) -> &S {
+        &self.sink
+    }
+
+    /// Consumes this sink, returning the downstream sink.
+    pub fn into_inner(self) -> S {
+        self.sink
+    }
+
+    // Parse the current chunk, forwarding outputs while the downstream sink is ready.
+    fn poll_parse(&mut self, cx: &mut Context) -> Poll<Result<(), Error<S::Error>>> {
+        loop {
+            if self.pending.is_some() {
+                match Pin::new(&mut self.sink).poll_ready(cx) {
+                    Poll::Pending => return Poll::Pending,
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(Error::Sink(err))),
+                    Poll::Ready(Ok(())) => (),
+                }
+                let output = self.pending.take().unwrap();
+                if let Err(err) = Pin::new(&mut self.sink).start_send(output) {
+                    return Poll::Ready(Err(Error::Sink(err)));
+                }
+            }
+            if self.offset == self.chunk.len() {
+                return Poll::Ready(Ok(()));
+            }
+            let mut data = self.chunk[self.offset..].chars();
+            match self.driver.step(&self.parser, &mut data) {
+                Ok(output) => self.pending = output,
+                Err(position) => return Poll::Ready(Err(Error::Parse(position))),
+            }
+            self.offset = self.chunk.len() - data.as_str().len();
+        }
+    }
+}
+
+impl<P, S, Output> Sink<String> for ParseSink<P, S, Output>
+    where P: Erasable<Output> + Unpin,
+          S: Sink<Output> + Unpin,
+          Output: Unpin,
+{
+    type Error = Error<S::Error>;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        self.get_mut().poll_parse(cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, chunk: String) -> Result<(), Self::Error> {
+        let this = self.get_mut();
+        assert!(this.offset == this.chunk.len(), "start_send called before poll_ready");
+        this.chunk = chunk;
+        this.offset = 0;
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        match this.poll_parse(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.sink).poll_flush(cx).map_err(Error::Sink),
+            result => result,
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Result<(), Self::Error>> {
+        let this = self.get_mut();
+        match this.poll_parse(cx) {
+            Poll::Ready(Ok(())) => (),
+            result => return result,
+        }
+        this.pending = this.driver.finish();
+        match this.poll_parse(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.sink).poll_close(cx).map_err(Error::Sink),
+            result => result,
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[cfg(test)]
+struct Slow {
+    received: Vec<String>,
+    capacity: usize,
+    closed: bool,
+}
+
+#[cfg(test)]
+impl Sink<String> for Slow {
+    type Error = ();
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), ()>> {
+        if self.received.len() < self.capacity { Poll::Ready(Ok(())) } else { Poll::Pending }
+    }
+    fn start_send(self: Pin<&mut Self>, item: String) -> Result<(), ()> {
+        self.get_mut().received.push(item);
+        Ok(())
+    }
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), ()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context) -> Poll<Result<(), ()>> {
+        self.get_mut().closed = true;
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_parse_sink() {
+    use super::{character, Parser};
+    use std::task::Waker;
+    fn ignore() {}
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let WHITESPACE = character(char::is_whitespace);
+    let parser = ALPHANUMERIC.plus(String::new).and_then_discard(WHITESPACE.star(ignore));
+    let slow = Slow { received: Vec::new(), capacity: 1, closed: false };
+    let mut sink = ParseSink::new(parser, slow);
+    let mut cx = Context::from_waker(Waker::noop());
+    assert_eq!(Pin::new(&mut sink).poll_ready(&mut cx), Poll::Ready(Ok(())));
+    Pin::new(&mut sink).start_send(String::from("ab cd ef")).unwrap();
+    assert_eq!(Pin::new(&mut sink).poll_ready(&mut cx), Poll::Pending);
+    assert_eq!(sink.get_ref().received, vec!["ab"]);
+    sink.sink.capacity = 2;
+    assert_eq!(Pin::new(&mut sink).poll_ready(&mut cx), Poll::Ready(Ok(())));
+    assert_eq!(sink.get_ref().received, vec!["ab", "cd"]);
+    sink.sink.capacity = 10;
+    Pin::new(&mut sink).start_send(String::from("gh ")).unwrap();
+    assert_eq!(Pin::new(&mut sink).poll_flush(&mut cx), Poll::Ready(Ok(())));
+    assert_eq!(sink.get_ref().received, vec!["ab", "cd"]);
+    Pin::new(&mut sink).start_send(String::from("ij")).unwrap();
+    assert_eq!(Pin::new(&mut sink).poll_close(&mut cx), Poll::Ready(Ok(())));
+    let slow = sink.into_inner();
+    assert_eq!(slow.received, vec!["ab", "cd", "efgh", "ij"]);
+    assert!(slow.closed);
+    let slow = Slow { received: Vec::new(), capacity: 10, closed: false };
+    let mut sink = ParseSink::new(ALPHANUMERIC.plus(String::new).and_then_discard(WHITESPACE.star(ignore)), slow);
+    Pin::new(&mut sink).start_send(String::from("ab !")).unwrap();
+    assert_eq!(Pin::new(&mut sink).poll_ready(&mut cx), Poll::Ready(Err(Error::Parse(3))));
+}
@@ -0,0 +1,142 @@
+//! Versioned snapshots of suspended parser state.
+//!
+//! A long-lived parse may be suspended, stored, and resumed later by a different
+//! build of the program. If the grammar changed in the meantime, resuming with the old
+//! state would silently misparse. A `Snapshot` records the name and version of the
+//! grammar which produced the state, and refuses to resume with a different one,
+//! for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+//! # use parsell::snapshot::Snapshot;
+//! let parser = character(char::is_alphanumeric).star(String::new);
+//! let parsing = parser.init_str("abc").unwrap().unContinue();
+//! let snapshot = Snapshot::new("words", 1, parsing);
+//! assert!(snapshot.clone().resume("words", 2).is_err());
+//! let parsing = snapshot.resume("words", 1).unwrap();
+//! assert_eq!(parsing.last_str("def!"), "abcdef");
+//! ```
+//!
+//! The name and version are chosen by the user, since there is no stable way to
+//! identify a parser across builds. The version should be bumped whenever the grammar
+//! changes, including changes (such as the body of a closure) which do not change
+//! the type of the parser.
+//!
+//! With the `serde` feature, a snapshot is serializable if its state is,
+//! as the tuple `(grammar, version, state)`.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std;
+
+/// Suspended parser state, tagged with the grammar it belongs to.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot<S> {
+    /// The user-supplied grammar name.
+    pub grammar: String,
+    /// The user-supplied grammar version.
+    pub version: u64,
+    /// The suspended state.
+    pub state: S,
+}
+
+impl<S> Snapshot<S> {
+    /// Take a snapshot of state produced by the given grammar.
+    pub fn new(grammar: &str, version: u64, state: S) -> Self {
+        Snapshot {
+            grammar: String::from(grammar),
+            version,
+            state,
+        }
+    }
+
+    /// Check that the snapshot was produced by the given grammar,
+    /// returning the state if it was.
+    pub fn resume(self, grammar: &str, version: u64) -> Result<S, Mismatch> {
+        if self.grammar != grammar {
+            Err(Mismatch::Grammar { expected: String::from(grammar), found: self.grammar })
+        } else if self.version != version {
+            Err(Mismatch::Version { expected: version, found: self.version })
+        } else {
+            Ok(self.state)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<S> ::serde::Serialize for Snapshot<S>
+    where S: ::serde::Serialize,
+{
+    fn serialize<Ser>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error>
+        where Ser: ::serde::Serializer,
+    {
+        (&self.grammar, self.version, &self.state).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, S> ::serde::Deserialize<'de> for Snapshot<S>
+    where S: ::serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: ::serde::Deserializer<'de>,
+    {
+        let (grammar, version, state) = try!(<(String, u64, S)>::deserialize(deserializer));
+        Ok(Snapshot {
+            grammar,
+            version,
+            state,
+        })
+    }
+}
+
+/// The error returned when resuming a snapshot with a different grammar.
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Mismatch {
+    /// The grammar names differ.
+    Grammar { expected: String, found: String },
+    /// The grammar versions differ.
+    Version { expected: u64, found: u64 },
+}
+
+impl Display for Mismatch {
+    fn fmt(&self, fmt: &mut Formatter) -> std::fmt::Result {
+        match *self {
+            Mismatch::Grammar { ref expected, ref found } =>
+                write!(fmt, "snapshot is of grammar {:?}, expected {:?}", found, expected),
+            Mismatch::Version { expected, found } =>
+                write!(fmt, "snapshot has grammar version {}, expected {}", found, expected),
+        }
+    }
+}
+
+impl Error for Mismatch {}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_snapshot() {
+    use super::{character, Parser, UncommittedStr, StatefulStr};
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    let parser = ALPHANUMERIC.star(String::new);
+    let parsing = parser.init_str("ab").unwrap().unContinue();
+    let snapshot = Snapshot::new("words", 3, parsing);
+    assert_eq!(snapshot.clone().resume("words", 4).unwrap_err(),
+               Mismatch::Version { expected: 4, found: 3 });
+    assert_eq!(snapshot.clone().resume("numbers", 3).unwrap_err().to_string(),
+               "snapshot is of grammar \"words\", expected \"numbers\"");
+    assert_eq!(snapshot.resume("words", 3).unwrap().last_str("c!"), "abc");
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn test_snapshot_serde() {
+    use serde::{Deserialize, Serialize};
+    // Snapshots are serializable whenever their state is.
+    fn assert_serde<T>() where T: Serialize + for<'de> Deserialize<'de> {}
+    assert_serde::<Snapshot<u64>>();
+    assert_serde::<Snapshot<Vec<String>>>();
+}
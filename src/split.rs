@@ -0,0 +1,181 @@
+//! Splitting streams into records.
+//!
+//! Many formats are sequences of records separated by a delimiter, such as newline-separated
+//! logs or CSV rows. Scanning for the delimiter is much faster than parsing, so a stream can
+//! be split into records first, and each record then parsed as a complete input, for example
+//! in parallel, or so that an error in one record does not affect the others:
+//!
+//! ```
+//! # use parsell::split::{split_on, Quotes};
+//! let mut lines = split_on('\n').quoting(Quotes::new('"'));
+//! let records: Vec<String> = lines.push("a,b\n\"c\nd\"").map(String::from).collect();
+//! assert_eq!(records, vec!["a,b"]);
+//! let records: Vec<String> = lines.push(",e\nf").map(String::from).collect();
+//! assert_eq!(records, vec!["\"c\nd\",e"]);
+//! assert_eq!(lines.finish(), Some(String::from("f")));
+//! ```
+//!
+//! Records which lie entirely inside one chunk are borrowed from it, and only records
+//! which are split across chunks are copied. Quoting rules are given by a `Quoting`
+//! state machine, which decides whether each character is quoted, and so cannot be
+//! a delimiter.
+
+use std::borrow::Cow;
+
+/// A state machine for quoting rules.
+
+pub trait Quoting {
+    /// Process the next character, returning whether it is quoted.
+    fn quoted(&mut self, ch: char) -> bool;
+}
+
+impl<F> Quoting for F where F: FnMut(char) -> bool {
+    fn quoted(&mut self, ch: char) -> bool {
+        self(ch)
+    }
+}
+
+/// No quoting: every delimiter is a record boundary.
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct NoQuotes;
+
+impl Quoting for NoQuotes {
+    fn quoted(&mut self, _: char) -> bool {
+        false
+    }
+}
+
+/// Quoting with a quote character, as in CSV.
+///
+/// Delimiters between an opening and closing quote are not boundaries.
+/// A doubled quote inside quotes closes and reopens them, so CSV escaping works unchanged.
+
+#[derive(Copy, Clone, Debug)]
+pub struct Quotes {
+    quote: char,
+    inside: bool,
+}
+
+impl Quotes {
+    pub fn new(quote: char) -> Self {
+        Quotes { quote, inside: false }
+    }
+}
+
+impl Quoting for Quotes {
+    fn quoted(&mut self, ch: char) -> bool {
+        if ch == self.quote {
+            self.inside = !self.inside;
+            true
+        } else {
+            self.inside
+        }
+    }
+}
+
+/// A scanner which splits chunks of input into records.
+
+#[derive(Clone, Debug)]
+pub struct SplitOn<Q> {
+    delimiter: char,
+    quoting: Q,
+    buffer: String,
+}
+
+/// A scanner for records separated by `delimiter`, with no quoting.
+
+pub fn split_on(delimiter: char) -> SplitOn<NoQuotes> {
+    SplitOn {
+        delimiter,
+        quoting: NoQuotes,
+        buffer: String::new(),
+    }
+}
+
+impl<Q> SplitOn<Q> where Q: Quoting {
+    /// Use the given quoting rules.
+    pub fn quoting<R>(self, quoting: R) -> SplitOn<R> where R: Quoting {
+        SplitOn {
+            delimiter: self.delimiter,
+            quoting,
+            buffer: self.buffer,
+        }
+    }
+
+    /// Feed a chunk of input, returning an iterator over the records it completes.
+    ///
+    /// Any incomplete record at the end of the chunk is kept until the next chunk,
+    /// once the iterator has been run to completion.
+    pub fn push<'a>(&'a mut self, chunk: &'a str) -> Records<'a, Q> {
+        Records {
+            split: self,
+            rest: chunk,
+        }
+    }
+
+    /// End the input, returning the last record if it is non-empty.
+    pub fn finish(self) -> Option<String> {
+        if self.buffer.is_empty() { None } else { Some(self.buffer) }
+    }
+}
+
+/// An iterator over the records completed by a chunk.
+
+pub struct Records<'a, Q: 'a> {
+    split: &'a mut SplitOn<Q>,
+    rest: &'a str,
+}
+
+impl<'a, Q> Iterator for Records<'a, Q> where Q: Quoting {
+    type Item = Cow<'a, str>;
+    fn next(&mut self) -> Option<Cow<'a, str>> {
+        let delimiter = self.split.delimiter;
+        let boundary = {
+            let quoting = &mut self.split.quoting;
+            self.rest.char_indices().find(|&(_, ch)| !quoting.quoted(ch) && ch == delimiter)
+        };
+        match boundary {
+            None => {
+                self.split.buffer.push_str(self.rest);
+                self.rest = "";
+                None
+            },
+            Some((index, _)) => {
+                let record = &self.rest[..index];
+                self.rest = &self.rest[index + delimiter.len_utf8()..];
+                if self.split.buffer.is_empty() {
+                    Some(Cow::Borrowed(record))
+                } else {
+                    self.split.buffer.push_str(record);
+                    Some(Cow::Owned(self.split.buffer.split_off(0)))
+                }
+            },
+        }
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_split_on() {
+    let mut split = split_on(';');
+    assert_eq!(split.push("ab;;c").collect::<Vec<_>>(), vec![Cow::Borrowed("ab"), Cow::Borrowed("")]);
+    assert_eq!(split.push("d").count(), 0);
+    assert_eq!(split.push("e;f;").collect::<Vec<_>>(), vec![Cow::Owned::<str>(String::from("cde")), Cow::Borrowed("f")]);
+    assert_eq!(split.finish(), None);
+    let mut split = split_on(',').quoting(Quotes::new('\''));
+    assert_eq!(split.push("'a,b',c,'d").collect::<Vec<_>>(), vec!["'a,b'", "c"]);
+    assert_eq!(split.push("'',e'").count(), 0);
+    assert_eq!(split.push(",f").collect::<Vec<_>>(), vec!["'d'',e'"]);
+    assert_eq!(split.finish(), Some(String::from("f")));
+    let mut escaped = false;
+    let mut split = split_on(' ').quoting(|ch| {
+        let quoted = escaped;
+        escaped = !escaped && ch == '\\';
+        quoted
+    });
+    assert_eq!(split.push("a\\ b c\\").collect::<Vec<_>>(), vec!["a\\ b"]);
+    assert_eq!(split.push(" d e").collect::<Vec<_>>(), vec!["c\\ d"]);
+    assert_eq!(split.finish(), Some(String::from("e")));
+}
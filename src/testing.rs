@@ -0,0 +1,599 @@
+//! Tools for testing parsers.
+
+use super::{Uncommitted, UncommittedInfer, Stateful, ParseResult, Erasable};
+use super::ParseResult::{Done, Continue};
+use super::session;
+use super::session::Session;
+
+use std::str::Chars;
+use std::fmt::Debug;
+use std::io;
+use std::io::{Read, Write};
+
+// ----------- Chunk invariance ---------------
+
+/// Asserts that a parser gives the same result however its input is chunked.
+///
+/// The parser `parser` is run on `input` in one go, and then on `input` split
+/// into chunks in many different ways (one character at a time, and a number of
+/// `Chunker` chunkings with a range of mean chunk sizes). This panics if any run produces a different
+/// output, or consumes a different amount of input, than the unchunked run.
+///
+/// For example:
+///
+/// ```
+/// # use parsell::{character,Parser};
+/// # use parsell::testing::assert_chunk_invariant;
+/// let parser = character(char::is_alphanumeric).star(String::new);
+/// assert_chunk_invariant(parser, "abc123!");
+/// ```
+///
+/// Streaming parsers are meant to be independent of chunk boundaries, and this
+/// is a way to check that user-written combinators and `Function` implementations
+/// uphold that promise.
+
+pub fn assert_chunk_invariant<'a, P>(parser: P, input: &'a str)
+    where P: UncommittedInfer<char, Chars<'a>>,
+          P::State: Stateful<char, Chars<'a>, P::Output>,
+          P::Output: Debug + PartialEq,
+{
+    let expected = run_chunked(&parser, &unchunked(input));
+    let mut chunkings = vec![Chunker::new(Distribution::EveryChar).split(input)];
+    for index in 0..CHUNKINGS {
+        // Mean chunk sizes range from a byte to the whole input.
+        let mean = 1.0 + (index % 10) as f64 * input.len() as f64 / 10.0;
+        let seed = (input.len() * CHUNKINGS + index) as u64;
+        chunkings.push(Chunker::new(Distribution::Exponential(mean)).seed(seed).split(input));
+    }
+    for chunks in chunkings {
+        let actual = run_chunked(&parser, &chunks);
+        if actual != expected {
+            panic!("Chunked parse of {:?} gave {:?}, unchunked gave {:?}", chunks, actual, expected);
+        }
+    }
+}
+
+const CHUNKINGS: usize = 100;
+
+// The input as a single chunk, or no chunks at all if it is empty.
+
+fn unchunked(input: &str) -> Vec<&str> {
+    if input.is_empty() { Vec::new() } else { vec![input] }
+}
+
+// Run a parser on a sequence of chunks, returning the output (if any)
+// together with the number of bytes of input that were consumed.
+// Every chunk is given to the parser, including any empty chunks.
+
+fn run_chunked<'a, P, Output>(parser: &P, chunks: &[&'a str]) -> (Option<Output>, usize)
+    where P: Uncommitted<char, Chars<'a>, Output>,
+          P::State: Stateful<char, Chars<'a>, Output>,
+{
+    let mut consumed = 0;
+    let mut chunks = chunks.iter();
+    let mut state = match chunks.next() {
+        None => return (None, 0),
+        Some(chunk) => {
+            let mut data = chunk.chars();
+            let result = parser.init(&mut data);
+            consumed += chunk.len() - data.as_str().len();
+            match result {
+                None => return (None, consumed),
+                Some(Done(result)) => return (Some(result), consumed),
+                Some(Continue(state)) => state,
+            }
+        }
+    };
+    for chunk in chunks {
+        let mut data = chunk.chars();
+        let result: ParseResult<P::State, Output> = state.more(&mut data);
+        consumed += chunk.len() - data.as_str().len();
+        match result {
+            Done(result) => return (Some(result), consumed),
+            Continue(next) => state = next,
+        }
+    }
+    (Some(state.done()), consumed)
+}
+
+// ----------- Equivalence ---------------
+
+/// Asserts that two parsers agree on every generated input.
+///
+/// Each input produced by `inputs` is given to both `lhs` and `rhs`, and this panics
+/// if they produce different outputs, or consume different amounts of input.
+/// Before panicking, the input is minimized by repeatedly deleting characters
+/// while the parsers still disagree, and the panic message reports both the original
+/// and the minimized input.
+///
+/// This is useful when replacing a combinator grammar by a hand-optimized parser.
+/// The inputs can be generated by `random_strings`, for example:
+///
+/// ```
+/// # use parsell::{character,Parser};
+/// # use parsell::testing::{assert_equivalent,random_strings};
+/// fn is_digit(ch: char) -> bool { ch.is_digit(10) }
+/// let lhs = character(char::is_numeric).star(String::new);
+/// let rhs = character(is_digit).star(String::new);
+/// assert_equivalent(lhs, rhs, random_strings("0123456789a!", 10).take(1000));
+/// ```
+///
+/// Since the inputs are generated and then discarded, the output type of the
+/// parsers cannot borrow from the input.
+
+pub fn assert_equivalent<P, Q, I, Output>(lhs: P, rhs: Q, inputs: I)
+    where P: for<'a> Uncommitted<char, Chars<'a>, Output>,
+          Q: for<'a> Uncommitted<char, Chars<'a>, Output>,
+          for<'a> <P as Uncommitted<char, Chars<'a>, Output>>::State: Stateful<char, Chars<'a>, Output>,
+          for<'a> <Q as Uncommitted<char, Chars<'a>, Output>>::State: Stateful<char, Chars<'a>, Output>,
+          I: IntoIterator<Item = String>,
+          Output: Debug + PartialEq,
+{
+    for input in inputs {
+        if !agree(&lhs, &rhs, &input) {
+            let minimized = minimize(&input, |input| !agree(&lhs, &rhs, input));
+            panic!("Parsers disagree on {:?} (minimized from {:?}): {:?} vs {:?}",
+                   minimized, input,
+                   run_chunked(&lhs, &unchunked(&minimized)),
+                   run_chunked(&rhs, &unchunked(&minimized)));
+        }
+    }
+}
+
+fn agree<P, Q, Output>(lhs: &P, rhs: &Q, input: &str) -> bool
+    where P: for<'a> Uncommitted<char, Chars<'a>, Output>,
+          Q: for<'a> Uncommitted<char, Chars<'a>, Output>,
+          for<'a> <P as Uncommitted<char, Chars<'a>, Output>>::State: Stateful<char, Chars<'a>, Output>,
+          for<'a> <Q as Uncommitted<char, Chars<'a>, Output>>::State: Stateful<char, Chars<'a>, Output>,
+          Output: PartialEq,
+{
+    run_chunked(lhs, &unchunked(input)) == run_chunked(rhs, &unchunked(input))
+}
+
+// Delete runs of characters from the input for as long as the property holds,
+// halving the run length each time no deletion succeeds.
+
+fn minimize<F>(input: &str, property: F) -> String
+    where F: Fn(&str) -> bool,
+{
+    let mut current: Vec<char> = input.chars().collect();
+    let mut run = current.len();
+    while run > 0 {
+        let mut index = 0;
+        while index + run <= current.len() {
+            let candidate: String = current[..index].iter().chain(&current[(index + run)..]).cloned().collect();
+            if property(&candidate) {
+                current.drain(index..(index + run));
+            } else {
+                index += 1;
+            }
+        }
+        run /= 2;
+    }
+    current.into_iter().collect()
+}
+
+/// An infinite iterator of pseudo-random strings.
+///
+/// The strings are drawn from the characters in `alphabet`, and are at most `max_len`
+/// characters long. The sequence is deterministic, so failures are reproducible.
+
+pub fn random_strings(alphabet: &str, max_len: usize) -> RandomStrings {
+    RandomStrings {
+        alphabet: alphabet.chars().collect(),
+        max_len,
+        rng: Rng::new(max_len as u64),
+    }
+}
+
+/// The iterator returned by `random_strings`.
+
+pub struct RandomStrings {
+    alphabet: Vec<char>,
+    max_len: usize,
+    rng: Rng,
+}
+
+impl Iterator for RandomStrings {
+    type Item = String;
+    fn next(&mut self) -> Option<String> {
+        let len = self.rng.next() as usize % (self.max_len + 1);
+        let mut result = String::new();
+        if !self.alphabet.is_empty() {
+            for _ in 0..len {
+                result.push(self.alphabet[self.rng.next() as usize % self.alphabet.len()]);
+            }
+        }
+        Some(result)
+    }
+}
+
+// ----------- Recording and replay ---------------
+
+/// A recording of the chunks of input given to a parser.
+///
+/// Some bugs only show up when the input is split at a particular place.
+/// To make such bugs reproducible, a driver can record each chunk as it
+/// passes it to the parser, then save the recording with `write_to`.
+/// The recording can be loaded and run again with `replay`.
+///
+/// ```
+/// # use parsell::{character,Parser,UncommittedStr,StatefulStr};
+/// # use parsell::ParseResult::Continue;
+/// # use parsell::testing::{Recording,replay};
+/// let parser = character(char::is_alphanumeric).star(String::new);
+/// let mut recording = Recording::new();
+/// let mut file = Vec::new();
+/// match parser.init_str(recording.record("abc")).unwrap() {
+///     Continue(parsing) => assert_eq!(parsing.last_str(recording.record("123!")), "abc123"),
+///     _ => panic!("Can't happen"),
+/// }
+/// recording.write_to(&mut file).unwrap();
+/// assert_eq!(replay(&file[..], parser).unwrap(), Some(String::from("abc123")));
+/// ```
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Recording {
+    chunks: Vec<String>,
+}
+
+impl Recording {
+    /// Creates an empty recording.
+    pub fn new() -> Recording {
+        Recording { chunks: Vec::new() }
+    }
+
+    /// Records a chunk, and returns it so it can be passed on to the parser.
+    pub fn record<'a>(&mut self, chunk: &'a str) -> &'a str {
+        self.chunks.push(String::from(chunk));
+        chunk
+    }
+
+    /// The chunks recorded so far.
+    pub fn chunks(&self) -> &[String] {
+        &self.chunks
+    }
+
+    /// Writes the recording.
+    ///
+    /// Each chunk is written as its length in bytes (in LEB128 format) followed by its contents.
+    pub fn write_to<W>(&self, mut file: W) -> io::Result<()>
+        where W: Write,
+    {
+        for chunk in &self.chunks {
+            let mut len = chunk.len();
+            while len >= 0x80 {
+                try!(file.write_all(&[(len as u8) | 0x80]));
+                len >>= 7;
+            }
+            try!(file.write_all(&[len as u8]));
+            try!(file.write_all(chunk.as_bytes()));
+        }
+        Ok(())
+    }
+
+    /// Reads a recording that was written by `write_to`.
+    pub fn read_from<R>(mut file: R) -> io::Result<Recording>
+        where R: Read,
+    {
+        let mut bytes = Vec::new();
+        try!(file.read_to_end(&mut bytes));
+        let mut bytes = &bytes[..];
+        let mut result = Recording::new();
+        while !bytes.is_empty() {
+            let mut len = 0;
+            let mut shift = 0;
+            loop {
+                match bytes.split_first() {
+                    Some((&byte, rest)) if shift < 64 => {
+                        bytes = rest;
+                        len |= ((byte & 0x7F) as usize) << shift;
+                        shift += 7;
+                        if byte < 0x80 { break; }
+                    },
+                    _ => return Err(invalid_recording()),
+                }
+            }
+            if bytes.len() < len {
+                return Err(invalid_recording());
+            }
+            let (chunk, rest) = bytes.split_at(len);
+            match String::from_utf8(chunk.to_vec()) {
+                Ok(chunk) => result.chunks.push(chunk),
+                Err(_) => return Err(invalid_recording()),
+            }
+            bytes = rest;
+        }
+        Ok(result)
+    }
+
+    /// Runs a parser on the recorded chunks.
+    ///
+    /// Each chunk is given to the parser exactly as it was recorded, including empty chunks.
+    /// This returns `None` if the parser backtracked, and otherwise its output.
+    pub fn replay<P, Output>(&self, parser: P) -> Option<Output>
+        where P: for<'a> Uncommitted<char, Chars<'a>, Output>,
+              for<'a> <P as Uncommitted<char, Chars<'a>, Output>>::State: Stateful<char, Chars<'a>, Output>,
+    {
+        let chunks: Vec<&str> = self.chunks.iter().map(|chunk| &**chunk).collect();
+        run_chunked(&parser, &chunks).0
+    }
+
+    /// Runs a session on the recorded chunks, as recorded by `Session::record`.
+    ///
+    /// Like `Pipeline::finish`, this returns the outputs the session completed,
+    /// together with the error if the session rejected its input.
+    pub fn replay_session<P, Output>(&self, parser: P) -> Result<Vec<Output>, (Vec<Output>, session::Error)>
+        where P: 'static + Erasable<Output>,
+    {
+        let mut session = Session::new(parser);
+        let mut outputs = Vec::new();
+        for chunk in &self.chunks {
+            match session.push(chunk) {
+                Ok(completed) => outputs.extend(completed),
+                Err((completed, err)) => {
+                    outputs.extend(completed);
+                    return Err((outputs, err));
+                },
+            }
+        }
+        outputs.extend(session.finish());
+        Ok(outputs)
+    }
+}
+
+fn invalid_recording() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "invalid parsell recording")
+}
+
+/// Reads a recording written by `Recording::write_to`, and runs a parser on it.
+
+pub fn replay<R, P, Output>(file: R, parser: P) -> io::Result<Option<Output>>
+    where R: Read,
+          P: for<'a> Uncommitted<char, Chars<'a>, Output>,
+          for<'a> <P as Uncommitted<char, Chars<'a>, Output>>::State: Stateful<char, Chars<'a>, Output>,
+{
+    let recording = try!(Recording::read_from(file));
+    Ok(recording.replay(parser))
+}
+
+// ----------- Chunking strategies ---------------
+
+/// How a `Chunker` chooses the sizes of chunks.
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Distribution {
+    /// Chunks of a fixed number of bytes.
+    Fixed(usize),
+    /// Chunks whose sizes are exponentially distributed with a mean number of bytes,
+    /// as given by a network which delivers mostly small packets.
+    Exponential(f64),
+    /// Chunks of one character each, the worst case for parsers which buffer.
+    EveryChar,
+}
+
+/// A deterministic splitter of input into chunks.
+///
+/// Parsers should behave the same however their input is chunked, but their performance
+/// depends on the chunk sizes. A chunker splits a corpus into chunks whose sizes follow
+/// a `Distribution`, using a seeded pseudo-random generator, so benchmarks and tests
+/// can measure the effect of chunking reproducibly. For example:
+///
+/// ```
+/// # use parsell::testing::{Chunker,Distribution};
+/// let mut chunker = Chunker::new(Distribution::Fixed(3));
+/// assert_eq!(chunker.split("abcdefg"), vec!["abc", "def", "g"]);
+/// let mut chunker = Chunker::new(Distribution::Exponential(4.0)).seed(17);
+/// let chunks = chunker.split("The quick brown fox jumps over the lazy dog.");
+/// assert_eq!(chunks.concat(), "The quick brown fox jumps over the lazy dog.");
+/// assert_eq!(chunks, Chunker::new(Distribution::Exponential(4.0)).seed(17).split(&chunks.concat()));
+/// ```
+///
+/// Chunks are never empty, and are extended where necessary so that they do not split a character.
+/// Successive calls to `split` continue the same pseudo-random sequence.
+
+#[derive(Clone, Debug)]
+pub struct Chunker {
+    distribution: Distribution,
+    rng: Rng,
+}
+
+impl Chunker {
+    /// A chunker whose chunk sizes follow `distribution`.
+    pub fn new(distribution: Distribution) -> Chunker {
+        Chunker {
+            distribution,
+            rng: Rng::new(0),
+        }
+    }
+
+    /// The same chunker, with its pseudo-random generator seeded by `seed`.
+    pub fn seed(self, seed: u64) -> Chunker {
+        Chunker {
+            distribution: self.distribution,
+            rng: Rng::new(seed),
+        }
+    }
+
+    /// Split `input` into chunks.
+    pub fn split<'a>(&mut self, input: &'a str) -> Vec<&'a str> {
+        if self.distribution == Distribution::EveryChar {
+            return split_at_every_char(input);
+        }
+        let mut result = Vec::new();
+        let mut rest = input;
+        while !rest.is_empty() {
+            let mut index = rest.len().min(self.size().max(1));
+            while !rest.is_char_boundary(index) {
+                index += 1;
+            }
+            result.push(&rest[..index]);
+            rest = &rest[index..];
+        }
+        result
+    }
+
+    fn size(&mut self) -> usize {
+        match self.distribution {
+            Distribution::Fixed(size) => size,
+            Distribution::Exponential(mean) => {
+                // A uniform sample from (0, 1], from the top 53 bits.
+                let uniform = ((self.rng.next() >> 11) + 1) as f64 / (1u64 << 53) as f64;
+                (-mean * uniform.ln()).ceil() as usize
+            },
+            Distribution::EveryChar => 1,
+        }
+    }
+}
+
+fn split_at_every_char(input: &str) -> Vec<&str> {
+    input.char_indices().map(|(index, ch)| &input[index..(index + ch.len_utf8())]).collect()
+}
+
+// A small xorshift generator, so that chunkings are reproducible.
+
+#[derive(Clone, Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed ^ 0x2545_F491_4F6C_DD1D)
+    }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+fn test_chunker() {
+    let input = "a\u{e9}\u{1F600}bcdefghijklmnopqrstuvwxyz";
+    let mut chunker = Chunker::new(Distribution::Fixed(2));
+    assert_eq!(chunker.split("a\u{e9}\u{1F600}bc"), vec!["a\u{e9}", "\u{1F600}", "bc"]);
+    assert_eq!(chunker.split(""), Vec::<&str>::new());
+    let mut chunker = Chunker::new(Distribution::Fixed(0));
+    assert_eq!(chunker.split("ab"), vec!["a", "b"]);
+    let mut chunker = Chunker::new(Distribution::EveryChar);
+    assert_eq!(chunker.split("a\u{e9}b"), vec!["a", "\u{e9}", "b"]);
+    let mut chunker = Chunker::new(Distribution::Exponential(3.0)).seed(5);
+    let mut sizes = Vec::new();
+    for _ in 0..100 {
+        let chunks = chunker.split(input);
+        assert!(chunks.iter().all(|chunk| !chunk.is_empty()));
+        assert_eq!(chunks.concat(), input);
+        sizes.extend(chunks.iter().map(|chunk| chunk.len()));
+    }
+    let mean = sizes.iter().sum::<usize>() as f64 / sizes.len() as f64;
+    assert!(2.0 < mean && mean < 5.0, "Unexpected mean chunk size {}", mean);
+    let mut first = Chunker::new(Distribution::Exponential(3.0)).seed(5);
+    let mut second = first.clone();
+    assert_eq!(first.split(input), second.split(input));
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_assert_chunk_invariant() {
+    use super::{character, Parser, CHARACTER};
+    fn ignore() {}
+    let ALPHABETIC = character(char::is_alphabetic);
+    let ALPHANUMERIC = character(char::is_alphanumeric);
+    assert_chunk_invariant(ALPHANUMERIC.star(String::new), "abc123!");
+    assert_chunk_invariant(ALPHANUMERIC.star(String::new), "abc123");
+    assert_chunk_invariant(ALPHABETIC.and_then(ALPHANUMERIC.star(ignore)).buffer(), "a\u{e9}\u{1F600}1!");
+    assert_chunk_invariant(ALPHABETIC.and_then(CHARACTER), "!?");
+    assert_chunk_invariant(ALPHABETIC.and_then(CHARACTER), "");
+}
+
+#[test]
+fn test_minimize() {
+    assert_eq!(minimize("abc!def", |input| input.contains('!')), "!");
+    assert_eq!(minimize("ab!cd!ef", |input| input.matches('!').count() == 2), "!!");
+    assert_eq!(minimize("", |_| true), "");
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_assert_equivalent() {
+    use super::{character, Parser, CHARACTER};
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    fn mk_none(_: Option<char>) -> Option<char> { None }
+    let lhs = character(char::is_numeric).map(Some).or_else(CHARACTER.map(mk_none)).star(Vec::<Option<char>>::new);
+    let rhs = character(is_digit).map(Some).or_else(CHARACTER.map(mk_none)).star(Vec::<Option<char>>::new);
+    assert_equivalent(lhs, rhs, random_strings("0123456789a!", 10).take(1000));
+}
+
+#[test]
+#[should_panic(expected = "Parsers disagree on \"\u{663}\"")]
+fn test_assert_equivalent_fails() {
+    use super::{character, Parser};
+    fn is_digit(ch: char) -> bool { ch.is_ascii_digit() }
+    let lhs = character(char::is_numeric).star(String::new);
+    let rhs = character(is_digit).star(String::new);
+    assert_equivalent(lhs, rhs, random_strings("01a\u{663}", 10).take(1000));
+}
+
+#[test]
+fn test_recording() {
+    use super::{character, Parser};
+    let long: String = "x".repeat(300);
+    let mut recording = Recording::new();
+    recording.record("ab\u{e9}");
+    recording.record("");
+    recording.record(&long);
+    recording.record("!");
+    let mut file = Vec::new();
+    recording.write_to(&mut file).unwrap();
+    assert_eq!(file.len(), 1 + 4 + 1 + 2 + 300 + 1 + 1);
+    assert_eq!(Recording::read_from(&file[..]).unwrap(), recording);
+    let parser = character(char::is_alphanumeric).star(String::new);
+    assert_eq!(replay(&file[..], parser).unwrap(), Some(format!("ab\u{e9}{}", long)));
+    assert!(Recording::read_from(&file[..7]).is_err());
+    assert!(Recording::read_from(&[0x01, 0xFF][..]).is_err());
+}
+
+// A parser which only reads the first chunk it is given.
+
+#[cfg(test)]
+#[derive(Copy, Clone, Debug)]
+struct FirstChunk;
+
+#[cfg(test)]
+impl super::Parser for FirstChunk {}
+
+#[cfg(test)]
+impl<'a> super::HasOutput<char, Chars<'a>> for FirstChunk {
+    type Output = String;
+}
+
+#[cfg(test)]
+impl<'a> Uncommitted<char, Chars<'a>, String> for FirstChunk {
+    type State = FirstChunk;
+    fn init(&self, string: &mut Chars<'a>) -> Option<ParseResult<FirstChunk, String>> {
+        Some(Done(string.collect()))
+    }
+}
+
+#[cfg(test)]
+impl<'a> Stateful<char, Chars<'a>, String> for FirstChunk {
+    fn more(self, _: &mut Chars<'a>) -> ParseResult<FirstChunk, String> { Done(String::new()) }
+    fn done(self) -> String { String::new() }
+}
+
+#[test]
+fn test_replay_empty_chunks() {
+    let mut recording = Recording::new();
+    recording.record("");
+    recording.record("abc");
+    assert_eq!(recording.replay(FirstChunk), Some(String::new()));
+    assert_eq!(Recording::new().replay(FirstChunk), None);
+}
+
+#[test]
+#[should_panic]
+fn test_assert_chunk_invariant_fails() {
+    assert_chunk_invariant(FirstChunk, "abc");
+}
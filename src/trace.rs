@@ -0,0 +1,77 @@
+// Events reported by named parsers.
+//
+// Each event is counted for coverage and, with the `tracing` feature, emitted as a
+// `tracing` event with target `parsell`. Running a named parser on a chunk of input
+// happens inside a `tracing` span whose `rule` field is its name, so events from nested rules,
+// and from the application, can be correlated with the rule being parsed.
+// With the `debugger` feature, events are also recorded by a debugger which is taking a step.
+
+use super::coverage;
+#[cfg(feature = "debugger")]
+use super::debugger::{self, RuleEvent};
+use super::events;
+
+#[cfg(feature = "tracing")]
+pub(crate) type Span = ::tracing::span::EnteredSpan;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) struct Span;
+
+#[cfg(feature = "tracing")]
+fn span(name: &'static str, event: &'static str) -> Span {
+    let span = ::tracing::trace_span!(target: "parsell", "rule", rule = name).entered();
+    ::tracing::trace!(target: "parsell", rule = name, "{}", event);
+    span
+}
+
+#[cfg(not(feature = "tracing"))]
+fn span(_: &'static str, _: &'static str) -> Span {
+    Span
+}
+
+#[cfg(feature = "tracing")]
+fn event(name: &'static str, event: &'static str) {
+    ::tracing::trace!(target: "parsell", rule = name, "{}", event);
+}
+
+#[cfg(not(feature = "tracing"))]
+fn event(_: &'static str, _: &'static str) {}
+
+pub(crate) fn enter(name: &'static str) -> Span {
+    #[cfg(feature = "debugger")]
+    debugger::record(RuleEvent::Enter(name));
+    events::entered(name);
+    span(name, "enter")
+}
+
+pub(crate) fn resume(name: &'static str) -> Span {
+    #[cfg(feature = "debugger")]
+    debugger::record(RuleEvent::Resume(name));
+    span(name, "resume")
+}
+
+pub(crate) fn matched(name: &'static str) {
+    #[cfg(feature = "debugger")]
+    debugger::record(RuleEvent::Complete(name));
+    event(name, "complete");
+    events::matched(name);
+    coverage::matched(name)
+}
+
+pub(crate) fn suspended(name: &'static str) {
+    #[cfg(feature = "debugger")]
+    debugger::record(RuleEvent::Suspend(name));
+    event(name, "suspend");
+    coverage::suspended(name)
+}
+
+pub(crate) fn rejected(name: &'static str) {
+    #[cfg(feature = "debugger")]
+    debugger::record(RuleEvent::Reject(name));
+    event(name, "reject");
+    coverage::rejected(name)
+}
+
+pub(crate) fn backtracked() {
+    events::backtracked()
+}
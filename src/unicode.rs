@@ -0,0 +1,337 @@
+//! Unicode text transforms.
+//!
+//! Text which looks the same can be encoded as different sequences of characters, for
+//! example `é` is either `U+00E9` or `e` followed by the combining accent `U+0301`.
+//! Language front-ends following UAX #31 compare identifiers after normalizing them.
+//! With the `unicode-normalization` feature, the `Nfc` function normalizes text to NFC,
+//! and can be applied to the output of a parser with `parser.map(Nfc)`, or to the items
+//! of a repetition with `map_consumer(consumer, Nfc)`.
+//!
+//! Similarly, what a user thinks of as one character may be several `char`s, such as
+//! `e` followed by a combining accent, or a flag made of two regional indicators.
+//! With the `unicode-segmentation` feature, `Graphemes` splits text into grapheme
+//! clusters, which can be parsed instead of `char`s.
+//!
+//! Case-insensitive grammars can parse their input through `CaseFold`, which case folds
+//! each character as it is read, so their predicates and literals only need to match
+//! lowercase text. The original text is passed on to a side consumer, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,Uncommitted};
+//! # use parsell::ParseResult::Done;
+//! # use parsell::unicode::CaseFold;
+//! let keyword = character(char::is_alphabetic).plus(String::new);
+//! let mut input = CaseFold::with_original("Select *".chars(), String::new());
+//! match keyword.init(&mut input).unwrap() {
+//!     Done(word) => assert_eq!(word, "select"),
+//!     _ => panic!("can't happen"),
+//! }
+//! assert_eq!(input.original(), "Select");
+//! ```
+
+use super::{Consumer, Function, PeekableIterator};
+
+#[cfg(any(feature = "unicode-normalization", feature = "unicode-segmentation"))]
+use std::borrow::Cow;
+#[cfg(feature = "unicode-segmentation")]
+use std::iter::Peekable;
+#[cfg(feature = "unicode-segmentation")]
+use std::vec;
+
+#[cfg(feature = "unicode-normalization")]
+use unicode_normalization::{is_nfc, UnicodeNormalization};
+#[cfg(feature = "unicode-segmentation")]
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A function which normalizes text to NFC.
+///
+/// Text which is already normalized is passed through unchanged, so borrowed text
+/// is only copied if it needs normalizing. This can be used before interning or
+/// comparing identifiers, for example:
+///
+/// ```
+/// # use parsell::{character,Parser,UncommittedStr};
+/// # use parsell::unicode::Nfc;
+/// fn ignore() {}
+/// let identifier = character(|ch| ch != ' ' && ch != '!').plus(ignore).buffer().map(Nfc);
+/// assert_eq!(identifier.init_str("cafe\u{301}!").unwrap().unDone(), "caf\u{e9}");
+/// ```
+///
+/// This is only available with the `unicode-normalization` feature.
+
+#[cfg(feature = "unicode-normalization")]
+#[derive(Copy, Clone, Debug)]
+pub struct Nfc;
+
+#[cfg(feature = "unicode-normalization")]
+impl<'a> Function<Cow<'a, str>> for Nfc {
+    type Output = Cow<'a, str>;
+    fn apply(&self, text: Cow<'a, str>) -> Cow<'a, str> {
+        if is_nfc(&text) {
+            text
+        } else {
+            Cow::Owned(text.nfc().collect())
+        }
+    }
+}
+
+#[cfg(feature = "unicode-normalization")]
+impl<'a> Function<&'a str> for Nfc {
+    type Output = Cow<'a, str>;
+    fn apply(&self, text: &'a str) -> Cow<'a, str> {
+        self.apply(Cow::Borrowed(text))
+    }
+}
+
+#[cfg(feature = "unicode-normalization")]
+impl Function<String> for Nfc {
+    type Output = String;
+    fn apply(&self, text: String) -> String {
+        self.apply(Cow::Owned(text)).into_owned()
+    }
+}
+
+/// An input adapter, which splits chunks of text into extended grapheme clusters.
+///
+/// The last cluster of a chunk may be continued by the next chunk, so it is held back
+/// until the next chunk arrives, or the input is finished. Clusters which lie within
+/// a chunk are borrowed from it, for example:
+///
+/// ```
+/// # use parsell::{character_ref,Parser,Uncommitted,Stateful};
+/// # use parsell::ParseResult::Continue;
+/// # use parsell::unicode::Graphemes;
+/// # use std::borrow::Cow;
+/// let parser = character_ref(|cluster: &Cow<str>| cluster != " ").star(Vec::new);
+/// let mut graphemes = Graphemes::new();
+/// let state = match parser.init(&mut graphemes.push("cafe")).unwrap() {
+///     Continue(state) => state,
+///     _ => panic!("can't happen"),
+/// };
+/// let clusters = state.last(&mut graphemes.push("\u{301}s"));
+/// assert_eq!(clusters, vec!["c", "a", "f", "e\u{301}"]);
+/// assert_eq!(graphemes.finish().collect::<Vec<_>>(), vec!["s"]);
+/// ```
+///
+/// This is only available with the `unicode-segmentation` feature.
+
+#[cfg(feature = "unicode-segmentation")]
+#[derive(Clone, Debug, Default)]
+pub struct Graphemes {
+    // The last cluster of the previous chunk.
+    pending: String,
+}
+
+#[cfg(feature = "unicode-segmentation")]
+impl Graphemes {
+    /// An adapter which has not seen any input yet.
+    pub fn new() -> Self {
+        Graphemes::default()
+    }
+
+    /// Split a chunk into clusters, holding back the last one.
+    pub fn push<'a>(&mut self, chunk: &'a str) -> Peekable<vec::IntoIter<Cow<'a, str>>> {
+        let mut clusters = Vec::new();
+        if chunk.is_empty() {
+            return clusters.into_iter().peekable();
+        }
+        let held = self.pending.len();
+        self.pending.push_str(chunk);
+        let mut last = 0;
+        for (index, cluster) in self.pending.grapheme_indices(true) {
+            last = index;
+            if index < held {
+                clusters.push(Cow::Owned(String::from(cluster)));
+            } else {
+                clusters.push(Cow::Borrowed(&chunk[index - held..index - held + cluster.len()]));
+            }
+        }
+        clusters.pop();
+        self.pending.drain(..last);
+        clusters.into_iter().peekable()
+    }
+
+    /// End the input, returning the cluster which was held back.
+    pub fn finish(&mut self) -> Peekable<vec::IntoIter<Cow<'static, str>>> {
+        let mut clusters = Vec::new();
+        if !self.pending.is_empty() {
+            clusters.push(Cow::Owned(self.pending.split_off(0)));
+        }
+        clusters.into_iter().peekable()
+    }
+}
+
+/// An input adapter, which case folds characters as they are read.
+///
+/// This uses simple case folding, so each character is replaced by exactly one character,
+/// and characters whose only folding is longer, such as `U+0130`, are left unchanged.
+/// Folding is mostly lowercasing, but also identifies variant forms, such as final sigma `ς`
+/// with `σ` and long s `ſ` with `s`, and folds Cherokee to uppercase.
+/// The original characters which are read are passed on to a side consumer.
+
+#[derive(Copy, Clone, Debug)]
+pub struct CaseFold<I, C> {
+    input: I,
+    original: C,
+}
+
+impl<I> CaseFold<I, ()> {
+    /// An adapter which lowercases `input`, and discards the original text.
+    pub fn new(input: I) -> Self {
+        CaseFold::with_original(input, ())
+    }
+}
+
+impl<I, C> CaseFold<I, C> {
+    /// An adapter which lowercases `input`, passing the original text to `consumer`.
+    pub fn with_original(input: I, consumer: C) -> Self {
+        CaseFold {
+            input,
+            original: consumer,
+        }
+    }
+
+    /// The consumer of the original text.
+    pub fn original(&self) -> &C {
+        &self.original
+    }
+
+    /// The input which is still to be read, and the consumer of the original text.
+    pub fn into_inner(self) -> (I, C) {
+        (self.input, self.original)
+    }
+}
+
+// The simple case foldings which are not the lowercase of the character, apart from Cherokee,
+// from the `C` and `S` mappings of the Unicode `CaseFolding.txt`, sorted by character.
+
+const SIMPLE_FOLDS: &[(char, char)] = &[
+    ('\u{B5}', '\u{3BC}'), ('\u{17F}', 's'), ('\u{345}', '\u{3B9}'), ('\u{3C2}', '\u{3C3}'),
+    ('\u{3D0}', '\u{3B2}'), ('\u{3D1}', '\u{3B8}'), ('\u{3D5}', '\u{3C6}'), ('\u{3D6}', '\u{3C0}'),
+    ('\u{3F0}', '\u{3BA}'), ('\u{3F1}', '\u{3C1}'), ('\u{3F5}', '\u{3B5}'),
+    ('\u{1C80}', '\u{432}'), ('\u{1C81}', '\u{434}'), ('\u{1C82}', '\u{43E}'), ('\u{1C83}', '\u{441}'),
+    ('\u{1C84}', '\u{442}'), ('\u{1C85}', '\u{442}'), ('\u{1C86}', '\u{44A}'), ('\u{1C87}', '\u{463}'),
+    ('\u{1C88}', '\u{A64B}'), ('\u{1E9B}', '\u{1E61}'), ('\u{1FBE}', '\u{3B9}'), ('\u{1FD3}', '\u{390}'),
+    ('\u{1FE3}', '\u{3B0}'), ('\u{FB05}', '\u{FB06}'),
+];
+
+fn fold(ch: char) -> char {
+    match ch {
+        // Cherokee has an older uppercase block and a newer lowercase one, and folds to uppercase.
+        '\u{13A0}'..='\u{13F5}' => ch,
+        '\u{13F8}'..='\u{13FD}' => char::from_u32(ch as u32 - 8).unwrap_or(ch),
+        '\u{AB70}'..='\u{ABBF}' => char::from_u32(ch as u32 - 0xAB70 + 0x13A0).unwrap_or(ch),
+        _ => match SIMPLE_FOLDS.binary_search_by_key(&ch, |&(from, _)| from) {
+            Ok(index) => SIMPLE_FOLDS[index].1,
+            Err(_) => {
+                let mut lower = ch.to_lowercase();
+                match (lower.next(), lower.next()) {
+                    (Some(folded), None) => folded,
+                    _ => ch,
+                }
+            },
+        },
+    }
+}
+
+impl<I, C> Iterator for CaseFold<I, C>
+    where I: Iterator<Item = char>,
+          C: Consumer<char>,
+{
+    type Item = char;
+    fn next(&mut self) -> Option<char> {
+        let original = &mut self.original;
+        self.input.next().map(|ch| {
+            original.accept(ch);
+            fold(ch)
+        })
+    }
+}
+
+impl<I, C> PeekableIterator for CaseFold<I, C>
+    where I: PeekableIterator<Item = char>,
+          C: Consumer<char>,
+{
+    fn is_empty(&mut self) -> bool {
+        self.input.is_empty()
+    }
+
+    fn next_if_ref<F>(&mut self, f: F) -> Option<char>
+        where F: for<'a> Function<&'a char, Output = bool>
+    {
+        let original = &mut self.original;
+        self.input.next_if_ref(|ch: &char| f.apply(&fold(*ch))).map(|ch| {
+            original.accept(ch);
+            fold(ch)
+        })
+    }
+
+    fn progress(&self) -> Option<usize> {
+        self.input.progress()
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[cfg(feature = "unicode-normalization")]
+#[allow(non_snake_case)]
+fn test_nfc() {
+    use super::{character, map_consumer, Parser, UncommittedStr};
+    fn ignore() {}
+    let WORD = character(|ch| ch != ' ' && ch != '!').plus(ignore).buffer();
+    let SPACE = character(|ch| ch == ' ').plus(ignore);
+    assert_eq!(Nfc.apply("caf\u{e9}"), Cow::Borrowed("caf\u{e9}"));
+    assert_eq!(Nfc.apply("cafe\u{301}"), Cow::<str>::Owned(String::from("caf\u{e9}")));
+    assert_eq!(Nfc.apply(String::from("cafe\u{301}")), "caf\u{e9}");
+    let words = WORD.and_then_discard(SPACE.opt()).star(|| map_consumer(Vec::new(), Nfc));
+    let words = words.init_str("cafe\u{301} caf\u{e9}!").unwrap().unDone().into_inner();
+    assert_eq!(words[0], words[1]);
+}
+
+#[test]
+#[cfg(feature = "unicode-segmentation")]
+fn test_graphemes() {
+    let mut graphemes = Graphemes::new();
+    let clusters: Vec<Cow<str>> = graphemes.push("ab").collect();
+    assert_eq!(clusters, vec![Cow::Borrowed("a")]);
+    let clusters: Vec<Cow<str>> = graphemes.push("").collect();
+    assert!(clusters.is_empty());
+    let clusters: Vec<Cow<str>> = graphemes.push("\u{301}\u{300}").collect();
+    assert!(clusters.is_empty());
+    let clusters: Vec<Cow<str>> = graphemes.push("cd").collect();
+    assert_eq!(clusters, vec![Cow::<str>::Owned(String::from("b\u{301}\u{300}")), Cow::Borrowed("c")]);
+    assert_eq!(graphemes.finish().collect::<Vec<_>>(), vec!["d"]);
+    assert_eq!(graphemes.finish().next(), None);
+}
+
+#[test]
+#[allow(non_snake_case)]
+fn test_case_fold() {
+    use super::{character, IntoParser, Parser, Uncommitted, UncommittedStr};
+    use super::ParseResult::Done;
+    let ALPHABETIC = character(char::is_alphabetic);
+    let SELECT = "select".parser();
+    let mut input = CaseFold::with_original("SeLeCt Ab\u{130}!".chars(), String::new());
+    match SELECT.init(&mut input).unwrap() {
+        Done(_) => (),
+        _ => panic!("can't happen"),
+    }
+    assert_eq!(input.next(), Some(' '));
+    match ALPHABETIC.star(String::new).init(&mut input).unwrap() {
+        Done(word) => assert_eq!(word, "ab\u{130}"),
+        _ => panic!("can't happen"),
+    }
+    let (mut rest, original) = input.into_inner();
+    assert_eq!(rest.next(), Some('!'));
+    assert_eq!(original, "SeLeCt Ab\u{130}");
+    let mut input = CaseFold::new("\u{3a3}x".chars());
+    assert_eq!(input.next_if(|ch| ch == '\u{3c3}'), Some('\u{3c3}'));
+    assert_eq!(input.next_if(|ch| ch == 'X'), None);
+    assert_eq!(input.next(), Some('x'));
+    assert!(input.is_empty());
+    assert!(SELECT.init_str("SELECT").is_none());
+    let folded: String = CaseFold::new("\u{3A3}\u{3C2}\u{17F}\u{B5}\u{1E9E}\u{FB05}\u{13A0}\u{AB70}\u{13F8}\u{1C80}".chars()).collect();
+    assert_eq!(folded, "\u{3C3}\u{3C3}s\u{3BC}\u{DF}\u{FB06}\u{13A0}\u{13A0}\u{13F0}\u{432}");
+    assert!(SIMPLE_FOLDS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+}
@@ -0,0 +1,254 @@
+//! Parsing UTF-16 code units.
+//!
+//! Text from Windows APIs or JavaScript environments is often a sequence of UTF-16
+//! code units, rather than a `str`. A `Utf16Decoder` turns chunks of code units into
+//! input whose items are `char`s, so it can be parsed by the same parsers as strings,
+//! without transcoding it first. Characters outside the Basic Multilingual Plane are
+//! encoded as a surrogate pair, which may be split across chunks, for example:
+//!
+//! ```
+//! # use parsell::{character,Parser,Uncommitted,Stateful};
+//! # use parsell::ParseResult::Continue;
+//! # use parsell::utf16::Utf16Decoder;
+//! let parser = character(|ch| ch != ' ').star(String::new);
+//! let units: Vec<u16> = "a\u{1F600} b".encode_utf16().collect();
+//! let mut decoder = Utf16Decoder::new();
+//! let state = match parser.init(&mut decoder.push(&units[..2])).unwrap() {
+//!     Continue(state) => state,
+//!     _ => panic!("can't happen"),
+//! };
+//! assert_eq!(state.last(&mut decoder.push(&units[2..])), "a\u{1F600}");
+//! ```
+//!
+//! Unpaired surrogates, which are allowed by those environments but are not characters,
+//! are decoded as `U+FFFD REPLACEMENT CHARACTER`, like `String::from_utf16_lossy`.
+//!
+//! Parsers can also be run directly over code units, using `character(f)`
+//! with the character-class helpers in this module, such as `bmp(char::is_alphabetic)`.
+
+use super::{Function, PeekableIterator};
+
+use std::char;
+use std::iter::Peekable;
+use std::option;
+
+const REPLACEMENT: char = '\u{FFFD}';
+
+/// Is this code unit the first of a surrogate pair?
+
+pub fn is_high_surrogate(unit: u16) -> bool {
+    (0xD800..0xDC00).contains(&unit)
+}
+
+/// Is this code unit the second of a surrogate pair?
+
+pub fn is_low_surrogate(unit: u16) -> bool {
+    (0xDC00..0xE000).contains(&unit)
+}
+
+/// The character encoded by this code unit, if it is not part of a surrogate pair.
+///
+/// This can be used with `character_map` to read characters in the Basic Multilingual Plane.
+
+pub fn bmp_char(unit: u16) -> Option<char> {
+    char::from_u32(unit as u32)
+}
+
+/// A predicate on code units, which is `f(ch)` for code units encoding a character `ch`
+/// in the Basic Multilingual Plane, and `false` for surrogates.
+///
+/// This is used with `character`, for example:
+///
+/// ```
+/// # use parsell::{character,Parser,Uncommitted};
+/// # use parsell::ParseResult::Done;
+/// # use parsell::utf16::bmp;
+/// let parser = character(bmp(char::is_alphabetic)).plus(Vec::new);
+/// let units: Vec<u16> = "caf\u{e9}!".encode_utf16().collect();
+/// match parser.init(&mut units.iter().cloned().peekable()).unwrap() {
+///     Done(word) => assert_eq!(String::from_utf16(&word).unwrap(), "caf\u{e9}"),
+///     _ => panic!("can't happen"),
+/// }
+/// ```
+
+pub fn bmp<F>(f: F) -> Bmp<F> {
+    Bmp(f)
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Bmp<F>(F);
+
+impl<F> Function<u16> for Bmp<F>
+    where F: Function<char, Output = bool>,
+{
+    type Output = bool;
+    fn apply(&self, unit: u16) -> bool {
+        bmp_char(unit).is_some_and(|ch| self.0.apply(ch))
+    }
+}
+
+/// A decoder for chunks of UTF-16 code units.
+///
+/// If a chunk ends with the first of a surrogate pair, it is held back until the next chunk.
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct Utf16Decoder {
+    pending: Option<u16>,
+}
+
+impl Utf16Decoder {
+    /// A decoder which has not seen any input yet.
+    pub fn new() -> Self {
+        Utf16Decoder::default()
+    }
+
+    /// The characters encoded by a chunk of code units.
+    pub fn push<'a>(&'a mut self, chunk: &'a [u16]) -> Utf16Chars<'a> {
+        Utf16Chars {
+            pending: &mut self.pending,
+            units: chunk,
+        }
+    }
+
+    /// End the input, returning a replacement character if it ended part way through a surrogate pair.
+    pub fn finish(&mut self) -> Peekable<option::IntoIter<char>> {
+        self.pending.take().map(|_| REPLACEMENT).into_iter().peekable()
+    }
+}
+
+/// The characters encoded by a chunk of code units, produced by `decoder.push(chunk)`.
+
+#[derive(Debug)]
+pub struct Utf16Chars<'a> {
+    pending: &'a mut Option<u16>,
+    units: &'a [u16],
+}
+
+impl<'a> Utf16Chars<'a> {
+    // The next character, and how many units of the chunk it uses.
+    fn peek(&mut self) -> Option<(char, usize)> {
+        if let Some(high) = *self.pending {
+            return self.units.first().map(|&low| match pair(high, low) {
+                Some(ch) => (ch, 1),
+                None => (REPLACEMENT, 0),
+            });
+        }
+        let unit = match self.units.first() {
+            Some(&unit) => unit,
+            None => return None,
+        };
+        if !is_high_surrogate(unit) {
+            return Some((bmp_char(unit).unwrap_or(REPLACEMENT), 1));
+        }
+        match self.units.get(1) {
+            Some(&low) => Some(match pair(unit, low) {
+                Some(ch) => (ch, 2),
+                None => (REPLACEMENT, 1),
+            }),
+            None => {
+                *self.pending = Some(unit);
+                self.units = &[];
+                None
+            },
+        }
+    }
+
+    fn advance(&mut self, used: usize) {
+        *self.pending = None;
+        self.units = &self.units[used..];
+    }
+}
+
+fn pair(high: u16, low: u16) -> Option<char> {
+    if is_low_surrogate(low) {
+        char::from_u32(0x10000 + (((high as u32) - 0xD800) << 10) + ((low as u32) - 0xDC00))
+    } else {
+        None
+    }
+}
+
+impl<'a> Iterator for Utf16Chars<'a> {
+    type Item = char;
+    fn next(&mut self) -> Option<char> {
+        self.peek().map(|(ch, used)| {
+            self.advance(used);
+            ch
+        })
+    }
+}
+
+impl<'a> PeekableIterator for Utf16Chars<'a> {
+    fn is_empty(&mut self) -> bool {
+        self.peek().is_none()
+    }
+
+    fn next_if_ref<F>(&mut self, f: F) -> Option<char>
+        where F: for<'b> Function<&'b char, Output = bool>
+    {
+        match self.peek() {
+            Some((ch, used)) if f.apply(&ch) => {
+                self.advance(used);
+                Some(ch)
+            },
+            _ => None,
+        }
+    }
+
+    fn progress(&self) -> Option<usize> {
+        Some(self.units.len() + self.pending.iter().count())
+    }
+}
+
+// ----------- Tests -------------
+
+#[test]
+#[allow(non_snake_case)]
+fn test_utf16() {
+    use super::{character, character_map, IntoParser, Parser, Uncommitted, Stateful};
+    use super::ParseResult::{Done, Continue};
+    fn decode(units: &[u16]) -> String {
+        let mut decoder = Utf16Decoder::new();
+        let mut text: String = decoder.push(units).collect();
+        text.extend(decoder.finish());
+        text
+    }
+    let ALPHABETIC = character(char::is_alphabetic);
+    let NUMERIC = character(char::is_numeric);
+    let units: Vec<u16> = "abc123\u{1F600}".encode_utf16().collect();
+    let mut decoder = Utf16Decoder::new();
+    let mut chars = decoder.push(&units);
+    match ALPHABETIC.star(String::new).init(&mut chars).unwrap() {
+        Done(word) => assert_eq!(word, "abc"),
+        _ => panic!("can't happen"),
+    }
+    match NUMERIC.star(String::new).init(&mut chars).unwrap() {
+        Done(digits) => assert_eq!(digits, "123"),
+        _ => panic!("can't happen"),
+    }
+    assert!("\u{1F600}".parser().init(&mut chars).is_some());
+    assert!(chars.is_empty());
+    let mut decoder = Utf16Decoder::new();
+    let state = match ALPHABETIC.star(String::new).init(&mut decoder.push(&[0x61, 0xD83D])).unwrap() {
+        Continue(state) => state,
+        _ => panic!("can't happen"),
+    };
+    let mut chars = decoder.push(&[0xDE00]);
+    assert_eq!(chars.next_if(char::is_alphabetic), None);
+    assert_eq!(state.last(&mut chars), "a");
+    assert_eq!(chars.next(), Some('\u{1F600}'));
+    assert_eq!(chars.next(), None);
+    assert_eq!(decode(&[0xDE00, 0x61, 0xD83D, 0x62, 0xD83D]), "\u{FFFD}a\u{FFFD}b\u{FFFD}");
+    let mut decoder = Utf16Decoder::new();
+    let mut text: String = decoder.push(&[0xD83D]).collect();
+    text.extend(decoder.push(&[]));
+    text.extend(decoder.push(&[0x62]));
+    assert_eq!(text, "\u{FFFD}b");
+    let UNIT = character_map(bmp_char);
+    match character(bmp(char::is_alphabetic)).plus(Vec::new).and_then(UNIT.opt()).init(&mut vec![0x61, 0x62, 0x21].into_iter().peekable()).unwrap() {
+        Done((letters, bang)) => assert_eq!((letters, bang), (vec![0x61, 0x62], Some('!'))),
+        _ => panic!("can't happen"),
+    }
+    assert!(!bmp(char::is_alphabetic).apply(0xD83D));
+    assert!(is_high_surrogate(0xD83D) && !is_high_surrogate(0xDE00));
+    assert!(is_low_surrogate(0xDE00) && !is_low_surrogate(0xD83D));
+}
@@ -0,0 +1,71 @@
+//! A streaming driver for JavaScript.
+//!
+//! This module is only available with the `wasm` feature.
+//! A `StreamParser` is exported to JavaScript using `wasm-bindgen`, and can be fed chunks
+//! of a string as they arrive, for example while reading a file upload, returning the values
+//! completed by each chunk. Since exported types cannot be generic, the parser should
+//! produce `JsValue`s, and an application exports a function which builds one, for example:
+//!
+//! ```text
+//! #[wasm_bindgen]
+//! pub fn words() -> StreamParser {
+//!     StreamParser::new(character(char::is_alphanumeric).plus(String::new)
+//!         .and_then_discard(character(char::is_whitespace).star(ignore))
+//!         .map(JsValue::from))
+//! }
+//! ```
+//!
+//! which can then be used from JavaScript:
+//!
+//! ```text
+//! const parser = words();
+//! for await (const chunk of file.stream().pipeThrough(new TextDecoderStream())) {
+//!     for (const word of parser.push(chunk)) { ... }
+//! }
+//! const last = parser.finish();
+//! ```
+
+use super::Erasable;
+use super::session::Session;
+
+use wasm_bindgen::prelude::*;
+
+use std::mem;
+
+/// A parser which can be fed chunks of input from JavaScript.
+
+#[wasm_bindgen]
+pub struct StreamParser(Session<JsValue>, Vec<JsValue>);
+
+impl StreamParser {
+    /// A stream parser which runs `parser` repeatedly.
+    pub fn new<P>(parser: P) -> Self where P: 'static + Erasable<JsValue> {
+        StreamParser(Session::new(parser), Vec::new())
+    }
+}
+
+#[wasm_bindgen]
+impl StreamParser {
+    /// Feed a chunk of input, returning the values it completed,
+    /// or throwing an error if the input is rejected.
+    pub fn push(&mut self, chunk: &str) -> Result<Vec<JsValue>, JsValue> {
+        match self.0.push(chunk) {
+            Ok(values) => Ok(values),
+            Err((values, err)) => {
+                self.1 = values;
+                Err(JsValue::from_str(&err.to_string()))
+            },
+        }
+    }
+
+    /// The values completed by a chunk before its input was rejected, which are kept
+    /// until they are taken, since the error thrown by `push` cannot carry them.
+    pub fn take_completed(&mut self) -> Vec<JsValue> {
+        mem::take(&mut self.1)
+    }
+
+    /// End the input, returning the value which was split across the end of the input, if any.
+    pub fn finish(self) -> Option<JsValue> {
+        self.0.finish()
+    }
+}
@@ -0,0 +1,16 @@
+// Test that erasing parsers keeps typechecking time under control
+// https://github.com/rust-lang/rust/issues/31849
+
+extern crate parsell;
+use parsell::{Parser, UncommittedStr, CHARACTER};
+
+#[test]
+fn test_typecheck_time() {
+    let pair = CHARACTER.and_then(CHARACTER).erase_committed();
+    let quad = pair.clone().and_then(pair).erase_committed();
+    let octet = quad.clone().and_then(quad).erase_committed();
+    let word = octet.clone().and_then(octet).erase_committed();
+    let dword = word.clone().and_then(word).erase_committed();
+    let qword = dword.clone().and_then(dword).erase_committed();
+    qword.clone().and_then(qword).init_str("hello, world");
+}